@@ -0,0 +1,58 @@
+//! Times per-pixel operations on a 24 megapixel image. Run with and without the `simd` or
+//! `fast-resize` features to compare, such as `cargo bench --bench pixels --features simd`.
+
+use image::{DynamicImage, Rgb, RgbImage};
+use imageless::Operation;
+use serde::Deserialize;
+use std::time::Instant;
+
+const RUNS: u32 = 5;
+
+#[derive(Deserialize)]
+struct Config {
+	operations: Vec<Operation>,
+}
+
+fn main() {
+	let config: Config = toml::from_str(
+		r##"
+		[[operations]]
+		adjust-brightness = { brighten = 40 }
+
+		[[operations]]
+		adjust-brightness = { darken = 40 }
+
+		[[operations]]
+		curves = { rgb = [[0, 0], [64, 48], [192, 208], [255, 255]] }
+
+		[[operations]]
+		solarize = { threshold = 128, per_channel = true }
+
+		[[operations]]
+		tint = { color = "#1e90ff", mode = "screen", strength = 0.8 }
+
+		[[operations]]
+		resize = { width = { pixel = { pixels = 1500 } }, filter = "lanczos3", crop_mode = "exact" }
+		"##,
+	)
+	.unwrap();
+
+	let image = DynamicImage::ImageRgb8(RgbImage::from_fn(6000, 4000, |x, y| {
+		Rgb([x as u8, y as u8, (x ^ y) as u8])
+	}));
+
+	for operation in config.operations.iter() {
+		let mut total = 0.0;
+		for _ in 0..RUNS {
+			let image = image.clone();
+			let start = Instant::now();
+			operation.get_process().process(image).unwrap();
+			total += start.elapsed().as_secs_f64();
+		}
+		println!(
+			"{:<20} {:>8.2} ms",
+			operation.name(),
+			total * 1000.0 / RUNS as f64
+		);
+	}
+}
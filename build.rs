@@ -0,0 +1,14 @@
+fn main() {
+	#[cfg(feature = "grpc")]
+	{
+		// Use a vendored protoc so that building the gRPC service doesn't need one installed
+		if std::env::var_os("PROTOC").is_none() {
+			let protoc =
+				protoc_bin_vendored::protoc_bin_path().expect("vendored protoc is available");
+			std::env::set_var("PROTOC", protoc);
+		}
+
+		tonic_prost_build::compile_protos("proto/imageless.proto")
+			.expect("proto/imageless.proto compiles");
+	}
+}
@@ -0,0 +1,47 @@
+/*
+ * C interface to imageless. Build the shared library with
+ * `cargo rustc --lib --release --crate-type cdylib --features ffi`.
+ */
+
+#ifndef IMAGELESS_H
+#define IMAGELESS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define IMAGELESS_OK 0
+#define IMAGELESS_ERROR_INVALID_ARGUMENT 1
+#define IMAGELESS_ERROR_INVALID_PIPELINE 2
+#define IMAGELESS_ERROR_PROCESSING 3
+#define IMAGELESS_ERROR_PANIC 4
+
+/*
+ * Processes an encoded image with a JSON encoded pipeline, e.g.
+ * {"out_format": {"jpeg": {"quality": 80}}, "operations": [{"grayscale": {}}]}
+ *
+ * On success, `output` must be released with `imageless_free_bytes`. On failure, `output` is set
+ * to NULL and `output_len` to 0, and `error_message` (if not NULL) must be released with
+ * `imageless_free_string`.
+ */
+int imageless_process_bytes(
+	const uint8_t *input,
+	size_t input_len,
+	const char *pipeline,
+	uint8_t **output,
+	size_t *output_len,
+	char **error_message
+);
+
+void imageless_free_bytes(uint8_t *data, size_t len);
+
+void imageless_free_string(char *message);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
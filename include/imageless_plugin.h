@@ -0,0 +1,47 @@
+#ifndef IMAGELESS_PLUGIN_H
+#define IMAGELESS_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define IMAGELESS_PLUGIN_ABI_VERSION 1
+
+/* An RGBA8 image with `width * height * 4` bytes of pixel data. */
+typedef struct {
+	uint32_t width;
+	uint32_t height;
+	uint8_t *data;
+} imageless_plugin_image;
+
+typedef struct {
+	/* Name of the operation in configs */
+	const char *name;
+	/*
+	 * Processes `image` in place, with the parameters from the config encoded as JSON.
+	 *
+	 * A plugin which changes the dimensions replaces `data` with its own allocation, which is
+	 * released with `free`. On failure a non-zero status is returned and `error` may be set to a
+	 * message which is also released with `free`.
+	 */
+	int (*process)(const char *params, imageless_plugin_image *image, char **error);
+	void (*free)(void *ptr);
+} imageless_plugin_operation;
+
+typedef struct {
+	uint32_t abi_version;
+	size_t operation_count;
+	const imageless_plugin_operation *operations;
+} imageless_plugin_declaration;
+
+/* Exported by every plugin */
+const imageless_plugin_declaration *imageless_plugin_declare(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
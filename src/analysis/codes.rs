@@ -0,0 +1,97 @@
+use image::DynamicImage;
+use serde::Serialize;
+
+/// Bounding box of a QR code found in an image.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CodeRegion {
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+	/// Decoded payload, when decoding was requested and succeeded
+	pub content: Option<String>,
+}
+
+/// Locates QR codes in an image, optionally decoding their content.
+pub fn detect_qr_codes(image: &DynamicImage, decode: bool) -> Vec<CodeRegion> {
+	let luma = image.to_luma8();
+	let (width, height) = luma.dimensions();
+
+	let mut prepared =
+		rqrr::PreparedImage::prepare_from_greyscale(width as usize, height as usize, |x, y| {
+			luma.get_pixel(x as u32, y as u32).0[0]
+		});
+
+	prepared
+		.detect_grids()
+		.into_iter()
+		.map(|grid| {
+			let clamp_x = |x: i32| x.clamp(0, width as i32) as u32;
+			let clamp_y = |y: i32| y.clamp(0, height as i32) as u32;
+
+			let left = clamp_x(grid.bounds.iter().map(|point| point.x).min().unwrap_or(0));
+			let right = clamp_x(grid.bounds.iter().map(|point| point.x).max().unwrap_or(0));
+			let top = clamp_y(grid.bounds.iter().map(|point| point.y).min().unwrap_or(0));
+			let bottom = clamp_y(grid.bounds.iter().map(|point| point.y).max().unwrap_or(0));
+
+			let content = if decode {
+				grid.decode().ok().map(|(_, content)| content)
+			} else {
+				None
+			};
+
+			CodeRegion {
+				x: left,
+				y: top,
+				width: right - left,
+				height: bottom - top,
+				content,
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::detect_qr_codes;
+	use image::{DynamicImage, GrayImage, Luma};
+	use qrcode::{Color, QrCode};
+
+	/// Renders `content` as a QR code with modules of 4 pixels, with its top left corner at `left`
+	/// and `top` on a white image. Returns the image and the size of the code.
+	fn render(content: &str, left: u32, top: u32) -> (DynamicImage, u32) {
+		let code = QrCode::new(content).unwrap();
+		let modules = code.width() as u32;
+		let size = modules * 4;
+		let colors = code.to_colors();
+		let image = GrayImage::from_fn(left + size + 32, top + size + 32, |x, y| {
+			let inside = (left..left + size).contains(&x) && (top..top + size).contains(&y);
+			let module = || ((y - top) / 4 * modules + (x - left) / 4) as usize;
+			match inside && colors[module()] == Color::Dark {
+				true => Luma([0]),
+				false => Luma([255]),
+			}
+		});
+		(DynamicImage::ImageLuma8(image), size)
+	}
+
+	#[test]
+	fn finds_qr_codes() {
+		let (image, size) = render("https://example.com", 40, 24);
+
+		let regions = detect_qr_codes(&image, true);
+		assert_eq!(1, regions.len());
+		let region = &regions[0];
+		assert_eq!(Some("https://example.com"), region.content.as_deref());
+		// The bounds are found to within a module
+		assert!(region.x.abs_diff(40) <= 4, "{region:?}");
+		assert!(region.y.abs_diff(24) <= 4, "{region:?}");
+		assert!(region.width.abs_diff(size) <= 8, "{region:?}");
+		assert!(region.height.abs_diff(size) <= 8, "{region:?}");
+
+		assert_eq!(None, detect_qr_codes(&image, false)[0].content);
+		let blank = DynamicImage::ImageLuma8(GrayImage::from_pixel(64, 64, Luma([255])));
+		assert!(detect_qr_codes(&blank, true).is_empty());
+	}
+}
@@ -0,0 +1,163 @@
+use crate::ImageOutputFormat;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, GrayImage};
+use serde::{Deserialize, Serialize};
+
+/// Longest side images are downscaled to before scoring, which keeps analysis cheap on large
+/// inputs without changing the result meaningfully.
+const ANALYSIS_SIZE: u32 = 256;
+
+/// Gradient magnitude above which a pixel is counted as part of an edge.
+const EDGE_THRESHOLD: i32 = 32;
+
+/// Scores how visually complex an image is, from `0.0` for a flat color to `1.0` for dense
+/// detail.
+///
+/// The score is the mean of the luminance histogram's normalized Shannon entropy and the fraction
+/// of pixels lying on an edge.
+pub fn complexity(image: &DynamicImage) -> f32 {
+	let (width, height) = image.dimensions();
+	let luma = if width > ANALYSIS_SIZE || height > ANALYSIS_SIZE {
+		image
+			.resize(ANALYSIS_SIZE, ANALYSIS_SIZE, FilterType::Triangle)
+			.into_luma8()
+	} else {
+		image.to_luma8()
+	};
+
+	(entropy(&luma) + edge_density(&luma)) / 2.0
+}
+
+fn entropy(luma: &GrayImage) -> f32 {
+	let mut histogram = [0u32; 256];
+	for pixel in luma.pixels() {
+		histogram[pixel.0[0] as usize] += 1;
+	}
+
+	let total = (luma.width() * luma.height()) as f32;
+	let entropy: f32 = histogram
+		.iter()
+		.filter(|count| **count > 0)
+		.map(|count| {
+			let probability = *count as f32 / total;
+			-probability * probability.log2()
+		})
+		.sum();
+
+	// 8 bits is the maximum entropy of an 8-bit channel
+	entropy / 8.0
+}
+
+fn edge_density(luma: &GrayImage) -> f32 {
+	let (width, height) = luma.dimensions();
+	if width < 2 || height < 2 {
+		return 0.0;
+	}
+
+	let value = |x: u32, y: u32| luma.get_pixel(x, y).0[0] as i32;
+	let mut edges = 0u32;
+	for y in 0..height - 1 {
+		for x in 0..width - 1 {
+			let dx = value(x + 1, y) - value(x, y);
+			let dy = value(x, y + 1) - value(x, y);
+			if dx.abs() + dy.abs() > EDGE_THRESHOLD {
+				edges += 1;
+			}
+		}
+	}
+
+	edges as f32 / ((width - 1) * (height - 1)) as f32
+}
+
+/// Picks an encoder quality between `min` and `max` based on the [`complexity`] of the image, so
+/// flat graphics are encoded at low quality and detailed photos at high quality.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct AdaptiveQuality {
+	pub min: u8,
+	pub max: u8,
+}
+
+impl AdaptiveQuality {
+	pub fn quality(&self, image: &DynamicImage) -> u8 {
+		let (min, max) = (self.min.min(self.max), self.min.max(self.max));
+		let range = (max - min) as f32;
+		min + (range * complexity(image)).round() as u8
+	}
+
+	/// Applies the chosen quality to formats which have a quality setting.
+	pub fn apply(&self, format: ImageOutputFormat, image: &DynamicImage) -> ImageOutputFormat {
+		match format {
+			ImageOutputFormat::Jpeg { .. } => ImageOutputFormat::Jpeg {
+				quality: self.quality(image),
+			},
+			ImageOutputFormat::WebP {
+				lossless: false,
+				alpha_quality,
+				..
+			} => ImageOutputFormat::WebP {
+				quality: self.quality(image) as f32,
+				lossless: false,
+				alpha_quality,
+			},
+			ImageOutputFormat::Jxl {
+				lossless: false,
+				effort,
+				..
+			} => ImageOutputFormat::Jxl {
+				quality: self.quality(image) as f32,
+				effort,
+				lossless: false,
+			},
+			other => other,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{complexity, AdaptiveQuality};
+	use crate::ImageOutputFormat;
+	use image::{DynamicImage, Rgb, RgbImage};
+
+	fn flat() -> DynamicImage {
+		DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, Rgb([120, 120, 120])))
+	}
+
+	fn checkerboard() -> DynamicImage {
+		DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+			if (x + y) % 2 == 0 {
+				Rgb([0, 0, 0])
+			} else {
+				Rgb([255, 255, 255])
+			}
+		}))
+	}
+
+	#[test]
+	fn scores_detail() {
+		assert_eq!(0.0, complexity(&flat()));
+		// Every pixel is on an edge, but two levels only take a bit of entropy
+		let score = complexity(&checkerboard());
+		assert!((score - (1.0 + 1.0 / 8.0) / 2.0).abs() < 1e-3, "{score}");
+		assert_eq!(
+			0.0,
+			complexity(&DynamicImage::ImageRgb8(RgbImage::new(0, 0)))
+		);
+	}
+
+	#[test]
+	fn adapts_quality() {
+		let adaptive = AdaptiveQuality { min: 90, max: 40 };
+		assert_eq!(40, adaptive.quality(&flat()));
+		assert_eq!(68, adaptive.quality(&checkerboard()));
+
+		assert_eq!(
+			ImageOutputFormat::Jpeg { quality: 40 },
+			adaptive.apply(ImageOutputFormat::Jpeg { quality: 80 }, &flat())
+		);
+		assert_eq!(
+			ImageOutputFormat::png(),
+			adaptive.apply(ImageOutputFormat::png(), &flat())
+		);
+	}
+}
@@ -0,0 +1,260 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{decode, DecodeLimits, Error, ProcessOptions};
+use image::{imageops::FilterType, DynamicImage};
+use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, io::BufReader, path::Path};
+
+const HASH_WIDTH: u32 = 9;
+const HASH_HEIGHT: u32 = 8;
+
+/// A 64-bit difference hash (dHash) of an image.
+///
+/// Images which look alike produce hashes with a small Hamming distance, regardless of their
+/// dimensions or encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PerceptualHash(u64);
+
+impl PerceptualHash {
+	pub fn from_image(image: &DynamicImage) -> Self {
+		let luma = image
+			.resize_exact(HASH_WIDTH, HASH_HEIGHT, FilterType::Triangle)
+			.into_luma8();
+
+		let mut hash = 0u64;
+		for y in 0..HASH_HEIGHT {
+			for x in 0..HASH_WIDTH - 1 {
+				let left = luma.get_pixel(x, y).0[0];
+				let right = luma.get_pixel(x + 1, y).0[0];
+				hash = (hash << 1) | u64::from(left > right);
+			}
+		}
+
+		Self(hash)
+	}
+
+	/// Number of differing bits between two hashes.
+	pub fn distance(&self, other: &Self) -> u32 {
+		(self.0 ^ other.0).count_ones()
+	}
+}
+
+impl From<PerceptualHash> for u64 {
+	fn from(value: PerceptualHash) -> Self {
+		value.0
+	}
+}
+
+#[derive(Debug)]
+pub struct DuplicateCluster {
+	pub paths: Vec<PathBuf>,
+}
+
+/// Result of [`find_duplicates`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Default)]
+pub struct Duplicates {
+	pub clusters: Vec<DuplicateCluster>,
+	/// Files which could not be read or decoded, and were left out of the comparison
+	pub unreadable: Vec<(PathBuf, Error)>,
+}
+
+/// Groups `paths` into clusters of near-duplicate images.
+///
+/// Two images belong to the same cluster when the Hamming distance between their perceptual hashes
+/// is at most `threshold`, or when they are both near-duplicates of a third image. Images without
+/// any duplicates are not reported, and files which fail to decode, or exceed `limits`, are
+/// reported separately rather than stopping the search.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn find_duplicates<P: AsRef<Path>>(
+	paths: &[P],
+	threshold: u32,
+	limits: DecodeLimits,
+) -> Duplicates {
+	let options = ProcessOptions {
+		limits,
+		..Default::default()
+	};
+	let hash = |path: &Path| -> Result<PerceptualHash, Error> {
+		let image = decode(BufReader::new(File::open(path)?), &options)?;
+		Ok(PerceptualHash::from_image(&image))
+	};
+
+	let mut duplicates = Duplicates::default();
+	let mut hashes = Vec::with_capacity(paths.len());
+	let mut paths_read = Vec::with_capacity(paths.len());
+	for path in paths {
+		let path = path.as_ref();
+		match hash(path) {
+			Ok(hash) => {
+				hashes.push(hash);
+				paths_read.push(path);
+			}
+			Err(error) => duplicates.unreadable.push((path.to_path_buf(), error)),
+		}
+	}
+
+	let mut parents: Vec<usize> = (0..hashes.len()).collect();
+	for (i, hash) in hashes.iter().enumerate() {
+		for (j, other) in hashes.iter().enumerate().skip(i + 1) {
+			if hash.distance(other) <= threshold {
+				let i = find_root(&mut parents, i);
+				let j = find_root(&mut parents, j);
+				parents[j] = i;
+			}
+		}
+	}
+
+	let mut clusters: Vec<(usize, DuplicateCluster)> = Vec::new();
+	for (index, path) in paths_read.into_iter().enumerate() {
+		let root = find_root(&mut parents, index);
+		let path = path.to_path_buf();
+		match clusters
+			.iter_mut()
+			.find(|(cluster_root, _)| *cluster_root == root)
+		{
+			Some((_, cluster)) => cluster.paths.push(path),
+			None => clusters.push((root, DuplicateCluster { paths: vec![path] })),
+		}
+	}
+
+	duplicates.clusters = clusters
+		.into_iter()
+		.map(|(_, cluster)| cluster)
+		.filter(|cluster| cluster.paths.len() > 1)
+		.collect();
+	duplicates
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn find_root(parents: &mut [usize], mut index: usize) -> usize {
+	while parents[index] != index {
+		parents[index] = parents[parents[index]];
+		index = parents[index];
+	}
+	index
+}
+
+#[cfg(test)]
+mod tests {
+	use super::PerceptualHash;
+	#[cfg(not(target_arch = "wasm32"))]
+	use crate::DecodeLimits;
+	use image::{DynamicImage, Rgb, RgbImage};
+	#[cfg(not(target_arch = "wasm32"))]
+	use std::fs;
+
+	fn gradient(width: u32, height: u32) -> DynamicImage {
+		DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, _| {
+			let value = (x * 255 / width) as u8;
+			Rgb([value, value, value])
+		}))
+	}
+
+	#[test]
+	fn perceptual_hash_ignores_dimensions() {
+		let small = PerceptualHash::from_image(&gradient(90, 80));
+		let large = PerceptualHash::from_image(&gradient(900, 800));
+
+		assert!(small.distance(&large) <= 2);
+	}
+
+	#[test]
+	fn perceptual_hash_distinguishes_mirrored_images() {
+		let image = gradient(90, 80);
+		let hash = PerceptualHash::from_image(&image);
+		let mirrored = PerceptualHash::from_image(&image.fliph());
+
+		assert!(hash.distance(&mirrored) > 32);
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	#[test]
+	fn finds_near_duplicates() {
+		let dir = std::env::temp_dir().join(format!("imageless-duplicates-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let image = gradient(90, 80);
+		let paths = [
+			dir.join("original.png"),
+			dir.join("mirrored.png"),
+			dir.join("resized.png"),
+			dir.join("brighter.jpg"),
+		];
+		image.save(&paths[0]).unwrap();
+		image.fliph().save(&paths[1]).unwrap();
+		image.thumbnail(45, 40).save(&paths[2]).unwrap();
+		image.brighten(8).save(&paths[3]).unwrap();
+
+		let duplicates = super::find_duplicates(&paths, 5, DecodeLimits::default());
+		fs::remove_dir_all(&dir).ok();
+
+		assert!(duplicates.unreadable.is_empty());
+		let clusters = duplicates.clusters;
+		assert_eq!(1, clusters.len());
+		assert_eq!(
+			vec![paths[0].clone(), paths[2].clone(), paths[3].clone()],
+			clusters[0].paths
+		);
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	#[test]
+	fn reports_unreadable_files() {
+		let dir = std::env::temp_dir().join(format!("imageless-unreadable-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let image = gradient(90, 80);
+		let paths = [
+			dir.join("original.png"),
+			dir.join("corrupt.png"),
+			dir.join("missing.png"),
+			dir.join("copy.png"),
+		];
+		image.save(&paths[0]).unwrap();
+		fs::write(&paths[1], b"not a png").unwrap();
+		image.save(&paths[3]).unwrap();
+
+		let duplicates = super::find_duplicates(&paths, 0, DecodeLimits::default());
+		fs::remove_dir_all(&dir).ok();
+
+		let unreadable: Vec<_> = duplicates
+			.unreadable
+			.iter()
+			.map(|(path, _)| path.clone())
+			.collect();
+		assert_eq!(vec![paths[1].clone(), paths[2].clone()], unreadable);
+		assert_eq!(1, duplicates.clusters.len());
+		assert_eq!(
+			vec![paths[0].clone(), paths[3].clone()],
+			duplicates.clusters[0].paths
+		);
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	#[test]
+	fn decodes_by_contents_within_limits() {
+		let dir = std::env::temp_dir().join(format!("imageless-limits-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let paths = [
+			dir.join("original.png"),
+			dir.join("mislabeled.jpg"),
+			dir.join("large.png"),
+		];
+		gradient(90, 80).save(&paths[0]).unwrap();
+		fs::copy(&paths[0], &paths[1]).unwrap();
+		gradient(400, 300).save(&paths[2]).unwrap();
+
+		let limits = DecodeLimits {
+			max_pixels: Some(90 * 80),
+			..DecodeLimits::default()
+		};
+		let duplicates = super::find_duplicates(&paths, 0, limits);
+		fs::remove_dir_all(&dir).ok();
+
+		assert_eq!(1, duplicates.unreadable.len());
+		assert_eq!(paths[2], duplicates.unreadable[0].0);
+		assert_eq!(
+			vec![paths[0].clone(), paths[1].clone()],
+			duplicates.clusters[0].paths
+		);
+	}
+}
@@ -0,0 +1,13 @@
+#[cfg(feature = "qr")]
+mod codes;
+mod complexity;
+mod hash;
+mod quality;
+
+#[cfg(feature = "qr")]
+pub use codes::{detect_qr_codes, CodeRegion};
+pub use complexity::{complexity, AdaptiveQuality};
+#[cfg(not(target_arch = "wasm32"))]
+pub use hash::{find_duplicates, Duplicates};
+pub use hash::{DuplicateCluster, PerceptualHash};
+pub use quality::{ssim, suggest_quality, DEFAULT_TARGET_SSIM};
@@ -0,0 +1,194 @@
+use crate::{
+	encode_to, process_bytes, url::with_quality, Error, ImageOutputFormat, OperationError,
+};
+use image::{DynamicImage, GenericImageView, GrayImage};
+use std::io::Cursor;
+
+/// SSIM which is visually indistinguishable from the original for most content.
+pub const DEFAULT_TARGET_SSIM: f32 = 0.98;
+
+const CROP_SIZE: u32 = 64;
+const CROP_COUNT: usize = 4;
+const GRID_SIZE: u32 = 4;
+const WINDOW_SIZE: u32 = 8;
+
+/// Recommends the lowest encoder quality for `format` at which representative crops of `image`
+/// still reach `target_ssim` once encoded.
+///
+/// Returns `None` for lossless formats and formats without a quality setting.
+pub fn suggest_quality(
+	image: &DynamicImage,
+	format: &ImageOutputFormat,
+	target_ssim: f32,
+) -> Result<Option<u8>, Error> {
+	let lossy = match format {
+		ImageOutputFormat::Jpeg { .. } => true,
+		// WebP is only encoded lossily with libwebp, which is not built for wasm
+		ImageOutputFormat::WebP { lossless, .. } => !lossless && cfg!(not(target_arch = "wasm32")),
+		ImageOutputFormat::Jxl { lossless, .. } => !lossless && cfg!(feature = "jxl"),
+		_ => false,
+	};
+	if !lossy {
+		return Ok(None);
+	}
+
+	let crops = representative_crops(image);
+
+	let (mut low, mut high) = (1u8, 100u8);
+	while low < high {
+		let quality = low + (high - low) / 2;
+		let format = with_quality(format.clone(), Some(quality));
+
+		let mut total = 0.0;
+		for crop in crops.iter() {
+			let mut encoded = Cursor::new(Vec::new());
+			encode_to(&mut encoded, crop, format.clone())?;
+			let decoded = process_bytes(encoded.get_ref(), Vec::new())?;
+			total += ssim(&crop.to_luma8(), &decoded.to_luma8())?;
+		}
+
+		if total / crops.len() as f32 >= target_ssim {
+			high = quality;
+		} else {
+			low = quality + 1;
+		}
+	}
+
+	Ok(Some(low))
+}
+
+/// Picks the most detailed crops from a grid over the image, since those are where compression
+/// artifacts show first.
+fn representative_crops(image: &DynamicImage) -> Vec<DynamicImage> {
+	let (width, height) = image.dimensions();
+	if width <= CROP_SIZE || height <= CROP_SIZE {
+		return vec![image.clone()];
+	}
+
+	let mut crops = (0..GRID_SIZE * GRID_SIZE)
+		.map(|cell| {
+			let x = (width - CROP_SIZE) * (cell % GRID_SIZE) / (GRID_SIZE - 1);
+			let y = (height - CROP_SIZE) * (cell / GRID_SIZE) / (GRID_SIZE - 1);
+			let crop = image.crop_imm(x, y, CROP_SIZE, CROP_SIZE);
+			(super::complexity(&crop), crop)
+		})
+		.collect::<Vec<_>>();
+
+	crops.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+	crops
+		.into_iter()
+		.take(CROP_COUNT)
+		.map(|(_, crop)| crop)
+		.collect()
+}
+
+/// Mean structural similarity between two equally sized grayscale images, over 8x8 windows.
+/// Errors when the images differ in size or are empty.
+pub fn ssim(a: &GrayImage, b: &GrayImage) -> Result<f32, Error> {
+	const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+	const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+	if a.dimensions() != b.dimensions() {
+		return Err(OperationError::new(format!(
+			"Cannot compare a {}x{} image with a {}x{} image",
+			a.width(),
+			a.height(),
+			b.width(),
+			b.height()
+		))
+		.into());
+	}
+	let (width, height) = a.dimensions();
+	if width == 0 || height == 0 {
+		return Err(OperationError::new("Cannot compare empty images".to_string()).into());
+	}
+	let window_width = WINDOW_SIZE.min(width);
+	let window_height = WINDOW_SIZE.min(height);
+
+	let mut total = 0.0;
+	let mut windows = 0;
+	for top in (0..=height - window_height).step_by((window_height / 2).max(1) as usize) {
+		for left in (0..=width - window_width).step_by((window_width / 2).max(1) as usize) {
+			let samples = (window_width * window_height) as f64;
+			let pixels = || {
+				(top..top + window_height).flat_map(move |y| {
+					(left..left + window_width).map(move |x| {
+						(a.get_pixel(x, y).0[0] as f64, b.get_pixel(x, y).0[0] as f64)
+					})
+				})
+			};
+
+			let (sum_a, sum_b) = pixels().fold((0.0, 0.0), |(sa, sb), (pa, pb)| (sa + pa, sb + pb));
+			let (mean_a, mean_b) = (sum_a / samples, sum_b / samples);
+
+			let (var_a, var_b, covariance) =
+				pixels().fold((0.0, 0.0, 0.0), |(va, vb, cov), (pa, pb)| {
+					let (da, db) = (pa - mean_a, pb - mean_b);
+					(va + da * da, vb + db * db, cov + da * db)
+				});
+			let (var_a, var_b, covariance) =
+				(var_a / samples, var_b / samples, covariance / samples);
+
+			total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+				/ ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+			windows += 1;
+		}
+	}
+
+	Ok((total / windows as f64) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ssim, suggest_quality};
+	use crate::ImageOutputFormat;
+	use image::{DynamicImage, GrayImage, Luma};
+
+	fn pattern() -> GrayImage {
+		GrayImage::from_fn(32, 32, |x, y| Luma([((x * 7 + y * 13) % 256) as u8]))
+	}
+
+	#[test]
+	fn identical_images() {
+		let image = pattern();
+		assert_eq!(1.0, ssim(&image, &image).unwrap());
+	}
+
+	#[test]
+	fn degraded_images() {
+		let image = pattern();
+		// Flattening detail costs more similarity than a slight change in brightness
+		let brighter = GrayImage::from_fn(32, 32, |x, y| {
+			Luma([image.get_pixel(x, y).0[0].saturating_add(4)])
+		});
+		let posterized =
+			GrayImage::from_fn(32, 32, |x, y| Luma([image.get_pixel(x, y).0[0] & 0xC0]));
+
+		let brighter = ssim(&image, &brighter).unwrap();
+		let posterized = ssim(&image, &posterized).unwrap();
+		assert!(brighter > 0.95 && brighter < 1.0);
+		assert!(posterized < brighter);
+	}
+
+	#[test]
+	fn mismatched_images() {
+		assert!(ssim(&pattern(), &GrayImage::new(16, 32)).is_err());
+		assert!(ssim(&GrayImage::new(0, 0), &GrayImage::new(0, 0)).is_err());
+	}
+
+	#[test]
+	fn suggests_webp_quality() {
+		let image = DynamicImage::ImageLuma8(pattern());
+		let lossy = ImageOutputFormat::webp();
+		let lenient = suggest_quality(&image, &lossy, 0.8).unwrap().unwrap();
+		let strict = suggest_quality(&image, &lossy, 0.99).unwrap().unwrap();
+		assert!(lenient < strict);
+
+		let lossless = ImageOutputFormat::WebP {
+			quality: 80.0,
+			lossless: true,
+			alpha_quality: 100,
+		};
+		assert_eq!(None, suggest_quality(&image, &lossless, 0.99).unwrap());
+	}
+}
@@ -0,0 +1,327 @@
+//! Processing of animated GIF, APNG and WebP images. Every frame runs through the operations
+//! separately, and the frames are encoded together again as an animated GIF, PNG or WebP.
+//! Animations can also be assembled from stills with [`Animation::from_stills`].
+//!
+//! Frames are decoded whole, as the animation shows them, so each frame can be processed on its
+//! own. Operations sized by the pixels, such as `trim`, can size frames differently, which fails
+//! rather than producing an animation with frames out of place.
+
+use crate::{
+	apply_operations, bytes_orientation, decode, encode, metadata, metadata::LoopCount,
+	plan::ImageInfo, Error, ImageOutputFormat, Operation, OperationError, ProcessOptions,
+};
+use image::{
+	codecs::gif::{GifEncoder, Repeat},
+	error::{
+		ImageFormatHint, ParameterError, ParameterErrorKind, UnsupportedError, UnsupportedErrorKind,
+	},
+	Delay, DynamicImage, GenericImageView, ImageError,
+};
+use std::io::{Cursor, Write};
+
+/// A frame of an animation.
+#[derive(Clone, Debug)]
+pub struct Frame {
+	pub image: DynamicImage,
+	/// How long the frame is shown in milliseconds
+	pub delay: u32,
+}
+
+/// Frames of the same size which are shown one after another.
+#[derive(Clone, Debug)]
+pub struct Animation {
+	pub frames: Vec<Frame>,
+	pub loop_count: LoopCount,
+}
+
+/// Whether `format` can hold animations.
+pub fn supports(format: &ImageOutputFormat) -> bool {
+	match format {
+		ImageOutputFormat::Gif | ImageOutputFormat::Png { .. } => true,
+		#[cfg(not(target_arch = "wasm32"))]
+		ImageOutputFormat::WebP { .. } => true,
+		_ => false,
+	}
+}
+
+impl Animation {
+	/// Decodes the frames of an animated GIF, APNG or WebP, or returns `None` for stills and other
+	/// formats. The limits of `options` apply to every frame, and the memory limit to all frames
+	/// together.
+	pub fn decode(bytes: &[u8], options: &ProcessOptions) -> Result<Option<Self>, Error> {
+		let Some((decoded, loop_count)) = metadata::decode_frames(bytes)? else {
+			return Ok(None);
+		};
+
+		let mut frames = Vec::new();
+		let mut size = 0;
+		for frame in decoded {
+			let frame = frame?;
+			let delay = metadata::frame_delay(&frame);
+			let image = DynamicImage::ImageRgba8(frame.into_buffer());
+
+			options.limits.check(image.width(), image.height())?;
+			size += ImageInfo::of(&image).bytes();
+			options.check_memory(|| size)?;
+			frames.push(Frame { image, delay });
+		}
+
+		if frames.len() < 2 {
+			return Ok(None);
+		}
+
+		Ok(Some(Self { frames, loop_count }))
+	}
+
+	/// Decodes `stills` in order as the frames of an animation which plays forever, each shown for
+	/// its delay in `delays`. A single delay applies to every frame. Stills are oriented when
+	/// `options` ask for it, and the memory limit applies to all of them together.
+	pub fn from_stills<B: AsRef<[u8]>>(
+		stills: &[B],
+		delays: &[u32],
+		options: &ProcessOptions,
+	) -> Result<Self, Error> {
+		if stills.is_empty()
+			|| delays.is_empty()
+			|| delays.len() > 1 && delays.len() != stills.len()
+		{
+			return Err(ImageError::Parameter(ParameterError::from_kind(
+				ParameterErrorKind::Generic(format!(
+					"{} stills need a single delay or one for each, got {}",
+					stills.len(),
+					delays.len()
+				)),
+			))
+			.into());
+		}
+
+		let mut frames = Vec::new();
+		let mut size = 0;
+		for (index, still) in stills.iter().enumerate() {
+			let bytes = still.as_ref();
+			let mut image = decode(Cursor::new(bytes), options)?;
+			if let Some(orientation) = bytes_orientation(bytes, &[], options) {
+				image = orientation.apply(image);
+			}
+
+			size += ImageInfo::of(&image).bytes();
+			options.check_memory(|| size)?;
+			frames.push(Frame {
+				image,
+				delay: delays[index.min(delays.len() - 1)],
+			});
+		}
+
+		Ok(Self {
+			frames,
+			loop_count: LoopCount::Infinite,
+		})
+	}
+
+	/// Runs `operations` on every frame, keeping the delays and loop count.
+	pub fn process(
+		self,
+		operations: &[Operation],
+		options: &ProcessOptions,
+	) -> Result<Self, Error> {
+		let frames = self
+			.frames
+			.into_iter()
+			.map(|frame| {
+				let (image, _) = apply_operations(frame.image, operations, options, None)?;
+				Ok(Frame {
+					image,
+					delay: frame.delay,
+				})
+			})
+			.collect::<Result<Vec<_>, Error>>()?;
+
+		if let Some(first) = frames.first() {
+			let dimensions = first.image.dimensions();
+			if frames
+				.iter()
+				.any(|frame| frame.image.dimensions() != dimensions)
+			{
+				return Err(OperationError::new(
+					"Operations resized the frames of the animation differently".to_string(),
+				)
+				.into());
+			}
+		}
+
+		Ok(Self {
+			frames,
+			loop_count: self.loop_count,
+		})
+	}
+
+	/// Encodes the frames as an animation in `format`, which must be one [`supports`] accepts.
+	pub fn encode_to<W: Write>(
+		&self,
+		writer: &mut W,
+		format: ImageOutputFormat,
+	) -> Result<(), Error> {
+		match format {
+			ImageOutputFormat::Gif => {
+				let mut encoder = GifEncoder::new(writer);
+				// GIFs without a loop count play once, and count repetitions after the first play
+				match self.loop_count {
+					LoopCount::Infinite => encoder.set_repeat(Repeat::Infinite)?,
+					LoopCount::Finite(plays @ 2..) => encoder
+						.set_repeat(Repeat::Finite((plays - 1).try_into().unwrap_or(u16::MAX)))?,
+					LoopCount::Finite(_) => {}
+				}
+				encoder.encode_frames(self.frames.iter().map(|frame| {
+					image::Frame::from_parts(
+						frame.image.to_rgba8(),
+						0,
+						0,
+						Delay::from_numer_denom_ms(frame.delay, 1),
+					)
+				}))?;
+			}
+			ImageOutputFormat::Png { optimize, .. } => {
+				let plays = match self.loop_count {
+					LoopCount::Infinite => 0,
+					LoopCount::Finite(plays) => plays.max(1),
+				};
+				encode::apng(writer, &self.frames, plays, optimize)?;
+			}
+			#[cfg(not(target_arch = "wasm32"))]
+			ImageOutputFormat::WebP {
+				quality,
+				lossless,
+				alpha_quality,
+			} => {
+				let loops = match self.loop_count {
+					LoopCount::Infinite => 0,
+					LoopCount::Finite(plays) => plays.clamp(1, u16::MAX as u32) as u16,
+				};
+				encode::animated_webp(
+					writer,
+					&self.frames,
+					loops,
+					quality,
+					lossless,
+					alpha_quality,
+				)?;
+			}
+			_ => {
+				return Err(
+					ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+						ImageFormatHint::Unknown,
+						UnsupportedErrorKind::GenericFeature("animation".to_string()),
+					))
+					.into(),
+				)
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Animation, Frame};
+	use crate::{
+		metadata::{read_animation_info_from_bytes, LoopCount},
+		ImageOutputFormat, Operation, Pipeline, ProcessOptions,
+	};
+	use image::{DynamicImage, GenericImageView, RgbImage, Rgba, RgbaImage};
+	use std::io::Cursor;
+
+	fn encoded(alpha: u8, format: ImageOutputFormat) -> Vec<u8> {
+		let frames = [Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, alpha])]
+			.into_iter()
+			.zip([100, 250])
+			.map(|(color, delay)| Frame {
+				image: DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 30, color)),
+				delay,
+			})
+			.collect();
+
+		let mut encoded = Vec::new();
+		Animation {
+			frames,
+			loop_count: LoopCount::Finite(3),
+		}
+		.encode_to(&mut encoded, format)
+		.unwrap();
+		encoded
+	}
+
+	#[test]
+	fn transcodes_animations() {
+		let pipeline = |format: &str| -> Pipeline {
+			toml::from_str(&format!(
+				r#"
+				out_format = {format}
+
+				[[operations]]
+				scale = {{ factor = 0.5 }}
+				"#
+			))
+			.unwrap()
+		};
+		let gif = encoded(255, ImageOutputFormat::Gif);
+		let apng = encoded(128, ImageOutputFormat::png());
+
+		let outputs = [
+			(pipeline("{ web-p = { lossless = true } }"), gif, 255),
+			(pipeline(r#""png""#), apng, 128),
+		];
+		for (pipeline, input, alpha) in outputs {
+			let output = pipeline.process_bytes(&input).unwrap();
+			let info = read_animation_info_from_bytes(&output).unwrap().unwrap();
+			assert_eq!(vec![100, 250], info.delays);
+			assert_eq!(LoopCount::Finite(3), info.loop_count);
+
+			let animation = Animation::decode(&output, &Default::default())
+				.unwrap()
+				.unwrap();
+			let last = &animation.frames[1].image;
+			assert_eq!((20, 15), last.dimensions());
+			assert_eq!([0, 0, 255, alpha], last.get_pixel(10, 7).0);
+		}
+	}
+
+	#[test]
+	fn assembles_stills() {
+		let stills: Vec<Vec<u8>> = [(40, 30), (80, 60)]
+			.into_iter()
+			.map(|(width, height)| {
+				let mut encoded = Cursor::new(Vec::new());
+				DynamicImage::ImageRgb8(RgbImage::new(width, height))
+					.write_to(&mut encoded, image::ImageOutputFormat::Png)
+					.unwrap();
+				encoded.into_inner()
+			})
+			.collect();
+		let operations: Vec<Operation> = toml::from_str::<Pipeline>(
+			r#"
+			out_format = "gif"
+
+			[[operations]]
+			resize = { width = { pixel = { pixels = 20 } }, height = { pixel = { pixels = 15 } }, filter = "nearest", crop_mode = "exact" }
+			"#,
+		)
+		.unwrap()
+		.operations;
+
+		let options = ProcessOptions::default();
+		let animation = Animation::from_stills(&stills, &[70], &options).unwrap();
+		assert!(animation.clone().process(&[], &options).is_err());
+
+		let mut gif = Vec::new();
+		animation
+			.process(&operations, &options)
+			.unwrap()
+			.encode_to(&mut gif, ImageOutputFormat::Gif)
+			.unwrap();
+		let info = read_animation_info_from_bytes(&gif).unwrap().unwrap();
+		assert_eq!(vec![70, 70], info.delays);
+		assert_eq!(LoopCount::Infinite, info.loop_count);
+
+		assert!(Animation::from_stills(&stills, &[70, 80, 90], &options).is_err());
+	}
+}
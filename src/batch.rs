@@ -0,0 +1,362 @@
+//! Processing every image in a directory tree.
+
+use crate::{
+	animation, cached, decode_and_encode, encode_to,
+	metadata::OutputMetadata,
+	process_path,
+	progress::{timed, Progress},
+	Error, ImageOutputFormat, Operation, ProcessOptions,
+};
+use image::ImageFormat;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{
+	collections::HashMap,
+	fs::{self, File},
+	io::BufWriter,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Options for [`process_dir`].
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+	pub out_format: ImageOutputFormat,
+	/// Extensions of the files to process, compared case-insensitively. When empty, every file
+	/// with the extension of a format the crate can decode is processed.
+	pub extensions: Vec<String>,
+	pub process: ProcessOptions,
+	/// Number of files processed at once with the `parallel` feature, defaulting to one per CPU
+	pub threads: Option<usize>,
+}
+
+impl BatchOptions {
+	pub fn new(out_format: ImageOutputFormat) -> Self {
+		Self {
+			out_format,
+			extensions: Vec::new(),
+			process: ProcessOptions::default(),
+			threads: None,
+		}
+	}
+
+	fn includes(&self, path: &Path) -> bool {
+		let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+			return false;
+		};
+
+		if self.extensions.is_empty() {
+			ImageFormat::from_extension(extension).is_some()
+				|| cfg!(feature = "heic")
+					&& ["heic", "heif"]
+						.iter()
+						.any(|heif| heif.eq_ignore_ascii_case(extension))
+				|| cfg!(feature = "jxl") && extension.eq_ignore_ascii_case("jxl")
+		} else {
+			self.extensions
+				.iter()
+				.any(|included| included.eq_ignore_ascii_case(extension))
+		}
+	}
+}
+
+/// The outcome of processing a single file.
+#[derive(Debug)]
+pub struct FileReport {
+	pub input: PathBuf,
+	pub output: PathBuf,
+	pub result: Result<(), Error>,
+}
+
+/// Results of [`process_dir`], in path order.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+	pub files: Vec<FileReport>,
+	/// Files which were left out because of their extension
+	pub skipped: Vec<PathBuf>,
+}
+
+impl BatchReport {
+	/// Files which failed to process.
+	pub fn failed(&self) -> impl Iterator<Item = &FileReport> {
+		self.files.iter().filter(|file| file.result.is_err())
+	}
+
+	pub fn summary(&self) -> BatchSummary {
+		let failed = self.failed().count();
+		BatchSummary {
+			processed: self.files.len() - failed,
+			skipped: self.skipped.len(),
+			failed,
+		}
+	}
+}
+
+/// Number of files in each outcome of a [`BatchReport`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct BatchSummary {
+	pub processed: usize,
+	pub skipped: usize,
+	pub failed: usize,
+}
+
+/// Processes every image below `in_dir` with `operations`, writing the results to the same
+/// relative paths below `out_dir` with the extension of the output format. A file which fails
+/// is recorded in the report and the rest are still processed. Files which would be written to
+/// the same output, such as `a.png` and `a.jpg`, all fail with [`Error::OutputCollision`].
+///
+/// With the `parallel` feature, files are processed concurrently on [`BatchOptions::threads`]
+/// threads.
+pub fn process_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+	in_dir: P,
+	out_dir: Q,
+	operations: &[Operation],
+	options: &BatchOptions,
+) -> Result<BatchReport, Error> {
+	let (in_dir, out_dir) = (in_dir.as_ref(), out_dir.as_ref());
+	let mut report = BatchReport::default();
+
+	let mut paths = Vec::new();
+	collect_files(in_dir, out_dir.canonicalize().ok().as_deref(), &mut paths)?;
+	paths.sort();
+
+	let (inputs, skipped): (Vec<_>, Vec<_>) =
+		paths.into_iter().partition(|path| options.includes(path));
+	report.skipped = skipped;
+
+	let output_of = |input: &Path| {
+		let relative = input.strip_prefix(in_dir).unwrap_or(input);
+		out_dir
+			.join(relative)
+			.with_extension(options.out_format.extension())
+	};
+	let mut outputs = HashMap::new();
+	for input in inputs.iter() {
+		*outputs.entry(output_of(input)).or_insert(0) += 1;
+	}
+
+	let count = inputs.len();
+	let completed = AtomicUsize::new(0);
+	let progress = |progress: Progress<'_>| {
+		if let Some(handler) = &options.process.progress {
+			handler.on_progress(progress);
+		}
+	};
+
+	let process = |input: PathBuf| {
+		let output = output_of(&input);
+
+		progress(Progress::FileStarted {
+			path: &input,
+			count,
+		});
+		let (result, elapsed) = timed(|| {
+			if outputs[&output] > 1 {
+				return Err(Error::OutputCollision {
+					output: output.clone(),
+				});
+			}
+			process_one(&input, &output, operations, options)
+		});
+		progress(Progress::FileFinished {
+			path: &input,
+			completed: completed.fetch_add(1, Ordering::Relaxed) + 1,
+			count,
+			elapsed,
+			succeeded: result.is_ok(),
+		});
+
+		FileReport {
+			input,
+			output,
+			result,
+		}
+	};
+
+	#[cfg(feature = "parallel")]
+	{
+		let pool = rayon::ThreadPoolBuilder::new()
+			.num_threads(options.threads.unwrap_or(0))
+			.build()
+			.map_err(std::io::Error::other)?;
+		report.files = pool.install(|| inputs.into_par_iter().map(process).collect());
+	}
+	#[cfg(not(feature = "parallel"))]
+	{
+		report.files = inputs.into_iter().map(process).collect();
+	}
+
+	Ok(report)
+}
+
+/// Files below `dir`, leaving out the canonical `out_dir` in case it is nested inside.
+fn collect_files(
+	dir: &Path,
+	out_dir: Option<&Path>,
+	paths: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.is_dir() {
+			let is_out_dir = out_dir.is_some_and(|out_dir| {
+				path.canonicalize()
+					.is_ok_and(|canonical| canonical == out_dir)
+			});
+			if !is_out_dir {
+				collect_files(&path, out_dir, paths)?;
+			}
+		} else if path.is_file() {
+			paths.push(path);
+		}
+	}
+
+	Ok(())
+}
+
+fn process_one(
+	input: &Path,
+	output: &Path,
+	operations: &[Operation],
+	options: &BatchOptions,
+) -> Result<(), Error> {
+	if let Some(parent) = output.parent() {
+		fs::create_dir_all(parent)?;
+	}
+
+	// Animations and metadata are only kept when processing the bytes
+	if options.process.cache.is_some()
+		|| !OutputMetadata::new(operations, &options.process).is_empty()
+		|| animation::supports(&options.out_format)
+	{
+		let bytes = fs::read(input)?;
+		#[cfg(not(feature = "pdf"))]
+		let key = (
+			operations,
+			&options.out_format,
+			options.process.auto_orient,
+			options.process.keep_metadata,
+		);
+		#[cfg(feature = "pdf")]
+		let key = (
+			operations,
+			&options.out_format,
+			options.process.auto_orient,
+			options.process.keep_metadata,
+			options.process.pdf,
+		);
+		let encoded = cached(&bytes, &key, &options.process, || {
+			decode_and_encode(
+				&bytes,
+				operations,
+				options.out_format.clone(),
+				&options.process,
+			)
+		})?;
+		return Ok(fs::write(output, encoded)?);
+	}
+
+	let (image, _) = process_path(input, operations, &options.process)?;
+	let mut out = BufWriter::new(File::create(output)?);
+	encode_to(&mut out, &image, options.out_format.clone())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{process_dir, BatchOptions, BatchSummary};
+	use crate::{operations::Invert, Error, ImageOutputFormat, Operation};
+	use image::{DynamicImage, RgbImage};
+	use std::fs;
+
+	#[test]
+	fn mirrors_directories() {
+		let dir = std::env::temp_dir().join(format!("imageless-batch-{}", std::process::id()));
+		let (in_dir, out_dir) = (dir.join("in"), dir.join("out"));
+		fs::create_dir_all(in_dir.join("nested")).unwrap();
+		let image = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+		image.save(in_dir.join("a.png")).unwrap();
+		image.save(in_dir.join("nested/b.bmp")).unwrap();
+		fs::write(in_dir.join("notes.txt"), "").unwrap();
+		fs::write(in_dir.join("broken.png"), "").unwrap();
+
+		let report = process_dir(
+			&in_dir,
+			&out_dir,
+			&[Operation::Invert(Invert {})],
+			&BatchOptions::new(ImageOutputFormat::png()),
+		)
+		.unwrap();
+		fs::remove_dir_all(&dir).ok();
+
+		assert_eq!(
+			BatchSummary {
+				processed: 2,
+				skipped: 1,
+				failed: 1,
+			},
+			report.summary()
+		);
+		assert_eq!(vec![in_dir.join("notes.txt")], report.skipped);
+		let failed: Vec<_> = report.failed().map(|file| &file.input).collect();
+		assert_eq!(vec![&in_dir.join("broken.png")], failed);
+		assert!(report
+			.files
+			.iter()
+			.any(|file| file.output == out_dir.join("nested/b.png")));
+	}
+
+	#[test]
+	fn fails_files_with_the_same_output() {
+		let dir = std::env::temp_dir().join(format!("imageless-collide-{}", std::process::id()));
+		let (in_dir, out_dir) = (dir.join("in"), dir.join("out"));
+		fs::create_dir_all(&in_dir).unwrap();
+		let image = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+		image.save(in_dir.join("a.png")).unwrap();
+		image.save(in_dir.join("a.bmp")).unwrap();
+		image.save(in_dir.join("b.bmp")).unwrap();
+
+		let report = process_dir(
+			&in_dir,
+			&out_dir,
+			&[Operation::Invert(Invert {})],
+			&BatchOptions::new(ImageOutputFormat::png()),
+		)
+		.unwrap();
+		let written = out_dir.join("a.png").exists();
+		fs::remove_dir_all(&dir).ok();
+
+		let failed: Vec<_> = report.failed().map(|file| &file.input).collect();
+		assert_eq!(vec![&in_dir.join("a.bmp"), &in_dir.join("a.png")], failed);
+		assert!(report
+			.failed()
+			.all(|file| matches!(file.result, Err(Error::OutputCollision { .. }))));
+		assert!(!written);
+		assert_eq!(1, report.summary().processed);
+	}
+
+	#[test]
+	fn skips_out_dir_given_another_way() {
+		let dir = std::env::temp_dir().join(format!("imageless-nested-{}", std::process::id()));
+		fs::create_dir_all(dir.join("nested")).unwrap();
+		DynamicImage::ImageRgb8(RgbImage::new(4, 4))
+			.save(dir.join("a.png"))
+			.unwrap();
+
+		let process = || {
+			process_dir(
+				&dir,
+				dir.join("nested/../out"),
+				&[Operation::Invert(Invert {})],
+				&BatchOptions::new(ImageOutputFormat::png()),
+			)
+			.unwrap()
+		};
+		process();
+		let report = process();
+		fs::remove_dir_all(&dir).ok();
+
+		let inputs: Vec<_> = report.files.iter().map(|file| &file.input).collect();
+		assert_eq!(vec![&dir.join("a.png")], inputs);
+	}
+}
@@ -1,49 +1,975 @@
-use clap::Parser;
-use imageless::{process_file, Error, ImageOutputFormat, Operation};
+use anyhow::bail;
+use clap::{Args, Parser, Subcommand};
+use image::{io::Reader as ImageReader, DynamicImage};
+use imageless::{
+	analysis::{find_duplicates, suggest_quality, AdaptiveQuality, DEFAULT_TARGET_SSIM},
+	animation::{self, Animation},
+	batch::{process_dir, BatchOptions},
+	cache::{self, Cache, DirCache},
+	encode_to, magick,
+	metadata::{
+		read_animation_info, read_color_profile, read_metadata, AnimationInfo, ColorProfile,
+		ImageMetadata, LoopCount, OutputMetadata,
+	},
+	optimize, params,
+	plan::{self, ImageInfo},
+	presets, process_file_with_options,
+	progress::{Progress, ProgressHandler},
+	tiled,
+	variants::{Manifest, Variant, Variants},
+	Branch, DecodeLimits, Error, ImageOutputFormat, Operation, ProcessOptions, ProcessingReport,
+};
 use serde::{Deserialize, Serialize};
-use std::{fs, fs::File, io::BufWriter, path::PathBuf};
+use std::{
+	collections::BTreeMap,
+	ffi::OsString,
+	fs,
+	fs::File,
+	io::{BufReader, BufWriter, Cursor},
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-	/// File to process
-	#[arg(short, long)]
-	file: PathBuf,
-	/// Output file
-	#[arg(short, long)]
-	out: PathBuf,
-	/// Path to an Imageless config file
-	#[arg(short, long)]
-	config: PathBuf,
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+	/// Process an image with the operations in a config file
+	///
+	/// This is also what runs when the arguments start with an option instead of a subcommand, as
+	/// in `imageless -f … -o … -c …`.
+	Process {
+		/// File to process
+		#[arg(short, long)]
+		file: PathBuf,
+		/// Output file
+		#[arg(short, long, required_unless_present = "dry_run")]
+		out: Option<PathBuf>,
+		/// Path to an Imageless config file
+		#[arg(short, long, required_unless_present = "preset")]
+		config: Option<PathBuf>,
+		/// Built-in pipeline to run instead of a config file, such as `avatar` or `og-image@1`
+		#[arg(long, conflicts_with_all = ["config", "pipeline"])]
+		preset: Option<String>,
+		/// Check the config and print the dimensions after each operation without processing
+		/// the image
+		#[arg(long)]
+		dry_run: bool,
+		/// Merge and drop redundant operations for the size of the file before processing
+		#[arg(long)]
+		optimize_pipeline: bool,
+		/// Make PNG output smaller with oxipng, trying harder at higher efforts from 0 to 6
+		#[arg(long, value_name = "EFFORT", conflicts_with = "tiled")]
+		optimize: Option<u8>,
+		/// Write snapshots from `stats` operations to a JSON file
+		#[arg(long)]
+		stats: Option<PathBuf>,
+		/// Print the lowest output quality which keeps the image visually lossless
+		#[arg(long)]
+		suggest_quality: bool,
+		/// Write a JSON manifest of the `variants` in the config, for building `srcset`s
+		#[arg(long)]
+		manifest: Option<PathBuf>,
+		/// Named pipeline from the config to run instead of its top level operations
+		#[arg(short, long)]
+		pipeline: Option<String>,
+		/// Value for a `${name}` placeholder in the config, can be repeated
+		#[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_param)]
+		params: Vec<(String, String)>,
+		/// Print progress to stderr
+		#[arg(long)]
+		progress: bool,
+		#[command(flatten)]
+		limits: Limits,
+		/// Fail instead of using more than this much memory for pixels, such as `512M` or `2G`
+		#[arg(long, value_parser = parse_size)]
+		max_memory: Option<u64>,
+		/// Directory to reuse outputs from when the file and config are unchanged
+		#[arg(long, conflicts_with_all = ["stats", "suggest_quality", "manifest"])]
+		cache_dir: Option<PathBuf>,
+		/// Process the file in strips of rows instead of decoding it in full, for very large PNG
+		/// and TIFF files. Only supports PNG output and operations which crop, resize or filter
+		#[arg(long, conflicts_with_all = ["stats", "suggest_quality", "manifest", "cache_dir"])]
+		tiled: bool,
+		/// Directory to load plugin operations from, can be repeated
+		#[cfg(feature = "plugins")]
+		#[arg(long)]
+		plugin_dir: Vec<PathBuf>,
+		#[cfg(feature = "pdf")]
+		#[command(flatten)]
+		pdf: Pdf,
+		/// Resize, blur, convolve and adjust colors on the GPU when there is one
+		#[cfg(all(feature = "gpu", not(target_arch = "wasm32")))]
+		#[arg(long)]
+		gpu: bool,
+	},
+	/// Process every image in a directory with the operations in a config file
+	Batch {
+		/// Directory of images to process, including subdirectories
+		#[arg(short, long)]
+		input_dir: PathBuf,
+		/// Directory to write results to, mirroring the input directory
+		#[arg(short, long)]
+		out_dir: PathBuf,
+		/// Path to an Imageless config file
+		#[arg(short, long, required_unless_present = "preset")]
+		config: Option<PathBuf>,
+		/// Built-in pipeline to run instead of a config file, such as `avatar` or `og-image@1`
+		#[arg(long, conflicts_with_all = ["config", "pipeline"])]
+		preset: Option<String>,
+		/// Extension of the files to process, can be repeated. Defaults to all image formats
+		#[arg(short, long)]
+		extension: Vec<String>,
+		/// Named pipeline from the config to run instead of its top level operations
+		#[arg(short, long)]
+		pipeline: Option<String>,
+		/// Value for a `${name}` placeholder in the config, can be repeated
+		#[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_param)]
+		params: Vec<(String, String)>,
+		/// Print progress to stderr
+		#[arg(long)]
+		progress: bool,
+		#[command(flatten)]
+		limits: Limits,
+		/// Fail instead of using more than this much memory for pixels, such as `512M` or `2G`
+		#[arg(long, value_parser = parse_size)]
+		max_memory: Option<u64>,
+		/// Directory to reuse outputs from when a file and the config are unchanged
+		#[arg(long)]
+		cache_dir: Option<PathBuf>,
+		/// Make PNG output smaller with oxipng, trying harder at higher efforts from 0 to 6
+		#[arg(long, value_name = "EFFORT")]
+		optimize: Option<u8>,
+		/// Number of files to process at once, defaulting to one per CPU
+		#[cfg(feature = "parallel")]
+		#[arg(short, long)]
+		threads: Option<usize>,
+		/// Resize, blur, convolve and adjust colors on the GPU when there is one
+		#[cfg(all(feature = "gpu", not(target_arch = "wasm32")))]
+		#[arg(long)]
+		gpu: bool,
+	},
+	/// Assemble an animation from stills, running the operations in a config file on each frame
+	Animate {
+		/// Files to use as frames, in order
+		#[arg(required = true)]
+		files: Vec<PathBuf>,
+		/// Output file, which needs a GIF, PNG or WebP output format
+		#[arg(short, long)]
+		out: PathBuf,
+		/// Path to an Imageless config file
+		#[arg(short, long, required_unless_present = "preset")]
+		config: Option<PathBuf>,
+		/// Built-in pipeline to run instead of a config file, such as `avatar` or `og-image@1`
+		#[arg(long, conflicts_with_all = ["config", "pipeline"])]
+		preset: Option<String>,
+		/// Named pipeline from the config to run instead of its top level operations
+		#[arg(short, long)]
+		pipeline: Option<String>,
+		/// Value for a `${name}` placeholder in the config, can be repeated
+		#[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_param)]
+		params: Vec<(String, String)>,
+		/// Milliseconds to show each frame for, either once for every frame or a comma separated
+		/// list with one for each file
+		#[arg(short, long, value_delimiter = ',', default_value = "100")]
+		delay: Vec<u32>,
+		/// Number of times to play the animation, looping forever when unset
+		#[arg(long)]
+		plays: Option<u32>,
+		#[command(flatten)]
+		limits: Limits,
+		/// Fail instead of using more than this much memory for pixels, such as `512M` or `2G`
+		#[arg(long, value_parser = parse_size)]
+		max_memory: Option<u64>,
+	},
+	/// Report clusters of near-duplicate images
+	Duplicates {
+		/// Files to compare
+		#[arg(required = true)]
+		files: Vec<PathBuf>,
+		/// Maximum Hamming distance between perceptual hashes of duplicates
+		#[arg(short, long, default_value_t = 5)]
+		threshold: u32,
+		#[command(flatten)]
+		limits: Limits,
+	},
+	/// Print details about an image as JSON
+	Info {
+		/// File to inspect
+		file: PathBuf,
+	},
+	/// Print the ImageMagick arguments for a config, or config operations for ImageMagick arguments
+	Magick {
+		/// Config file to translate to ImageMagick arguments
+		#[arg(short, long, required_unless_present = "args", conflicts_with = "args")]
+		config: Option<PathBuf>,
+		/// ImageMagick arguments to translate to config operations
+		#[arg(allow_hyphen_values = true, trailing_var_arg = true)]
+		args: Vec<String>,
+	},
+}
+
+/// Limits on sources, so that decompression bombs fail instead of taking all memory.
+#[derive(Debug, Args)]
+struct Limits {
+	/// Largest source width to decode
+	#[arg(long, default_value_t = 65535)]
+	max_width: u32,
+	/// Largest source height to decode
+	#[arg(long, default_value_t = 65535)]
+	max_height: u32,
+	/// Largest number of source pixels to decode
+	#[arg(long, default_value_t = 1 << 28)]
+	max_pixels: u64,
+	/// Most memory a decoder may allocate at once, such as `512M` or `2G`
+	#[arg(long, default_value = "1G", value_parser = parse_size)]
+	max_alloc: u64,
+}
+
+/// Which page of a PDF source to render, and at what resolution.
+#[cfg(feature = "pdf")]
+#[derive(Debug, Args)]
+struct Pdf {
+	/// Page of a PDF source to render, from 0
+	#[arg(long, default_value_t = 0, conflicts_with_all = ["cache_dir", "tiled"])]
+	page: u16,
+	/// Resolution to render a PDF source at, in pixels per inch
+	#[arg(long, default_value_t = 72.0, conflicts_with_all = ["cache_dir", "tiled"])]
+	dpi: f32,
+}
+
+#[cfg(feature = "pdf")]
+impl From<Pdf> for imageless::pdf::PdfOptions {
+	fn from(pdf: Pdf) -> Self {
+		Self {
+			page: pdf.page,
+			dpi: pdf.dpi,
+		}
+	}
+}
+
+impl From<Limits> for DecodeLimits {
+	fn from(limits: Limits) -> Self {
+		Self {
+			max_width: Some(limits.max_width),
+			max_height: Some(limits.max_height),
+			max_pixels: Some(limits.max_pixels),
+			max_alloc: Some(limits.max_alloc),
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+struct Info {
+	format: Option<String>,
+	width: u32,
+	height: u32,
+	metadata: ImageMetadata,
+	color_profile: Option<ColorProfile>,
+	animation: Option<AnimationInfo>,
+}
+
+#[derive(Debug, Serialize)]
+struct Operations {
+	operations: Vec<Operation>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Config {
 	out_format: ImageOutputFormat,
+	/// Overrides the output quality based on the complexity of the processed image
+	adaptive_quality: Option<AdaptiveQuality>,
+	/// Applies the EXIF orientation of the source before any operations
+	#[serde(default)]
+	auto_orient: bool,
+	/// Copies the EXIF, XMP and IPTC metadata of the source into the outputs
+	#[serde(default)]
+	keep_metadata: bool,
+	#[serde(default)]
 	operations: Vec<Operation>,
+	/// Pipelines which can be selected by name instead of `operations`
+	#[serde(default)]
+	pipelines: BTreeMap<String, NamedPipeline>,
+	/// Further outputs which continue from the result of `operations`
+	#[serde(default)]
+	branches: Vec<Branch>,
+	/// Resized copies of the result, written next to the output
+	variants: Option<Variants>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NamedPipeline {
+	/// Pipelines whose operations run before these, in order
+	#[serde(default)]
+	include: Vec<String>,
+	#[serde(default)]
+	operations: Vec<Operation>,
+	/// Overrides the output format of the config
+	out_format: Option<ImageOutputFormat>,
+}
+
+impl Config {
+	/// Reads the config file, or the preset when there is no file.
+	fn load(
+		path: Option<PathBuf>,
+		preset: Option<String>,
+		pipeline: Option<String>,
+		params: Vec<(String, String)>,
+	) -> anyhow::Result<Self> {
+		match (path, preset) {
+			(Some(path), _) => Self::read(&path.canonicalize()?, pipeline.as_deref(), params),
+			(None, Some(name)) => Self::preset(&name),
+			(None, None) => bail!("Either a config file or a preset is required"),
+		}
+	}
+
+	fn preset(name: &str) -> anyhow::Result<Self> {
+		let Some(preset) = presets::get(name) else {
+			let names = presets::list()
+				.into_iter()
+				.map(|(name, _)| name)
+				.collect::<Vec<_>>();
+			bail!(
+				"Unknown preset `{name}`, expected one of {}",
+				names.join(", ")
+			);
+		};
+
+		Ok(Self {
+			out_format: preset.out_format,
+			adaptive_quality: None,
+			auto_orient: preset.auto_orient,
+			keep_metadata: false,
+			operations: preset.operations,
+			pipelines: BTreeMap::new(),
+			branches: Vec::new(),
+			variants: None,
+		})
+	}
+
+	fn read(
+		path: &Path,
+		pipeline: Option<&str>,
+		params: Vec<(String, String)>,
+	) -> anyhow::Result<Self> {
+		let params = params.into_iter().collect();
+		let mut config: Self = params::from_toml(&fs::read_to_string(path)?, &params)?;
+		if let Some(name) = pipeline {
+			let mut operations = Vec::new();
+			let out_format = config.include_pipeline(name, &mut Vec::new(), &mut operations)?;
+			config.operations = operations;
+			if let Some(out_format) = out_format {
+				config.out_format = out_format;
+			}
+		}
+
+		Ok(config)
+	}
+
+	/// Appends the operations of the named pipeline and its includes to `operations`, returning
+	/// its output format. `including` holds the pipelines being resolved, which catches cycles.
+	fn include_pipeline<'a>(
+		&'a self,
+		name: &'a str,
+		including: &mut Vec<&'a str>,
+		operations: &mut Vec<Operation>,
+	) -> anyhow::Result<Option<ImageOutputFormat>> {
+		let Some(pipeline) = self.pipelines.get(name) else {
+			bail!("Pipeline `{name}` is not defined");
+		};
+		if let Some(start) = including.iter().position(|included| *included == name) {
+			if start == including.len() - 1 {
+				bail!("Pipeline `{name}` includes itself");
+			}
+			let chain = including[start..]
+				.iter()
+				.chain([&name])
+				.map(|name| format!("`{name}`"))
+				.collect::<Vec<_>>();
+			bail!("Pipelines {} include each other", chain.join(" → "));
+		}
+
+		including.push(name);
+		for include in pipeline.include.iter() {
+			self.include_pipeline(include, including, operations)?;
+		}
+		including.pop();
+		operations.extend(pipeline.operations.iter().cloned());
+
+		Ok(pipeline.out_format.clone())
+	}
+}
+
+fn print_progress(progress: Progress<'_>) {
+	match progress {
+		Progress::OperationFinished {
+			position,
+			count,
+			operation,
+			elapsed,
+		} => eprintln!(
+			"[{}/{count}] {} {elapsed:.2?}",
+			position + 1,
+			operation.name()
+		),
+		Progress::FileFinished {
+			path,
+			completed,
+			count,
+			elapsed,
+			succeeded,
+		} => {
+			let outcome = if succeeded { "done" } else { "failed" };
+			eprintln!(
+				"[{completed}/{count}] {} {outcome} {elapsed:.2?}",
+				path.display()
+			);
+		}
+		Progress::OperationStarted { .. } | Progress::FileStarted { .. } => {}
+	}
+}
+
+/// Size, color type and orientation of the image at `path`, upright when the config orients it
+/// before any operations.
+fn source_info(path: &Path, config: &Config) -> anyhow::Result<ImageInfo> {
+	let info = plan::read_info(BufReader::new(File::open(path)?))?;
+	let orients = config.auto_orient
+		|| config
+			.operations
+			.iter()
+			.any(|operation| matches!(operation, Operation::AutoOrient(_)));
+	if !orients {
+		return Ok(info);
+	}
+
+	let info = info.with_orientation(read_metadata(path)?.orientation);
+	Ok(if config.auto_orient {
+		info.upright()
+	} else {
+		info
+	})
+}
+
+/// Prints the dimensions after each operation of the config and its branches for `path`.
+fn print_plan(path: &Path, config: &Config) -> anyhow::Result<()> {
+	let info = source_info(path, config)?;
+	println!("{}x{} {:?}", info.width, info.height, info.color_type);
+
+	let print_steps = |prefix: &str, steps: &[plan::Step]| {
+		for (position, step) in steps.iter().enumerate() {
+			let dimensions = match step.dimensions {
+				Some((width, height)) => format!("{width}x{height}"),
+				None => "depends on the image".to_string(),
+			};
+			println!("{prefix}[{}] {} {dimensions}", position + 1, step.operation);
+		}
+	};
+	let steps = plan::plan(&config.operations, info)?;
+	print_steps("", &steps);
+
+	let last = steps
+		.last()
+		.map_or(Some(info.dimensions()), |step| step.dimensions);
+	for branch in config.branches.iter() {
+		println!("{}:", branch.name);
+		match last {
+			Some((width, height)) => {
+				let info = ImageInfo::new(width, height, info.color_type);
+				print_steps("  ", &plan::plan(&branch.operations, info)?);
+			}
+			None => println!("  depends on the image"),
+		}
+	}
+
+	Ok(())
+}
+
+fn parse_param(param: &str) -> Result<(String, String), String> {
+	param
+		.split_once('=')
+		.map(|(name, value)| (name.to_string(), value.to_string()))
+		.ok_or_else(|| format!("expected NAME=VALUE, got `{param}`"))
+}
+
+/// Parses a number of bytes, optionally followed by `K`, `M` or `G` for binary multiples.
+fn parse_size(size: &str) -> Result<u64, String> {
+	let upper = size.trim().to_ascii_uppercase();
+	let number = upper.trim_end_matches(['K', 'M', 'G']);
+	let shift = match &upper[number.len()..] {
+		"" => 0,
+		"K" => 10,
+		"M" => 20,
+		"G" => 30,
+		_ => return Err(format!("expected a size such as 512M, got `{size}`")),
+	};
+	number
+		.parse::<u64>()
+		.ok()
+		.and_then(|number| number.checked_mul(1 << shift))
+		.ok_or_else(|| format!("expected a size such as 512M, got `{size}`"))
+}
+
+/// Arguments with `process` inserted when they start with an option other than help or version,
+/// so that invocations from before there were subcommands keep working.
+fn with_default_command(mut args: Vec<OsString>) -> Vec<OsString> {
+	let starts_with_option = args.get(1).is_some_and(|arg| {
+		let arg = arg.to_string_lossy();
+		arg.starts_with('-') && !["-h", "--help", "-V", "--version"].contains(&arg.as_ref())
+	});
+	if starts_with_option {
+		args.insert(1, OsString::from("process"));
+	}
+
+	args
 }
 
 fn main() -> anyhow::Result<()> {
-	let cli = Cli::parse();
-	let config_file = cli.config.canonicalize()?;
-	let config: Config = toml::from_str(&fs::read_to_string(config_file)?)?;
+	let cli = Cli::parse_from(with_default_command(std::env::args_os().collect()));
+
+	match cli.command {
+		Command::Process {
+			file,
+			out,
+			config,
+			preset,
+			dry_run,
+			optimize_pipeline,
+			optimize,
+			stats,
+			suggest_quality,
+			manifest,
+			pipeline,
+			params,
+			progress,
+			limits,
+			max_memory,
+			cache_dir,
+			tiled,
+			#[cfg(feature = "plugins")]
+			plugin_dir,
+			#[cfg(feature = "pdf")]
+			pdf,
+			#[cfg(all(feature = "gpu", not(target_arch = "wasm32")))]
+			gpu,
+		} => {
+			#[cfg(feature = "plugins")]
+			for dir in plugin_dir {
+				// SAFETY: plugins are trusted code the user asked to load
+				unsafe { imageless::plugins::load_plugin_dir(dir)? };
+			}
+
+			let mut config = Config::load(config, preset, pipeline, params)?;
+			if let Some(effort) = optimize {
+				set_png_effort(&mut config.out_format, effort)?;
+			}
+			if optimize_pipeline {
+				let info = source_info(&file, &config)?;
+				config.operations = optimize::optimize(config.operations, info);
+			}
+			let Some(out) = out.filter(|_| !dry_run) else {
+				return print_plan(&file, &config);
+			};
+
+			let options = ProcessOptions {
+				limits: limits.into(),
+				max_memory,
+				#[cfg(feature = "pdf")]
+				pdf: pdf.into(),
+				#[cfg(all(feature = "gpu", not(target_arch = "wasm32")))]
+				gpu,
+				..Default::default()
+			};
+
+			if tiled {
+				if !matches!(config.out_format, ImageOutputFormat::Png { .. }) {
+					bail!("Tiled processing only writes PNG");
+				}
+				if !config.branches.is_empty()
+					|| config.variants.is_some()
+					|| config.auto_orient
+					|| config.keep_metadata
+					|| !OutputMetadata::new(&config.operations, &Default::default()).is_empty()
+				{
+					bail!(
+						"Configs with branches, variants, auto_orient or metadata to write can't \
+						 be processed tiled"
+					);
+				}
+				tiled::process_strips(
+					BufReader::new(File::open(file)?),
+					BufWriter::new(File::create(out)?),
+					&config.operations,
+					tiled::DEFAULT_STRIP_HEIGHT,
+					&options,
+				)?;
+				return Ok(());
+			}
+
+			if let Some(cache_dir) = cache_dir {
+				if !config.branches.is_empty() || config.variants.is_some() {
+					bail!("Configs with branches or variants can't be cached");
+				}
+
+				let cache = DirCache::new(cache_dir);
+				let key = cache::key(&fs::read(&file)?, &config)?;
+				match cache.get(&key) {
+					Some(output) => fs::write(out, output)?,
+					None => {
+						process_and_save(file, out.clone(), config, false, progress, options)?;
+						cache.put(&key, &fs::read(out)?);
+					}
+				}
+				return Ok(());
+			}
+
+			let (report, variants) =
+				process_and_save(file, out, config, suggest_quality, progress, options)?;
+
+			if let Some(stats) = stats {
+				let stats_file = BufWriter::new(File::create(stats)?);
+				serde_json::to_writer_pretty(stats_file, &report)?;
+			}
+
+			if let Some(manifest) = manifest {
+				let manifest_file = BufWriter::new(File::create(manifest)?);
+				serde_json::to_writer_pretty(manifest_file, &Manifest::new(&variants))?;
+			}
+		}
+		Command::Batch {
+			input_dir,
+			out_dir,
+			config,
+			preset,
+			extension,
+			pipeline,
+			params,
+			progress,
+			limits,
+			max_memory,
+			cache_dir,
+			optimize,
+			#[cfg(feature = "parallel")]
+			threads,
+			#[cfg(all(feature = "gpu", not(target_arch = "wasm32")))]
+			gpu,
+		} => {
+			let mut config = Config::load(config, preset, pipeline, params)?;
+			if !config.branches.is_empty()
+				|| config.variants.is_some()
+				|| config.adaptive_quality.is_some()
+			{
+				bail!(
+					"Configs with branches, variants or adaptive_quality can't be batch processed"
+				);
+			}
+			if let Some(effort) = optimize {
+				set_png_effort(&mut config.out_format, effort)?;
+			}
+			let mut options = BatchOptions::new(config.out_format);
+			options.extensions = extension;
+			options.process.auto_orient = config.auto_orient;
+			options.process.keep_metadata = config.keep_metadata;
+			options.process.limits = limits.into();
+			options.process.max_memory = max_memory;
+			#[cfg(all(feature = "gpu", not(target_arch = "wasm32")))]
+			{
+				options.process.gpu = gpu;
+			}
+			if let Some(cache_dir) = cache_dir {
+				options.process.cache = Some(Arc::new(DirCache::new(cache_dir)));
+			}
+			if progress {
+				options.process.progress = Some(Arc::new(|progress: Progress<'_>| {
+					if let Progress::FileFinished { .. } = progress {
+						print_progress(progress);
+					}
+				}));
+			}
+			#[cfg(feature = "parallel")]
+			{
+				options.threads = threads;
+			}
 
-	process_and_save(cli.file, cli.out, config.out_format, config.operations)?;
+			let report = process_dir(input_dir, out_dir, &config.operations, &options)?;
+			for file in report.failed() {
+				if let Err(error) = &file.result {
+					eprintln!("{}: {error}", file.input.display());
+				}
+			}
+			println!("{}", serde_json::to_string_pretty(&report.summary())?);
+		}
+		Command::Animate {
+			files,
+			out,
+			config,
+			preset,
+			pipeline,
+			params,
+			delay,
+			plays,
+			limits,
+			max_memory,
+		} => {
+			let config = Config::load(config, preset, pipeline, params)?;
+			if !animation::supports(&config.out_format) {
+				bail!("Animations need a GIF, PNG or WebP output format");
+			}
+
+			let options = ProcessOptions {
+				auto_orient: config.auto_orient,
+				limits: limits.into(),
+				max_memory,
+				..Default::default()
+			};
+			let stills = files.iter().map(fs::read).collect::<Result<Vec<_>, _>>()?;
+			let mut animation = Animation::from_stills(&stills, &delay, &options)?;
+			if let Some(plays) = plays {
+				animation.loop_count = LoopCount::Finite(plays);
+			}
+
+			let mut out_buf = BufWriter::new(File::create(out)?);
+			animation
+				.process(&config.operations, &options)?
+				.encode_to(&mut out_buf, config.out_format)?;
+		}
+		Command::Duplicates {
+			files,
+			threshold,
+			limits,
+		} => {
+			let duplicates = find_duplicates(&files, threshold, limits.into());
+			for (path, error) in duplicates.unreadable.iter() {
+				eprintln!("{}: {error}", path.display());
+			}
+			for (index, cluster) in duplicates.clusters.iter().enumerate() {
+				if index > 0 {
+					println!();
+				}
+				for path in cluster.paths.iter() {
+					println!("{}", path.display());
+				}
+			}
+		}
+		Command::Info { file } => {
+			let reader = ImageReader::open(&file)?.with_guessed_format()?;
+			let format = reader.format().map(|format| format!("{format:?}"));
+			let (width, height) = reader.into_dimensions()?;
+
+			let info = Info {
+				format,
+				width,
+				height,
+				metadata: read_metadata(&file)?,
+				color_profile: read_color_profile(&file)?,
+				animation: read_animation_info(&file)?,
+			};
+			println!("{}", serde_json::to_string_pretty(&info)?);
+		}
+		Command::Magick { config, args } => match config {
+			Some(config) => {
+				let config = Config::read(&config, None, Vec::new())?;
+				println!("{}", magick::to_args(&config.operations)?.join(" "));
+			}
+			None => {
+				let operations = Operations {
+					operations: magick::parse_args(&args)?,
+				};
+				print!("{}", toml::to_string(&operations)?);
+			}
+		},
+	}
 
 	Ok(())
 }
 
+/// Optimizes PNG output with `effort`, failing for other formats.
+fn set_png_effort(format: &mut ImageOutputFormat, effort: u8) -> anyhow::Result<()> {
+	let ImageOutputFormat::Png { optimize, .. } = format else {
+		bail!("--optimize needs a PNG output format");
+	};
+
+	*optimize = Some(effort);
+	Ok(())
+}
+
 fn process_and_save(
 	in_path: PathBuf,
 	out_path: PathBuf,
-	out_format: ImageOutputFormat,
-	operations: Vec<Operation>,
-) -> Result<(), Error> {
-	let image = process_file(in_path, operations)?;
+	config: Config,
+	print_suggested_quality: bool,
+	print_operations: bool,
+	options: ProcessOptions,
+) -> Result<(ProcessingReport, Vec<Variant>), Error> {
+	let progress: Option<Arc<dyn ProgressHandler>> = if print_operations {
+		Some(Arc::new(print_progress))
+	} else {
+		None
+	};
+	let options = ProcessOptions {
+		auto_orient: config.auto_orient,
+		keep_metadata: config.keep_metadata,
+		progress,
+		..options
+	};
+	let metadata = OutputMetadata::new(&config.operations, &options);
+	let source = match metadata.reads_source() {
+		true => fs::read(&in_path)?,
+		false => Vec::new(),
+	};
+	let write = |encoded: Vec<u8>, out_path: &Path| -> Result<(), Error> {
+		Ok(fs::write(out_path, metadata.write(&source, encoded)?)?)
+	};
 
-	let out_file = File::create(out_path)?;
-	let mut out_buf = BufWriter::new(out_file);
-	image.write_to(&mut out_buf, out_format)?;
+	// Branches, variants and quality suggestions work on stills, so animations are only kept
+	// without them
+	if animation::supports(&config.out_format)
+		&& !print_suggested_quality
+		&& config.branches.is_empty()
+		&& config.variants.is_none()
+	{
+		if let Some(animation) = Animation::decode(&fs::read(&in_path)?, &options)? {
+			let mut encoded = Vec::new();
+			animation
+				.process(&config.operations, &options)?
+				.encode_to(&mut encoded, config.out_format)?;
+			write(encoded, &out_path)?;
+			return Ok((ProcessingReport::default(), Vec::new()));
+		}
+	}
 
-	Ok(())
+	let (image, report) = process_file_with_options(in_path, config.operations, &options)?;
+
+	if print_suggested_quality {
+		match suggest_quality(&image, &config.out_format, DEFAULT_TARGET_SSIM)? {
+			Some(quality) => println!("Suggested quality: {quality}"),
+			None => println!("Output format is lossless or has no quality setting"),
+		}
+	}
+
+	let save = |image: &DynamicImage, out_format: ImageOutputFormat, out_path: &Path| {
+		let out_format = match &config.adaptive_quality {
+			Some(adaptive_quality) => adaptive_quality.apply(out_format, image),
+			None => out_format,
+		};
+
+		let mut encoded = Cursor::new(Vec::new());
+		encode_to(&mut encoded, image, out_format)?;
+		write(encoded.into_inner(), out_path)
+	};
+
+	for branch in config.branches.iter() {
+		let branch_image = branch.process(&image)?;
+		save(
+			&branch_image,
+			branch.out_format.clone(),
+			&branch.output_path(&out_path),
+		)?;
+	}
+	save(&image, config.out_format.clone(), &out_path)?;
+
+	let variants = match &config.variants {
+		Some(variants) => variants.write(&image, &out_path)?,
+		None => Vec::new(),
+	};
+
+	Ok((report, variants))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Config;
+	use imageless::{ImageOutputFormat, Operation};
+	use std::{fs, path::PathBuf};
+
+	fn write_config(name: &str, source: &str) -> PathBuf {
+		let path =
+			std::env::temp_dir().join(format!("imageless-{name}-{}.toml", std::process::id()));
+		fs::write(&path, source).unwrap();
+		path
+	}
+
+	#[test]
+	fn selects_named_pipelines() {
+		let path = write_config(
+			"pipelines",
+			r#"
+out_format = "bmp"
+
+[[operations]]
+sepia = {}
+
+[pipelines.base]
+operations = [{ grayscale = {} }]
+
+[pipelines.inverted]
+include = ["base"]
+operations = [{ invert = {} }]
+out_format = "png"
+
+[pipelines.twice]
+include = ["base", "inverted"]
+"#,
+		);
+
+		let config = Config::read(&path, None, Vec::new()).unwrap();
+		assert!(matches!(config.operations[..], [Operation::Sepia(_)]));
+
+		let config = Config::read(&path, Some("inverted"), Vec::new()).unwrap();
+		assert!(matches!(
+			config.operations[..],
+			[Operation::Grayscale(_), Operation::Invert(_)]
+		));
+		assert_eq!(ImageOutputFormat::png(), config.out_format);
+
+		let config = Config::read(&path, Some("twice"), Vec::new()).unwrap();
+		assert!(matches!(
+			config.operations[..],
+			[
+				Operation::Grayscale(_),
+				Operation::Grayscale(_),
+				Operation::Invert(_)
+			]
+		));
+		assert_eq!(ImageOutputFormat::Bmp, config.out_format);
+
+		assert!(Config::read(&path, Some("missing"), Vec::new()).is_err());
+		fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn rejects_pipeline_cycles() {
+		let path = write_config(
+			"pipeline-cycles",
+			r#"
+out_format = "bmp"
+operations = []
+
+[pipelines.first]
+include = ["second"]
+operations = [{ grayscale = {} }]
+
+[pipelines.second]
+include = ["first"]
+operations = [{ invert = {} }]
+
+[pipelines.third]
+include = ["third"]
+operations = []
+"#,
+		);
+
+		let error = Config::read(&path, Some("first"), Vec::new()).unwrap_err();
+		assert_eq!(
+			"Pipelines `first` → `second` → `first` include each other",
+			error.to_string()
+		);
+		let error = Config::read(&path, Some("third"), Vec::new()).unwrap_err();
+		assert_eq!("Pipeline `third` includes itself", error.to_string());
+		fs::remove_file(path).unwrap();
+	}
 }
@@ -0,0 +1,149 @@
+//! Caching of encoded outputs, so that processing the same input with the same pipeline again
+//! returns the earlier result instead of decoding, processing and encoding it.
+//!
+//! Entries are keyed by a hash of the input bytes and the serialized pipeline. Files a pipeline
+//! reads while processing, such as overlays and plugins, aren't part of the key, so a cache should
+//! be cleared when they change.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, path::PathBuf};
+
+/// Storage for encoded outputs by key. Caches are best-effort, so failing to read or write an
+/// entry is treated as a miss rather than an error.
+pub trait Cache: Send + Sync {
+	fn get(&self, key: &str) -> Option<Vec<u8>>;
+	fn put(&self, key: &str, output: &[u8]);
+}
+
+impl fmt::Debug for dyn Cache {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Cache")
+	}
+}
+
+/// Key of the output of `pipeline` for `input`, as a hex SHA-256 digest. The crate version is
+/// included so that entries from other versions, which may process differently, aren't reused.
+pub fn key(input: &[u8], pipeline: &impl Serialize) -> Result<String, serde_json::Error> {
+	let pipeline = serde_json::to_vec(pipeline)?;
+
+	let mut hasher = Sha256::new();
+	for part in [env!("CARGO_PKG_VERSION").as_bytes(), input, &pipeline] {
+		hasher.update((part.len() as u64).to_le_bytes());
+		hasher.update(part);
+	}
+
+	Ok(hasher
+		.finalize()
+		.iter()
+		.map(|byte| format!("{byte:02x}"))
+		.collect())
+}
+
+/// A cache storing each entry as a file named by its key in a directory.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct DirCache {
+	dir: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DirCache {
+	/// A cache in `dir`, which is created when the first entry is written.
+	pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+		Self { dir: dir.into() }
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Cache for DirCache {
+	fn get(&self, key: &str) -> Option<Vec<u8>> {
+		fs::read(self.dir.join(key)).ok()
+	}
+
+	fn put(&self, key: &str, output: &[u8]) {
+		// Written to a temporary file first so that readers never see part of an entry
+		let temp = self.dir.join(format!("{key}.{}.tmp", std::process::id()));
+		let written = fs::create_dir_all(&self.dir)
+			.and_then(|_| fs::write(&temp, output))
+			.and_then(|_| fs::rename(&temp, self.dir.join(key)));
+		if written.is_err() {
+			let _ = fs::remove_file(&temp);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{key, Cache};
+	#[cfg(feature = "pdf")]
+	use crate::pdf::PdfOptions;
+	use crate::{Pipeline, ProcessOptions};
+	use image::{DynamicImage, ImageOutputFormat, RgbImage};
+	use std::{
+		collections::HashMap,
+		io::Cursor,
+		sync::{Arc, Mutex},
+	};
+
+	#[derive(Default)]
+	struct MemoryCache(Mutex<HashMap<String, Vec<u8>>>);
+
+	impl Cache for MemoryCache {
+		fn get(&self, key: &str) -> Option<Vec<u8>> {
+			self.0.lock().unwrap().get(key).cloned()
+		}
+
+		fn put(&self, key: &str, output: &[u8]) {
+			self.0
+				.lock()
+				.unwrap()
+				.insert(key.to_string(), output.to_vec());
+		}
+	}
+
+	#[test]
+	fn reuses_cached_outputs() {
+		let pipeline: Pipeline = toml::from_str(
+			r#"
+			out_format = "png"
+
+			[[operations]]
+			scale = { factor = 0.5 }
+			"#,
+		)
+		.unwrap();
+
+		let mut input = Cursor::new(Vec::new());
+		DynamicImage::ImageRgb8(RgbImage::new(40, 20))
+			.write_to(&mut input, ImageOutputFormat::Png)
+			.unwrap();
+		let input = input.into_inner();
+
+		let cache = Arc::new(MemoryCache::default());
+		let options = || ProcessOptions {
+			cache: Some(cache.clone()),
+			..Default::default()
+		};
+		let output = pipeline
+			.process_bytes_with_options(&input, options())
+			.unwrap();
+		assert_eq!(1, cache.0.lock().unwrap().len());
+
+		// A hit returns whatever was cached without processing again
+		#[cfg(not(feature = "pdf"))]
+		let key = key(&input, &(&pipeline, false, false)).unwrap();
+		#[cfg(feature = "pdf")]
+		let key = key(&input, &(&pipeline, false, false, PdfOptions::default())).unwrap();
+		cache.put(&key, b"cached");
+		assert_eq!(
+			b"cached".to_vec(),
+			pipeline
+				.process_bytes_with_options(&input, options())
+				.unwrap()
+		);
+		assert_ne!(b"cached".to_vec(), output);
+	}
+}
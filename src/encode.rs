@@ -0,0 +1,649 @@
+//! Encoders for output settings which the encoders in `image` don't support.
+
+use crate::{animation::Frame, Error, PngCompression, PngFilter};
+use flate2::write::ZlibEncoder;
+use image::{
+	error::{EncodingError, ImageFormatHint},
+	DynamicImage, ImageError, ImageFormat,
+};
+use png::{BitDepth, ColorType, Compression};
+use std::io::Write;
+
+/// Origin and spacing of the pixels in each of the seven passes of Adam7 interlacing
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+	(0, 0, 8, 8),
+	(4, 0, 8, 8),
+	(0, 4, 4, 8),
+	(2, 0, 4, 4),
+	(0, 2, 2, 4),
+	(1, 0, 2, 2),
+	(0, 1, 1, 2),
+];
+
+/// Pixels ready to be written as a PNG.
+struct Candidate {
+	color: ColorType,
+	depth: BitDepth,
+	/// Rows of samples packed as PNG stores them
+	data: Vec<u8>,
+}
+
+/// Writes `image` as a PNG made as small as `effort` manages with oxipng, from 0 to 6. Higher
+/// levels try more color types, row filters and compression settings. Levels above 6 are the same
+/// as 6.
+#[cfg(all(feature = "oxipng", not(target_arch = "wasm32")))]
+pub(crate) fn optimized_png<W: Write>(
+	writer: &mut W,
+	image: &DynamicImage,
+	effort: u8,
+) -> Result<(), Error> {
+	let candidate = Samples::of(image).candidate();
+	let color = match candidate.color {
+		ColorType::Grayscale => oxipng::ColorType::Grayscale {
+			transparent_shade: None,
+		},
+		ColorType::GrayscaleAlpha => oxipng::ColorType::GrayscaleAlpha,
+		ColorType::Rgb => oxipng::ColorType::RGB {
+			transparent_color: None,
+		},
+		_ => oxipng::ColorType::RGBA,
+	};
+	let depth = match candidate.depth {
+		BitDepth::Sixteen => oxipng::BitDepth::Sixteen,
+		_ => oxipng::BitDepth::Eight,
+	};
+
+	let optimized =
+		oxipng::RawImage::new(image.width(), image.height(), color, depth, candidate.data)
+			.and_then(|raw| raw.create_optimized_png(&oxipng::Options::from_preset(effort)))
+			.map_err(|error| {
+				ImageError::Encoding(EncodingError::new(
+					ImageFormatHint::Exact(ImageFormat::Png),
+					error,
+				))
+			})?;
+	writer.write_all(&optimized)?;
+	Ok(())
+}
+
+#[cfg(not(all(feature = "oxipng", not(target_arch = "wasm32"))))]
+pub(crate) fn optimized_png<W: Write>(
+	_writer: &mut W,
+	_image: &DynamicImage,
+	_effort: u8,
+) -> Result<(), Error> {
+	use image::error::{UnsupportedError, UnsupportedErrorKind};
+
+	Err(
+		ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+			ImageFormatHint::Exact(ImageFormat::Png),
+			UnsupportedErrorKind::GenericFeature(
+				"optimization without the oxipng feature".to_string(),
+			),
+		))
+		.into(),
+	)
+}
+
+/// Writes `image` as a PNG interlaced with Adam7, which the encoder of `png` doesn't do. Each pass
+/// is filtered as an image of its own, and all of them are compressed together.
+pub(crate) fn interlaced_png<W: Write>(
+	writer: &mut W,
+	image: &DynamicImage,
+	compression: PngCompression,
+	filter: PngFilter,
+) -> Result<(), Error> {
+	let (width, height) = (image.width() as usize, image.height() as usize);
+	let candidate = Samples::of(image).candidate();
+	let pixel = candidate.color.samples()
+		* if candidate.depth == BitDepth::Sixteen {
+			2
+		} else {
+			1
+		};
+	let stride = width * pixel;
+
+	let mut filtered = Vec::new();
+	for (x, y, dx, dy) in ADAM7 {
+		if x >= width {
+			continue;
+		}
+		let mut previous = vec![0; (width - x).div_ceil(dx) * pixel];
+		for row in (y..height).step_by(dy) {
+			let row: Vec<u8> = (x..width)
+				.step_by(dx)
+				.flat_map(|column| {
+					let start = row * stride + column * pixel;
+					candidate.data[start..start + pixel].iter().copied()
+				})
+				.collect();
+			filter_row(filter, &row, &previous, pixel, &mut filtered);
+			previous = row;
+		}
+	}
+
+	let level = match compression {
+		PngCompression::Fast => flate2::Compression::fast(),
+		PngCompression::Balanced => flate2::Compression::default(),
+		PngCompression::Best => flate2::Compression::best(),
+	};
+	let mut encoder = ZlibEncoder::new(Vec::new(), level);
+	encoder.write_all(&filtered)?;
+	let data = encoder.finish()?;
+
+	let mut info = png::Info::with_size(image.width(), image.height());
+	info.color_type = candidate.color;
+	info.bit_depth = candidate.depth;
+	info.interlaced = true;
+	png::Encoder::with_info(writer, info)
+		.and_then(png::Encoder::write_header)
+		.and_then(|mut writer| {
+			writer.write_chunk(png::chunk::IDAT, &data)?;
+			writer.finish()
+		})
+		.map_err(png_encoding_error)
+}
+
+/// Appends the filter type and the filtered bytes of `row`, with `previous` being the row before
+/// it, or zeros for the first row.
+fn filter_row(filter: PngFilter, row: &[u8], previous: &[u8], pixel: usize, out: &mut Vec<u8>) {
+	let filtered = |kind: u8| -> Vec<u8> {
+		row.iter()
+			.enumerate()
+			.map(|(index, &sample)| {
+				let left = index.checked_sub(pixel).map_or(0, |left| row[left]);
+				let up = previous[index];
+				let up_left = index.checked_sub(pixel).map_or(0, |left| previous[left]);
+				let prediction = match kind {
+					0 => 0,
+					1 => left,
+					2 => up,
+					3 => ((left as u16 + up as u16) / 2) as u8,
+					_ => paeth(left, up, up_left),
+				};
+				sample.wrapping_sub(prediction)
+			})
+			.collect()
+	};
+
+	let kind = match filter {
+		PngFilter::None => 0,
+		PngFilter::Sub => 1,
+		PngFilter::Up => 2,
+		PngFilter::Avg => 3,
+		PngFilter::Paeth => 4,
+		// The filter leaving the smallest differences usually compresses best
+		PngFilter::Adaptive => (0..5)
+			.min_by_key(|&kind| {
+				filtered(kind)
+					.iter()
+					.map(|&sample| (sample as i8).unsigned_abs() as u32)
+					.sum::<u32>()
+			})
+			.unwrap_or(0),
+	};
+	out.push(kind);
+	out.extend(filtered(kind));
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+	let estimate = left as i16 + up as i16 - up_left as i16;
+	let distance = |value: u8| (estimate - value as i16).abs();
+	if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+		left
+	} else if distance(up) <= distance(up_left) {
+		up
+	} else {
+		up_left
+	}
+}
+
+/// Interleaved samples of an image, with 8-bit samples widened.
+struct Samples {
+	samples: Vec<u16>,
+	channels: usize,
+	sixteen_bit: bool,
+}
+
+impl Samples {
+	/// Samples of an image in a color type PNG supports.
+	fn of(image: &DynamicImage) -> Self {
+		let widen = |samples: &[u8]| samples.iter().map(|&sample| sample as u16).collect();
+		let (samples, sixteen_bit) = match image {
+			DynamicImage::ImageLuma8(_)
+			| DynamicImage::ImageLumaA8(_)
+			| DynamicImage::ImageRgb8(_)
+			| DynamicImage::ImageRgba8(_) => (widen(image.as_bytes()), false),
+			DynamicImage::ImageLuma16(buffer) => (buffer.as_raw().clone(), true),
+			DynamicImage::ImageLumaA16(buffer) => (buffer.as_raw().clone(), true),
+			DynamicImage::ImageRgb16(buffer) => (buffer.as_raw().clone(), true),
+			DynamicImage::ImageRgba16(buffer) => (buffer.as_raw().clone(), true),
+			image => (image.to_rgba16().into_raw(), true),
+		};
+		let channels = samples.len() / (image.width() as usize * image.height() as usize).max(1);
+
+		Self {
+			samples,
+			channels: channels.max(1),
+			sixteen_bit,
+		}
+	}
+
+	fn candidate(&self) -> Candidate {
+		let color = match self.channels {
+			1 => ColorType::Grayscale,
+			2 => ColorType::GrayscaleAlpha,
+			3 => ColorType::Rgb,
+			_ => ColorType::Rgba,
+		};
+		let (depth, data) = if self.sixteen_bit {
+			let data = self
+				.samples
+				.iter()
+				.flat_map(|sample| sample.to_be_bytes())
+				.collect();
+			(BitDepth::Sixteen, data)
+		} else {
+			let data = self.samples.iter().map(|&sample| sample as u8).collect();
+			(BitDepth::Eight, data)
+		};
+
+		Candidate { color, depth, data }
+	}
+}
+
+/// Writes `frames`, which must all be the same size, as an APNG played `plays` times, or forever
+/// for 0. Frames are stored as 8-bit RGBA, and any `effort` to optimize compresses them harder.
+pub(crate) fn apng<W: Write>(
+	writer: &mut W,
+	frames: &[Frame],
+	plays: u32,
+	effort: Option<u8>,
+) -> Result<(), Error> {
+	let Some(first) = frames.first() else {
+		return Err(ImageError::Encoding(EncodingError::new(
+			ImageFormatHint::Exact(ImageFormat::Png),
+			"An animation needs at least one frame",
+		))
+		.into());
+	};
+
+	let mut encoder = png::Encoder::new(writer, first.image.width(), first.image.height());
+	encoder.set_color(ColorType::Rgba);
+	encoder.set_depth(BitDepth::Eight);
+	if effort.is_some() {
+		encoder.set_compression(Compression::Best);
+	}
+	encoder
+		.set_animated(frames.len() as u32, plays)
+		.and_then(|_| {
+			let mut writer = encoder.write_header()?;
+			for frame in frames {
+				// Delays beyond a u16 of milliseconds are kept to the second
+				let (numerator, denominator) = match u16::try_from(frame.delay) {
+					Ok(delay) => (delay, 1000),
+					Err(_) => ((frame.delay / 1000).min(u16::MAX as u32) as u16, 1),
+				};
+				writer.set_frame_delay(numerator, denominator)?;
+				writer.write_image_data(&frame.image.to_rgba8())?;
+			}
+			writer.finish()
+		})
+		.map_err(png_encoding_error)
+}
+
+/// Writes `image` as a WebP with settings which the encoder in `image` doesn't have.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn webp<W: Write>(
+	writer: &mut W,
+	image: &DynamicImage,
+	quality: f32,
+	lossless: bool,
+	alpha_quality: u8,
+) -> Result<(), Error> {
+	let config = webp_config(quality, lossless, alpha_quality)?;
+
+	let (width, height) = (image.width(), image.height());
+	let encoded = if image.color().has_alpha() {
+		let pixels = image.to_rgba8();
+		webp::Encoder::from_rgba(&pixels, width, height).encode_advanced(&config)
+	} else {
+		let pixels = image.to_rgb8();
+		webp::Encoder::from_rgb(&pixels, width, height).encode_advanced(&config)
+	}
+	.map_err(|code| webp_error(format!("Cannot encode WebP: {code:?}")))?;
+
+	writer.write_all(&encoded)?;
+	Ok(())
+}
+
+/// Writes `frames`, which must all be the same size, as an animated WebP played `loops` times, or
+/// forever for 0.
+///
+/// The animation encoder of the `webp` crate always shows the last frame for the average delay of
+/// the others, so this drives libwebp directly instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn animated_webp<W: Write>(
+	writer: &mut W,
+	frames: &[Frame],
+	loops: u16,
+	quality: f32,
+	lossless: bool,
+	alpha_quality: u8,
+) -> Result<(), Error> {
+	use libwebp_sys::{
+		WebPAnimEncoderDelete, WebPAnimEncoderNewInternal, WebPAnimEncoderOptions,
+		WebPAnimEncoderOptionsInitInternal, WebPGetMuxABIVersion,
+	};
+	use std::mem::MaybeUninit;
+
+	let config = webp_config(quality, lossless, alpha_quality)?;
+	let Some(first) = frames.first() else {
+		return Err(webp_error(
+			"An animation needs at least one frame".to_string(),
+		));
+	};
+	let (width, height) = (first.image.width(), first.image.height());
+
+	// SAFETY: the options are initialized by libwebp before being read, and the encoder is deleted
+	// once, after its last use
+	let encoded = unsafe {
+		let mut options = MaybeUninit::<WebPAnimEncoderOptions>::uninit();
+		if WebPAnimEncoderOptionsInitInternal(options.as_mut_ptr(), WebPGetMuxABIVersion()) == 0 {
+			return Err(webp_error("Cannot configure the WebP encoder".to_string()));
+		}
+		let mut options = options.assume_init();
+		options.anim_params.loop_count = loops.into();
+
+		let encoder = WebPAnimEncoderNewInternal(
+			width as i32,
+			height as i32,
+			&options,
+			WebPGetMuxABIVersion(),
+		);
+		if encoder.is_null() {
+			return Err(webp_error("Cannot create the WebP encoder".to_string()));
+		}
+		let encoded = assemble_webp(encoder, frames, &config);
+		WebPAnimEncoderDelete(encoder);
+		encoded?
+	};
+
+	writer.write_all(&encoded)?;
+	Ok(())
+}
+
+/// Adds `frames` to `encoder` and assembles the animation.
+///
+/// # Safety
+///
+/// `encoder` must be a live encoder for frames of the size of those in `frames`.
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn assemble_webp(
+	encoder: *mut libwebp_sys::WebPAnimEncoder,
+	frames: &[Frame],
+	config: &webp::WebPConfig,
+) -> Result<Vec<u8>, Error> {
+	use libwebp_sys::{
+		WebPAnimEncoderAdd, WebPAnimEncoderAssemble, WebPAnimEncoderGetError, WebPData,
+		WebPDataClear, WebPPicture, WebPPictureFree, WebPPictureImportRGBA, WebPPictureInit,
+	};
+	use std::{ffi::CStr, mem::MaybeUninit, ptr, slice};
+
+	let error = || {
+		let message = CStr::from_ptr(WebPAnimEncoderGetError(encoder)).to_string_lossy();
+		webp_error(format!("Cannot encode animated WebP: {message}"))
+	};
+
+	let mut timestamp: i32 = 0;
+	for frame in frames {
+		let pixels = frame.image.to_rgba8();
+		let mut picture = MaybeUninit::<WebPPicture>::uninit();
+		if !WebPPictureInit(picture.as_mut_ptr()) {
+			return Err(webp_error("Cannot configure the WebP encoder".to_string()));
+		}
+		let mut picture = picture.assume_init();
+		picture.use_argb = 1;
+		picture.width = pixels.width() as i32;
+		picture.height = pixels.height() as i32;
+
+		let added = WebPPictureImportRGBA(&mut picture, pixels.as_ptr(), picture.width * 4) != 0
+			&& WebPAnimEncoderAdd(encoder, &mut picture, timestamp, config) != 0;
+		WebPPictureFree(&mut picture);
+		if !added {
+			return Err(error());
+		}
+		timestamp = timestamp.saturating_add(frame.delay.try_into().unwrap_or(i32::MAX));
+	}
+
+	// The timestamp after the last frame sets how long that frame is shown
+	if WebPAnimEncoderAdd(encoder, ptr::null_mut(), timestamp, ptr::null()) == 0 {
+		return Err(error());
+	}
+	let mut data = WebPData {
+		bytes: ptr::null(),
+		size: 0,
+	};
+	if WebPAnimEncoderAssemble(encoder, &mut data) == 0 {
+		return Err(error());
+	}
+	let encoded = slice::from_raw_parts(data.bytes, data.size).to_vec();
+	WebPDataClear(&mut data);
+	Ok(encoded)
+}
+
+/// Encoder settings for a WebP of `quality` and `alpha_quality`, which are from 0 to 100.
+#[cfg(not(target_arch = "wasm32"))]
+fn webp_config(quality: f32, lossless: bool, alpha_quality: u8) -> Result<webp::WebPConfig, Error> {
+	if !(0.0..=100.0).contains(&quality) || alpha_quality > 100 {
+		return Err(webp_error(format!(
+			"WebP qualities must be between 0 and 100, got {quality} and an alpha quality of \
+			 {alpha_quality}"
+		)));
+	}
+
+	let mut config = webp::WebPConfig::new()
+		.map_err(|_| webp_error("Cannot configure the WebP encoder".to_string()))?;
+	config.quality = quality;
+	config.lossless = lossless.into();
+	config.alpha_quality = alpha_quality.into();
+	Ok(config)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn webp_error(message: String) -> Error {
+	ImageError::Encoding(EncodingError::new(
+		ImageFormatHint::Exact(ImageFormat::WebP),
+		message,
+	))
+	.into()
+}
+
+pub(crate) fn png_encoding_error(error: png::EncodingError) -> Error {
+	ImageError::Encoding(EncodingError::new(
+		ImageFormatHint::Exact(ImageFormat::Png),
+		error,
+	))
+	.into()
+}
+
+/// Writes `image` as a progressive JPEG with mozjpeg, which uses trellis quantization and
+/// optimized scans. Any alpha channel is dropped.
+#[cfg(all(feature = "mozjpeg", not(target_arch = "wasm32")))]
+pub(crate) fn mozjpeg<W: Write>(
+	writer: &mut W,
+	image: &DynamicImage,
+	quality: u8,
+) -> Result<(), Error> {
+	use mozjpeg::{ColorSpace, Compress};
+
+	let (color_space, pixels) = if image.color().has_color() {
+		(ColorSpace::JCS_RGB, image.to_rgb8().into_raw())
+	} else {
+		(ColorSpace::JCS_GRAYSCALE, image.to_luma8().into_raw())
+	};
+
+	// libjpeg reports errors by unwinding
+	let encoded = std::panic::catch_unwind(|| {
+		let mut compress = Compress::new(color_space);
+		compress.set_size(image.width() as usize, image.height() as usize);
+		compress.set_quality(quality.min(100) as f32);
+		compress.set_progressive_mode();
+		compress.set_optimize_scans(true);
+
+		let mut started = compress.start_compress(Vec::new())?;
+		started.write_scanlines(&pixels)?;
+		started.finish()
+	})
+	.map_err(|_| jpeg_error("Cannot encode JPEG with mozjpeg".to_string()))??;
+
+	writer.write_all(&encoded)?;
+	Ok(())
+}
+
+#[cfg(all(feature = "mozjpeg", not(target_arch = "wasm32")))]
+fn jpeg_error(message: String) -> Error {
+	ImageError::Encoding(EncodingError::new(
+		ImageFormatHint::Exact(ImageFormat::Jpeg),
+		message,
+	))
+	.into()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{encode_to, ImageOutputFormat};
+	use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+	use std::io::Cursor;
+
+	fn optimized_png(optimize: Option<u8>) -> ImageOutputFormat {
+		ImageOutputFormat::Png {
+			optimize,
+			compression: Default::default(),
+			filter: Default::default(),
+			interlace: false,
+		}
+	}
+
+	#[test]
+	fn reads_png_optimization() {
+		let formats: Vec<ImageOutputFormat> =
+			serde_json::from_str(r#"["png", { "png": { "optimize": 3 } }]"#).unwrap();
+		assert_eq!(ImageOutputFormat::png(), formats[0]);
+		assert_eq!(
+			r#"["png",{"png":{"optimize":3}}]"#,
+			serde_json::to_string(&formats).unwrap()
+		);
+	}
+
+	#[test]
+	#[cfg(all(feature = "oxipng", not(target_arch = "wasm32")))]
+	fn optimizes_png_losslessly() {
+		// A few colors, which fit a palette
+		let colors = [[200, 30, 30, 255], [30, 200, 30, 128], [30, 30, 200, 255]];
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(61, 40, |x, y| {
+			Rgba(colors[((x / 8 + y / 8) % 3) as usize])
+		}));
+		let encoded: Vec<Vec<u8>> = [None, Some(2)]
+			.into_iter()
+			.map(|optimize| {
+				let mut out = Cursor::new(Vec::new());
+				encode_to(&mut out, &image, optimized_png(optimize)).unwrap();
+				out.into_inner()
+			})
+			.collect();
+
+		assert!(encoded[1].len() < encoded[0].len());
+		let decoded = image::load_from_memory(&encoded[1]).unwrap();
+		assert_eq!(image.to_rgba8(), decoded.to_rgba8());
+	}
+
+	#[test]
+	#[cfg(not(feature = "oxipng"))]
+	fn needs_oxipng_feature() {
+		let image = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+		let result = encode_to(&mut Cursor::new(Vec::new()), &image, optimized_png(Some(2)));
+		assert!(matches!(
+			result,
+			Err(crate::Error::ImageError(image::ImageError::Unsupported(_)))
+		));
+	}
+
+	#[test]
+	fn interlaces_png() {
+		// Sizes which leave some passes short or empty
+		for (width, height) in [(37, 23), (3, 2), (1, 1)] {
+			let image = DynamicImage::ImageRgba16(ImageBuffer::from_fn(width, height, |x, y| {
+				Rgba([
+					(x * 1700) as u16,
+					(y * 2900) as u16,
+					(x * y * 300) as u16,
+					65535,
+				])
+			}));
+			for filter in ["adaptive", "none", "sub", "up", "avg", "paeth"] {
+				let format: ImageOutputFormat = serde_json::from_str(&format!(
+					r#"{{ "png": {{ "compression": "best", "filter": "{filter}", "interlace": true }} }}"#
+				))
+				.unwrap();
+				let mut out = Cursor::new(Vec::new());
+				encode_to(&mut out, &image, format).unwrap();
+				let encoded = out.into_inner();
+
+				// The interlace method of the header
+				assert_eq!(1, encoded[28]);
+				let decoded = image::load_from_memory(&encoded).unwrap();
+				assert_eq!(image.to_rgba16(), decoded.to_rgba16());
+			}
+		}
+	}
+
+	#[test]
+	fn encodes_webp_settings() {
+		let formats: Vec<ImageOutputFormat> = serde_json::from_str(
+			r#"["web-p", { "web-p": { "quality": 10 } }, { "web-p": { "lossless": true } }]"#,
+		)
+		.unwrap();
+		assert_eq!(ImageOutputFormat::webp(), formats[0]);
+		assert_eq!(r#""web-p""#, serde_json::to_string(&formats[0]).unwrap());
+
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 48, |x, y| {
+			Rgba([(x * 4) as u8, (y * 5) as u8, (x * y) as u8, 255 - x as u8])
+		}));
+		let encoded: Vec<Vec<u8>> = formats
+			.into_iter()
+			.map(|format| {
+				let mut out = Cursor::new(Vec::new());
+				encode_to(&mut out, &image, format).unwrap();
+				out.into_inner()
+			})
+			.collect();
+
+		assert!(encoded[1].len() < encoded[0].len());
+		let decoded = image::load_from_memory(&encoded[2]).unwrap();
+		assert_eq!(image.to_rgba8(), decoded.to_rgba8());
+	}
+
+	#[cfg(feature = "mozjpeg")]
+	#[test]
+	fn encodes_progressive_jpeg() {
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(96, 64, |x, y| {
+			Rgba([(x * 2) as u8, (y * 3) as u8, ((x + y) % 16 * 16) as u8, 128])
+		}));
+		let mut out = Cursor::new(Vec::new());
+		encode_to(&mut out, &image, ImageOutputFormat::Jpeg { quality: 80 }).unwrap();
+		let encoded = out.into_inner();
+
+		let mut baseline = Vec::new();
+		image::codecs::jpeg::JpegEncoder::new_with_quality(&mut baseline, 80)
+			.encode_image(&image.to_rgb8())
+			.unwrap();
+		assert!(encoded.len() < baseline.len());
+
+		// A progressive start of frame
+		assert!(encoded.windows(2).any(|marker| marker == [0xff, 0xc2]));
+		let decoded = image::load_from_memory(&encoded).unwrap();
+		assert_eq!((96, 64), (decoded.width(), decoded.height()));
+		assert!(!decoded.color().has_alpha());
+	}
+}
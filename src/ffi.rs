@@ -0,0 +1,239 @@
+//! C interface, enabled with the `ffi` feature. See `include/imageless.h` for the declarations.
+//!
+//! Build the shared library with `cargo rustc --lib --release --crate-type cdylib --features ffi`.
+
+use crate::Pipeline;
+use std::{
+	ffi::{c_char, c_int, CStr, CString},
+	panic, ptr, slice,
+};
+
+pub const IMAGELESS_OK: c_int = 0;
+pub const IMAGELESS_ERROR_INVALID_ARGUMENT: c_int = 1;
+pub const IMAGELESS_ERROR_INVALID_PIPELINE: c_int = 2;
+pub const IMAGELESS_ERROR_PROCESSING: c_int = 3;
+pub const IMAGELESS_ERROR_PANIC: c_int = 4;
+
+/// Processes an encoded image with a JSON encoded [`Pipeline`].
+///
+/// On success the encoded output is written to `output` and `output_len`, and must be released
+/// with [`imageless_free_bytes`]. On failure a non-zero status is returned, `output` is set to null
+/// and `output_len` to zero, and, if `error_message` is not null, a description of the error is
+/// written to it which must be released with [`imageless_free_string`].
+///
+/// # Safety
+///
+/// `input` must point to `input_len` readable bytes, `pipeline` must be a valid NUL-terminated
+/// string, and `output` and `output_len` must be valid for writes. `error_message` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn imageless_process_bytes(
+	input: *const u8,
+	input_len: usize,
+	pipeline: *const c_char,
+	output: *mut *mut u8,
+	output_len: *mut usize,
+	error_message: *mut *mut c_char,
+) -> c_int {
+	if !error_message.is_null() {
+		*error_message = ptr::null_mut();
+	}
+	if !output.is_null() {
+		*output = ptr::null_mut();
+	}
+	if !output_len.is_null() {
+		*output_len = 0;
+	}
+
+	if input.is_null() || pipeline.is_null() || output.is_null() || output_len.is_null() {
+		set_error(error_message, "Null pointer argument");
+		return IMAGELESS_ERROR_INVALID_ARGUMENT;
+	}
+
+	let input = slice::from_raw_parts(input, input_len);
+	let pipeline = CStr::from_ptr(pipeline);
+
+	let result = panic::catch_unwind(|| {
+		let pipeline: Pipeline = serde_json::from_str(
+			pipeline
+				.to_str()
+				.map_err(|err| (IMAGELESS_ERROR_INVALID_PIPELINE, err.to_string()))?,
+		)
+		.map_err(|err| (IMAGELESS_ERROR_INVALID_PIPELINE, err.to_string()))?;
+
+		pipeline
+			.process_bytes(input)
+			.map_err(|err| (IMAGELESS_ERROR_PROCESSING, error_chain(&err)))
+	});
+
+	match result {
+		Ok(Ok(bytes)) => {
+			let bytes = bytes.into_boxed_slice();
+			*output_len = bytes.len();
+			*output = Box::into_raw(bytes) as *mut u8;
+			IMAGELESS_OK
+		}
+		Ok(Err((code, message))) => {
+			set_error(error_message, &message);
+			code
+		}
+		Err(_) => {
+			set_error(error_message, "Panic while processing image");
+			IMAGELESS_ERROR_PANIC
+		}
+	}
+}
+
+/// Releases output returned by [`imageless_process_bytes`].
+///
+/// # Safety
+///
+/// `data` and `len` must be exactly as returned by [`imageless_process_bytes`], and must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn imageless_free_bytes(data: *mut u8, len: usize) {
+	if !data.is_null() {
+		drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+	}
+}
+
+/// Releases an error message returned by [`imageless_process_bytes`].
+///
+/// # Safety
+///
+/// `message` must have been returned by [`imageless_process_bytes`] and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn imageless_free_string(message: *mut c_char) {
+	if !message.is_null() {
+		drop(CString::from_raw(message));
+	}
+}
+
+unsafe fn set_error(error_message: *mut *mut c_char, message: &str) {
+	if !error_message.is_null() {
+		let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+		*error_message = message.into_raw();
+	}
+}
+
+fn error_chain(err: &dyn std::error::Error) -> String {
+	let mut message = err.to_string();
+	let mut source = err.source();
+	while let Some(err) = source {
+		message = format!("{message}: {err}");
+		source = err.source();
+	}
+	message
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		imageless_free_bytes, imageless_free_string, imageless_process_bytes,
+		IMAGELESS_ERROR_INVALID_ARGUMENT, IMAGELESS_ERROR_INVALID_PIPELINE, IMAGELESS_OK,
+	};
+	use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat, RgbImage};
+	use std::{
+		ffi::{c_int, CStr, CString},
+		io::Cursor,
+		ptr, slice,
+	};
+
+	/// Runs `pipeline` through the C interface, returning the status with the output or the error
+	/// message.
+	fn process(input: &[u8], pipeline: &str) -> (c_int, Vec<u8>, Option<String>) {
+		let pipeline = CString::new(pipeline).unwrap();
+		let (mut output, mut output_len, mut error) = (ptr::null_mut(), 0, ptr::null_mut());
+
+		// SAFETY: every pointer is valid, and the results are copied before being released
+		unsafe {
+			let status = imageless_process_bytes(
+				input.as_ptr(),
+				input.len(),
+				pipeline.as_ptr(),
+				&mut output,
+				&mut output_len,
+				&mut error,
+			);
+			let bytes = match output.is_null() {
+				true => Vec::new(),
+				false => slice::from_raw_parts(output, output_len).to_vec(),
+			};
+			let message =
+				(!error.is_null()).then(|| CStr::from_ptr(error).to_string_lossy().into_owned());
+			imageless_free_bytes(output, output_len);
+			imageless_free_string(error);
+			(status, bytes, message)
+		}
+	}
+
+	#[test]
+	fn processes_bytes() {
+		let mut png = Cursor::new(Vec::new());
+		DynamicImage::ImageRgb8(RgbImage::new(8, 6))
+			.write_to(&mut png, ImageOutputFormat::Png)
+			.unwrap();
+
+		let pipeline = r#"{ "out_format": "bmp", "operations": [{ "grayscale": {} }] }"#;
+		let (status, output, message) = process(png.get_ref(), pipeline);
+		assert_eq!((IMAGELESS_OK, None), (status, message));
+		assert_eq!(ImageFormat::Bmp, image::guess_format(&output).unwrap());
+		assert_eq!(
+			(8, 6),
+			image::load_from_memory(&output).unwrap().dimensions()
+		);
+
+		let (status, output, message) = process(png.get_ref(), "{");
+		assert_eq!(IMAGELESS_ERROR_INVALID_PIPELINE, status);
+		assert!(output.is_empty() && message.is_some());
+	}
+
+	#[test]
+	fn rejects_null_arguments() {
+		let mut error = ptr::null_mut();
+		// SAFETY: null pointers are checked before anything is read
+		let status = unsafe {
+			imageless_process_bytes(
+				ptr::null(),
+				0,
+				ptr::null(),
+				ptr::null_mut(),
+				ptr::null_mut(),
+				&mut error,
+			)
+		};
+		assert_eq!(IMAGELESS_ERROR_INVALID_ARGUMENT, status);
+		// SAFETY: the message was returned by imageless_process_bytes
+		unsafe {
+			assert_eq!(
+				"Null pointer argument",
+				CStr::from_ptr(error).to_str().unwrap()
+			);
+			imageless_free_string(error);
+		}
+	}
+
+	#[test]
+	fn clears_output_on_failure() {
+		let pipeline = CString::new("{").unwrap();
+		let mut byte = 0;
+		let (mut output, mut output_len, mut error) =
+			(ptr::addr_of_mut!(byte), 42, ptr::null_mut());
+		// SAFETY: every pointer is valid, and the message is released after the call
+		let status = unsafe {
+			let status = imageless_process_bytes(
+				[0].as_ptr(),
+				1,
+				pipeline.as_ptr(),
+				&mut output,
+				&mut output_len,
+				&mut error,
+			);
+			imageless_free_string(error);
+			status
+		};
+		assert_eq!(IMAGELESS_ERROR_INVALID_PIPELINE, status);
+		assert!(output.is_null());
+		assert_eq!(0, output_len);
+	}
+}
@@ -0,0 +1,94 @@
+// Adjusts the color of each pixel on its own, keeping the alpha. Results are clamped when they are
+// read back.
+
+struct Params {
+	width: u32,
+	height: u32,
+	// 0 brightens by `amount`, 1 inverts, 2 scales the saturation by `amount` and 3 tones sepia
+	// with an intensity of `amount`
+	mode: u32,
+	amount: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> input: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> output: array<vec4<f32>>;
+
+fn rem_euclid(value: f32, divisor: f32) -> f32 {
+	return value - divisor * floor(value / divisor);
+}
+
+fn rgb_to_hsl(rgb: vec3<f32>) -> vec3<f32> {
+	let high = max(max(rgb.r, rgb.g), rgb.b);
+	let low = min(min(rgb.r, rgb.g), rgb.b);
+	let lightness = (high + low) / 2.0;
+	let delta = high - low;
+
+	if delta == 0.0 {
+		return vec3<f32>(0.0, 0.0, lightness);
+	}
+
+	let saturation = delta / (1.0 - abs(2.0 * lightness - 1.0));
+	var hue: f32;
+	if high == rgb.r {
+		hue = 60.0 * rem_euclid((rgb.g - rgb.b) / delta, 6.0);
+	} else if high == rgb.g {
+		hue = 60.0 * ((rgb.b - rgb.r) / delta + 2.0);
+	} else {
+		hue = 60.0 * ((rgb.r - rgb.g) / delta + 4.0);
+	}
+
+	return vec3<f32>(hue, saturation, lightness);
+}
+
+fn hsl_to_rgb(hsl: vec3<f32>) -> vec3<f32> {
+	let chroma = (1.0 - abs(2.0 * hsl.z - 1.0)) * hsl.y;
+	let h = rem_euclid(hsl.x, 360.0) / 60.0;
+	let x = chroma * (1.0 - abs(h % 2.0 - 1.0));
+	let m = hsl.z - chroma / 2.0;
+
+	var rgb: vec3<f32>;
+	switch u32(h) {
+		case 0u: { rgb = vec3<f32>(chroma, x, 0.0); }
+		case 1u: { rgb = vec3<f32>(x, chroma, 0.0); }
+		case 2u: { rgb = vec3<f32>(0.0, chroma, x); }
+		case 3u: { rgb = vec3<f32>(0.0, x, chroma); }
+		case 4u: { rgb = vec3<f32>(x, 0.0, chroma); }
+		default: { rgb = vec3<f32>(chroma, 0.0, x); }
+	}
+
+	return rgb + m;
+}
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+	if id.x >= params.width || id.y >= params.height {
+		return;
+	}
+
+	let index = id.y * params.width + id.x;
+	let pixel = input[index];
+	var rgb = pixel.rgb;
+	switch params.mode {
+		case 0u: {
+			rgb += params.amount;
+		}
+		case 1u: {
+			rgb = 1.0 - rgb;
+		}
+		case 2u: {
+			let hsl = rgb_to_hsl(rgb);
+			rgb = hsl_to_rgb(vec3<f32>(hsl.x, min(hsl.y * params.amount, 1.0), hsl.z));
+		}
+		default: {
+			let sepia = vec3<f32>(
+				dot(rgb, vec3<f32>(0.393, 0.769, 0.189)),
+				dot(rgb, vec3<f32>(0.349, 0.686, 0.168)),
+				dot(rgb, vec3<f32>(0.272, 0.534, 0.131)),
+			);
+			rgb = mix(rgb, sepia, params.amount);
+		}
+	}
+
+	output[index] = vec4<f32>(rgb, pixel.a);
+}
@@ -0,0 +1,39 @@
+// Convolves the color channels with a square kernel, extending the edges, and keeps the alpha.
+
+struct Params {
+	width: u32,
+	height: u32,
+	size: u32,
+	divisor: f32,
+	offset: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> input: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> output: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read> weights: array<f32>;
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+	if id.x >= params.width || id.y >= params.height {
+		return;
+	}
+
+	let radius = i32(params.size / 2u);
+	let last = vec2<i32>(i32(params.width) - 1, i32(params.height) - 1);
+	var sum = vec3<f32>(0.0);
+	for (var i = 0u; i < params.size * params.size; i++) {
+		let weight = weights[i];
+		if weight == 0.0 {
+			continue;
+		}
+
+		let offset = vec2<i32>(i32(i % params.size), i32(i / params.size)) - radius;
+		let source = clamp(vec2<i32>(id.xy) + offset, vec2<i32>(0), last);
+		sum += input[u32(source.y) * params.width + u32(source.x)].rgb * weight;
+	}
+
+	let index = id.y * params.width + id.x;
+	let color = clamp(sum / params.divisor + params.offset, vec3<f32>(0.0), vec3<f32>(1.0));
+	output[index] = vec4<f32>(color, input[index].a);
+}
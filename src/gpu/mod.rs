@@ -0,0 +1,630 @@
+//! Runs resizing, blurring, convolution and color adjustments as compute shaders, enabled with the
+//! `gpu` feature.
+//!
+//! Images are uploaded as normalized RGBA floats and converted back to the color type the CPU
+//! would produce. Resampling uses the same taps and weights as `image`, so results match the CPU to
+//! within rounding. Other operations, float images and images too large for the buffers of the
+//! adapter run on the CPU, as does everything when there is no adapter.
+//!
+//! ```no_run
+//! use imageless::{gpu::Accelerated, operations::Blur, Operation, Process};
+//!
+//! let image = image::open("photo.jpg").unwrap();
+//! let blur = Operation::Blur(Blur { sigma: 4.0 });
+//! let blurred = Accelerated(&blur).process(image).unwrap();
+//! ```
+
+use crate::{
+	operations::{AdjustBrightness, Blur, CropMode, Resize, Saturation, Scale, Sepia},
+	tiled::{gaussian, Axis},
+	ImageInfo, Operation, OperationError, Process,
+};
+use image::{ColorType, DynamicImage, ImageBuffer, Primitive, Rgba32FImage};
+use num::NumCast;
+use std::sync::{mpsc, OnceLock};
+use wgpu::util::DeviceExt;
+
+/// Width and height of the workgroups of each shader
+const WORKGROUP_SIZE: u32 = 16;
+/// Bytes in each pixel on the GPU
+const PIXEL_BYTES: u64 = 16;
+
+/// A GPU device with compute pipelines for the supported operations.
+pub struct Gpu {
+	device: wgpu::Device,
+	queue: wgpu::Queue,
+	resample: wgpu::ComputePipeline,
+	convolve: wgpu::ComputePipeline,
+	color: wgpu::ComputePipeline,
+}
+
+impl Gpu {
+	/// Opens the highest performance adapter, or returns `None` when there isn't one.
+	pub fn new() -> Option<Self> {
+		let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle());
+		let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+			power_preference: wgpu::PowerPreference::HighPerformance,
+			..Default::default()
+		}))
+		.ok()?;
+		let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+			label: Some("imageless"),
+			// Large images need the largest buffers the adapter has
+			required_limits: adapter.limits(),
+			..Default::default()
+		}))
+		.ok()?;
+
+		let pipeline = |shader: wgpu::ShaderModuleDescriptor| {
+			let module = device.create_shader_module(shader);
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: None,
+				layout: None,
+				module: &module,
+				entry_point: Some("main"),
+				compilation_options: Default::default(),
+				cache: None,
+			})
+		};
+
+		Some(Self {
+			resample: pipeline(wgpu::include_wgsl!("resample.wgsl")),
+			convolve: pipeline(wgpu::include_wgsl!("convolve.wgsl")),
+			color: pipeline(wgpu::include_wgsl!("color.wgsl")),
+			device,
+			queue,
+		})
+	}
+
+	/// The GPU used by [`Accelerated`], opened on first use.
+	pub fn shared() -> Option<&'static Self> {
+		static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+		GPU.get_or_init(Self::new).as_ref()
+	}
+
+	/// Runs `operation` with a compute shader when it has one and the image fits in the buffers
+	/// of the adapter, and on the CPU otherwise.
+	pub fn process(
+		&self,
+		operation: &Operation,
+		image: DynamicImage,
+	) -> Result<DynamicImage, OperationError> {
+		let output = Job::of(operation, &image).and_then(|job| self.run(&job, &image));
+		match output {
+			Some(output) => Ok(output),
+			None => operation.get_process().process(image),
+		}
+	}
+
+	/// Runs `job` on `image`, or returns `None` when it can't be run on the GPU.
+	fn run(&self, job: &Job, image: &DynamicImage) -> Option<DynamicImage> {
+		let color = output_color(image.color(), job)?;
+		let (width, height) = (image.width(), image.height());
+		let (output_width, output_height) = match job {
+			Job::Resample { width, height, .. } => (*width, *height),
+			_ => (width, height),
+		};
+		self.fits(width, height.max(output_height))?;
+		self.fits(output_width, output_height)?;
+
+		// Buffers which don't fit in memory fall back to the CPU, rather than panicking
+		let scope = self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+		let input = self.buffer(
+			bytemuck::cast_slice(image.to_rgba32f().as_raw()),
+			wgpu::BufferUsages::STORAGE,
+		);
+		let output = self.pixels(output_width, output_height);
+		let mut encoder = self.device.create_command_encoder(&Default::default());
+
+		match job {
+			Job::Resample {
+				vertical,
+				horizontal,
+				..
+			} => {
+				// Vertically then horizontally, like `image`, clamping only once both are done
+				let resampled = self.pixels(width, output_height);
+				let params = [width, width, output_height, 1, 0];
+				self.sample(&mut encoder, &input, &resampled, params, vertical);
+				let params = [width, output_width, output_height, 0, 1];
+				self.sample(&mut encoder, &resampled, &output, params, horizontal);
+			}
+			Job::Convolve {
+				weights,
+				size,
+				divisor,
+				offset,
+			} => {
+				let params = [width, height, *size, divisor.to_bits(), offset.to_bits()];
+				let weights =
+					self.buffer(bytemuck::cast_slice(weights), wgpu::BufferUsages::STORAGE);
+				self.dispatch(
+					&mut encoder,
+					&self.convolve,
+					&[&self.params(&params), &input, &output, &weights],
+					(width, height),
+				);
+			}
+			Job::Color { mode, amount, .. } => {
+				let params = [width, height, *mode as u32, amount.to_bits()];
+				self.dispatch(
+					&mut encoder,
+					&self.color,
+					&[&self.params(&params), &input, &output],
+					(width, height),
+				);
+			}
+		}
+
+		let pixels = self.read(encoder, &output)?;
+		if pollster::block_on(scope.pop()).is_some() {
+			return None;
+		}
+
+		let pixels = Rgba32FImage::from_raw(output_width, output_height, pixels)?;
+		Some(convert(pixels, color))
+	}
+
+	/// Whether a `width` by `height` image fits in a buffer and can be dispatched in one go.
+	fn fits(&self, width: u32, height: u32) -> Option<()> {
+		let limits = self.device.limits();
+		let bytes = width as u64 * height as u64 * PIXEL_BYTES;
+		let groups = width.max(height).div_ceil(WORKGROUP_SIZE);
+
+		(bytes
+			<= limits
+				.max_storage_buffer_binding_size
+				.min(limits.max_buffer_size)
+			&& groups <= limits.max_compute_workgroups_per_dimension)
+			.then_some(())
+	}
+
+	/// Records one pass of a resample of `input` into `output` along `axis`, with the `params`
+	/// of the resample shader.
+	fn sample(
+		&self,
+		encoder: &mut wgpu::CommandEncoder,
+		input: &wgpu::Buffer,
+		output: &wgpu::Buffer,
+		params: [u32; 5],
+		axis: &Axis,
+	) {
+		let mut spans = Vec::with_capacity(axis.taps.len() * 3);
+		let mut weights: Vec<f32> = Vec::new();
+		for taps in &axis.taps {
+			spans.extend([taps.start, taps.weights.len() as u32, weights.len() as u32]);
+			weights.extend(&taps.weights);
+		}
+
+		let storage = wgpu::BufferUsages::STORAGE;
+		self.dispatch(
+			encoder,
+			&self.resample,
+			&[
+				&self.params(&params),
+				input,
+				output,
+				&self.buffer(bytemuck::cast_slice(&spans), storage),
+				&self.buffer(bytemuck::cast_slice(&weights), storage),
+			],
+			(params[1], params[2]),
+		);
+	}
+
+	/// Records a pass of `pipeline` over a `width` by `height` output, with `buffers` bound in
+	/// order.
+	fn dispatch(
+		&self,
+		encoder: &mut wgpu::CommandEncoder,
+		pipeline: &wgpu::ComputePipeline,
+		buffers: &[&wgpu::Buffer],
+		(width, height): (u32, u32),
+	) {
+		let entries: Vec<_> = buffers
+			.iter()
+			.enumerate()
+			.map(|(binding, buffer)| wgpu::BindGroupEntry {
+				binding: binding as u32,
+				resource: buffer.as_entire_binding(),
+			})
+			.collect();
+		let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: None,
+			layout: &pipeline.get_bind_group_layout(0),
+			entries: &entries,
+		});
+
+		let mut pass = encoder.begin_compute_pass(&Default::default());
+		pass.set_pipeline(pipeline);
+		pass.set_bind_group(0, &bind_group, &[]);
+		pass.dispatch_workgroups(
+			width.div_ceil(WORKGROUP_SIZE),
+			height.div_ceil(WORKGROUP_SIZE),
+			1,
+		);
+	}
+
+	fn buffer(&self, contents: &[u8], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+		self.device
+			.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: None,
+				contents,
+				usage,
+			})
+	}
+
+	fn params(&self, params: &[u32]) -> wgpu::Buffer {
+		self.buffer(bytemuck::cast_slice(params), wgpu::BufferUsages::UNIFORM)
+	}
+
+	fn pixels(&self, width: u32, height: u32) -> wgpu::Buffer {
+		self.device.create_buffer(&wgpu::BufferDescriptor {
+			label: None,
+			size: width as u64 * height as u64 * PIXEL_BYTES,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+			mapped_at_creation: false,
+		})
+	}
+
+	/// Submits `encoder` and reads `output` back once it has finished.
+	fn read(&self, mut encoder: wgpu::CommandEncoder, output: &wgpu::Buffer) -> Option<Vec<f32>> {
+		let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+			label: None,
+			size: output.size(),
+			usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		encoder.copy_buffer_to_buffer(output, 0, &staging, 0, output.size());
+		self.queue.submit([encoder.finish()]);
+
+		let (sender, receiver) = mpsc::channel();
+		staging.map_async(wgpu::MapMode::Read, .., move |result| {
+			let _ = sender.send(result);
+		});
+		self.device.poll(wgpu::PollType::wait_indefinitely()).ok()?;
+		receiver.recv().ok()?.ok()?;
+
+		let pixels = bytemuck::pod_collect_to_vec(&staging.get_mapped_range(..).ok()?);
+		Some(pixels)
+	}
+}
+
+/// Runs an operation on the [shared](Gpu::shared) GPU when it can, and on the CPU otherwise.
+pub struct Accelerated<'a>(pub &'a Operation);
+
+impl Process for Accelerated<'_> {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		match Gpu::shared() {
+			Some(gpu) => gpu.process(self.0, image),
+			None => self.0.get_process().process(image),
+		}
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		self.0.get_process().plan(image)
+	}
+}
+
+/// Modes of the color shader
+#[derive(Clone, Copy)]
+enum ColorMode {
+	Brighten,
+	Invert,
+	Saturate,
+	Sepia,
+}
+
+/// An operation as work for the shaders, in normalized RGBA.
+enum Job {
+	Resample {
+		width: u32,
+		height: u32,
+		vertical: Axis,
+		horizontal: Axis,
+	},
+	Convolve {
+		weights: Vec<f32>,
+		size: u32,
+		divisor: f32,
+		offset: f32,
+	},
+	Color {
+		mode: ColorMode,
+		amount: f32,
+		/// Whether gray images become RGB, as they do on the CPU
+		rgb: bool,
+	},
+}
+
+impl Job {
+	/// The work for `operation` on `image`, or `None` when it runs on the CPU. Invalid operations
+	/// run on the CPU as well, which reports the error.
+	fn of(operation: &Operation, image: &DynamicImage) -> Option<Self> {
+		let info = ImageInfo::of(image);
+		let (width, height) = info.dimensions();
+
+		match operation {
+			Operation::Blur(Blur { sigma }) if sigma.is_finite() => {
+				let sigma = if *sigma <= 0.0 { 1.0 } else { *sigma };
+				let axis =
+					|size| Axis::with_kernel(size, size, |x| gaussian(x, sigma), 2.0 * sigma);
+				Some(Self::Resample {
+					width,
+					height,
+					vertical: axis(height),
+					horizontal: axis(width),
+				})
+			}
+			Operation::Resize(Resize {
+				filter,
+				crop_mode:
+					CropMode::Preserve | CropMode::Exact | CropMode::ShrinkOnly | CropMode::EnlargeOnly,
+				..
+			})
+			| Operation::Scale(Scale { filter, .. }) => {
+				let (output_width, output_height) = operation.get_process().plan(info).ok()??;
+				// Images which keep their size are only copied
+				if (output_width, output_height) == (width, height) {
+					return None;
+				}
+
+				Some(Self::Resample {
+					width: output_width,
+					height: output_height,
+					vertical: Axis::new(height, output_height, *filter),
+					horizontal: Axis::new(width, output_width, *filter),
+				})
+			}
+			Operation::Convolve(convolve) => {
+				let convolution = convolve.convolution().ok()?;
+				Some(Self::Convolve {
+					weights: convolution.weights,
+					size: convolution.size as u32,
+					divisor: convolution.divisor,
+					offset: convolution.offset,
+				})
+			}
+			Operation::AdjustBrightness(adjust) => {
+				let value = match adjust {
+					AdjustBrightness::Darken(value) => -(*value as f32),
+					AdjustBrightness::Brighten(value) => *value as f32,
+				};
+				// Brightness is adjusted in steps of the channel type
+				let max = match image.color().bytes_per_pixel() / image.color().channel_count() {
+					1 => u8::MAX as f32,
+					_ => u16::MAX as f32,
+				};
+				Some(Self::Color {
+					mode: ColorMode::Brighten,
+					amount: value / max,
+					rgb: false,
+				})
+			}
+			Operation::Invert(_) => Some(Self::Color {
+				mode: ColorMode::Invert,
+				amount: 0.0,
+				rgb: false,
+			}),
+			Operation::Saturation(Saturation { percentage })
+				if percentage.is_finite() && *percentage >= -100.0 =>
+			{
+				Some(Self::Color {
+					mode: ColorMode::Saturate,
+					amount: 1.0 + percentage / 100.0,
+					rgb: false,
+				})
+			}
+			Operation::Sepia(Sepia { intensity }) if (0.0..=1.0).contains(intensity) => {
+				Some(Self::Color {
+					mode: ColorMode::Sepia,
+					amount: *intensity,
+					rgb: true,
+				})
+			}
+			_ => None,
+		}
+	}
+}
+
+/// The color type `job` turns an image of `color` into, or `None` when the GPU doesn't handle
+/// images of `color`.
+fn output_color(color: ColorType, job: &Job) -> Option<ColorType> {
+	let rgb = matches!(job, Job::Color { rgb: true, .. });
+	match color {
+		ColorType::L8 if rgb => Some(ColorType::Rgb8),
+		ColorType::La8 if rgb => Some(ColorType::Rgba8),
+		ColorType::L16 if rgb => Some(ColorType::Rgb16),
+		ColorType::La16 if rgb => Some(ColorType::Rgba16),
+		ColorType::L8
+		| ColorType::La8
+		| ColorType::Rgb8
+		| ColorType::Rgba8
+		| ColorType::L16
+		| ColorType::La16
+		| ColorType::Rgb16
+		| ColorType::Rgba16 => Some(color),
+		_ => None,
+	}
+}
+
+/// Converts normalized RGBA to `color`, clamping and rounding channels. Gray images take the red
+/// channel, which all channels share until a job makes them RGB.
+fn convert(pixels: Rgba32FImage, color: ColorType) -> DynamicImage {
+	let (width, height) = pixels.dimensions();
+	macro_rules! gray {
+		($variant:ident, $alpha:expr) => {{
+			let samples = pixels
+				.pixels()
+				.flat_map(|pixel| [pixel[0], pixel[3]].into_iter().take(1 + $alpha as usize))
+				.map(quantize)
+				.collect();
+			let buffer = ImageBuffer::from_raw(width, height, samples);
+			DynamicImage::$variant(buffer.expect("samples fill the image"))
+		}};
+	}
+
+	match color {
+		ColorType::L8 => gray!(ImageLuma8, false),
+		ColorType::La8 => gray!(ImageLumaA8, true),
+		ColorType::L16 => gray!(ImageLuma16, false),
+		ColorType::La16 => gray!(ImageLumaA16, true),
+		ColorType::Rgb8 => DynamicImage::ImageRgb8(DynamicImage::ImageRgba32F(pixels).into_rgb8()),
+		ColorType::Rgb16 => {
+			DynamicImage::ImageRgb16(DynamicImage::ImageRgba32F(pixels).into_rgb16())
+		}
+		ColorType::Rgba16 => {
+			DynamicImage::ImageRgba16(DynamicImage::ImageRgba32F(pixels).into_rgba16())
+		}
+		_ => DynamicImage::ImageRgba8(DynamicImage::ImageRgba32F(pixels).into_rgba8()),
+	}
+}
+
+fn quantize<S: Primitive>(value: f32) -> S {
+	let max = S::DEFAULT_MAX_VALUE.to_f32().unwrap_or(1.0);
+	NumCast::from((value.clamp(0.0, 1.0) * max).round()).unwrap_or(S::DEFAULT_MIN_VALUE)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Gpu;
+	use crate::{
+		operations::{
+			AdjustBrightness, Blur, Convolve, CropMode, FilterType, Invert, Kernel, KernelPreset,
+			Resize, Saturation, Scale, Sepia,
+		},
+		Operation, PixelUnit, Unit,
+	};
+	use image::{DynamicImage, GenericImageView, GrayImage, Luma, Rgba, RgbaImage};
+
+	fn image() -> DynamicImage {
+		DynamicImage::ImageRgba8(RgbaImage::from_fn(37, 23, |x, y| {
+			Rgba([
+				(x * 7) as u8,
+				(y * 11) as u8,
+				((x * y) % 256) as u8,
+				200 + (x % 56) as u8,
+			])
+		}))
+	}
+
+	/// Asserts that `operation` gives the same result on the GPU and the CPU, to within rounding.
+	fn assert_matches_cpu(gpu: &Gpu, operation: Operation, image: DynamicImage) {
+		// The GPU follows the image crate, while fast_image_resize resamples 8-bit images in
+		// integers, which differs by up to a dozen levels at sharp edges
+		let tolerance = match operation {
+			Operation::Resize(_) | Operation::Scale(_) if cfg!(feature = "fast-resize") => 12.0,
+			_ => 1.001,
+		};
+		let expected = operation.get_process().process(image.clone()).unwrap();
+		let actual = gpu.process(&operation, image).unwrap();
+
+		assert_eq!(expected.color(), actual.color(), "{operation:?}");
+		assert_eq!(expected.dimensions(), actual.dimensions(), "{operation:?}");
+		let (expected, actual) = (expected.to_rgba32f(), actual.to_rgba32f());
+		let differences = expected.iter().zip(actual.iter());
+		let difference = differences.fold(0.0, |max, (a, b)| (a - b).abs().max(max));
+		assert!(
+			difference * 255.0 <= tolerance,
+			"{operation:?} differs by {difference}"
+		);
+	}
+
+	fn pixels(pixels: u32) -> Option<Unit> {
+		Some(Unit::Pixel(PixelUnit::from(pixels)))
+	}
+
+	#[test]
+	fn matches_cpu() {
+		// Adapters aren't available everywhere tests run
+		let Some(gpu) = Gpu::shared() else {
+			return;
+		};
+
+		for filter in [
+			FilterType::Nearest,
+			FilterType::Triangle,
+			FilterType::CatmullRom,
+			FilterType::Gaussian,
+			FilterType::Lanczos3,
+		] {
+			for (width, height, crop_mode) in [
+				(15, 40, CropMode::Exact),
+				(80, 9, CropMode::Exact),
+				(20, 20, CropMode::Preserve),
+			] {
+				let resize = Resize {
+					width: pixels(width),
+					height: pixels(height),
+					filter,
+					crop_mode,
+				};
+				assert_matches_cpu(gpu, Operation::Resize(resize), image());
+			}
+			let scale = Scale {
+				factor: 0.3,
+				filter,
+			};
+			assert_matches_cpu(gpu, Operation::Scale(scale), image());
+		}
+
+		for operation in [
+			Operation::Blur(Blur { sigma: 2.5 }),
+			Operation::Blur(Blur { sigma: 0.0 }),
+			Operation::Convolve(Convolve {
+				kernel: Kernel::Preset(KernelPreset::Emboss),
+				divisor: None,
+				offset: 128.0,
+			}),
+			Operation::AdjustBrightness(AdjustBrightness::Brighten(40)),
+			Operation::AdjustBrightness(AdjustBrightness::Darken(300)),
+			Operation::Invert(Invert {}),
+			Operation::Saturation(Saturation { percentage: 60.0 }),
+			Operation::Saturation(Saturation { percentage: -100.0 }),
+			Operation::Sepia(Sepia { intensity: 0.7 }),
+		] {
+			assert_matches_cpu(gpu, operation, image());
+		}
+	}
+
+	#[test]
+	fn keeps_color_types() {
+		let Some(gpu) = Gpu::shared() else {
+			return;
+		};
+
+		let gray = DynamicImage::ImageLuma8(GrayImage::from_fn(19, 12, |x, y| {
+			Luma([(x * 13 + y * 5) as u8])
+		}));
+		for image in [gray.clone(), DynamicImage::ImageRgb16(gray.to_rgb16())] {
+			assert_matches_cpu(gpu, Operation::Blur(Blur { sigma: 1.5 }), image.clone());
+			assert_matches_cpu(gpu, Operation::Invert(Invert {}), image.clone());
+			let saturation = Saturation { percentage: 50.0 };
+			assert_matches_cpu(gpu, Operation::Saturation(saturation), image.clone());
+			// Gray images become RGB for toning, as they do on the CPU
+			assert_matches_cpu(gpu, Operation::Sepia(Sepia { intensity: 1.0 }), image);
+		}
+	}
+
+	#[test]
+	fn falls_back_to_cpu() {
+		let Some(gpu) = Gpu::shared() else {
+			return;
+		};
+
+		let fill = Operation::Resize(Resize {
+			width: pixels(10),
+			height: pixels(30),
+			filter: FilterType::Triangle,
+			crop_mode: CropMode::Fill,
+		});
+		assert_matches_cpu(gpu, fill, image());
+		assert_matches_cpu(
+			gpu,
+			Operation::Invert(Invert {}),
+			image().to_rgba32f().into(),
+		);
+
+		let invalid = Operation::Sepia(Sepia { intensity: 2.0 });
+		assert!(gpu.process(&invalid, image()).is_err());
+	}
+}
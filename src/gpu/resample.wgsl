@@ -0,0 +1,50 @@
+// One pass of a separable resample. Each output pixel sums the input pixels in its span along a
+// single axis, multiplied by the span's weights.
+
+struct Params {
+	input_width: u32,
+	output_width: u32,
+	output_height: u32,
+	// 1 to sample along columns, 0 along rows
+	vertical: u32,
+	// 1 to clamp the sums to 0–1, in the last pass
+	clamp_output: u32,
+}
+
+struct Span {
+	start: u32,
+	count: u32,
+	// Index of the first weight of the span
+	offset: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> input: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> output: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read> spans: array<Span>;
+@group(0) @binding(4) var<storage, read> weights: array<f32>;
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+	if id.x >= params.output_width || id.y >= params.output_height {
+		return;
+	}
+
+	var sum = vec4<f32>(0.0);
+	if params.vertical == 1u {
+		let span = spans[id.y];
+		for (var i = 0u; i < span.count; i++) {
+			sum += input[(span.start + i) * params.input_width + id.x] * weights[span.offset + i];
+		}
+	} else {
+		let span = spans[id.x];
+		for (var i = 0u; i < span.count; i++) {
+			sum += input[id.y * params.input_width + span.start + i] * weights[span.offset + i];
+		}
+	}
+
+	if params.clamp_output == 1u {
+		sum = clamp(sum, vec4<f32>(0.0), vec4<f32>(1.0));
+	}
+	output[id.y * params.output_width + id.x] = sum;
+}
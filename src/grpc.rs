@@ -0,0 +1,270 @@
+//! A gRPC service processing images sent with a structured pipeline, enabled with the `grpc`
+//! feature. The API is defined in `proto/imageless.proto`.
+//!
+//! ```ignore
+//! #[tokio::main]
+//! async fn main() -> Result<(), tonic::transport::Error> {
+//!     imageless::grpc::serve("0.0.0.0:50051".parse().unwrap()).await
+//! }
+//! ```
+
+use crate::{
+	operations::{
+		AdjustBrightness, Blur, Crop, CropMode, CropOrigin, FilterType, Grayscale, Resize, Stats,
+	},
+	Coordinate, ImageOutputFormat, Operation, PercentageUnit, Pipeline, PixelUnit, Unit,
+};
+use std::net::SocketAddr;
+use tonic::{Request, Response, Status};
+
+/// Types generated from `proto/imageless.proto`.
+#[allow(clippy::all)]
+pub mod proto {
+	tonic::include_proto!("imageless");
+}
+
+use proto::{
+	imageless_server::{Imageless, ImagelessServer},
+	ProcessRequest, ProcessResponse,
+};
+
+/// Implementation of the `Imageless` service.
+#[derive(Debug, Default)]
+pub struct ImagelessService;
+
+impl ImagelessService {
+	pub fn into_server(self) -> ImagelessServer<Self> {
+		ImagelessServer::new(self)
+	}
+}
+
+/// Serves the `Imageless` service on `addr`.
+pub async fn serve(addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+	tonic::transport::Server::builder()
+		.add_service(ImagelessService.into_server())
+		.serve(addr)
+		.await
+}
+
+#[tonic::async_trait]
+impl Imageless for ImagelessService {
+	async fn process(
+		&self,
+		request: Request<ProcessRequest>,
+	) -> Result<Response<ProcessResponse>, Status> {
+		let request = request.into_inner();
+		let pipeline = Pipeline::try_from(
+			request
+				.pipeline
+				.ok_or_else(|| Status::invalid_argument("Missing pipeline"))?,
+		)?;
+
+		let mime_type = pipeline.out_format.mime_type().to_string();
+		let image = tokio::task::spawn_blocking(move || pipeline.process_bytes(&request.image))
+			.await
+			.map_err(|err| Status::internal(err.to_string()))?
+			.map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+		Ok(Response::new(ProcessResponse { image, mime_type }))
+	}
+}
+
+impl TryFrom<proto::Pipeline> for Pipeline {
+	type Error = Status;
+
+	fn try_from(pipeline: proto::Pipeline) -> Result<Self, Self::Error> {
+		let out_format = match ImageOutputFormat::from_extension(&pipeline.out_format) {
+			Some(ImageOutputFormat::Jpeg { quality }) => ImageOutputFormat::Jpeg {
+				quality: match pipeline.quality {
+					Some(value) => u8::try_from(value)
+						.map_err(|_| Status::invalid_argument("Quality must be at most 255"))?,
+					None => quality,
+				},
+			},
+			Some(out_format) => out_format,
+			None => {
+				return Err(Status::invalid_argument(format!(
+					"Unknown output format `{}`",
+					pipeline.out_format
+				)))
+			}
+		};
+
+		let operations = pipeline
+			.operations
+			.into_iter()
+			.map(Operation::try_from)
+			.collect::<Result<_, _>>()?;
+
+		Ok(Pipeline {
+			out_format,
+			auto_orient: pipeline.auto_orient,
+			keep_metadata: pipeline.keep_metadata,
+			operations,
+		})
+	}
+}
+
+impl TryFrom<proto::Operation> for Operation {
+	type Error = Status;
+
+	fn try_from(operation: proto::Operation) -> Result<Self, Self::Error> {
+		use proto::operation::Operation as Kind;
+
+		let operation = match operation
+			.operation
+			.ok_or_else(|| Status::invalid_argument("Missing operation"))?
+		{
+			Kind::AdjustBrightness(adjust) => {
+				let value = u16::try_from(adjust.amount.unsigned_abs())
+					.map_err(|_| Status::invalid_argument("Brightness amount is out of range"))?;
+				Operation::AdjustBrightness(if adjust.amount < 0 {
+					AdjustBrightness::Darken(value)
+				} else {
+					AdjustBrightness::Brighten(value)
+				})
+			}
+			Kind::Blur(blur) => Operation::Blur(Blur { sigma: blur.sigma }),
+			Kind::Crop(crop) => {
+				let to = coordinate(crop.to)?;
+				Operation::Crop(Crop {
+					from: coordinate(crop.from)?,
+					to: match crop.origin() {
+						proto::crop::Origin::Minimum => CropOrigin::Minimum(to),
+						proto::crop::Origin::Maximum => CropOrigin::Maximum(to),
+						proto::crop::Origin::CropStart => CropOrigin::CropStart(to),
+					},
+				})
+			}
+			Kind::Grayscale(_) => Operation::Grayscale(Grayscale {}),
+			Kind::Resize(resize) => Operation::Resize(Resize {
+				width: resize.width.map(|width| length(Some(width))).transpose()?,
+				height: resize
+					.height
+					.map(|height| length(Some(height)))
+					.transpose()?,
+				filter: match resize.filter() {
+					proto::resize::Filter::Nearest => FilterType::Nearest,
+					proto::resize::Filter::Triangle => FilterType::Triangle,
+					proto::resize::Filter::CatmullRom => FilterType::CatmullRom,
+					proto::resize::Filter::Gaussian => FilterType::Gaussian,
+					proto::resize::Filter::Lanczos3 => FilterType::Lanczos3,
+				},
+				crop_mode: match resize.crop_mode() {
+					proto::resize::CropMode::Preserve => CropMode::Preserve,
+					proto::resize::CropMode::Fill => CropMode::Fill,
+					proto::resize::CropMode::Exact => CropMode::Exact,
+					proto::resize::CropMode::ShrinkOnly => CropMode::ShrinkOnly,
+					proto::resize::CropMode::EnlargeOnly => CropMode::EnlargeOnly,
+				},
+			}),
+			Kind::Stats(stats) => Operation::Stats(Stats { label: stats.label }),
+			Kind::Json(json) => serde_json::from_str(&json)
+				.map_err(|err| Status::invalid_argument(format!("Invalid operation: {err}")))?,
+		};
+
+		Ok(operation)
+	}
+}
+
+fn coordinate(coordinate: Option<proto::Coordinate>) -> Result<Coordinate, Status> {
+	let coordinate =
+		coordinate.ok_or_else(|| Status::invalid_argument("Missing crop coordinate"))?;
+
+	Ok(Coordinate {
+		x: length(coordinate.x)?,
+		y: length(coordinate.y)?,
+	})
+}
+
+fn length(length: Option<proto::Length>) -> Result<Unit, Status> {
+	use proto::length::Unit as Kind;
+
+	match length.and_then(|length| length.unit) {
+		Some(Kind::Pixels(pixels)) => Ok(Unit::Pixel(PixelUnit::from(pixels))),
+		Some(Kind::FromEnd(pixels)) => Ok(Unit::FromEnd(PixelUnit::from(pixels))),
+		Some(Kind::Percentage(percentage)) => PercentageUnit::try_from(percentage)
+			.map(Unit::Percentage)
+			.map_err(|err| Status::invalid_argument(err.to_string())),
+		None => Err(Status::invalid_argument("Missing length")),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		proto::{self, imageless_server::Imageless, length::Unit, operation::Operation as Kind},
+		ImagelessService, ProcessRequest,
+	};
+	use crate::{ImageOutputFormat, Pipeline};
+	use image::{DynamicImage, GenericImageView, RgbImage};
+	use std::io::Cursor;
+	use tonic::{Code, Request};
+
+	fn length(unit: Unit) -> Option<proto::Length> {
+		Some(proto::Length { unit: Some(unit) })
+	}
+
+	#[test]
+	fn processes_requests() {
+		let mut png = Cursor::new(Vec::new());
+		DynamicImage::ImageRgb8(RgbImage::new(8, 6))
+			.write_to(&mut png, image::ImageOutputFormat::Png)
+			.unwrap();
+
+		let resize = proto::Resize {
+			width: length(Unit::Pixels(4)),
+			height: length(Unit::Percentage(0.5)),
+			filter: proto::resize::Filter::Triangle.into(),
+			crop_mode: proto::resize::CropMode::Preserve.into(),
+		};
+		let request = ProcessRequest {
+			image: png.into_inner(),
+			pipeline: Some(proto::Pipeline {
+				out_format: "jpg".to_string(),
+				quality: Some(70),
+				operations: vec![
+					proto::Operation {
+						operation: Some(Kind::Resize(resize)),
+					},
+					proto::Operation {
+						operation: Some(Kind::Json(r#"{"grayscale":{}}"#.to_string())),
+					},
+				],
+				auto_orient: false,
+				keep_metadata: false,
+			}),
+		};
+
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.unwrap();
+		let response = runtime
+			.block_on(ImagelessService.process(Request::new(request)))
+			.unwrap()
+			.into_inner();
+		assert_eq!("image/jpeg", response.mime_type);
+		let image = image::load_from_memory(&response.image).unwrap();
+		assert_eq!((4, 3), image.dimensions());
+	}
+
+	#[test]
+	fn rejects_invalid_pipelines() {
+		let pipeline = |out_format: &str, quality| proto::Pipeline {
+			out_format: out_format.to_string(),
+			quality,
+			..Default::default()
+		};
+
+		for invalid in [pipeline("doc", None), pipeline("jpg", Some(256))] {
+			let error = Pipeline::try_from(invalid).unwrap_err();
+			assert_eq!(Code::InvalidArgument, error.code());
+		}
+		assert_eq!(
+			ImageOutputFormat::Jpeg { quality: 80 },
+			Pipeline::try_from(pipeline("jpeg", None))
+				.unwrap()
+				.out_format
+		);
+	}
+}
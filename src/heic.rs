@@ -0,0 +1,316 @@
+//! Decodes HEIC and HEIF sources, enabled with the `heic` feature.
+//!
+//! Sources in a HEIF container, such as photos from iPhones, are decoded with libheif instead of
+//! `image`. libheif is loaded from the system libraries at runtime, e.g. `libheif.so.1` on Linux.
+//! It applies the rotation and mirroring stored in the container while decoding, so the EXIF
+//! orientation of these sources is not applied again.
+
+use crate::{Error, ProcessOptions};
+use image::{
+	error::{DecodingError, ImageFormatHint},
+	DynamicImage, ImageError, RgbImage, RgbaImage,
+};
+use libloading::Library;
+use std::{
+	ffi::{c_char, c_int, c_void, CStr, OsString},
+	ptr, slice,
+	sync::OnceLock,
+};
+
+/// Brands of the `ftyp` box which mark a HEIF image, rather than another format in the same
+/// container such as AVIF
+const BRANDS: [&[u8]; 10] = [
+	b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"hevm", b"hevs", b"mif1", b"msf1",
+];
+
+const COLORSPACE_RGB: c_int = 1;
+const CHROMA_INTERLEAVED_RGB: c_int = 10;
+const CHROMA_INTERLEAVED_RGBA: c_int = 11;
+const CHANNEL_INTERLEAVED: c_int = 10;
+
+/// `struct heif_error`, returned by most libheif functions. A code of 0 is success.
+#[repr(C)]
+struct HeifError {
+	code: c_int,
+	subcode: c_int,
+	message: *const c_char,
+}
+
+type Release = unsafe extern "C" fn(*mut c_void);
+
+/// The libheif functions used to decode an image.
+struct LibHeif {
+	context_alloc: unsafe extern "C" fn() -> *mut c_void,
+	context_free: Release,
+	context_read_from_memory_without_copy:
+		unsafe extern "C" fn(*mut c_void, *const c_void, usize, *const c_void) -> HeifError,
+	context_get_primary_image_handle:
+		unsafe extern "C" fn(*mut c_void, *mut *mut c_void) -> HeifError,
+	image_handle_release: Release,
+	image_handle_get_width: unsafe extern "C" fn(*const c_void) -> c_int,
+	image_handle_get_height: unsafe extern "C" fn(*const c_void) -> c_int,
+	image_handle_has_alpha_channel: unsafe extern "C" fn(*const c_void) -> c_int,
+	decode_image: unsafe extern "C" fn(
+		*const c_void,
+		*mut *mut c_void,
+		c_int,
+		c_int,
+		*const c_void,
+	) -> HeifError,
+	image_release: Release,
+	image_get_width: unsafe extern "C" fn(*const c_void, c_int) -> c_int,
+	image_get_height: unsafe extern "C" fn(*const c_void, c_int) -> c_int,
+	image_get_plane_readonly: unsafe extern "C" fn(*const c_void, c_int, *mut c_int) -> *const u8,
+	// Keeps the functions above loaded
+	_library: Library,
+}
+
+impl LibHeif {
+	/// Loads libheif, or returns why it can't be loaded. It is only loaded once.
+	fn get() -> Result<&'static Self, Error> {
+		static LIBHEIF: OnceLock<Result<LibHeif, String>> = OnceLock::new();
+		LIBHEIF
+			.get_or_init(|| {
+				// SAFETY: libheif has no initialisation routines which are unsound to run here,
+				// and the symbols are declared with their signatures from `heif.h`
+				unsafe { Self::load() }.map_err(|error| error.to_string())
+			})
+			.as_ref()
+			.map_err(|error| heif_error(format!("Cannot load libheif: {error}")))
+	}
+
+	unsafe fn load() -> Result<Self, libloading::Error> {
+		let names: [OsString; 3] = [
+			"libheif.so.1".into(),
+			"libheif.1.dylib".into(),
+			libloading::library_filename("heif"),
+		];
+		let mut library = Library::new(&names[0]);
+		for name in &names[1..] {
+			if library.is_err() {
+				library = Library::new(name);
+			}
+		}
+		let library = library?;
+
+		// libheif 1.13 and later want to be initialised, which earlier versions do implicitly
+		if let Ok(init) =
+			library.get::<unsafe extern "C" fn(*const c_void) -> HeifError>(b"heif_init\0")
+		{
+			init(ptr::null());
+		}
+
+		Ok(Self {
+			context_alloc: *library.get(b"heif_context_alloc\0")?,
+			context_free: *library.get(b"heif_context_free\0")?,
+			context_read_from_memory_without_copy: *library
+				.get(b"heif_context_read_from_memory_without_copy\0")?,
+			context_get_primary_image_handle: *library
+				.get(b"heif_context_get_primary_image_handle\0")?,
+			image_handle_release: *library.get(b"heif_image_handle_release\0")?,
+			image_handle_get_width: *library.get(b"heif_image_handle_get_width\0")?,
+			image_handle_get_height: *library.get(b"heif_image_handle_get_height\0")?,
+			image_handle_has_alpha_channel: *library
+				.get(b"heif_image_handle_has_alpha_channel\0")?,
+			decode_image: *library.get(b"heif_decode_image\0")?,
+			image_release: *library.get(b"heif_image_release\0")?,
+			image_get_width: *library.get(b"heif_image_get_width\0")?,
+			image_get_height: *library.get(b"heif_image_get_height\0")?,
+			image_get_plane_readonly: *library.get(b"heif_image_get_plane_readonly\0")?,
+			_library: library,
+		})
+	}
+}
+
+/// A libheif object, released when dropped.
+struct Owned {
+	ptr: *mut c_void,
+	release: Release,
+}
+
+impl Owned {
+	fn new(ptr: *mut c_void, release: Release) -> Result<Self, Error> {
+		match ptr.is_null() {
+			true => Err(heif_error("libheif returned no object".to_string())),
+			false => Ok(Self { ptr, release }),
+		}
+	}
+}
+
+impl Drop for Owned {
+	fn drop(&mut self) {
+		// SAFETY: the object came from libheif and is released once
+		unsafe { (self.release)(self.ptr) }
+	}
+}
+
+/// Whether `bytes` start with the `ftyp` box of a HEIF image.
+pub fn is_heif(bytes: &[u8]) -> bool {
+	bytes.len() >= 12 && &bytes[4..8] == b"ftyp" && BRANDS.contains(&&bytes[8..12])
+}
+
+/// Decodes the primary image of the HEIF in `bytes` as 8-bit RGB, or RGBA when it has an alpha
+/// channel, after checking its size against the limits of `options`.
+pub(crate) fn decode(bytes: &[u8], options: &ProcessOptions) -> Result<DynamicImage, Error> {
+	let heif = LibHeif::get()?;
+
+	// SAFETY: the functions are called as documented in `heif.h`. `bytes` outlives the context
+	// which reads it without copying, and each object is released before the one it came from.
+	unsafe {
+		let context = Owned::new((heif.context_alloc)(), heif.context_free)?;
+		check((heif.context_read_from_memory_without_copy)(
+			context.ptr,
+			bytes.as_ptr().cast(),
+			bytes.len(),
+			ptr::null(),
+		))?;
+
+		let mut handle = ptr::null_mut();
+		check((heif.context_get_primary_image_handle)(
+			context.ptr,
+			&mut handle,
+		))?;
+		let handle = Owned::new(handle, heif.image_handle_release)?;
+
+		let (width, height) = dimensions(
+			(heif.image_handle_get_width)(handle.ptr),
+			(heif.image_handle_get_height)(handle.ptr),
+		)?;
+		options.limits.check(width, height)?;
+		let alpha = (heif.image_handle_has_alpha_channel)(handle.ptr) != 0;
+		let channels = if alpha { 4 } else { 3 };
+		options.check_memory(|| width as u64 * height as u64 * channels as u64)?;
+
+		let chroma = match alpha {
+			true => CHROMA_INTERLEAVED_RGBA,
+			false => CHROMA_INTERLEAVED_RGB,
+		};
+		let mut image = ptr::null_mut();
+		check((heif.decode_image)(
+			handle.ptr,
+			&mut image,
+			COLORSPACE_RGB,
+			chroma,
+			ptr::null(),
+		))?;
+		let image = Owned::new(image, heif.image_release)?;
+
+		// The decoded image is turned upright, so it can be taller than it is wide
+		let (width, height) = dimensions(
+			(heif.image_get_width)(image.ptr, CHANNEL_INTERLEAVED),
+			(heif.image_get_height)(image.ptr, CHANNEL_INTERLEAVED),
+		)?;
+		let mut stride = 0;
+		let plane = (heif.image_get_plane_readonly)(image.ptr, CHANNEL_INTERLEAVED, &mut stride);
+		let row = width as usize * channels;
+		if plane.is_null() || (stride as usize) < row {
+			return Err(heif_error(
+				"libheif decoded no interleaved RGB plane".to_string(),
+			));
+		}
+
+		let mut pixels = Vec::with_capacity(row * height as usize);
+		for y in 0..height as usize {
+			pixels.extend_from_slice(slice::from_raw_parts(plane.add(y * stride as usize), row));
+		}
+
+		Ok(match alpha {
+			true => RgbaImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+			false => RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+		}
+		.expect("the buffer holds a row for each line"))
+	}
+}
+
+fn dimensions(width: c_int, height: c_int) -> Result<(u32, u32), Error> {
+	match (u32::try_from(width), u32::try_from(height)) {
+		(Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+		_ => Err(heif_error(format!(
+			"Invalid HEIF dimensions {width}x{height}"
+		))),
+	}
+}
+
+/// Turns a failed `heif_error` into an error with its message.
+///
+/// # Safety
+///
+/// The message of `error` must be NULL or point to a NUL-terminated string.
+unsafe fn check(error: HeifError) -> Result<(), Error> {
+	if error.code == 0 {
+		return Ok(());
+	}
+
+	let message = match error.message.is_null() {
+		true => format!("libheif error {}.{}", error.code, error.subcode),
+		false => CStr::from_ptr(error.message).to_string_lossy().into_owned(),
+	};
+	Err(heif_error(format!("Cannot decode HEIF: {message}")))
+}
+
+fn heif_error(message: String) -> Error {
+	ImageError::Decoding(DecodingError::new(
+		ImageFormatHint::Name("HEIF".to_string()),
+		message,
+	))
+	.into()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{decode, is_heif};
+	use crate::{process_file, DecodeLimits, Error, ProcessOptions};
+	use image::{GenericImageView, Rgba};
+
+	static RGB: &[u8] = include_bytes!("../tests/images/gradient.heic");
+	static ALPHA: &[u8] = include_bytes!("../tests/images/gradient-alpha.heic");
+
+	#[test]
+	fn detects_heif() {
+		assert!(is_heif(RGB));
+		assert!(is_heif(b"\0\0\0\x18ftypmif1\0\0\0\0mif1heic"));
+		assert!(!is_heif(b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf"));
+		assert!(!is_heif(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+		assert!(!is_heif(b"\0\0\0\x18ftyp"));
+	}
+
+	#[test]
+	#[ignore = "requires libheif"]
+	fn decodes_heic() {
+		// Red on the left and blue on the right, with green increasing downwards
+		let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/images/gradient.heic");
+		let image = process_file(path, Vec::new()).unwrap();
+		assert_eq!((32, 24), image.dimensions());
+		assert!(!image.color().has_alpha());
+		let close = |actual: Rgba<u8>, expected: [u8; 3]| {
+			actual.0[..3]
+				.iter()
+				.zip(expected)
+				.all(|(&actual, expected)| actual.abs_diff(expected) <= 12)
+		};
+		assert!(close(image.get_pixel(2, 2), [220, 22, 30]));
+		assert!(close(image.get_pixel(29, 21), [30, 232, 220]));
+
+		let image = decode(ALPHA, &ProcessOptions::default()).unwrap();
+		assert!(image.color().has_alpha());
+		assert_eq!(255, image.get_pixel(16, 2).0[3]);
+		assert_eq!(0, image.get_pixel(16, 21).0[3]);
+	}
+
+	#[test]
+	#[ignore = "requires libheif"]
+	fn checks_limits_before_decoding() {
+		let options = ProcessOptions {
+			limits: DecodeLimits {
+				max_width: Some(16),
+				..DecodeLimits::unlimited()
+			},
+			..Default::default()
+		};
+		assert!(matches!(
+			decode(RGB, &options),
+			Err(Error::ImageError(image::ImageError::Limits(_)))
+		));
+		assert!(decode(&RGB[..RGB.len() / 2], &ProcessOptions::default()).is_err());
+	}
+}
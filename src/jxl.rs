@@ -0,0 +1,557 @@
+//! Decodes and encodes JPEG XL, enabled with the `jxl` feature.
+//!
+//! libjxl is loaded from the system libraries at runtime, e.g. `libjxl.so.0.11` on Linux. Sources
+//! are decoded at the bit depth they were stored with, turned upright by libjxl, and only the
+//! first frame of an animation is kept.
+
+use crate::{Error, ProcessOptions};
+use image::{
+	error::{DecodingError, EncodingError, ImageFormatHint},
+	DynamicImage, ImageBuffer, ImageError, Pixel, Primitive,
+};
+use libloading::Library;
+use std::{
+	ffi::{c_int, c_void, OsString},
+	io::Write,
+	mem::MaybeUninit,
+	ptr,
+	sync::OnceLock,
+};
+
+/// Signature of a bare codestream
+const CODESTREAM: &[u8] = &[0xff, 0x0a];
+/// Signature box of the ISO BMFF container
+const CONTAINER: &[u8] = &[
+	0x00, 0x00, 0x00, 0x0c, b'J', b'X', b'L', b' ', 0x0d, 0x0a, 0x87, 0x0a,
+];
+
+/// Versions of libjxl with the API used here, newest first
+const VERSIONS: [&str; 5] = ["0.11", "0.10", "0.9", "0.8", "0.7"];
+
+const DEC_SUCCESS: c_int = 0;
+const DEC_NEED_IMAGE_OUT_BUFFER: c_int = 5;
+const DEC_BASIC_INFO: c_int = 0x40;
+const DEC_FULL_IMAGE: c_int = 0x1000;
+
+const ENC_SUCCESS: c_int = 0;
+const ENC_NEED_MORE_OUTPUT: c_int = 2;
+const ENC_FRAME_SETTING_EFFORT: c_int = 0;
+
+const TYPE_FLOAT: c_int = 0;
+const TYPE_UINT8: c_int = 2;
+const TYPE_UINT16: c_int = 3;
+
+/// `JxlBasicInfo`, the header of an image.
+#[repr(C)]
+struct BasicInfo {
+	have_container: c_int,
+	xsize: u32,
+	ysize: u32,
+	bits_per_sample: u32,
+	exponent_bits_per_sample: u32,
+	intensity_target: f32,
+	min_nits: f32,
+	relative_to_max_display: c_int,
+	linear_below: f32,
+	uses_original_profile: c_int,
+	have_preview: c_int,
+	have_animation: c_int,
+	orientation: c_int,
+	num_color_channels: u32,
+	num_extra_channels: u32,
+	alpha_bits: u32,
+	alpha_exponent_bits: u32,
+	alpha_premultiplied: c_int,
+	preview: [u32; 2],
+	animation: [u32; 4],
+	intrinsic_xsize: u32,
+	intrinsic_ysize: u32,
+	padding: [u8; 100],
+}
+
+/// `JxlPixelFormat`, the layout of pixels passed to and from libjxl.
+#[repr(C)]
+struct PixelFormat {
+	num_channels: u32,
+	data_type: c_int,
+	endianness: c_int,
+	align: usize,
+}
+
+/// `JxlColorEncoding`, set to sRGB by libjxl.
+#[repr(C)]
+struct ColorEncoding {
+	color_space: c_int,
+	white_point: c_int,
+	white_point_xy: [f64; 2],
+	primaries: c_int,
+	primaries_red_xy: [f64; 2],
+	primaries_green_xy: [f64; 2],
+	primaries_blue_xy: [f64; 2],
+	transfer_function: c_int,
+	gamma: f64,
+	rendering_intent: c_int,
+}
+
+type Destroy = unsafe extern "C" fn(*mut c_void);
+
+/// The libjxl functions used to decode and encode an image.
+struct LibJxl {
+	decoder_create: unsafe extern "C" fn(*const c_void) -> *mut c_void,
+	decoder_destroy: Destroy,
+	decoder_subscribe_events: unsafe extern "C" fn(*mut c_void, c_int) -> c_int,
+	decoder_set_input: unsafe extern "C" fn(*mut c_void, *const u8, usize) -> c_int,
+	decoder_close_input: unsafe extern "C" fn(*mut c_void),
+	decoder_process_input: unsafe extern "C" fn(*mut c_void) -> c_int,
+	decoder_get_basic_info: unsafe extern "C" fn(*const c_void, *mut BasicInfo) -> c_int,
+	decoder_image_out_buffer_size:
+		unsafe extern "C" fn(*const c_void, *const PixelFormat, *mut usize) -> c_int,
+	decoder_set_image_out_buffer:
+		unsafe extern "C" fn(*mut c_void, *const PixelFormat, *mut c_void, usize) -> c_int,
+	encoder_create: unsafe extern "C" fn(*const c_void) -> *mut c_void,
+	encoder_destroy: Destroy,
+	encoder_init_basic_info: unsafe extern "C" fn(*mut BasicInfo),
+	encoder_set_basic_info: unsafe extern "C" fn(*mut c_void, *const BasicInfo) -> c_int,
+	color_encoding_set_to_srgb: unsafe extern "C" fn(*mut ColorEncoding, c_int),
+	encoder_set_color_encoding: unsafe extern "C" fn(*mut c_void, *const ColorEncoding) -> c_int,
+	encoder_frame_settings_create: unsafe extern "C" fn(*mut c_void, *const c_void) -> *mut c_void,
+	encoder_set_frame_distance: unsafe extern "C" fn(*mut c_void, f32) -> c_int,
+	encoder_set_frame_lossless: unsafe extern "C" fn(*mut c_void, c_int) -> c_int,
+	encoder_frame_settings_set_option: unsafe extern "C" fn(*mut c_void, c_int, i64) -> c_int,
+	encoder_add_image_frame:
+		unsafe extern "C" fn(*const c_void, *const PixelFormat, *const c_void, usize) -> c_int,
+	encoder_close_input: unsafe extern "C" fn(*mut c_void),
+	encoder_process_output: unsafe extern "C" fn(*mut c_void, *mut *mut u8, *mut usize) -> c_int,
+	// Keeps the functions above loaded
+	_library: Library,
+}
+
+impl LibJxl {
+	/// Loads libjxl, or returns why it can't be loaded. It is only loaded once.
+	fn get() -> Result<&'static Self, String> {
+		static LIBJXL: OnceLock<Result<LibJxl, String>> = OnceLock::new();
+		LIBJXL
+			.get_or_init(|| {
+				// SAFETY: libjxl has no initialisation routines, and the symbols are declared with
+				// their signatures from `jxl/decode.h` and `jxl/encode.h`
+				unsafe { Self::load() }.map_err(|error| format!("Cannot load libjxl: {error}"))
+			})
+			.as_ref()
+			.map_err(Clone::clone)
+	}
+
+	unsafe fn load() -> Result<Self, libloading::Error> {
+		let mut names: Vec<OsString> = VERSIONS
+			.iter()
+			.flat_map(|version| {
+				[
+					format!("libjxl.so.{version}").into(),
+					format!("libjxl.{version}.dylib").into(),
+				]
+			})
+			.collect();
+		names.push(libloading::library_filename("jxl"));
+
+		let mut library = Library::new(&names[0]);
+		for name in &names[1..] {
+			if library.is_err() {
+				library = Library::new(name);
+			}
+		}
+		let library = library?;
+
+		Ok(Self {
+			decoder_create: *library.get(b"JxlDecoderCreate\0")?,
+			decoder_destroy: *library.get(b"JxlDecoderDestroy\0")?,
+			decoder_subscribe_events: *library.get(b"JxlDecoderSubscribeEvents\0")?,
+			decoder_set_input: *library.get(b"JxlDecoderSetInput\0")?,
+			decoder_close_input: *library.get(b"JxlDecoderCloseInput\0")?,
+			decoder_process_input: *library.get(b"JxlDecoderProcessInput\0")?,
+			decoder_get_basic_info: *library.get(b"JxlDecoderGetBasicInfo\0")?,
+			decoder_image_out_buffer_size: *library.get(b"JxlDecoderImageOutBufferSize\0")?,
+			decoder_set_image_out_buffer: *library.get(b"JxlDecoderSetImageOutBuffer\0")?,
+			encoder_create: *library.get(b"JxlEncoderCreate\0")?,
+			encoder_destroy: *library.get(b"JxlEncoderDestroy\0")?,
+			encoder_init_basic_info: *library.get(b"JxlEncoderInitBasicInfo\0")?,
+			encoder_set_basic_info: *library.get(b"JxlEncoderSetBasicInfo\0")?,
+			color_encoding_set_to_srgb: *library.get(b"JxlColorEncodingSetToSRGB\0")?,
+			encoder_set_color_encoding: *library.get(b"JxlEncoderSetColorEncoding\0")?,
+			encoder_frame_settings_create: *library.get(b"JxlEncoderFrameSettingsCreate\0")?,
+			encoder_set_frame_distance: *library.get(b"JxlEncoderSetFrameDistance\0")?,
+			encoder_set_frame_lossless: *library.get(b"JxlEncoderSetFrameLossless\0")?,
+			encoder_frame_settings_set_option: *library
+				.get(b"JxlEncoderFrameSettingsSetOption\0")?,
+			encoder_add_image_frame: *library.get(b"JxlEncoderAddImageFrame\0")?,
+			encoder_close_input: *library.get(b"JxlEncoderCloseInput\0")?,
+			encoder_process_output: *library.get(b"JxlEncoderProcessOutput\0")?,
+			_library: library,
+		})
+	}
+}
+
+/// A libjxl decoder or encoder, destroyed when dropped.
+struct Owned {
+	ptr: *mut c_void,
+	destroy: Destroy,
+}
+
+impl Owned {
+	fn new(ptr: *mut c_void, destroy: Destroy) -> Option<Self> {
+		(!ptr.is_null()).then_some(Self { ptr, destroy })
+	}
+}
+
+impl Drop for Owned {
+	fn drop(&mut self) {
+		// SAFETY: the object came from libjxl and is destroyed once
+		unsafe { (self.destroy)(self.ptr) }
+	}
+}
+
+/// Whether `bytes` start with a JPEG XL codestream or container.
+pub fn is_jxl(bytes: &[u8]) -> bool {
+	bytes.starts_with(CODESTREAM) || bytes.starts_with(CONTAINER)
+}
+
+/// Decodes the JPEG XL in `bytes`, after checking its size against the limits of `options`.
+pub(crate) fn decode(bytes: &[u8], options: &ProcessOptions) -> Result<DynamicImage, Error> {
+	let jxl = LibJxl::get().map_err(decoding_error)?;
+	let failed = || decoding_error("Cannot decode JPEG XL".to_string());
+
+	// SAFETY: the functions are called as documented in `jxl/decode.h`. `bytes` outlives the
+	// decoder, and so does the output buffer set while decoding.
+	unsafe {
+		let decoder = Owned::new((jxl.decoder_create)(ptr::null()), jxl.decoder_destroy)
+			.ok_or_else(failed)?;
+		if (jxl.decoder_subscribe_events)(decoder.ptr, DEC_BASIC_INFO | DEC_FULL_IMAGE)
+			!= DEC_SUCCESS
+			|| (jxl.decoder_set_input)(decoder.ptr, bytes.as_ptr(), bytes.len()) != DEC_SUCCESS
+		{
+			return Err(failed());
+		}
+		(jxl.decoder_close_input)(decoder.ptr);
+
+		if (jxl.decoder_process_input)(decoder.ptr) != DEC_BASIC_INFO {
+			return Err(failed());
+		}
+		let mut info = MaybeUninit::<BasicInfo>::uninit();
+		if (jxl.decoder_get_basic_info)(decoder.ptr, info.as_mut_ptr()) != DEC_SUCCESS {
+			return Err(failed());
+		}
+		let info = info.assume_init();
+
+		// Orientations from 5 on transpose the image
+		let (width, height) = match info.orientation {
+			5.. => (info.ysize, info.xsize),
+			_ => (info.xsize, info.ysize),
+		};
+		options.limits.check(width, height)?;
+
+		let gray = info.num_color_channels == 1;
+		let alpha = info.alpha_bits > 0;
+		let float = info.exponent_bits_per_sample > 0 && !gray;
+		let wide = info.bits_per_sample > 8;
+		let channels = info.num_color_channels + u32::from(alpha);
+		let sample_size = match (float, wide) {
+			(true, _) => 4,
+			(false, true) => 2,
+			(false, false) => 1,
+		};
+		options
+			.check_memory(|| width as u64 * height as u64 * channels as u64 * sample_size as u64)?;
+
+		let format = |data_type| PixelFormat {
+			num_channels: channels,
+			data_type,
+			endianness: 0,
+			align: 0,
+		};
+		let frame = Frame {
+			jxl,
+			decoder: &decoder,
+			width,
+			height,
+		};
+		Ok(match (gray, alpha, float, wide) {
+			(_, false, true, _) => DynamicImage::ImageRgb32F(frame.read(&format(TYPE_FLOAT))?),
+			(_, true, true, _) => DynamicImage::ImageRgba32F(frame.read(&format(TYPE_FLOAT))?),
+			(true, false, _, true) => DynamicImage::ImageLuma16(frame.read(&format(TYPE_UINT16))?),
+			(true, true, _, true) => DynamicImage::ImageLumaA16(frame.read(&format(TYPE_UINT16))?),
+			(false, false, _, true) => DynamicImage::ImageRgb16(frame.read(&format(TYPE_UINT16))?),
+			(false, true, _, true) => DynamicImage::ImageRgba16(frame.read(&format(TYPE_UINT16))?),
+			(true, false, _, false) => DynamicImage::ImageLuma8(frame.read(&format(TYPE_UINT8))?),
+			(true, true, _, false) => DynamicImage::ImageLumaA8(frame.read(&format(TYPE_UINT8))?),
+			(false, false, _, false) => DynamicImage::ImageRgb8(frame.read(&format(TYPE_UINT8))?),
+			(false, true, _, false) => DynamicImage::ImageRgba8(frame.read(&format(TYPE_UINT8))?),
+		})
+	}
+}
+
+/// The first frame of a decoder which has read the basic info of a `width` by `height` image.
+struct Frame<'a> {
+	jxl: &'a LibJxl,
+	decoder: &'a Owned,
+	width: u32,
+	height: u32,
+}
+
+impl Frame<'_> {
+	/// Decodes the frame into pixels of `format`, which must have the channels and sample type
+	/// of `P`.
+	///
+	/// # Safety
+	///
+	/// The decoder must have just returned its basic info.
+	unsafe fn read<P: Pixel>(
+		&self,
+		format: &PixelFormat,
+	) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, Error> {
+		let failed = || decoding_error("Cannot decode JPEG XL".to_string());
+		let (jxl, decoder) = (self.jxl, self.decoder.ptr);
+
+		let len = self.width as usize * self.height as usize * P::CHANNEL_COUNT as usize;
+		let mut samples = vec![P::Subpixel::DEFAULT_MIN_VALUE; len];
+		loop {
+			match (jxl.decoder_process_input)(decoder) {
+				DEC_NEED_IMAGE_OUT_BUFFER => {
+					let size = std::mem::size_of_val(samples.as_slice());
+					let mut needed = 0;
+					if (jxl.decoder_image_out_buffer_size)(decoder, format, &mut needed)
+						!= DEC_SUCCESS || needed != size
+						|| (jxl.decoder_set_image_out_buffer)(
+							decoder,
+							format,
+							samples.as_mut_ptr().cast(),
+							size,
+						) != DEC_SUCCESS
+					{
+						return Err(failed());
+					}
+				}
+				// Later frames of an animation are left undecoded
+				DEC_FULL_IMAGE => break,
+				_ => return Err(failed()),
+			}
+		}
+
+		ImageBuffer::from_raw(self.width, self.height, samples).ok_or_else(failed)
+	}
+}
+
+/// Writes `image` as a JPEG XL. Lossy images are encoded with a `quality` from 0 to 100, where 90
+/// looks the same as the source to most people. `effort` is how hard to try for a smaller file,
+/// from 1 to 9.
+pub(crate) fn encode<W: Write>(
+	writer: &mut W,
+	image: &DynamicImage,
+	quality: f32,
+	effort: u8,
+	lossless: bool,
+) -> Result<(), Error> {
+	if !(0.0..=100.0).contains(&quality) || !(1..=9).contains(&effort) {
+		return Err(encoding_error(format!(
+			"JPEG XL quality must be between 0 and 100 and effort between 1 and 9, got {quality} \
+			 and {effort}"
+		)));
+	}
+	let jxl = LibJxl::get().map_err(encoding_error)?;
+	let failed = || encoding_error("Cannot encode JPEG XL".to_string());
+
+	let color = image.color();
+	let (bits, exponent_bits, data_type) = match color.bytes_per_pixel() / color.channel_count() {
+		1 => (8, 0, TYPE_UINT8),
+		2 => (16, 0, TYPE_UINT16),
+		_ => (32, 8, TYPE_FLOAT),
+	};
+	let gray = color.channel_count() <= 2;
+	let alpha = color.has_alpha();
+	let format = PixelFormat {
+		num_channels: color.channel_count().into(),
+		data_type,
+		endianness: 0,
+		align: 0,
+	};
+	let pixels = image.as_bytes();
+
+	// SAFETY: the functions are called as documented in `jxl/encode.h`, and the frame settings are
+	// owned by the encoder which outlives them
+	let encoded = unsafe {
+		let encoder = Owned::new((jxl.encoder_create)(ptr::null()), jxl.encoder_destroy)
+			.ok_or_else(failed)?;
+
+		let mut info = MaybeUninit::<BasicInfo>::uninit();
+		(jxl.encoder_init_basic_info)(info.as_mut_ptr());
+		let mut info = info.assume_init();
+		info.xsize = image.width();
+		info.ysize = image.height();
+		info.bits_per_sample = bits;
+		info.exponent_bits_per_sample = exponent_bits;
+		info.num_color_channels = if gray { 1 } else { 3 };
+		if alpha {
+			info.num_extra_channels = 1;
+			info.alpha_bits = bits;
+			info.alpha_exponent_bits = exponent_bits;
+		}
+		// Lossless encoding keeps the samples as they are rather than converting them to XYB
+		info.uses_original_profile = lossless.into();
+
+		let mut srgb = MaybeUninit::<ColorEncoding>::uninit();
+		(jxl.color_encoding_set_to_srgb)(srgb.as_mut_ptr(), gray.into());
+		let srgb = srgb.assume_init();
+
+		let settings = (jxl.encoder_frame_settings_create)(encoder.ptr, ptr::null());
+		if (jxl.encoder_set_basic_info)(encoder.ptr, &info) != ENC_SUCCESS
+			|| (jxl.encoder_set_color_encoding)(encoder.ptr, &srgb) != ENC_SUCCESS
+			|| settings.is_null()
+			|| (jxl.encoder_frame_settings_set_option)(
+				settings,
+				ENC_FRAME_SETTING_EFFORT,
+				effort.into(),
+			) != ENC_SUCCESS
+		{
+			return Err(failed());
+		}
+		let configured = match lossless {
+			true => {
+				(jxl.encoder_set_frame_lossless)(settings, 1) == ENC_SUCCESS
+					&& (jxl.encoder_set_frame_distance)(settings, 0.0) == ENC_SUCCESS
+			}
+			false => (jxl.encoder_set_frame_distance)(settings, distance(quality)) == ENC_SUCCESS,
+		};
+		if !configured
+			|| (jxl.encoder_add_image_frame)(
+				settings,
+				&format,
+				pixels.as_ptr().cast(),
+				pixels.len(),
+			) != ENC_SUCCESS
+		{
+			return Err(failed());
+		}
+		(jxl.encoder_close_input)(encoder.ptr);
+
+		let mut encoded = vec![0; 64 * 1024];
+		let mut written = 0;
+		loop {
+			let mut next = encoded.as_mut_ptr().add(written);
+			let mut available = encoded.len() - written;
+			let status = (jxl.encoder_process_output)(encoder.ptr, &mut next, &mut available);
+			written = encoded.len() - available;
+			match status {
+				ENC_SUCCESS => break,
+				ENC_NEED_MORE_OUTPUT => encoded.resize(encoded.len() * 2, 0),
+				_ => return Err(failed()),
+			}
+		}
+		encoded.truncate(written);
+		encoded
+	};
+
+	writer.write_all(&encoded)?;
+	Ok(())
+}
+
+/// Butteraugli distance for a quality, as picked by libjxl's `cjxl`. A distance of 1 is visually
+/// lossless and matches a quality of 90.
+fn distance(quality: f32) -> f32 {
+	if quality >= 100.0 {
+		0.0
+	} else if quality >= 30.0 {
+		0.1 + (100.0 - quality) * 0.09
+	} else {
+		53.0 / 3000.0 * quality * quality - 23.0 / 20.0 * quality + 25.0
+	}
+}
+
+fn decoding_error(message: String) -> Error {
+	ImageError::Decoding(DecodingError::new(
+		ImageFormatHint::Name("JPEG XL".to_string()),
+		message,
+	))
+	.into()
+}
+
+fn encoding_error(message: String) -> Error {
+	ImageError::Encoding(EncodingError::new(
+		ImageFormatHint::Name("JPEG XL".to_string()),
+		message,
+	))
+	.into()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{decode, distance, encode, is_jxl};
+	use crate::{ImageOutputFormat, ProcessOptions};
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage, RgbaImage};
+
+	#[test]
+	fn detects_jxl() {
+		assert!(is_jxl(&[0xff, 0x0a, 0xfa, 0x12]));
+		assert!(is_jxl(b"\0\0\0\x0cJXL \r\n\x87\n\0\0\0\x14ftypjxl "));
+		assert!(!is_jxl(&[0xff, 0xd8, 0xff, 0xe0]));
+		assert!(!is_jxl(b"\0\0\0\x18ftypheic"));
+	}
+
+	#[test]
+	fn reads_output_settings() {
+		let formats: Vec<ImageOutputFormat> =
+			serde_json::from_str(r#"["jxl", { "jxl": { "effort": 3, "lossless": true } }]"#)
+				.unwrap();
+		assert_eq!(
+			vec![
+				ImageOutputFormat::jxl(),
+				ImageOutputFormat::Jxl {
+					quality: 90.0,
+					effort: 3,
+					lossless: true
+				}
+			],
+			formats
+		);
+		assert_eq!(r#""jxl""#, serde_json::to_string(&formats[0]).unwrap());
+		assert_eq!(
+			Some(ImageOutputFormat::jxl()),
+			ImageOutputFormat::from_extension("JXL")
+		);
+	}
+
+	#[test]
+	fn maps_quality_to_distance() {
+		assert_eq!(0.0, distance(100.0));
+		assert!((distance(90.0) - 1.0).abs() < 1e-6);
+		assert!((distance(30.0) - 6.4).abs() < 1e-5);
+		assert!(distance(0.0) > distance(29.0));
+	}
+
+	#[test]
+	fn rejects_invalid_settings() {
+		let image = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+		assert!(encode(&mut Vec::new(), &image, 101.0, 7, false).is_err());
+		assert!(encode(&mut Vec::new(), &image, 90.0, 0, false).is_err());
+		assert!(encode(&mut Vec::new(), &image, 90.0, 10, false).is_err());
+	}
+
+	#[test]
+	#[ignore = "requires libjxl"]
+	fn round_trips() {
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 8, |x, y| {
+			image::Rgba([x as u8 * 16, y as u8 * 32, 128, 255 - x as u8])
+		}));
+		let mut encoded = Vec::new();
+		encode(&mut encoded, &image, 100.0, 7, true).unwrap();
+		assert!(is_jxl(&encoded));
+		assert_eq!(image, decode(&encoded, &ProcessOptions::default()).unwrap());
+
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 8, Rgb([200, 40, 40])));
+		let mut encoded = Vec::new();
+		encode(&mut encoded, &image, 90.0, 3, false).unwrap();
+		let decoded = decode(&encoded, &ProcessOptions::default()).unwrap();
+		assert_eq!((16, 8), decoded.dimensions());
+		let pixel = decoded.get_pixel(8, 4);
+		assert!(pixel.0[..3]
+			.iter()
+			.zip([200, 40, 40])
+			.all(|(&actual, expected)| actual.abs_diff(expected) <= 4));
+	}
+}
@@ -0,0 +1,159 @@
+//! AWS Lambda handlers, enabled with the `lambda` feature.
+//!
+//! A thumbnailer triggered by S3 uploads only needs to provide the pipeline and where to write
+//! the results:
+//!
+//! ```ignore
+//! use imageless::{lambda::{run_s3, S3Destination}, Pipeline};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), lambda_runtime::Error> {
+//!     let pipeline: Pipeline = serde_json::from_str(&std::env::var("PIPELINE")?)?;
+//!     let destination = S3Destination {
+//!         bucket: std::env::var("OUTPUT_BUCKET")?,
+//!         prefix: Some("thumbnails/".to_string()),
+//!     };
+//!
+//!     run_s3(pipeline, destination).await
+//! }
+//! ```
+
+use crate::Pipeline;
+use aws_config::BehaviorVersion;
+use aws_lambda_events::s3::S3Event;
+use aws_sdk_s3::{primitives::ByteStream, Client};
+use lambda_http::{http::header::CONTENT_TYPE, Body, Request, Response};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+
+/// Bucket and key prefix processed objects are written to.
+#[derive(Debug, Clone)]
+pub struct S3Destination {
+	pub bucket: String,
+	pub prefix: Option<String>,
+}
+
+impl S3Destination {
+	/// Builds the key for a processed object, replacing the source extension with the extension
+	/// of the output format.
+	fn key(&self, source_key: &str, extension: &str) -> String {
+		let stem = match source_key.rsplit_once('.') {
+			Some((stem, _)) if !stem.is_empty() && !stem.ends_with('/') => stem,
+			_ => source_key,
+		};
+		let prefix = self.prefix.as_deref().unwrap_or_default();
+
+		format!("{prefix}{stem}.{extension}")
+	}
+}
+
+/// Runs a Lambda function which processes objects from S3 event notifications with `pipeline`
+/// and uploads the results to `destination`.
+pub async fn run_s3(pipeline: Pipeline, destination: S3Destination) -> Result<(), Error> {
+	let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+	let client = Client::new(&config);
+
+	let (client, pipeline, destination) = (&client, &pipeline, &destination);
+	lambda_runtime::run(service_fn(move |event: LambdaEvent<S3Event>| async move {
+		handle_s3_event(client, pipeline, destination, event.payload).await
+	}))
+	.await
+}
+
+/// Processes every object referenced by an S3 event notification.
+pub async fn handle_s3_event(
+	client: &Client,
+	pipeline: &Pipeline,
+	destination: &S3Destination,
+	event: S3Event,
+) -> Result<(), Error> {
+	for record in event.records {
+		let bucket = record
+			.s3
+			.bucket
+			.name
+			.ok_or("S3 event record has no bucket")?;
+		let key = record
+			.s3
+			.object
+			.url_decoded_key
+			.or(record.s3.object.key)
+			.ok_or("S3 event record has no object key")?;
+
+		let out_key = destination.key(&key, pipeline.out_format.extension());
+		if bucket == destination.bucket && out_key == key {
+			return Err(format!("Refusing to overwrite source object s3://{bucket}/{key}").into());
+		}
+
+		let object = client.get_object().bucket(&bucket).key(&key).send().await?;
+		let input = object.body.collect().await?.into_bytes();
+
+		let output = pipeline.process_bytes(&input)?;
+
+		client
+			.put_object()
+			.bucket(&destination.bucket)
+			.key(out_key)
+			.content_type(pipeline.out_format.mime_type())
+			.body(ByteStream::from(output))
+			.send()
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// Runs a Lambda function behind API Gateway or a function URL which processes the image in the
+/// request body with `pipeline` and responds with the result.
+pub async fn run_http(pipeline: Pipeline) -> Result<(), Error> {
+	let pipeline = &pipeline;
+	lambda_http::run(service_fn(move |request: Request| async move {
+		handle_http_request(pipeline, request).await
+	}))
+	.await
+}
+
+/// Processes the image in the request body. Images which can't be processed produce a
+/// `422 Unprocessable Entity` response.
+pub async fn handle_http_request(
+	pipeline: &Pipeline,
+	request: Request,
+) -> Result<Response<Body>, Error> {
+	let response = match pipeline.process_bytes(request.body()) {
+		Ok(output) => Response::builder()
+			.header(CONTENT_TYPE, pipeline.out_format.mime_type())
+			.body(Body::Binary(output))?,
+		Err(err) => Response::builder()
+			.status(422)
+			.body(Body::Text(err.to_string()))?,
+	};
+
+	Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::S3Destination;
+
+	#[test]
+	fn builds_output_keys() {
+		let destination = |prefix: Option<&str>| S3Destination {
+			bucket: "thumbnails".to_string(),
+			prefix: prefix.map(str::to_string),
+		};
+
+		assert_eq!(
+			"photos/cat.webp",
+			destination(None).key("photos/cat.jpg", "webp")
+		);
+		assert_eq!(
+			"small/photos/cat.tar.png",
+			destination(Some("small/")).key("photos/cat.tar.gz", "png")
+		);
+		// Keys without an extension, or where the only dot starts the file name, are kept whole
+		assert_eq!("raw.png", destination(None).key("raw", "png"));
+		assert_eq!(
+			"dir/.hidden.png",
+			destination(None).key("dir/.hidden", "png")
+		);
+	}
+}
@@ -1,17 +1,80 @@
 use crate::{
-	operations::{AdjustBrightness, Blur, Crop, Grayscale, Resize},
-	Unit::{Percentage, Pixel},
+	animation::Animation,
+	cache::Cache,
+	operations::{
+		AdjustBrightness, AutoContrast, AutoOrient, BilateralFilter, Blur, ChromaKey,
+		ChromaticAberration, Clahe, Conditional, Convolve, Crop, CropGravity, CropToAspect, Curves,
+		Dither, DropShadow, EdgeDetect, Extend, Flip, GradientMap, Grayscale, HueRotate,
+		ImageStats, Invert, MedianFilter, Noise, OilPaint, Overlay, Pad, PadToAspect, Perspective,
+		Resize, RotateDegrees, RoundCorners, Saturation, Scale, SeamCarve, Sepia, SetMetadata,
+		Sharpen, Shear, SmartCrop, Solarize, Stats, StripMetadata, Thumbnail, TiltShift, Tint,
+		Trim, Unsharpen, WhiteBalance,
+	},
+	plan::{ImageInfo, Step},
+	progress::{timed, Progress, ProgressHandler},
+	Unit::{Expr, FromEnd, Percentage, Pixel},
 };
-use image::{io::Reader as ImageReader, DynamicImage};
-use serde::{Deserialize, Serialize};
+use image::{
+	codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder},
+	io::Reader as ImageReader,
+	DynamicImage,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
 use std::{
-	io,
+	cell::RefCell,
+	io::{self, BufRead, Cursor, Seek, Write},
 	ops::{Add, Sub},
-	path::Path,
+	str::FromStr,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
 };
 use thiserror::Error;
 
+pub mod analysis;
+pub mod animation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod batch;
+pub mod cache;
+mod encode;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(all(feature = "gpu", not(target_arch = "wasm32")))]
+pub mod gpu;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "heic")]
+pub mod heic;
+#[cfg(feature = "jxl")]
+pub mod jxl;
+#[cfg(feature = "lambda")]
+pub mod lambda;
+pub mod magick;
+pub mod metadata;
 pub mod operations;
+pub mod optimize;
+pub mod params;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod plan;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+pub mod presets;
+pub mod progress;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "server")]
+pub mod server;
+mod simd;
+pub mod tiled;
+pub mod url;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod variants;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 #[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -50,6 +113,30 @@ impl Add for PixelUnit {
 #[serde(rename_all = "snake_case")]
 pub struct PercentageUnit {
 	percentage: f32,
+	/// The image dimension the percentage is of, instead of the one it is used for
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	of: Option<PercentageAxis>,
+}
+
+/// An image dimension a percentage can be bound to, so that for example a margin can be the same
+/// fraction of the shorter side on both axes.
+#[derive(Clone, Copy, Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PercentageAxis {
+	Width,
+	Height,
+	Shorter,
+	Longer,
+}
+
+impl PercentageUnit {
+	/// Binds the percentage to a dimension of the image.
+	pub fn of(self, axis: PercentageAxis) -> Self {
+		Self {
+			of: Some(axis),
+			..self
+		}
+	}
 }
 
 #[derive(Error, Clone, Copy, Debug, PartialOrd, PartialEq)]
@@ -72,7 +159,10 @@ impl TryFrom<f32> for PercentageUnit {
 			return Err(PercentageOutOfRangeError::new(value));
 		}
 
-		Ok(Self { percentage: value })
+		Ok(Self {
+			percentage: value,
+			of: None,
+		})
 	}
 }
 
@@ -81,29 +171,223 @@ impl TryFrom<f32> for PercentageUnit {
 pub enum Unit {
 	Pixel(PixelUnit),
 	Percentage(PercentageUnit),
+	/// Pixels back from the far end of the dimension, the right or bottom edge for coordinates.
+	/// For example `{ from-end = { pixels = 16 } }` is 16 pixels less than the full width.
+	FromEnd(PixelUnit),
+	/// Arithmetic between two units, such as the full width less a margin:
+	/// `{ expr = { subtract = [{ percentage = { percentage = 1.0 } }, { pixel = { pixels = 32 } }] } }`
+	Expr(Box<UnitExpr>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Combines two units, each resolved against the same dimension. Results are clamped at 0.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnitExpr {
+	Add(Unit, Unit),
+	Subtract(Unit, Unit),
+	Min(Unit, Unit),
+	Max(Unit, Unit),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Coordinate {
 	x: Unit,
 	y: Unit,
 }
 
+impl Default for Coordinate {
+	fn default() -> Self {
+		Self {
+			x: Pixel(PixelUnit::from(0)),
+			y: Pixel(PixelUnit::from(0)),
+		}
+	}
+}
+
 impl Unit {
+	/// Resolves the unit along `dimension` of a `width` by `height` image. Percentages bound to an
+	/// axis use that axis instead.
 	#[inline]
-	fn as_pixel(&self, dimension: PixelUnit) -> PixelUnit {
+	fn as_pixel(&self, dimension: PixelUnit, (width, height): (u32, u32)) -> PixelUnit {
 		match self {
 			Pixel(pixels) => *pixels,
 			Percentage(percentage) => {
-				let dimension = dimension.pixels as f32;
+				let dimension = match percentage.of {
+					None => dimension.pixels,
+					Some(PercentageAxis::Width) => width,
+					Some(PercentageAxis::Height) => height,
+					Some(PercentageAxis::Shorter) => width.min(height),
+					Some(PercentageAxis::Longer) => width.max(height),
+				} as f32;
 				let pixels = dimension * percentage.percentage;
 				PixelUnit::from(pixels as u32)
 			}
+			FromEnd(pixels) => PixelUnit::from(dimension.pixels.saturating_sub(pixels.pixels)),
+			Expr(expr) => {
+				let resolve = |unit: &Unit| unit.as_pixel(dimension, (width, height)).pixels;
+				PixelUnit::from(match expr.as_ref() {
+					UnitExpr::Add(lhs, rhs) => resolve(lhs).saturating_add(resolve(rhs)),
+					UnitExpr::Subtract(lhs, rhs) => resolve(lhs).saturating_sub(resolve(rhs)),
+					UnitExpr::Min(lhs, rhs) => resolve(lhs).min(resolve(rhs)),
+					UnitExpr::Max(lhs, rhs) => resolve(lhs).max(resolve(rhs)),
+				})
+			}
 		}
 	}
 }
 
+/// Where an item is anchored within a larger area.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Gravity {
+	#[default]
+	TopLeft,
+	Top,
+	TopRight,
+	Left,
+	Center,
+	Right,
+	BottomLeft,
+	Bottom,
+	BottomRight,
+}
+
+impl Gravity {
+	/// Position of the top left corner of an `item` sized area anchored within `area`, moved away
+	/// from the anchored edges by `offset`. Centered axes are moved right or down by the offset.
+	pub fn position(self, area: (u32, u32), item: (u32, u32), offset: (u32, u32)) -> (i64, i64) {
+		let place = |area: u32, item: u32, offset: u32, anchor: i8| {
+			let (area, item, offset) = (area as i64, item as i64, offset as i64);
+			match anchor {
+				-1 => offset,
+				0 => (area - item) / 2 + offset,
+				_ => area - item - offset,
+			}
+		};
+
+		let (horizontal, vertical) = match self {
+			Self::TopLeft => (-1, -1),
+			Self::Top => (0, -1),
+			Self::TopRight => (1, -1),
+			Self::Left => (-1, 0),
+			Self::Center => (0, 0),
+			Self::Right => (1, 0),
+			Self::BottomLeft => (-1, 1),
+			Self::Bottom => (0, 1),
+			Self::BottomRight => (1, 1),
+		};
+
+		(
+			place(area.0, item.0, offset.0, horizontal),
+			place(area.1, item.1, offset.1, vertical),
+		)
+	}
+}
+
+/// An 8-bit RGBA color, written in configs as a hex string, `#rgb`, `#rrggbb` or `#rrggbbaa`, or
+/// one of the names `transparent`, `black` and `white`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+	pub r: u8,
+	pub g: u8,
+	pub b: u8,
+	pub a: u8,
+}
+
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+#[error("Invalid color: {color}")]
+pub struct ColorParseError {
+	pub color: String,
+}
+
+impl Color {
+	pub const TRANSPARENT: Self = Self::rgba(0, 0, 0, 0);
+	pub const BLACK: Self = Self::rgba(0, 0, 0, 255);
+	pub const WHITE: Self = Self::rgba(255, 255, 255, 255);
+
+	pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+		Self { r, g, b, a }
+	}
+
+	pub fn is_opaque(&self) -> bool {
+		self.a == 255
+	}
+}
+
+impl Default for Color {
+	fn default() -> Self {
+		Self::TRANSPARENT
+	}
+}
+
+impl FromStr for Color {
+	type Err = ColorParseError;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		let error = || ColorParseError {
+			color: value.to_string(),
+		};
+
+		match value.to_ascii_lowercase().as_str() {
+			"transparent" => return Ok(Self::TRANSPARENT),
+			"black" => return Ok(Self::BLACK),
+			"white" => return Ok(Self::WHITE),
+			_ => {}
+		}
+
+		let hex = value.strip_prefix('#').ok_or_else(error)?;
+		if !hex.is_ascii() {
+			return Err(error());
+		}
+		let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| error());
+
+		match hex.len() {
+			3 => {
+				let [r, g, b] = [0, 1, 2].map(|i| channel(&hex[i..=i]).map(|value| value * 17));
+				Ok(Self::rgba(r?, g?, b?, 255))
+			}
+			6 | 8 => {
+				let alpha = match hex.len() {
+					8 => channel(&hex[6..8])?,
+					_ => 255,
+				};
+				Ok(Self::rgba(
+					channel(&hex[0..2])?,
+					channel(&hex[2..4])?,
+					channel(&hex[4..6])?,
+					alpha,
+				))
+			}
+			_ => Err(error()),
+		}
+	}
+}
+
+impl TryFrom<String> for Color {
+	type Error = ColorParseError;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		value.parse()
+	}
+}
+
+impl From<Color> for String {
+	fn from(color: Color) -> Self {
+		match color.a {
+			255 => format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b),
+			a => format!("#{:02x}{:02x}{:02x}{a:02x}", color.r, color.g, color.b),
+		}
+	}
+}
+
+impl From<Color> for image::Rgba<u8> {
+	fn from(color: Color) -> Self {
+		image::Rgba([color.r, color.g, color.b, color.a])
+	}
+}
+
 #[derive(Error, Debug)]
 #[error("Error processing image: {message}")]
 pub struct OperationError {
@@ -118,36 +402,196 @@ impl OperationError {
 
 pub trait Process {
 	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError>;
+
+	/// Dimensions of the result for an image described by `image`, without processing it, or
+	/// `None` when they depend on the pixels. Operations which change the size must override
+	/// this.
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		Ok(Some(image.dimensions()))
+	}
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum Operation {
 	AdjustBrightness(AdjustBrightness),
+	AutoContrast(AutoContrast),
+	AutoOrient(AutoOrient),
+	BilateralFilter(BilateralFilter),
 	Blur(Blur),
+	ChromaKey(ChromaKey),
+	ChromaticAberration(ChromaticAberration),
+	Clahe(Clahe),
+	Conditional(Conditional),
+	Convolve(Convolve),
 	Crop(Crop),
+	CropGravity(CropGravity),
+	CropToAspect(CropToAspect),
+	Curves(Curves),
+	Dither(Dither),
+	#[cfg(feature = "text")]
+	DrawText(operations::DrawText),
+	DropShadow(DropShadow),
+	EdgeDetect(EdgeDetect),
+	Extend(Extend),
+	#[cfg(feature = "face-detect")]
+	FaceCrop(operations::FaceCrop),
+	Flip(Flip),
+	GradientMap(GradientMap),
 	Grayscale(Grayscale),
+	HueRotate(HueRotate),
+	Invert(Invert),
+	MedianFilter(MedianFilter),
+	Noise(Noise),
+	OilPaint(OilPaint),
+	Overlay(Overlay),
+	#[serde(alias = "border")]
+	Pad(Pad),
+	PadToAspect(PadToAspect),
+	Perspective(Perspective),
+	#[cfg(feature = "plugins")]
+	Plugin(operations::Plugin),
+	#[cfg(feature = "qr")]
+	QrCode(operations::QrCode),
 	Resize(Resize),
+	RotateDegrees(RotateDegrees),
+	RoundCorners(RoundCorners),
+	Saturation(Saturation),
+	Scale(Scale),
+	SeamCarve(SeamCarve),
+	Sepia(Sepia),
+	SetMetadata(SetMetadata),
+	Sharpen(Sharpen),
+	Shear(Shear),
+	SmartCrop(SmartCrop),
+	Solarize(Solarize),
+	Stats(Stats),
+	StripMetadata(StripMetadata),
+	Thumbnail(Thumbnail),
+	TiltShift(TiltShift),
+	Tint(Tint),
+	Trim(Trim),
+	Unsharpen(Unsharpen),
+	WhiteBalance(WhiteBalance),
 }
 
 impl Operation {
+	/// Name of the operation as written in configs, such as `crop-gravity`.
+	pub fn name(&self) -> String {
+		serde_json::to_value(self)
+			.ok()
+			.and_then(|value| value.as_object()?.keys().next().cloned())
+			.unwrap_or_default()
+	}
+
+	/// Dimensions of the result for an image of `dimensions`, without decoding it. Errors when
+	/// they depend on the pixels, as with `trim`, or on the color type, as with a `conditional`
+	/// on `grayscale`.
+	pub fn output_dimensions(&self, dimensions: (u32, u32)) -> Result<(u32, u32), OperationError> {
+		let (width, height) = dimensions;
+		// These differ in every property of color a condition can check, so that results which
+		// depend on color disagree
+		let [first, second] = [image::ColorType::Rgb8, image::ColorType::La16].map(|color_type| {
+			self.get_process()
+				.plan(ImageInfo::new(width, height, color_type))
+		});
+
+		match (first?, second?) {
+			(Some(first), Some(second)) if first == second => Ok(first),
+			(Some(_), Some(_)) => Err(OperationError::new(format!(
+				"The size after `{}` depends on the color type of the image",
+				self.name()
+			))),
+			_ => Err(OperationError::new(format!(
+				"The size after `{}` depends on the content of the image",
+				self.name()
+			))),
+		}
+	}
+
 	pub fn get_process(&self) -> &dyn Process {
 		match self {
 			Self::AdjustBrightness(adjust) => adjust,
+			Self::AutoContrast(auto_contrast) => auto_contrast,
+			Self::AutoOrient(auto_orient) => auto_orient,
+			Self::BilateralFilter(bilateral_filter) => bilateral_filter,
 			Self::Blur(blur) => blur,
+			Self::ChromaKey(chroma_key) => chroma_key,
+			Self::ChromaticAberration(chromatic_aberration) => chromatic_aberration,
+			Self::Clahe(clahe) => clahe,
+			Self::Conditional(conditional) => conditional,
+			Self::Convolve(convolve) => convolve,
 			Self::Crop(crop) => crop,
+			Self::CropGravity(crop_gravity) => crop_gravity,
+			Self::CropToAspect(crop_to_aspect) => crop_to_aspect,
+			Self::Curves(curves) => curves,
+			Self::Dither(dither) => dither,
+			#[cfg(feature = "text")]
+			Self::DrawText(draw_text) => draw_text,
+			Self::DropShadow(drop_shadow) => drop_shadow,
+			Self::EdgeDetect(edge_detect) => edge_detect,
+			Self::Extend(extend) => extend,
+			#[cfg(feature = "face-detect")]
+			Self::FaceCrop(face_crop) => face_crop,
+			Self::Flip(flip) => flip,
+			Self::GradientMap(gradient_map) => gradient_map,
 			Self::Grayscale(grayscale) => grayscale,
+			Self::HueRotate(hue_rotate) => hue_rotate,
+			Self::Invert(invert) => invert,
+			Self::MedianFilter(median_filter) => median_filter,
+			Self::Noise(noise) => noise,
+			Self::OilPaint(oil_paint) => oil_paint,
+			Self::Overlay(overlay) => overlay,
+			Self::Pad(pad) => pad,
+			Self::PadToAspect(pad_to_aspect) => pad_to_aspect,
+			Self::Perspective(perspective) => perspective,
+			#[cfg(feature = "plugins")]
+			Self::Plugin(plugin) => plugin,
+			#[cfg(feature = "qr")]
+			Self::QrCode(qr_code) => qr_code,
 			Self::Resize(resize) => resize,
+			Self::RotateDegrees(rotate_degrees) => rotate_degrees,
+			Self::RoundCorners(round_corners) => round_corners,
+			Self::Saturation(saturation) => saturation,
+			Self::Scale(scale) => scale,
+			Self::SeamCarve(seam_carve) => seam_carve,
+			Self::Sepia(sepia) => sepia,
+			Self::SetMetadata(set_metadata) => set_metadata,
+			Self::Sharpen(sharpen) => sharpen,
+			Self::Shear(shear) => shear,
+			Self::SmartCrop(smart_crop) => smart_crop,
+			Self::Solarize(solarize) => solarize,
+			Self::Stats(stats) => stats,
+			Self::StripMetadata(strip_metadata) => strip_metadata,
+			Self::Thumbnail(thumbnail) => thumbnail,
+			Self::TiltShift(tilt_shift) => tilt_shift,
+			Self::Tint(tint) => tint,
+			Self::Trim(trim) => trim,
+			Self::Unsharpen(unsharpen) => unsharpen,
+			Self::WhiteBalance(white_balance) => white_balance,
 		}
 	}
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(remote = "Self", rename_all = "kebab-case")]
 pub enum ImageOutputFormat {
-	/// An Image in PNG Format
-	Png,
-	/// An Image in JPEG Format with specified quality, up to 100
+	/// An Image in PNG Format, optionally optimized for size with oxipng at an effort from 0 to 6,
+	/// which needs the `oxipng` feature. Animated sources are written as APNG
+	Png {
+		/// Picks the compression and filters itself, and isn't applied to interlaced images
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		optimize: Option<u8>,
+		#[serde(default, skip_serializing_if = "is_default")]
+		compression: PngCompression,
+		#[serde(default, skip_serializing_if = "is_default")]
+		filter: PngFilter,
+		/// Interlaces with Adam7, so a coarse image shows before the whole file has loaded
+		#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+		interlace: bool,
+	},
+	/// An Image in JPEG Format with specified quality, up to 100. With the `mozjpeg` feature it is
+	/// encoded progressively with trellis quantization, for smaller files at the same quality
 	Jpeg { quality: u8 },
 	// /// An Image in one of the PNM Formats
 	// Pnm(PnmSubtype),
@@ -169,14 +613,130 @@ pub enum ImageOutputFormat {
 	Avif,
 	/// An image in QOI Format
 	Qoi,
-	/// An image in WebP Format.
-	WebP,
+	/// An image in WebP Format, lossy with a quality from 0 to 100 unless `lossless`, in which
+	/// case the quality is how hard to try for a smaller file. The alpha channel has its own
+	/// quality.
+	WebP {
+		#[serde(default = "ImageOutputFormat::default_webp_quality")]
+		quality: f32,
+		#[serde(default)]
+		lossless: bool,
+		#[serde(default = "ImageOutputFormat::default_webp_alpha_quality")]
+		alpha_quality: u8,
+	},
+	/// An image in JPEG XL Format, lossy with a quality from 0 to 100 unless `lossless`. `effort`
+	/// is how hard to try for a smaller file, from 1 to 9. Needs the `jxl` feature.
+	Jxl {
+		#[serde(default = "ImageOutputFormat::default_jxl_quality")]
+		quality: f32,
+		#[serde(default = "ImageOutputFormat::default_jxl_effort")]
+		effort: u8,
+		#[serde(default)]
+		lossless: bool,
+	},
+}
+
+/// zlib compression of PNG output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PngCompression {
+	#[default]
+	Fast,
+	Balanced,
+	Best,
+}
+
+impl From<PngCompression> for CompressionType {
+	fn from(value: PngCompression) -> Self {
+		match value {
+			PngCompression::Fast => Self::Fast,
+			PngCompression::Balanced => Self::Default,
+			PngCompression::Best => Self::Best,
+		}
+	}
+}
+
+/// Filter of the rows of PNG output, which predicts each row from the pixels before it so it
+/// compresses better. `adaptive` picks the filter of each row separately.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PngFilter {
+	#[default]
+	Adaptive,
+	None,
+	Sub,
+	Up,
+	Avg,
+	Paeth,
+}
+
+impl From<PngFilter> for PngFilterType {
+	fn from(value: PngFilter) -> Self {
+		match value {
+			PngFilter::Adaptive => Self::Adaptive,
+			PngFilter::None => Self::NoFilter,
+			PngFilter::Sub => Self::Sub,
+			PngFilter::Up => Self::Up,
+			PngFilter::Avg => Self::Avg,
+			PngFilter::Paeth => Self::Paeth,
+		}
+	}
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+	*value == T::default()
+}
+
+impl Serialize for ImageOutputFormat {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self {
+			// Kept as a name alone when there are no settings
+			format if *format == ImageOutputFormat::png() => {
+				serializer.serialize_unit_variant("ImageOutputFormat", 0, "png")
+			}
+			format if *format == ImageOutputFormat::webp() => {
+				serializer.serialize_unit_variant("ImageOutputFormat", 11, "web-p")
+			}
+			format if *format == ImageOutputFormat::jxl() => {
+				serializer.serialize_unit_variant("ImageOutputFormat", 12, "jxl")
+			}
+			format => ImageOutputFormat::serialize(format, serializer),
+		}
+	}
+}
+
+impl<'de> Deserialize<'de> for ImageOutputFormat {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		/// Formats with settings can also be given by name alone, to use the default settings
+		#[derive(Deserialize)]
+		#[serde(untagged)]
+		enum OutputFormat {
+			#[serde(deserialize_with = "ImageOutputFormat::deserialize")]
+			Format(ImageOutputFormat),
+			Name(Name),
+		}
+
+		#[derive(Deserialize)]
+		#[serde(rename_all = "kebab-case")]
+		enum Name {
+			Png,
+			WebP,
+			Jxl,
+		}
+
+		Ok(match OutputFormat::deserialize(deserializer)? {
+			OutputFormat::Format(format) => format,
+			OutputFormat::Name(Name::Png) => ImageOutputFormat::png(),
+			OutputFormat::Name(Name::WebP) => ImageOutputFormat::webp(),
+			OutputFormat::Name(Name::Jxl) => ImageOutputFormat::jxl(),
+		})
+	}
 }
 
 impl From<ImageOutputFormat> for image::ImageOutputFormat {
 	fn from(value: ImageOutputFormat) -> Self {
 		match value {
-			ImageOutputFormat::Png => Self::Png,
+			ImageOutputFormat::Png { .. } => Self::Png,
 			ImageOutputFormat::Jpeg { quality } => Self::Jpeg(quality),
 			ImageOutputFormat::Gif => Self::Gif,
 			ImageOutputFormat::Ico => Self::Ico,
@@ -185,17 +745,67 @@ impl From<ImageOutputFormat> for image::ImageOutputFormat {
 			ImageOutputFormat::Tga => Self::Tga,
 			ImageOutputFormat::OpenExr => Self::OpenExr,
 			ImageOutputFormat::Tiff => Self::Tiff,
+			#[cfg(not(target_arch = "wasm32"))]
 			ImageOutputFormat::Avif => Self::Avif,
+			#[cfg(target_arch = "wasm32")]
+			ImageOutputFormat::Avif => Self::Unsupported("AVIF is not supported on wasm32".to_string()),
 			ImageOutputFormat::Qoi => Self::Qoi,
-			ImageOutputFormat::WebP => Self::WebP,
+			ImageOutputFormat::WebP { .. } => Self::WebP,
+			ImageOutputFormat::Jxl { .. } => {
+				Self::Unsupported("JPEG XL needs the jxl feature".to_string())
+			}
 		}
 	}
 }
 
 impl ImageOutputFormat {
+	/// PNG with the default settings.
+	pub fn png() -> Self {
+		ImageOutputFormat::Png {
+			optimize: None,
+			compression: PngCompression::default(),
+			filter: PngFilter::default(),
+			interlace: false,
+		}
+	}
+
+	/// Lossy WebP at a quality of 80, as used when a format is given without settings.
+	pub fn webp() -> Self {
+		ImageOutputFormat::WebP {
+			quality: Self::default_webp_quality(),
+			lossless: false,
+			alpha_quality: Self::default_webp_alpha_quality(),
+		}
+	}
+
+	fn default_webp_quality() -> f32 {
+		80.0
+	}
+
+	fn default_webp_alpha_quality() -> u8 {
+		100
+	}
+
+	/// Lossy JPEG XL at a quality of 90 and an effort of 7, the defaults of libjxl.
+	pub fn jxl() -> Self {
+		ImageOutputFormat::Jxl {
+			quality: Self::default_jxl_quality(),
+			effort: Self::default_jxl_effort(),
+			lossless: false,
+		}
+	}
+
+	fn default_jxl_quality() -> f32 {
+		90.0
+	}
+
+	fn default_jxl_effort() -> u8 {
+		7
+	}
+
 	pub fn extension(&self) -> &'static str {
 		match self {
-			ImageOutputFormat::Png => "png",
+			ImageOutputFormat::Png { .. } => "png",
 			ImageOutputFormat::Jpeg { .. } => "jpg",
 			ImageOutputFormat::Gif => "gif",
 			ImageOutputFormat::Ico => "ico",
@@ -206,7 +816,48 @@ impl ImageOutputFormat {
 			ImageOutputFormat::Tiff => "tiff",
 			ImageOutputFormat::Avif => "avif",
 			ImageOutputFormat::Qoi => "qoi",
-			ImageOutputFormat::WebP => "webp",
+			ImageOutputFormat::WebP { .. } => "webp",
+			ImageOutputFormat::Jxl { .. } => "jxl",
+		}
+	}
+
+	/// Output format for a file extension, using a quality of 80 for JPEG and WebP.
+	pub fn from_extension(extension: &str) -> Option<Self> {
+		let format = match extension.to_ascii_lowercase().as_str() {
+			"png" => ImageOutputFormat::png(),
+			"jpg" | "jpeg" => ImageOutputFormat::Jpeg { quality: 80 },
+			"gif" => ImageOutputFormat::Gif,
+			"ico" => ImageOutputFormat::Ico,
+			"bmp" => ImageOutputFormat::Bmp,
+			"ff" => ImageOutputFormat::Farbfeld,
+			"tga" => ImageOutputFormat::Tga,
+			"exr" => ImageOutputFormat::OpenExr,
+			"tif" | "tiff" => ImageOutputFormat::Tiff,
+			"avif" => ImageOutputFormat::Avif,
+			"qoi" => ImageOutputFormat::Qoi,
+			"webp" => ImageOutputFormat::webp(),
+			"jxl" => ImageOutputFormat::jxl(),
+			_ => return None,
+		};
+
+		Some(format)
+	}
+
+	pub fn mime_type(&self) -> &'static str {
+		match self {
+			ImageOutputFormat::Png { .. } => "image/png",
+			ImageOutputFormat::Jpeg { .. } => "image/jpeg",
+			ImageOutputFormat::Gif => "image/gif",
+			ImageOutputFormat::Ico => "image/x-icon",
+			ImageOutputFormat::Bmp => "image/bmp",
+			ImageOutputFormat::Farbfeld => "image/x-farbfeld",
+			ImageOutputFormat::Tga => "image/x-tga",
+			ImageOutputFormat::OpenExr => "image/x-exr",
+			ImageOutputFormat::Tiff => "image/tiff",
+			ImageOutputFormat::Avif => "image/avif",
+			ImageOutputFormat::Qoi => "image/x-qoi",
+			ImageOutputFormat::WebP { .. } => "image/webp",
+			ImageOutputFormat::Jxl { .. } => "image/jxl",
 		}
 	}
 }
@@ -224,17 +875,1008 @@ pub enum Error {
 
 	#[error("Image error")]
 	ImageError(#[from] image::ImageError),
+
+	#[error("EXIF error")]
+	ExifError(#[from] exif::Error),
+
+	#[error("Processing was cancelled")]
+	Cancelled,
+
+	#[error("Processing needs about {required} bytes, more than the limit of {limit} bytes")]
+	MemoryLimitExceeded { required: u64, limit: u64 },
+
+	#[cfg(not(target_arch = "wasm32"))]
+	#[error("{} is also the output of another file", output.display())]
+	OutputCollision { output: PathBuf },
+}
+
+/// Details collected while running a pipeline.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ProcessingReport {
+	/// Snapshots taken by [`Stats`] operations, in pipeline order
+	pub stats: Vec<ImageStats>,
+}
+
+/// A list of operations together with the format to encode their result in.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Pipeline {
+	pub out_format: ImageOutputFormat,
+	/// Applies the EXIF orientation of the source before any operations
+	#[serde(default)]
+	pub auto_orient: bool,
+	/// Copies the metadata of the source into the output, as [`ProcessOptions::keep_metadata`]
+	#[serde(default)]
+	pub keep_metadata: bool,
+	#[serde(default)]
+	pub operations: Vec<Operation>,
 }
 
+impl Pipeline {
+	/// Decodes an image, applies the operations and encodes the result in the output format.
+	pub fn process_bytes(&self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+		self.process_bytes_with_options(bytes, ProcessOptions::default())
+	}
+
+	/// Like [`Pipeline::process_bytes`], with options such as progress reporting and caching. The
+	/// source is oriented, and its metadata kept, when either the pipeline or `options` ask for it.
+	pub fn process_bytes_with_options(
+		&self,
+		bytes: &[u8],
+		options: ProcessOptions,
+	) -> Result<Vec<u8>, Error> {
+		let options = ProcessOptions {
+			auto_orient: self.auto_orient || options.auto_orient,
+			keep_metadata: self.keep_metadata || options.keep_metadata,
+			..options
+		};
+		#[cfg(not(feature = "pdf"))]
+		let key = (self, options.auto_orient, options.keep_metadata);
+		#[cfg(feature = "pdf")]
+		let key = (
+			self,
+			options.auto_orient,
+			options.keep_metadata,
+			options.pdf,
+		);
+		cached(bytes, &key, &options, || {
+			decode_and_encode(bytes, &self.operations, self.out_format.clone(), &options)
+		})
+	}
+
+	/// Validates the operations and works out the dimensions after each of them for a source
+	/// described by `info`, without decoding or processing anything. With `auto_orient` the
+	/// orientation of `info` is applied first, and otherwise by any `auto-orient` operation.
+	pub fn plan(&self, info: ImageInfo) -> Result<Vec<Step>, Error> {
+		let info = if self.auto_orient {
+			info.upright()
+		} else {
+			info
+		};
+		plan::plan(&self.operations, info)
+	}
+
+	/// Dimensions of the output for a source of `dimensions` with the EXIF `orientation`, as
+	/// worked out by [`Operation::output_dimensions`]. The orientation is applied as in
+	/// [`Pipeline::plan`].
+	pub fn output_dimensions(
+		&self,
+		dimensions: (u32, u32),
+		orientation: Option<metadata::Orientation>,
+	) -> Result<(u32, u32), OperationError> {
+		let mut orientation = orientation;
+		let mut upright = |(width, height): (u32, u32)| match orientation.take() {
+			Some(orientation) if orientation.swaps_dimensions() => (height, width),
+			_ => (width, height),
+		};
+		let dimensions = if self.auto_orient {
+			upright(dimensions)
+		} else {
+			dimensions
+		};
+
+		self.operations
+			.iter()
+			.try_fold(dimensions, |dimensions, operation| match operation {
+				Operation::AutoOrient(_) => Ok(upright(dimensions)),
+				operation => operation.output_dimensions(dimensions),
+			})
+	}
+}
+
+/// Operations which continue from the result of a pipeline with their own output format, so that
+/// several outputs share decoding and the operations before them.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Branch {
+	/// Added to the name of the output file, as in `photo-{name}.webp`
+	pub name: String,
+	pub out_format: ImageOutputFormat,
+	#[serde(default)]
+	pub operations: Vec<Operation>,
+}
+
+impl Branch {
+	/// Applies the operations of the branch to a copy of `image`.
+	pub fn process(&self, image: &DynamicImage) -> Result<DynamicImage, Error> {
+		let options = ProcessOptions::default();
+		let (image, _) = apply_operations(image.clone(), &self.operations, &options, None)?;
+		Ok(image)
+	}
+
+	/// Path of the output of the branch, next to `path` with the name of the branch added.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn output_path<P: AsRef<Path>>(&self, path: P) -> std::path::PathBuf {
+		let path = path.as_ref();
+		let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+		path.with_file_name(format!(
+			"{stem}-{}.{}",
+			self.name,
+			self.out_format.extension()
+		))
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 pub fn process_file<P: AsRef<Path>>(
 	in_path: P,
 	operations: Vec<Operation>,
 ) -> Result<DynamicImage, Error> {
-	let mut image = ImageReader::open(in_path)?.decode()?;
+	let (image, _) = process_file_with_report(in_path, operations)?;
+	Ok(image)
+}
 
-	for operation in operations.into_iter() {
-		image = operation.get_process().process(image)?;
-	}
+#[cfg(not(target_arch = "wasm32"))]
+pub fn process_file_with_report<P: AsRef<Path>>(
+	in_path: P,
+	operations: Vec<Operation>,
+) -> Result<(DynamicImage, ProcessingReport), Error> {
+	process_file_with_options(in_path, operations, &ProcessOptions::default())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn process_file_with_options<P: AsRef<Path>>(
+	in_path: P,
+	operations: Vec<Operation>,
+	options: &ProcessOptions,
+) -> Result<(DynamicImage, ProcessingReport), Error> {
+	process_path(in_path, &operations, options)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn process_path<P: AsRef<Path>>(
+	in_path: P,
+	operations: &[Operation],
+	options: &ProcessOptions,
+) -> Result<(DynamicImage, ProcessingReport), Error> {
+	let mut reader = io::BufReader::new(std::fs::File::open(&in_path)?);
+	let orientation =
+		if needs_orientation(operations, options) && !oriented_by_decoder(reader.fill_buf()?) {
+			metadata::read_metadata(&in_path)
+				.ok()
+				.and_then(|metadata| metadata.orientation)
+		} else {
+			None
+		};
 
+	let image = decode(reader, options)?;
+	apply_operations(image, operations, options, orientation)
+}
+
+/// Processes an encoded image, guessing its format from its contents.
+pub fn process_bytes(bytes: &[u8], operations: Vec<Operation>) -> Result<DynamicImage, Error> {
+	let (image, _) = process_bytes_with_options(bytes, operations, &ProcessOptions::default())?;
+	Ok(image)
+}
+
+/// Like [`process_bytes`], decoding within the limits of `options`.
+pub fn process_bytes_with_options(
+	bytes: &[u8],
+	operations: Vec<Operation>,
+	options: &ProcessOptions,
+) -> Result<(DynamicImage, ProcessingReport), Error> {
+	let orientation = bytes_orientation(bytes, &operations, options);
+	let image = decode(Cursor::new(bytes), options)?;
+	apply_operations(image, &operations, options, orientation)
+}
+
+/// Processes an encoded image from a reader, guessing its format from its contents.
+pub fn process_reader<R: BufRead + Seek>(
+	reader: R,
+	operations: Vec<Operation>,
+) -> Result<DynamicImage, Error> {
+	let (image, _) = process_reader_with_options(reader, operations, &ProcessOptions::default())?;
 	Ok(image)
 }
+
+/// Like [`process_reader`], decoding within the limits of `options`.
+pub fn process_reader_with_options<R: BufRead + Seek>(
+	mut reader: R,
+	operations: Vec<Operation>,
+	options: &ProcessOptions,
+) -> Result<(DynamicImage, ProcessingReport), Error> {
+	if needs_orientation(&operations, options) {
+		// Metadata is read from the whole encoded image
+		let mut bytes = Vec::new();
+		reader.read_to_end(&mut bytes)?;
+		return process_bytes_with_options(&bytes, operations, options);
+	}
+
+	let image = decode(reader, options)?;
+	apply_operations(image, &operations, options, None)
+}
+
+/// Processes an encoded image and encodes the result in `format`.
+pub fn process_and_encode(
+	bytes: &[u8],
+	operations: Vec<Operation>,
+	format: ImageOutputFormat,
+) -> Result<Vec<u8>, Error> {
+	let image = process_bytes(bytes, operations)?;
+	let mut out = Cursor::new(Vec::new());
+	encode_to(&mut out, &image, format)?;
+	Ok(out.into_inner())
+}
+
+/// Encodes `image` in `format`. Images with a color type the format cannot store, such as 16-bit
+/// images as JPEG or floating point images as PNG, are converted to the closest one it can.
+pub fn encode_to<W: Write + Seek>(
+	writer: &mut W,
+	image: &DynamicImage,
+	format: ImageOutputFormat,
+) -> Result<(), Error> {
+	let converted = encodable(image, &format);
+	let image = converted.as_ref().unwrap_or(image);
+	match format {
+		ImageOutputFormat::Png {
+			compression,
+			filter,
+			interlace: true,
+			..
+		} => encode::interlaced_png(writer, image, compression, filter)?,
+		ImageOutputFormat::Png {
+			optimize: Some(effort),
+			..
+		} => encode::optimized_png(writer, image, effort)?,
+		ImageOutputFormat::Png {
+			compression,
+			filter,
+			..
+		} => image.write_with_encoder(PngEncoder::new_with_quality(
+			writer,
+			compression.into(),
+			filter.into(),
+		))?,
+		#[cfg(not(target_arch = "wasm32"))]
+		ImageOutputFormat::WebP {
+			quality,
+			lossless,
+			alpha_quality,
+		} => encode::webp(writer, image, quality, lossless, alpha_quality)?,
+		#[cfg(all(feature = "mozjpeg", not(target_arch = "wasm32")))]
+		ImageOutputFormat::Jpeg { quality } => encode::mozjpeg(writer, image, quality)?,
+		#[cfg(feature = "jxl")]
+		ImageOutputFormat::Jxl {
+			quality,
+			effort,
+			lossless,
+		} => jxl::encode(writer, image, quality, effort, lossless)?,
+		format => image.write_to(writer, format)?,
+	}
+	Ok(())
+}
+
+/// `image` converted to a color type `format` supports, or `None` when it already has one.
+fn encodable(image: &DynamicImage, format: &ImageOutputFormat) -> Option<DynamicImage> {
+	use image::ColorType::{La8, Rgb32F, Rgb8, Rgba16, Rgba32F, Rgba8, L8};
+
+	let color = image.color();
+	let alpha = color.has_alpha();
+	let eight_bit = matches!(color, L8 | La8 | Rgb8 | Rgba8);
+	let float = matches!(color, Rgb32F | Rgba32F);
+	let to_eight_bit = || {
+		if alpha {
+			DynamicImage::ImageRgba8(image.to_rgba8())
+		} else {
+			DynamicImage::ImageRgb8(image.to_rgb8())
+		}
+	};
+
+	match format {
+		ImageOutputFormat::Jpeg { .. }
+		| ImageOutputFormat::WebP { .. }
+		| ImageOutputFormat::Bmp
+		| ImageOutputFormat::Tga
+			if !eight_bit =>
+		{
+			Some(to_eight_bit())
+		}
+		ImageOutputFormat::Qoi if !matches!(color, Rgb8 | Rgba8) => Some(to_eight_bit()),
+		ImageOutputFormat::Png { .. }
+		| ImageOutputFormat::Tiff
+		| ImageOutputFormat::Ico
+		| ImageOutputFormat::Avif
+			if float =>
+		{
+			Some(if alpha {
+				DynamicImage::ImageRgba16(image.to_rgba16())
+			} else {
+				DynamicImage::ImageRgb16(image.to_rgb16())
+			})
+		}
+		ImageOutputFormat::Farbfeld if color != Rgba16 => {
+			Some(DynamicImage::ImageRgba16(image.to_rgba16()))
+		}
+		ImageOutputFormat::OpenExr if !float => Some(if alpha {
+			DynamicImage::ImageRgba32F(image.to_rgba32f())
+		} else {
+			DynamicImage::ImageRgb32F(image.to_rgb32f())
+		}),
+		_ => None,
+	}
+}
+
+/// Limits on the images a decoder accepts, to reject decompression bombs before they take up all
+/// memory. The default only limits allocations, to the same 512MiB as `image` does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "snake_case")]
+pub struct DecodeLimits {
+	pub max_width: Option<u32>,
+	pub max_height: Option<u32>,
+	/// Width times height
+	pub max_pixels: Option<u64>,
+	/// Bytes the decoder may allocate at once. Some decoders ignore this.
+	pub max_alloc: Option<u64>,
+}
+
+impl DecodeLimits {
+	/// Accepts images of any size.
+	pub fn unlimited() -> Self {
+		Self {
+			max_width: None,
+			max_height: None,
+			max_pixels: None,
+			max_alloc: None,
+		}
+	}
+
+	/// Errors when an image of `width` by `height` is beyond the limits on its size.
+	fn check(&self, width: u32, height: u32) -> Result<(), Error> {
+		let pixels = width as u64 * height as u64;
+		if self.max_width.is_some_and(|max_width| width > max_width)
+			|| self
+				.max_height
+				.is_some_and(|max_height| height > max_height)
+			|| self
+				.max_pixels
+				.is_some_and(|max_pixels| pixels > max_pixels)
+		{
+			return Err(
+				image::ImageError::Limits(image::error::LimitError::from_kind(
+					image::error::LimitErrorKind::DimensionError,
+				))
+				.into(),
+			);
+		}
+
+		Ok(())
+	}
+
+	fn to_image_limits(self) -> image::io::Limits {
+		let mut limits = image::io::Limits::no_limits();
+		limits.max_image_width = self.max_width;
+		limits.max_image_height = self.max_height;
+		limits.max_alloc = self.max_alloc;
+		limits
+	}
+}
+
+impl Default for DecodeLimits {
+	fn default() -> Self {
+		Self {
+			max_alloc: image::io::Limits::default().max_alloc,
+			..Self::unlimited()
+		}
+	}
+}
+
+/// Options for processing which are not operations of their own.
+#[derive(Clone, Debug, Default)]
+pub struct ProcessOptions {
+	/// Applies the EXIF orientation of the source before any operations
+	pub auto_orient: bool,
+	/// Copies the EXIF, XMP, IPTC and ICC metadata of the source into JPEG, PNG and WebP outputs,
+	/// with the orientation reset when the source is oriented. See [`metadata::copy_metadata`].
+	pub keep_metadata: bool,
+	/// Notified as each operation starts and finishes
+	pub progress: Option<Arc<dyn ProgressHandler>>,
+	/// Stops processing with [`Error::Cancelled`] once set. It is checked between operations,
+	/// and within the slowest ones.
+	pub cancel: Option<Arc<AtomicBool>>,
+	/// Returns earlier outputs for the same input and pipeline instead of processing again
+	pub cache: Option<Arc<dyn Cache>>,
+	/// Limits on the source, checked before it is decoded
+	pub limits: DecodeLimits,
+	/// Fails with [`Error::MemoryLimitExceeded`] instead of decoding a source, or running an
+	/// operation, whose pixels would take more bytes than this. Only the decoded source and the
+	/// input and output of each operation are counted, not buffers operations use internally.
+	pub max_memory: Option<u64>,
+	/// Page and resolution to render PDF sources at
+	#[cfg(feature = "pdf")]
+	pub pdf: pdf::PdfOptions,
+	/// Runs resizing, blurring, convolution and color adjustments on the GPU when there is an
+	/// adapter. See [`gpu`].
+	#[cfg(all(feature = "gpu", not(target_arch = "wasm32")))]
+	pub gpu: bool,
+}
+
+impl ProcessOptions {
+	/// Errors when more than [`ProcessOptions::max_memory`] bytes are `required`.
+	fn check_memory(&self, required: impl FnOnce() -> u64) -> Result<(), Error> {
+		match self.max_memory {
+			Some(limit) => {
+				let required = required();
+				if required > limit {
+					Err(Error::MemoryLimitExceeded { required, limit })
+				} else {
+					Ok(())
+				}
+			}
+			None => Ok(()),
+		}
+	}
+
+	fn is_cancelled(&self) -> bool {
+		self.cancel
+			.as_ref()
+			.is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+	}
+}
+
+thread_local! {
+	/// Cancellation flag of the operations running on this thread
+	static CANCEL: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Makes the cancellation flag available to operations until dropped.
+struct CancelGuard(Option<Arc<AtomicBool>>);
+
+impl CancelGuard {
+	fn set(cancel: Option<Arc<AtomicBool>>) -> Self {
+		Self(CANCEL.replace(cancel))
+	}
+}
+
+impl Drop for CancelGuard {
+	fn drop(&mut self) {
+		CANCEL.set(self.0.take());
+	}
+}
+
+/// Errors when the operations running on this thread have been cancelled, for long operations
+/// to check as they go.
+pub(crate) fn check_cancelled() -> Result<(), OperationError> {
+	let cancelled = CANCEL.with_borrow(|cancel| {
+		cancel
+			.as_ref()
+			.is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+	});
+	if cancelled {
+		Err(OperationError::new("Cancelled".to_string()))
+	} else {
+		Ok(())
+	}
+}
+
+/// Decodes an image within the limits of `options`, reading its header first when the pixel
+/// count or memory has to be checked.
+fn decode<R: BufRead + Seek>(reader: R, options: &ProcessOptions) -> Result<DynamicImage, Error> {
+	#[cfg(feature = "heic")]
+	let reader = {
+		let mut reader = reader;
+		if heic::is_heif(reader.fill_buf()?) {
+			let mut bytes = Vec::new();
+			reader.read_to_end(&mut bytes)?;
+			return heic::decode(&bytes, options);
+		}
+		reader
+	};
+
+	#[cfg(feature = "jxl")]
+	let reader = {
+		let mut reader = reader;
+		if jxl::is_jxl(reader.fill_buf()?) {
+			let mut bytes = Vec::new();
+			reader.read_to_end(&mut bytes)?;
+			return jxl::decode(&bytes, options);
+		}
+		reader
+	};
+
+	#[cfg(feature = "pdf")]
+	let reader = {
+		let mut reader = reader;
+		if pdf::is_pdf(reader.fill_buf()?) {
+			let mut bytes = Vec::new();
+			reader.read_to_end(&mut bytes)?;
+			return pdf::render(&bytes, options);
+		}
+		reader
+	};
+
+	let limits = options.limits;
+	let mut reader = ImageReader::new(reader).with_guessed_format()?;
+	if limits.max_pixels.is_some() || options.max_memory.is_some() {
+		let format = reader.format();
+		let mut inner = reader.into_inner();
+		let start = inner.stream_position()?;
+		let info = plan::read_info(&mut inner)?;
+		if limits
+			.max_pixels
+			.is_some_and(|max_pixels| info.width as u64 * info.height as u64 > max_pixels)
+		{
+			return Err(
+				image::ImageError::Limits(image::error::LimitError::from_kind(
+					image::error::LimitErrorKind::DimensionError,
+				))
+				.into(),
+			);
+		}
+		options.check_memory(|| info.bytes())?;
+
+		inner.seek(io::SeekFrom::Start(start))?;
+		reader = ImageReader::new(inner);
+		if let Some(format) = format {
+			reader.set_format(format);
+		}
+	}
+
+	reader.limits(limits.to_image_limits());
+	Ok(reader.decode()?)
+}
+
+/// Decodes `bytes`, applies `operations` and encodes the result in `format`.
+pub(crate) fn decode_and_encode(
+	bytes: &[u8],
+	operations: &[Operation],
+	format: ImageOutputFormat,
+	options: &ProcessOptions,
+) -> Result<Vec<u8>, Error> {
+	let animation = match animation::supports(&format) {
+		true => Animation::decode(bytes, options)?,
+		false => None,
+	};
+
+	let mut out = Cursor::new(Vec::new());
+	match animation {
+		Some(animation) => animation
+			.process(operations, options)?
+			.encode_to(&mut out, format)?,
+		None => {
+			let image = decode(Cursor::new(bytes), options)?;
+			let orientation = bytes_orientation(bytes, operations, options);
+			let (image, _) = apply_operations(image, operations, options, orientation)?;
+			encode_to(&mut out, &image, format)?;
+		}
+	}
+
+	metadata::OutputMetadata::new(operations, options).write(bytes, out.into_inner())
+}
+
+/// The output cached for `input` and `pipeline`, or the output of `encode` which is then cached.
+pub(crate) fn cached(
+	input: &[u8],
+	pipeline: &impl Serialize,
+	options: &ProcessOptions,
+	encode: impl FnOnce() -> Result<Vec<u8>, Error>,
+) -> Result<Vec<u8>, Error> {
+	let Some((cache, key)) = options
+		.cache
+		.as_ref()
+		.and_then(|cache| Some((cache, cache::key(input, pipeline).ok()?)))
+	else {
+		return encode();
+	};
+
+	if let Some(output) = cache.get(&key) {
+		return Ok(output);
+	}
+	let output = encode()?;
+	cache.put(&key, &output);
+	Ok(output)
+}
+
+fn needs_orientation(operations: &[Operation], options: &ProcessOptions) -> bool {
+	options.auto_orient
+		|| operations
+			.iter()
+			.any(|operation| matches!(operation, Operation::AutoOrient(_)))
+}
+
+/// Whether the decoder applies the orientation of a source starting with `header` itself, as
+/// libheif does for HEIF, leaving no EXIF orientation to apply.
+#[cfg(feature = "heic")]
+fn oriented_by_decoder(header: &[u8]) -> bool {
+	heic::is_heif(header)
+}
+
+#[cfg(not(feature = "heic"))]
+fn oriented_by_decoder(_header: &[u8]) -> bool {
+	false
+}
+
+fn bytes_orientation(
+	bytes: &[u8],
+	operations: &[Operation],
+	options: &ProcessOptions,
+) -> Option<metadata::Orientation> {
+	if !needs_orientation(operations, options) || oriented_by_decoder(bytes) {
+		return None;
+	}
+
+	metadata::read_metadata_from_bytes(bytes)
+		.ok()
+		.and_then(|metadata| metadata.orientation)
+}
+
+fn apply_operations(
+	mut image: DynamicImage,
+	operations: &[Operation],
+	options: &ProcessOptions,
+	mut orientation: Option<metadata::Orientation>,
+) -> Result<(DynamicImage, ProcessingReport), Error> {
+	let mut report = ProcessingReport::default();
+
+	if options.auto_orient {
+		if let Some(orientation) = orientation.take() {
+			image = orientation.apply(image);
+		}
+	}
+
+	let _cancel = CancelGuard::set(options.cancel.clone());
+	let count = operations.len();
+	let progress = |progress| {
+		if let Some(handler) = &options.progress {
+			handler.on_progress(progress);
+		}
+	};
+
+	for (position, operation) in operations.iter().enumerate() {
+		if options.is_cancelled() {
+			return Err(Error::Cancelled);
+		}
+
+		options.check_memory(|| {
+			let input = ImageInfo::of(&image);
+			let output = operation.get_process().plan(input).ok().flatten();
+			let output = output.map_or(0, |(width, height)| {
+				ImageInfo::new(width, height, input.color_type).bytes()
+			});
+			input.bytes() + output
+		})?;
+		progress(Progress::OperationStarted {
+			position,
+			count,
+			operation,
+		});
+		let (result, elapsed) = timed(|| match operation {
+			Operation::AutoOrient(_) => Ok(match orientation.take() {
+				Some(orientation) => orientation.apply(image),
+				None => image,
+			}),
+			Operation::Stats(stats) => {
+				report.stats.push(stats.snapshot(position, &image));
+				operation.get_process().process(image)
+			}
+			#[cfg(all(feature = "gpu", not(target_arch = "wasm32")))]
+			_ if options.gpu => gpu::Accelerated(operation).process(image),
+			_ => operation.get_process().process(image),
+		});
+		image = match result {
+			Err(_) if options.is_cancelled() => return Err(Error::Cancelled),
+			result => result?,
+		};
+		progress(Progress::OperationFinished {
+			position,
+			count,
+			operation,
+			elapsed,
+		});
+	}
+
+	Ok((image, report))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		apply_operations, check_cancelled, encode_to, metadata::Orientation,
+		process_bytes_with_options, process_reader, process_reader_with_options, Branch,
+		DecodeLimits, Error, ImageOutputFormat, Operation, Pipeline, ProcessOptions, Progress,
+	};
+	use crate::operations::Invert;
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage, Rgba32FImage};
+	use std::{
+		io::Cursor,
+		sync::{
+			atomic::{AtomicBool, Ordering},
+			Arc, Mutex,
+		},
+	};
+
+	#[test]
+	fn processes_from_readers() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 2, Rgb([10, 20, 30])));
+		let mut png = Cursor::new(Vec::new());
+		image
+			.write_to(&mut png, image::ImageOutputFormat::Png)
+			.unwrap();
+		png.set_position(0);
+
+		let inverted = process_reader(png, vec![Operation::Invert(Invert {})]).unwrap();
+		assert_eq!((4, 2), inverted.dimensions());
+		assert_eq!([245, 235, 225, 255], inverted.get_pixel(3, 1).0);
+
+		assert!(process_reader(Cursor::new(b"not an image"), Vec::new()).is_err());
+	}
+
+	#[test]
+	fn encodes_unsupported_color_types() {
+		let image = DynamicImage::ImageRgba32F(Rgba32FImage::new(4, 4));
+		for format in [
+			ImageOutputFormat::png(),
+			ImageOutputFormat::Jpeg { quality: 80 },
+			ImageOutputFormat::Qoi,
+			ImageOutputFormat::Farbfeld,
+		] {
+			let mut out = Cursor::new(Vec::new());
+			encode_to(&mut out, &image, format).unwrap();
+			assert!(image::load_from_memory(out.get_ref()).is_ok());
+		}
+	}
+
+	#[test]
+	#[cfg(not(feature = "jxl"))]
+	fn needs_jxl_feature() {
+		let image = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+		let error = encode_to(
+			&mut Cursor::new(Vec::new()),
+			&image,
+			ImageOutputFormat::jxl(),
+		);
+		assert!(matches!(
+			error,
+			Err(Error::ImageError(image::ImageError::Unsupported(_)))
+		));
+	}
+
+	#[test]
+	fn branch_output_path() {
+		let branch = Branch {
+			name: "small".to_string(),
+			out_format: ImageOutputFormat::webp(),
+			operations: Vec::new(),
+		};
+		assert_eq!(
+			std::path::Path::new("out/photo-small.webp"),
+			branch.output_path("out/photo.jpg")
+		);
+	}
+
+	#[test]
+	fn reports_progress() {
+		let pipeline: Pipeline = toml::from_str(
+			r#"
+			out_format = "png"
+			operations = [{ invert = {} }, { grayscale = {} }]
+			"#,
+		)
+		.unwrap();
+		let mut source = Cursor::new(Vec::new());
+		let image = DynamicImage::ImageRgba32F(Rgba32FImage::new(4, 4));
+		encode_to(&mut source, &image, ImageOutputFormat::png()).unwrap();
+
+		let events = Arc::new(Mutex::new(Vec::new()));
+		let handler = {
+			let events = events.clone();
+			move |progress: Progress<'_>| {
+				if let Progress::OperationFinished { operation, .. } = progress {
+					events.lock().unwrap().push(operation.name());
+				}
+			}
+		};
+		let options = ProcessOptions {
+			progress: Some(Arc::new(handler)),
+			..ProcessOptions::default()
+		};
+		pipeline
+			.process_bytes_with_options(source.get_ref(), options)
+			.unwrap();
+
+		assert_eq!(vec!["invert", "grayscale"], *events.lock().unwrap());
+	}
+
+	#[test]
+	fn predicts_output_dimensions() {
+		let pipeline = |operations: &str| {
+			let toml = format!("out_format = \"png\"\noperations = {operations}");
+			toml::from_str::<Pipeline>(&toml).unwrap()
+		};
+
+		let resize = pipeline(
+			r#"[
+				{ resize = { width = { pixel = { pixels = 300 } }, filter = "nearest", crop_mode = "preserve" } },
+				{ pad-to-aspect = { ratio = [1, 1] } },
+			]"#,
+		);
+		assert_eq!(
+			(300, 300),
+			resize.output_dimensions((1200, 800), None).unwrap()
+		);
+
+		let trim = pipeline("[{ trim = {} }]");
+		assert!(trim.output_dimensions((1200, 800), None).is_err());
+
+		let conditional = pipeline(
+			r#"[{ conditional = { if = "has-alpha", then = [{ scale = { factor = 0.5 } }] } }]"#,
+		);
+		assert!(conditional.output_dimensions((1200, 800), None).is_err());
+
+		let oriented = pipeline(
+			r#"[
+				{ resize = { width = { pixel = { pixels = 600 } }, filter = "nearest", crop_mode = "preserve" } },
+				{ auto-orient = {} },
+			]"#,
+		);
+		assert_eq!(
+			(400, 600),
+			oriented
+				.output_dimensions((1200, 800), Some(Orientation::Rotate90))
+				.unwrap()
+		);
+	}
+
+	#[test]
+	fn stops_when_cancelled() {
+		let operations: Vec<Operation> =
+			serde_json::from_str(r#"[{ "invert": {} }, { "grayscale": {} }]"#).unwrap();
+		let image = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+
+		// Cancelled as the first operation finishes, like a client disconnecting mid-way
+		let cancel = Arc::new(AtomicBool::new(false));
+		let handler = {
+			let cancel = cancel.clone();
+			move |progress: Progress<'_>| {
+				if let Progress::OperationFinished { .. } = progress {
+					cancel.store(true, Ordering::Relaxed);
+				}
+			}
+		};
+		let options = ProcessOptions {
+			progress: Some(Arc::new(handler)),
+			cancel: Some(cancel),
+			..ProcessOptions::default()
+		};
+		let result = apply_operations(image, &operations, &options, None);
+		assert!(matches!(result, Err(Error::Cancelled)));
+		assert!(check_cancelled().is_ok());
+	}
+
+	#[test]
+	fn stops_long_operations_part_way() {
+		let operations: Vec<Operation> = serde_json::from_str(
+			r#"[
+				{ "blur": { "sigma": 4.0 } },
+				{ "median-filter": { "radius": 3 } },
+				{ "bilateral-filter": { "spatial_sigma": 2.0, "range_sigma": 20.0 } },
+				{ "clahe": {} },
+				{ "oil-paint": { "radius": 3, "levels": 8 } }
+			]"#,
+		)
+		.unwrap();
+		let image = DynamicImage::ImageRgb8(RgbImage::new(64, 256));
+
+		// Cancelled as each operation starts, so only checks within it can stop it
+		for operation in operations {
+			let cancel = Arc::new(AtomicBool::new(false));
+			let handler = {
+				let cancel = cancel.clone();
+				move |progress: Progress<'_>| {
+					if let Progress::OperationStarted { .. } = progress {
+						cancel.store(true, Ordering::Relaxed);
+					}
+				}
+			};
+			let options = ProcessOptions {
+				progress: Some(Arc::new(handler)),
+				cancel: Some(cancel),
+				..ProcessOptions::default()
+			};
+			let result = apply_operations(image.clone(), &[operation], &options, None);
+			assert!(matches!(result, Err(Error::Cancelled)));
+		}
+	}
+
+	#[test]
+	fn enforces_decode_limits() {
+		let mut source = Cursor::new(Vec::new());
+		DynamicImage::ImageRgb8(RgbImage::new(20, 10))
+			.write_to(&mut source, image::ImageOutputFormat::Png)
+			.unwrap();
+		let pipeline = Pipeline {
+			out_format: ImageOutputFormat::png(),
+			auto_orient: false,
+			keep_metadata: false,
+			operations: Vec::new(),
+		};
+		let process = |limits| {
+			let options = ProcessOptions {
+				limits,
+				..ProcessOptions::default()
+			};
+			pipeline.process_bytes_with_options(source.get_ref(), options)
+		};
+
+		for limits in [
+			DecodeLimits {
+				max_width: Some(19),
+				..DecodeLimits::default()
+			},
+			DecodeLimits {
+				max_pixels: Some(199),
+				..DecodeLimits::default()
+			},
+		] {
+			assert!(matches!(
+				process(limits),
+				Err(Error::ImageError(image::ImageError::Limits(_)))
+			));
+		}
+		assert!(process(DecodeLimits {
+			max_width: Some(20),
+			max_pixels: Some(200),
+			..DecodeLimits::default()
+		})
+		.is_ok());
+
+		// The functions which return the image apply the same limits
+		let options = ProcessOptions {
+			limits: DecodeLimits {
+				max_pixels: Some(199),
+				..DecodeLimits::default()
+			},
+			..ProcessOptions::default()
+		};
+		assert!(matches!(
+			process_bytes_with_options(source.get_ref(), Vec::new(), &options),
+			Err(Error::ImageError(image::ImageError::Limits(_)))
+		));
+		assert!(matches!(
+			process_reader_with_options(Cursor::new(source.get_ref()), Vec::new(), &options),
+			Err(Error::ImageError(image::ImageError::Limits(_)))
+		));
+	}
+
+	#[test]
+	fn enforces_memory_limit() {
+		let operations: Vec<Operation> =
+			serde_json::from_str(r#"[{ "scale": { "factor": 2.0 } }]"#).unwrap();
+		let image = DynamicImage::ImageRgb8(RgbImage::new(10, 10));
+
+		// 300 bytes for the source and 1200 for the upscaled result
+		let options = |max_memory| ProcessOptions {
+			max_memory: Some(max_memory),
+			..ProcessOptions::default()
+		};
+		let result = apply_operations(image.clone(), &operations, &options(1499), None);
+		assert!(matches!(
+			result,
+			Err(Error::MemoryLimitExceeded {
+				required: 1500,
+				limit: 1499
+			})
+		));
+		assert!(apply_operations(image, &operations, &options(1500), None).is_ok());
+	}
+}
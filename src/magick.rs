@@ -0,0 +1,431 @@
+//! Translation between operations and ImageMagick `magick`/`convert` arguments, to help migrate
+//! scripts and compare results.
+//!
+//! Only operations with a direct equivalent are translated: resizing, cropping with pixel
+//! coordinates, trimming, flipping, auto-orientation, gaussian blur, grayscale and brightness.
+//! `stats` has no effect on the image and is skipped. `-strip` is `strip-metadata` without any
+//! fields kept.
+
+use crate::{
+	operations::{
+		AdjustBrightness, AutoOrient, Blur, Crop, CropMode, CropOrigin, FilterType, Flip,
+		Grayscale, Resize, StripMetadata, Trim,
+	},
+	Coordinate, Operation, PercentageUnit, PixelUnit, Unit,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MagickError {
+	#[error("Operation `{0}` has no ImageMagick equivalent")]
+	UnsupportedOperation(String),
+
+	#[error("Unsupported ImageMagick argument `{0}`")]
+	UnsupportedArgument(String),
+
+	#[error("Invalid value for ImageMagick argument `{0}`")]
+	InvalidArgument(String),
+}
+
+/// Formats operations as ImageMagick arguments, to be placed between the input and output files.
+pub fn to_args(operations: &[Operation]) -> Result<Vec<String>, MagickError> {
+	let mut args = Vec::new();
+
+	for operation in operations {
+		match operation {
+			Operation::AdjustBrightness(adjust) => {
+				let (method, value) = match adjust {
+					AdjustBrightness::Brighten(value) => ("Add", value),
+					AdjustBrightness::Darken(value) => ("Subtract", value),
+				};
+				args.extend([
+					"-evaluate".to_string(),
+					method.to_string(),
+					format!("{}%", percentage_of_range(*value)),
+				]);
+			}
+			Operation::AutoOrient(_) => args.push("-auto-orient".to_string()),
+			Operation::Blur(blur) => {
+				args.extend(["-gaussian-blur".to_string(), format!("0x{}", blur.sigma)]);
+			}
+			Operation::Crop(crop) => {
+				let unsupported = || MagickError::UnsupportedOperation("crop".to_string());
+				let x = pixels(&crop.from.x).ok_or_else(unsupported)?;
+				let y = pixels(&crop.from.y).ok_or_else(unsupported)?;
+				let (width, height) = match &crop.to {
+					CropOrigin::CropStart(size) => (
+						pixels(&size.x).ok_or_else(unsupported)?,
+						pixels(&size.y).ok_or_else(unsupported)?,
+					),
+					CropOrigin::Minimum(to) => (
+						pixels(&to.x)
+							.and_then(|right| right.checked_sub(x))
+							.ok_or_else(unsupported)?,
+						pixels(&to.y)
+							.and_then(|bottom| bottom.checked_sub(y))
+							.ok_or_else(unsupported)?,
+					),
+					CropOrigin::Maximum(_) => return Err(unsupported()),
+				};
+				args.extend([
+					"-crop".to_string(),
+					format!("{width}x{height}+{x}+{y}"),
+					"+repage".to_string(),
+				]);
+			}
+			Operation::Trim(Trim {
+				color: None,
+				tolerance,
+			}) => {
+				if *tolerance > 0.0 {
+					args.extend(["-fuzz".to_string(), format!("{}%", tolerance * 100.0)]);
+				}
+				args.extend(["-trim".to_string(), "+repage".to_string()]);
+			}
+			Operation::Flip(flip) => {
+				if matches!(flip, Flip::Horizontal | Flip::Both) {
+					args.push("-flop".to_string());
+				}
+				if matches!(flip, Flip::Vertical | Flip::Both) {
+					args.push("-flip".to_string());
+				}
+			}
+			Operation::Grayscale(_) => {
+				args.extend(["-colorspace".to_string(), "Gray".to_string()]);
+			}
+			Operation::Resize(resize) => {
+				let geometry = match (&resize.width, &resize.height) {
+					(Some(Unit::Pixel(width)), Some(Unit::Pixel(height))) => {
+						format!("{}x{}", width.pixels, height.pixels)
+					}
+					(Some(Unit::Pixel(width)), None) => width.pixels.to_string(),
+					(None, Some(Unit::Pixel(height))) => format!("x{}", height.pixels),
+					(Some(Unit::Percentage(width)), Some(Unit::Percentage(height))) => format!(
+						"{}x{}%",
+						width.percentage * 100.0,
+						height.percentage * 100.0
+					),
+					_ => return Err(MagickError::UnsupportedOperation("resize".to_string())),
+				};
+				args.extend([
+					"-filter".to_string(),
+					filter_name(resize.filter).to_string(),
+				]);
+				match resize.crop_mode {
+					CropMode::Preserve => args.extend(["-resize".to_string(), geometry]),
+					CropMode::Exact => args.extend(["-resize".to_string(), format!("{geometry}!")]),
+					CropMode::Fill => args.extend([
+						"-resize".to_string(),
+						format!("{geometry}^"),
+						"-gravity".to_string(),
+						"center".to_string(),
+						"-extent".to_string(),
+						geometry,
+					]),
+					CropMode::SeamCarve => {
+						args.extend(["-liquid-rescale".to_string(), format!("{geometry}!")])
+					}
+					CropMode::ShrinkOnly | CropMode::EnlargeOnly => {
+						return Err(MagickError::UnsupportedOperation("resize".to_string()))
+					}
+				}
+			}
+			Operation::Stats(_) => {}
+			Operation::StripMetadata(strip) if strip.keep.is_empty() => {
+				args.push("-strip".to_string())
+			}
+			#[allow(unreachable_patterns)]
+			other => {
+				let name = serde_json::to_value(other)
+					.ok()
+					.and_then(|value| value.as_object()?.keys().next().cloned())
+					.unwrap_or_default();
+				return Err(MagickError::UnsupportedOperation(name));
+			}
+		}
+	}
+
+	Ok(args)
+}
+
+/// Parses the subset of ImageMagick arguments produced by [`to_args`] into operations.
+///
+/// `-blur` is treated the same as `-gaussian-blur`, and `-type Grayscale` the same as
+/// `-colorspace Gray`. Any other argument returns [`MagickError::UnsupportedArgument`].
+pub fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<Vec<Operation>, MagickError> {
+	let mut args = args.iter().map(AsRef::as_ref).peekable();
+	let mut operations = Vec::new();
+	let mut filter = FilterType::Lanczos3;
+	let mut fuzz = 0.0;
+
+	while let Some(arg) = args.next() {
+		let mut value = || {
+			args.next()
+				.ok_or_else(|| MagickError::InvalidArgument(arg.to_string()))
+		};
+		let invalid = || MagickError::InvalidArgument(arg.to_string());
+
+		match arg {
+			"-filter" => {
+				filter = parse_filter(value()?).ok_or_else(invalid)?;
+			}
+			"-resize" => {
+				let geometry = value()?;
+				let (geometry, crop_mode) = match geometry.chars().last() {
+					Some('!') => (&geometry[..geometry.len() - 1], CropMode::Exact),
+					Some('^') => (&geometry[..geometry.len() - 1], CropMode::Fill),
+					_ => (geometry, CropMode::Preserve),
+				};
+				let (width, height) = parse_size(geometry).ok_or_else(invalid)?;
+
+				if let CropMode::Fill = crop_mode {
+					// Filling is only supported as a resize followed by a centered extent
+					let extent = [args.next(), args.next(), args.next(), args.next()];
+					if extent[..3] != [Some("-gravity"), Some("center"), Some("-extent")]
+						|| extent[3] != Some(geometry)
+					{
+						return Err(MagickError::UnsupportedArgument(format!(
+							"{arg} {geometry}^"
+						)));
+					}
+				}
+
+				operations.push(Operation::Resize(Resize {
+					width,
+					height,
+					filter,
+					crop_mode,
+				}));
+			}
+			"-liquid-rescale" => {
+				// Only exact sizes match seam carving, which never keeps the aspect ratio itself
+				let geometry = value()?;
+				let size = geometry
+					.strip_suffix('!')
+					.ok_or_else(|| MagickError::UnsupportedArgument(format!("{arg} {geometry}")))?;
+				let (width, height) = parse_size(size).ok_or_else(invalid)?;
+
+				operations.push(Operation::Resize(Resize {
+					width,
+					height,
+					filter,
+					crop_mode: CropMode::SeamCarve,
+				}));
+			}
+			"-crop" => {
+				let geometry = value()?;
+				let (size, offset) = geometry.split_once('+').ok_or_else(invalid)?;
+				let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+				let (x, y) = offset.split_once('+').ok_or_else(invalid)?;
+				let pixel = |value: &str| {
+					value
+						.parse::<u32>()
+						.map(|pixels| Unit::Pixel(PixelUnit::from(pixels)))
+						.map_err(|_| invalid())
+				};
+
+				operations.push(Operation::Crop(Crop {
+					from: Coordinate {
+						x: pixel(x)?,
+						y: pixel(y)?,
+					},
+					to: CropOrigin::CropStart(Coordinate {
+						x: pixel(width)?,
+						y: pixel(height)?,
+					}),
+				}));
+			}
+			"+repage" => {}
+			"-fuzz" => {
+				let percentage = value()?.strip_suffix('%').ok_or_else(invalid)?;
+				fuzz = percentage
+					.parse::<f32>()
+					.ok()
+					.filter(|fuzz| (0.0..=100.0).contains(fuzz))
+					.ok_or_else(invalid)?
+					/ 100.0;
+			}
+			"-trim" => operations.push(Operation::Trim(Trim {
+				color: None,
+				tolerance: fuzz,
+			})),
+			"-auto-orient" => operations.push(Operation::AutoOrient(AutoOrient {})),
+			"-strip" => operations.push(Operation::StripMetadata(StripMetadata::default())),
+			"-flop" => operations.push(Operation::Flip(Flip::Horizontal)),
+			"-flip" => operations.push(Operation::Flip(Flip::Vertical)),
+			"-blur" | "-gaussian-blur" => {
+				let (_, sigma) = value()?.split_once('x').ok_or_else(invalid)?;
+				operations.push(Operation::Blur(Blur {
+					sigma: sigma.parse().map_err(|_| invalid())?,
+				}));
+			}
+			"-colorspace" | "-type" => match value()? {
+				"Gray" | "gray" | "Grayscale" | "grayscale" => {
+					operations.push(Operation::Grayscale(Grayscale {}))
+				}
+				other => return Err(MagickError::UnsupportedArgument(format!("{arg} {other}"))),
+			},
+			"-evaluate" => {
+				let method = value()?;
+				let amount = value()?
+					.strip_suffix('%')
+					.and_then(|amount| amount.parse::<f32>().ok())
+					.filter(|amount| (0.0..=100.0).contains(amount))
+					.ok_or_else(invalid)?;
+				let amount = (amount * 255.0 / 100.0).round() as u16;
+
+				operations.push(Operation::AdjustBrightness(
+					match method.to_ascii_lowercase().as_str() {
+						"add" => AdjustBrightness::Brighten(amount),
+						"subtract" => AdjustBrightness::Darken(amount),
+						_ => {
+							return Err(MagickError::UnsupportedArgument(format!("{arg} {method}")))
+						}
+					},
+				));
+			}
+			other => return Err(MagickError::UnsupportedArgument(other.to_string())),
+		}
+	}
+
+	Ok(operations)
+}
+
+fn pixels(unit: &Unit) -> Option<u32> {
+	match unit {
+		Unit::Pixel(pixels) => Some(pixels.pixels),
+		Unit::Percentage(_) | Unit::FromEnd(_) | Unit::Expr(_) => None,
+	}
+}
+
+/// Brightness offset as a percentage of the 8-bit channel range.
+fn percentage_of_range(value: u16) -> f32 {
+	(value as f32 * 100.0 / 255.0 * 100.0).round() / 100.0
+}
+
+fn filter_name(filter: FilterType) -> &'static str {
+	match filter {
+		FilterType::Nearest => "Point",
+		FilterType::Triangle => "Triangle",
+		FilterType::CatmullRom => "Catrom",
+		FilterType::Gaussian => "Gaussian",
+		FilterType::Lanczos3 => "Lanczos",
+	}
+}
+
+fn parse_filter(name: &str) -> Option<FilterType> {
+	match name.to_ascii_lowercase().as_str() {
+		"point" | "box" => Some(FilterType::Nearest),
+		"triangle" => Some(FilterType::Triangle),
+		"catrom" | "catmull-rom" => Some(FilterType::CatmullRom),
+		"gaussian" => Some(FilterType::Gaussian),
+		"lanczos" => Some(FilterType::Lanczos3),
+		_ => None,
+	}
+}
+
+/// Parses `<width>x<height>`, `<width>`, `x<height>` or the percentage forms `<width>x<height>%`
+/// and `<scale>%`. A missing dimension is left out, to follow the aspect ratio.
+fn parse_size(geometry: &str) -> Option<(Option<Unit>, Option<Unit>)> {
+	if let Some(geometry) = geometry.strip_suffix('%') {
+		let percentage = |value: &str| {
+			PercentageUnit::try_from(value.parse::<f32>().ok()? / 100.0)
+				.ok()
+				.map(Unit::Percentage)
+		};
+		return match geometry.split_once('x') {
+			Some((width, height)) => Some((Some(percentage(width)?), Some(percentage(height)?))),
+			None => Some((Some(percentage(geometry)?), Some(percentage(geometry)?))),
+		};
+	}
+
+	let pixel = |value: &str| match value {
+		"" => Some(None),
+		value => Some(Some(Unit::Pixel(PixelUnit::from(
+			value.parse::<u32>().ok()?,
+		)))),
+	};
+	let (width, height) = geometry.split_once('x').unwrap_or((geometry, ""));
+	if width.is_empty() && height.is_empty() {
+		return None;
+	}
+
+	Some((pixel(width)?, pixel(height)?))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_args, to_args, MagickError};
+	use crate::Operation;
+
+	#[test]
+	fn round_trip() {
+		let args = [
+			"-crop",
+			"100x50+10+20",
+			"+repage",
+			"-filter",
+			"Catrom",
+			"-resize",
+			"40x40^",
+			"-gravity",
+			"center",
+			"-extent",
+			"40x40",
+			"-gaussian-blur",
+			"0x1.5",
+			"-colorspace",
+			"Gray",
+			"-evaluate",
+			"Subtract",
+			"20%",
+			"-strip",
+		];
+		let operations = parse_args(&args).unwrap();
+
+		assert_eq!(6, operations.len());
+		assert_eq!(args.to_vec(), to_args(&operations).unwrap());
+	}
+
+	#[test]
+	fn trim_round_trip() {
+		let args = ["-fuzz", "5%", "-trim", "+repage"];
+		let operations = parse_args(&args).unwrap();
+
+		assert_eq!(1, operations.len());
+		assert_eq!(args.to_vec(), to_args(&operations).unwrap());
+	}
+
+	#[test]
+	fn parse_resize_percentage() {
+		let operations = parse_args(&["-resize", "50%"]).unwrap();
+
+		assert_eq!(
+			vec!["-filter", "Lanczos", "-resize", "50x50%"],
+			to_args(&operations).unwrap()
+		);
+	}
+
+	#[test]
+	fn unsupported() {
+		assert_eq!(
+			Some(MagickError::UnsupportedArgument("-rotate".to_string())),
+			parse_args(&["-rotate", "90"]).err()
+		);
+		assert_eq!(
+			Some(MagickError::UnsupportedArgument(
+				"-resize 40x40^".to_string()
+			)),
+			parse_args(&["-resize", "40x40^"]).err()
+		);
+		assert!(matches!(
+			parse_args(&["-resize", "40x40>"]).err(),
+			Some(MagickError::InvalidArgument(_))
+		));
+		assert!(
+			to_args(&[Operation::Stats(crate::operations::Stats { label: None })])
+				.unwrap()
+				.is_empty()
+		);
+	}
+}
@@ -0,0 +1,192 @@
+use crate::{metadata::find_bytes, Error};
+use image::{
+	codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+	io::Reader as ImageReader,
+	AnimationDecoder, Frame, Frames, ImageFormat,
+};
+use serde::Serialize;
+use std::io::Cursor;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, path::Path};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoopCount {
+	Infinite,
+	/// Total number of times the animation is played
+	Finite(u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct AnimationInfo {
+	pub frame_count: usize,
+	/// Delay of each frame in milliseconds
+	pub delays: Vec<u32>,
+	pub loop_count: LoopCount,
+	/// Duration of a single play of the animation in milliseconds
+	pub duration: u32,
+}
+
+/// Reads animation details of a GIF, APNG or WebP file.
+///
+/// Returns `None` for still images, including single frame GIFs.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_animation_info<P: AsRef<Path>>(path: P) -> Result<Option<AnimationInfo>, Error> {
+	read_animation_info_from_bytes(&fs::read(path)?)
+}
+
+pub fn read_animation_info_from_bytes(bytes: &[u8]) -> Result<Option<AnimationInfo>, Error> {
+	let Some((frames, loop_count)) = decode_frames(bytes)? else {
+		return Ok(None);
+	};
+
+	let delays = frame_delays(frames)?;
+	if delays.len() < 2 {
+		return Ok(None);
+	}
+
+	Ok(Some(AnimationInfo {
+		frame_count: delays.len(),
+		duration: delays.iter().sum(),
+		delays,
+		loop_count,
+	}))
+}
+
+/// Frames of a GIF, APNG or WebP file along with its loop count, or `None` for other formats and
+/// PNG and WebP stills. GIFs can still have a single frame.
+pub(crate) fn decode_frames(bytes: &[u8]) -> Result<Option<(Frames<'_>, LoopCount)>, Error> {
+	let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+	let format = reader.format();
+	let reader = reader.into_inner();
+
+	Ok(Some(match format {
+		Some(ImageFormat::Gif) => (
+			GifDecoder::new(reader)?.into_frames(),
+			gif_loop_count(bytes),
+		),
+		Some(ImageFormat::Png) => {
+			let decoder = PngDecoder::new(reader)?;
+			if !decoder.is_apng() {
+				return Ok(None);
+			}
+			(decoder.apng().into_frames(), apng_loop_count(bytes))
+		}
+		Some(ImageFormat::WebP) => {
+			let decoder = WebPDecoder::new(reader)?;
+			if !decoder.has_animation() {
+				return Ok(None);
+			}
+			(decoder.into_frames(), webp_loop_count(bytes))
+		}
+		_ => return Ok(None),
+	}))
+}
+
+/// Delay of a frame in whole milliseconds.
+pub(crate) fn frame_delay(frame: &Frame) -> u32 {
+	let (numerator, denominator) = frame.delay().numer_denom_ms();
+	numerator / denominator.max(1)
+}
+
+fn frame_delays(frames: Frames) -> Result<Vec<u32>, Error> {
+	frames.map(|frame| Ok(frame_delay(&frame?))).collect()
+}
+
+/// GIFs loop through the NETSCAPE2.0 application extension, where the count is the number of
+/// repetitions after the first play. Without the extension the animation plays once.
+fn gif_loop_count(bytes: &[u8]) -> LoopCount {
+	let extension = find_bytes(bytes, b"NETSCAPE2.0")
+		.and_then(|start| bytes.get(start + 11..start + 15))
+		.filter(|block| block[0] == 3 && block[1] == 1);
+
+	match extension {
+		Some(block) => match u16::from_le_bytes([block[2], block[3]]) {
+			0 => LoopCount::Infinite,
+			repetitions => LoopCount::Finite(repetitions as u32 + 1),
+		},
+		None => LoopCount::Finite(1),
+	}
+}
+
+/// The `acTL` chunk holds the frame count followed by the number of plays.
+fn apng_loop_count(bytes: &[u8]) -> LoopCount {
+	let plays = find_bytes(bytes, b"acTL")
+		.and_then(|start| bytes.get(start + 8..start + 12))
+		.map(|plays| u32::from_be_bytes([plays[0], plays[1], plays[2], plays[3]]));
+
+	match plays {
+		Some(0) => LoopCount::Infinite,
+		Some(plays) => LoopCount::Finite(plays),
+		None => LoopCount::Finite(1),
+	}
+}
+
+/// The `ANIM` chunk holds the chunk size and background color followed by the loop count.
+fn webp_loop_count(bytes: &[u8]) -> LoopCount {
+	let loops = find_bytes(bytes, b"ANIM")
+		.and_then(|start| bytes.get(start + 12..start + 14))
+		.map(|loops| u16::from_le_bytes([loops[0], loops[1]]));
+
+	match loops {
+		Some(0) | None => LoopCount::Infinite,
+		Some(loops) => LoopCount::Finite(loops as u32),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{read_animation_info_from_bytes, AnimationInfo, LoopCount};
+	use image::{
+		codecs::gif::{GifEncoder, Repeat},
+		Delay, DynamicImage, Frame, ImageOutputFormat, Rgba, RgbaImage,
+	};
+	use std::io::Cursor;
+
+	fn gif(delays: &[u32], repeat: Repeat) -> Vec<u8> {
+		let mut bytes = Vec::new();
+		{
+			let mut encoder = GifEncoder::new(&mut bytes);
+			encoder.set_repeat(repeat).unwrap();
+			let frames = delays.iter().enumerate().map(|(index, &delay)| {
+				let pixels = RgbaImage::from_pixel(4, 4, Rgba([index as u8 * 60, 0, 0, 255]));
+				Frame::from_parts(pixels, 0, 0, Delay::from_numer_denom_ms(delay, 1))
+			});
+			encoder.encode_frames(frames).unwrap();
+		}
+		bytes
+	}
+
+	#[test]
+	fn reads_gif_animations() {
+		assert_eq!(
+			Some(AnimationInfo {
+				frame_count: 3,
+				delays: vec![100, 200, 50],
+				loop_count: LoopCount::Finite(3),
+				duration: 350,
+			}),
+			read_animation_info_from_bytes(&gif(&[100, 200, 50], Repeat::Finite(2))).unwrap()
+		);
+
+		let info = read_animation_info_from_bytes(&gif(&[40, 40], Repeat::Infinite))
+			.unwrap()
+			.unwrap();
+		assert_eq!(LoopCount::Infinite, info.loop_count);
+	}
+
+	#[test]
+	fn ignores_stills() {
+		assert_eq!(
+			None,
+			read_animation_info_from_bytes(&gif(&[100], Repeat::Infinite)).unwrap()
+		);
+
+		let mut png = Cursor::new(Vec::new());
+		DynamicImage::ImageRgba8(RgbaImage::new(4, 4))
+			.write_to(&mut png, ImageOutputFormat::Png)
+			.unwrap();
+		assert_eq!(None, read_animation_info_from_bytes(png.get_ref()).unwrap());
+	}
+}
@@ -0,0 +1,783 @@
+//! Copying of metadata blocks into encoded files. Encoders in `image` write pixels alone, so the
+//! blocks are read from the source file and inserted into the encoded output afterwards, without
+//! decoding either of them.
+
+use crate::{
+	metadata::{icc, xmp},
+	needs_orientation,
+	operations::{MetadataField, SetMetadata},
+	Error, Operation, ProcessOptions,
+};
+use ::exif::{experimental::Writer, Field, In, Tag, Value};
+use flate2::{write::ZlibEncoder, Compression};
+use image::{codecs::webp::WebPDecoder, ImageDecoder, ImageFormat};
+use std::io::{Cursor, Write};
+
+const APP0: u8 = 0xE0;
+const APP1: u8 = 0xE1;
+const APP2: u8 = 0xE2;
+const APP13: u8 = 0xED;
+const SOS: u8 = 0xDA;
+const EXIF_SIGNATURE: &[u8] = b"Exif\0\0";
+const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const IPTC_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+const ICC_SIGNATURE: &[u8] = b"ICC_PROFILE\0";
+const XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const ORIENTATION_TAG: u16 = 0x0112;
+/// Most of an ICC profile which fits in a JPEG segment, after the signature and the numbering
+const ICC_CHUNK_SIZE: usize = u16::MAX as usize - 2 - 14;
+
+/// Raw metadata blocks of a file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Blocks {
+	/// A TIFF header followed by the IFDs, as JPEG APP1, PNG eXIf and WebP EXIF hold it
+	pub exif: Option<Vec<u8>>,
+	/// An `x:xmpmeta` element
+	pub xmp: Option<String>,
+	/// Photoshop image resources holding IPTC records, which only JPEG has a place for
+	pub iptc: Option<Vec<u8>>,
+	/// An ICC profile describing the colors of the pixels
+	pub icc: Option<Vec<u8>>,
+}
+
+/// Copies the EXIF, XMP, IPTC and ICC metadata of `source` into `encoded`, a JPEG, PNG or WebP, or
+/// only the fields in `keep` as `strip-metadata` does. When `upright`, the pixels have already
+/// been oriented, so the orientation is reset to normal.
+///
+/// PNG and WebP have no place for IPTC, which most editors mirror into XMP anyway. Other output
+/// formats are returned as they are.
+pub fn copy_metadata(
+	source: &[u8],
+	encoded: Vec<u8>,
+	keep: Option<&[MetadataField]>,
+	upright: bool,
+) -> Result<Vec<u8>, Error> {
+	Blocks::copied(source, keep, upright).embed(encoded)
+}
+
+/// Metadata which outputs carry, as [`ProcessOptions::keep_metadata`] and the `strip-metadata` and
+/// `set-metadata` operations decide it.
+#[derive(Clone, Debug, Default)]
+pub struct OutputMetadata {
+	keep_all: bool,
+	/// Fields kept by the last `strip-metadata`
+	keep: Option<Vec<MetadataField>>,
+	upright: bool,
+	set: Vec<SetMetadata>,
+}
+
+impl OutputMetadata {
+	pub fn new(operations: &[Operation], options: &ProcessOptions) -> Self {
+		Self {
+			keep_all: options.keep_metadata,
+			keep: operations
+				.iter()
+				.rev()
+				.find_map(|operation| match operation {
+					Operation::StripMetadata(strip) => Some(strip.keep.clone()),
+					_ => None,
+				}),
+			upright: needs_orientation(operations, options),
+			set: operations
+				.iter()
+				.filter_map(|operation| match operation {
+					Operation::SetMetadata(set) => Some(set.clone()),
+					_ => None,
+				})
+				.collect(),
+		}
+	}
+
+	/// Whether any metadata is kept from the source, which then has to be read.
+	pub fn reads_source(&self) -> bool {
+		match &self.keep {
+			Some(keep) => !keep.is_empty(),
+			None => self.keep_all,
+		}
+	}
+
+	/// Whether outputs carry no metadata at all.
+	pub fn is_empty(&self) -> bool {
+		!self.reads_source() && self.set.is_empty()
+	}
+
+	/// Writes the metadata into `encoded`, a JPEG, PNG or WebP, with what's kept read from
+	/// `source`. Other formats are returned as they are.
+	pub fn write(&self, source: &[u8], encoded: Vec<u8>) -> Result<Vec<u8>, Error> {
+		let mut blocks = match self.reads_source() {
+			true => Blocks::copied(source, self.keep.as_deref(), self.upright),
+			false => Blocks::default(),
+		};
+		for set in &self.set {
+			blocks.set(set);
+		}
+		blocks.embed(encoded)
+	}
+}
+
+impl Blocks {
+	/// Blocks of a JPEG, PNG, TIFF or WebP file. Blocks which can't be read are left out, and so are
+	/// ICC profiles of color spaces the pixels are converted out of when decoding.
+	pub(crate) fn read(bytes: &[u8]) -> Self {
+		let exif = ::exif::Reader::new()
+			.read_from_container(&mut Cursor::new(bytes))
+			.ok()
+			.map(|exif| exif.buf().to_vec());
+		let icc = icc::read_profile(bytes)
+			.ok()
+			.flatten()
+			.filter(|(profile, channels)| {
+				icc::parse_profile(profile, *channels).is_some_and(|profile| !profile.converted)
+			})
+			.map(|(profile, _)| profile);
+
+		Self {
+			exif,
+			xmp: xmp::find_packet(bytes).map(str::to_string),
+			iptc: jpeg_segment(bytes, APP13, IPTC_SIGNATURE).map(<[u8]>::to_vec),
+			icc,
+		}
+	}
+
+	/// Blocks of `source` as [`copy_metadata`] copies them.
+	fn copied(source: &[u8], keep: Option<&[MetadataField]>, upright: bool) -> Self {
+		let mut blocks = Self::read(source);
+		if let Some(keep) = keep {
+			blocks = blocks.retain(keep);
+		}
+		if upright {
+			blocks.reset_orientation();
+		}
+		blocks
+	}
+
+	/// Only the fields in `keep`, written as EXIF and the ICC profile. Copyright, artist and camera
+	/// are taken from XMP when EXIF doesn't have them, and the rest of XMP and IPTC is dropped.
+	fn retain(self, keep: &[MetadataField]) -> Self {
+		let exif = self
+			.exif
+			.and_then(|exif| ::exif::Reader::new().read_raw(exif).ok());
+
+		let mut fields: Vec<Field> = Vec::new();
+		for &(tag, xmp_name) in keep.iter().flat_map(|field| retained_tags(*field)) {
+			if fields.iter().any(|field| field.tag == tag) {
+				continue;
+			}
+			let field = exif
+				.as_ref()
+				.and_then(|exif| exif.get_field(tag, In::PRIMARY))
+				.cloned()
+				.or_else(|| {
+					let value = xmp::property(self.xmp.as_deref()?, xmp_name?)?;
+					Some(Field {
+						tag,
+						ifd_num: In::PRIMARY,
+						value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+					})
+				});
+			fields.extend(field);
+		}
+
+		Self {
+			exif: write_exif(&fields),
+			icc: self
+				.icc
+				.filter(|_| keep.contains(&MetadataField::ColorProfile)),
+			..Self::default()
+		}
+	}
+
+	/// Writes the fields of `set` over those of the blocks, as both EXIF and XMP.
+	fn set(&mut self, set: &SetMetadata) {
+		let fields: Vec<Field> = [
+			(Tag::Copyright, &set.copyright),
+			(Tag::Artist, &set.artist),
+			(Tag::ImageDescription, &set.description),
+		]
+		.into_iter()
+		.filter_map(|(tag, value)| {
+			Some(Field {
+				tag,
+				ifd_num: In::PRIMARY,
+				value: Value::Ascii(vec![value.as_ref()?.as_bytes().to_vec()]),
+			})
+		})
+		.collect();
+		if !fields.is_empty() {
+			self.exif = merge_exif(self.exif.take(), &fields);
+		}
+
+		// Copyright and description are in alternative languages, and artists are ordered
+		let alternative = |value: &String| {
+			format!(
+				r#"<rdf:Alt><rdf:li xml:lang="x-default">{}</rdf:li></rdf:Alt>"#,
+				xmp::escape(value)
+			)
+		};
+		let mut properties: Vec<(String, String)> = [
+			("dc:rights", set.copyright.as_ref().map(alternative)),
+			(
+				"dc:creator",
+				set.artist.as_ref().map(|artist| {
+					format!(
+						"<rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq>",
+						xmp::escape(artist)
+					)
+				}),
+			),
+			("dc:description", set.description.as_ref().map(alternative)),
+		]
+		.into_iter()
+		.filter_map(|(name, value)| Some((name.to_string(), value?)))
+		.collect();
+		for (name, value) in &set.xmp {
+			if !properties.iter().any(|(existing, _)| existing == name) {
+				properties.push((name.clone(), xmp::escape(value)));
+			}
+		}
+		if properties.is_empty() {
+			return;
+		}
+
+		let mut namespaces: Vec<(&str, &str)> = Vec::new();
+		for (name, _) in &properties {
+			let Some((prefix, _)) = name.split_once(':') else {
+				continue;
+			};
+			if let Some(namespace) = set.namespace(prefix) {
+				if !namespaces.iter().any(|(existing, _)| *existing == prefix) {
+					namespaces.push((prefix, namespace));
+				}
+			}
+		}
+		self.xmp = Some(xmp::set_properties(
+			self.xmp.take(),
+			&properties,
+			&namespaces,
+		));
+	}
+
+	fn is_empty(&self) -> bool {
+		self.exif.is_none() && self.xmp.is_none() && self.iptc.is_none() && self.icc.is_none()
+	}
+
+	/// Sets the EXIF and XMP orientation to normal.
+	pub(crate) fn reset_orientation(&mut self) {
+		if let Some(exif) = &mut self.exif {
+			set_exif_orientation(exif, 1);
+		}
+		if let Some(packet) = &mut self.xmp {
+			for (open, close) in [
+				("tiff:Orientation=\"", "\""),
+				("<tiff:Orientation>", "</tiff:Orientation>"),
+			] {
+				let Some(start) = packet.find(open).map(|start| start + open.len()) else {
+					continue;
+				};
+				if let Some(end) = packet[start..].find(close) {
+					packet.replace_range(start..start + end, "1");
+				}
+			}
+		}
+	}
+
+	/// Inserts the blocks into an encoded JPEG, PNG or WebP, returning other formats as they are.
+	pub(crate) fn embed(&self, encoded: Vec<u8>) -> Result<Vec<u8>, Error> {
+		if self.is_empty() {
+			return Ok(encoded);
+		}
+
+		match image::guess_format(&encoded) {
+			Ok(ImageFormat::Jpeg) => Ok(self.embed_jpeg(&encoded)),
+			Ok(ImageFormat::Png) => self.embed_png(&encoded),
+			Ok(ImageFormat::WebP) => self.embed_webp(&encoded),
+			_ => Ok(encoded),
+		}
+	}
+
+	fn embed_jpeg(&self, jpeg: &[u8]) -> Vec<u8> {
+		// Segments go after SOI and the JFIF APP0 segment, which has to come first
+		let mut position = 2;
+		if let Some(&[0xFF, APP0, high, low]) = jpeg.get(2..6) {
+			position += 2 + u16::from_be_bytes([high, low]) as usize;
+		}
+
+		let mut out = jpeg[..position].to_vec();
+		let segments = [
+			(APP1, EXIF_SIGNATURE, self.exif.as_deref()),
+			(APP1, XMP_SIGNATURE, self.xmp.as_deref().map(str::as_bytes)),
+			(APP13, IPTC_SIGNATURE, self.iptc.as_deref()),
+		];
+		for (marker, signature, data) in segments {
+			let Some(data) = data else {
+				continue;
+			};
+			// Lengths are 16 bits, and larger blocks would have to be split, which few readers
+			// support
+			push_jpeg_segment(&mut out, marker, &[signature, data]);
+		}
+		if let Some(profile) = &self.icc {
+			// ICC profiles are the exception, split over segments numbered from 1
+			let chunks: Vec<&[u8]> = profile.chunks(ICC_CHUNK_SIZE).collect();
+			if let Ok(count) = u8::try_from(chunks.len()) {
+				for (number, chunk) in (1..).zip(chunks) {
+					push_jpeg_segment(&mut out, APP2, &[ICC_SIGNATURE, &[number, count], chunk]);
+				}
+			}
+		}
+		out.extend(&jpeg[position..]);
+		out
+	}
+
+	fn embed_png(&self, png: &[u8]) -> Result<Vec<u8>, Error> {
+		// Chunks go after the signature and the IHDR chunk with its 13 bytes of data
+		let position = 8 + 12 + 13;
+
+		let mut out = png[..position].to_vec();
+		if let Some(profile) = &self.icc {
+			// Named, followed by the compression method and the deflated profile
+			let mut data = b"ICC profile\0\0".to_vec();
+			let mut encoder = ZlibEncoder::new(&mut data, Compression::default());
+			encoder.write_all(profile)?;
+			encoder.finish()?;
+			push_png_chunk(&mut out, b"iCCP", &data);
+		}
+		if let Some(exif) = &self.exif {
+			push_png_chunk(&mut out, b"eXIf", exif);
+		}
+		if let Some(packet) = &self.xmp {
+			// Uncompressed, without a language or translated keyword
+			let mut data = XMP_KEYWORD.to_vec();
+			data.extend([0; 5]);
+			data.extend(packet.as_bytes());
+			push_png_chunk(&mut out, b"iTXt", &data);
+		}
+		out.extend(&png[position..]);
+		Ok(out)
+	}
+
+	fn embed_webp(&self, webp: &[u8]) -> Result<Vec<u8>, Error> {
+		const ICC: u8 = 0x20;
+		const ALPHA: u8 = 0x10;
+		const EXIF: u8 = 0x08;
+		const XMP: u8 = 0x04;
+
+		let mut chunks = webp.get(12..).unwrap_or_default().to_vec();
+		if !chunks.starts_with(b"VP8X") {
+			// Files with the bitstream alone need the extended header before other chunks
+			let decoder = WebPDecoder::new(Cursor::new(webp))?;
+			let (width, height) = decoder.dimensions();
+			let mut header = b"VP8X".to_vec();
+			header.extend(10u32.to_le_bytes());
+			header.push(if decoder.color_type().has_alpha() {
+				ALPHA
+			} else {
+				0
+			});
+			header.extend([0; 3]);
+			header.extend(&(width - 1).to_le_bytes()[..3]);
+			header.extend(&(height - 1).to_le_bytes()[..3]);
+			chunks.splice(0..0, header);
+		}
+
+		// The ICC profile comes right after the extended header
+		if let Some(profile) = &self.icc {
+			chunks[8] |= ICC;
+			let mut chunk = Vec::new();
+			push_riff_chunk(&mut chunk, b"ICCP", profile);
+			chunks.splice(18..18, chunk);
+		}
+
+		// EXIF and XMP come last, after the image or the frames of an animation
+		if let Some(exif) = &self.exif {
+			chunks[8] |= EXIF;
+			push_riff_chunk(&mut chunks, b"EXIF", exif);
+		}
+		if let Some(packet) = &self.xmp {
+			chunks[8] |= XMP;
+			push_riff_chunk(&mut chunks, b"XMP ", packet.as_bytes());
+		}
+
+		let mut out = b"RIFF".to_vec();
+		out.extend((chunks.len() as u32 + 4).to_le_bytes());
+		out.extend(b"WEBP");
+		out.extend(chunks);
+		Ok(out)
+	}
+}
+
+/// EXIF tags which a kept field is written as, each with an XMP property to fall back to.
+fn retained_tags(field: MetadataField) -> &'static [(Tag, Option<&'static str>)] {
+	match field {
+		MetadataField::Copyright => &[(Tag::Copyright, Some("dc:rights"))],
+		MetadataField::Artist => &[(Tag::Artist, Some("dc:creator"))],
+		MetadataField::Orientation => &[(Tag::Orientation, None)],
+		MetadataField::CaptureDate => &[
+			(Tag::DateTimeOriginal, None),
+			(Tag::OffsetTimeOriginal, None),
+			(Tag::DateTime, None),
+		],
+		MetadataField::Camera => &[
+			(Tag::Make, Some("tiff:Make")),
+			(Tag::Model, Some("tiff:Model")),
+			(Tag::LensModel, None),
+		],
+		MetadataField::ColorProfile => &[],
+	}
+}
+
+/// EXIF with `fields` written over those of the primary image in `exif`, leaving out the rest.
+/// When `exif` can't be rewritten, it's replaced by `fields` alone.
+fn merge_exif(exif: Option<Vec<u8>>, fields: &[Field]) -> Option<Vec<u8>> {
+	let exif = exif.and_then(|exif| ::exif::Reader::new().read_raw(exif).ok());
+	let mut merged: Vec<Field> = exif
+		.iter()
+		.flat_map(|exif| exif.fields())
+		.filter(|field| {
+			field.ifd_num == In::PRIMARY && !fields.iter().any(|set| set.tag == field.tag)
+		})
+		.cloned()
+		.collect();
+	merged.extend_from_slice(fields);
+	write_exif(&merged).or_else(|| write_exif(fields))
+}
+
+/// EXIF holding `fields`, or `None` without any.
+fn write_exif(fields: &[Field]) -> Option<Vec<u8>> {
+	if fields.is_empty() {
+		return None;
+	}
+
+	let mut writer = Writer::new();
+	for field in fields {
+		writer.push_field(field);
+	}
+	let mut tiff = Cursor::new(Vec::new());
+	writer.write(&mut tiff, true).ok()?;
+	Some(tiff.into_inner())
+}
+
+/// Data of the first JPEG segment with `marker` which starts with `signature`, after the
+/// signature.
+fn jpeg_segment<'a>(jpeg: &'a [u8], marker: u8, signature: &[u8]) -> Option<&'a [u8]> {
+	if !jpeg.starts_with(&[0xFF, 0xD8]) {
+		return None;
+	}
+
+	let mut position = 2;
+	while let Some(&[0xFF, current, high, low]) = jpeg.get(position..position + 4) {
+		// Metadata segments all come before the scan
+		if current == SOS {
+			break;
+		}
+		let end = position + 2 + u16::from_be_bytes([high, low]) as usize;
+		let data = jpeg.get(position + 4..end)?;
+		if current == marker && data.starts_with(signature) {
+			return Some(&data[signature.len()..]);
+		}
+		position = end;
+	}
+	None
+}
+
+/// Overwrites the orientation in the first IFD of a TIFF header and IFDs.
+fn set_exif_orientation(tiff: &mut [u8], orientation: u16) {
+	let big_endian = match tiff.get(..2) {
+		Some(b"MM") => true,
+		Some(b"II") => false,
+		_ => return,
+	};
+	let read_u16 = |tiff: &[u8], offset: usize| {
+		let bytes = tiff.get(offset..offset + 2)?;
+		let bytes = [bytes[0], bytes[1]];
+		Some(match big_endian {
+			true => u16::from_be_bytes(bytes),
+			false => u16::from_le_bytes(bytes),
+		})
+	};
+	let Some(ifd) = tiff.get(4..8).map(|bytes| {
+		let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+		match big_endian {
+			true => u32::from_be_bytes(bytes),
+			false => u32::from_le_bytes(bytes),
+		}
+	}) else {
+		return;
+	};
+	let ifd = ifd as usize;
+	let Some(count) = read_u16(tiff, ifd) else {
+		return;
+	};
+
+	// Entries are a tag, a type, a count and the value itself when it fits in 4 bytes
+	for entry in (0..count as usize).map(|index| ifd + 2 + index * 12) {
+		if read_u16(tiff, entry) == Some(ORIENTATION_TAG) && read_u16(tiff, entry + 2) == Some(3) {
+			let value = match big_endian {
+				true => orientation.to_be_bytes(),
+				false => orientation.to_le_bytes(),
+			};
+			if let Some(slot) = tiff.get_mut(entry + 8..entry + 10) {
+				slot.copy_from_slice(&value);
+			}
+			return;
+		}
+	}
+}
+
+/// Appends a segment made of `parts`, unless it's too long for one.
+fn push_jpeg_segment(out: &mut Vec<u8>, marker: u8, parts: &[&[u8]]) {
+	let size: usize = parts.iter().map(|part| part.len()).sum();
+	let Ok(length) = u16::try_from(2 + size) else {
+		return;
+	};
+	out.extend([0xFF, marker]);
+	out.extend(length.to_be_bytes());
+	for part in parts {
+		out.extend(*part);
+	}
+}
+
+fn push_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+	out.extend((data.len() as u32).to_be_bytes());
+	let start = out.len();
+	out.extend(kind);
+	out.extend(data);
+	let crc = crc32fast::hash(&out[start..]);
+	out.extend(crc.to_be_bytes());
+}
+
+fn push_riff_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+	out.extend(kind);
+	out.extend((data.len() as u32).to_le_bytes());
+	out.extend(data);
+	// Chunks are padded to an even size
+	if data.len() % 2 == 1 {
+		out.push(0);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Blocks;
+	use crate::{
+		metadata::{
+			read_color_profile_from_bytes, read_metadata_from_bytes, xmp, ColorSpace, Orientation,
+		},
+		plan::read_info,
+		ImageOutputFormat, Pipeline,
+	};
+	use image::{DynamicImage, GenericImageView, RgbImage};
+	use std::io::Cursor;
+
+	/// Little-endian EXIF with the orientation and the camera make.
+	fn exif(orientation: u16) -> Vec<u8> {
+		let mut tiff = b"II*\0".to_vec();
+		tiff.extend(8u32.to_le_bytes());
+		tiff.extend(2u16.to_le_bytes());
+		// Make, as ASCII at the offset after the IFD
+		tiff.extend([0x0F, 0x01, 2, 0, 6, 0, 0, 0]);
+		tiff.extend(38u32.to_le_bytes());
+		// Orientation, as a SHORT in the entry
+		tiff.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0]);
+		tiff.extend(orientation.to_le_bytes());
+		tiff.extend([0, 0]);
+		tiff.extend(0u32.to_le_bytes());
+		tiff.extend(b"Canon\0");
+		tiff
+	}
+
+	/// A JPEG with every kind of block, rotated by 90 degrees.
+	fn source() -> Vec<u8> {
+		let mut jpeg = Cursor::new(Vec::new());
+		DynamicImage::ImageRgb8(RgbImage::new(30, 20))
+			.write_to(&mut jpeg, image::ImageOutputFormat::Jpeg(90))
+			.unwrap();
+		// An RGB profile without any tags
+		let mut profile = vec![0; 132];
+		profile[16..20].copy_from_slice(b"RGB ");
+
+		let source = Blocks {
+			exif: Some(exif(6)),
+			xmp: Some(r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description tiff:Orientation="6" xmp:CreateDate="2023-06-01T12:30:00"><dc:rights><rdf:Alt><rdf:li xml:lang="x-default">Jane Doe</rdf:li></rdf:Alt></dc:rights></rdf:Description></rdf:RDF></x:xmpmeta>"#.to_string()),
+			iptc: Some(b"8BIM\x04\x04\0\0\0\0\0\0".to_vec()),
+			icc: Some(profile),
+		}
+		.embed(jpeg.into_inner())
+		.unwrap();
+		assert_eq!(
+			Some(Orientation::Rotate90),
+			read_metadata_from_bytes(&source).unwrap().orientation
+		);
+		source
+	}
+
+	fn formats() -> [ImageOutputFormat; 3] {
+		[
+			ImageOutputFormat::Jpeg { quality: 80 },
+			ImageOutputFormat::png(),
+			ImageOutputFormat::webp(),
+		]
+	}
+
+	#[test]
+	fn keeps_metadata() {
+		let source = source();
+		for (format, auto_orient) in formats().into_iter().zip([true, false, true]) {
+			let pipeline = Pipeline {
+				out_format: format,
+				auto_orient,
+				keep_metadata: true,
+				operations: Vec::new(),
+			};
+			let output = pipeline.process_bytes(&source).unwrap();
+			image::load_from_memory(&output).unwrap();
+			let blocks = Blocks::read(&output);
+			let metadata = read_metadata_from_bytes(&output).unwrap();
+			assert_eq!("Canon", metadata.camera.unwrap().make.unwrap());
+			assert!(metadata.capture_date.is_some());
+			assert_eq!(
+				ColorSpace::Rgb,
+				read_color_profile_from_bytes(&output)
+					.unwrap()
+					.unwrap()
+					.color_space
+			);
+
+			// Oriented pixels are upright, otherwise the orientation still applies
+			let orientation = match auto_orient {
+				true => (Orientation::Normal, "tiff:Orientation=\"1\""),
+				false => (Orientation::Rotate90, "tiff:Orientation=\"6\""),
+			};
+			assert_eq!(Some(orientation.0), metadata.orientation);
+			assert!(blocks.xmp.unwrap().contains(orientation.1));
+			let is_jpeg = matches!(pipeline.out_format, ImageOutputFormat::Jpeg { .. });
+			assert_eq!(is_jpeg, blocks.iptc.is_some());
+		}
+	}
+
+	#[test]
+	fn strips_metadata() {
+		let source = source();
+		for format in formats() {
+			let mut pipeline: Pipeline = toml::from_str(
+				r#"
+				out_format = "gif"
+				keep_metadata = true
+
+				[[operations]]
+				strip-metadata = { keep = ["copyright", "orientation", "color-profile"] }
+				"#,
+			)
+			.unwrap();
+			pipeline.out_format = format;
+			let output = pipeline.process_bytes(&source).unwrap();
+			image::load_from_memory(&output).unwrap();
+
+			let blocks = Blocks::read(&output);
+			assert_eq!((None, None), (blocks.xmp, blocks.iptc));
+			assert!(blocks.icc.is_some());
+			let exif = ::exif::Reader::new()
+				.read_raw(blocks.exif.unwrap())
+				.unwrap();
+			let copyright = exif.get_field(::exif::Tag::Copyright, ::exif::In::PRIMARY);
+			assert_eq!(
+				"\"Jane Doe\"",
+				copyright.unwrap().display_value().to_string()
+			);
+			let metadata = read_metadata_from_bytes(&output).unwrap();
+			assert_eq!(Some(Orientation::Rotate90), metadata.orientation);
+			assert_eq!((None, None), (metadata.camera, metadata.capture_date));
+		}
+	}
+
+	#[test]
+	fn sets_metadata() {
+		let source = source();
+		let config = |xmp: &str| {
+			format!(
+				r#"
+				out_format = "gif"
+				keep_metadata = true
+
+				[[operations]]
+				set-metadata = {{ copyright = "Example & Co", artist = "Joe Bloggs", xmp = {{ {xmp} }}, namespaces = {{ ex = "https://example.com/ns/" }} }}
+				"#
+			)
+		};
+		for format in formats() {
+			let mut pipeline: Pipeline = toml::from_str(&config(
+				r#""photoshop:Credit" = "Example", "ex:Source" = "render""#,
+			))
+			.unwrap();
+			pipeline.out_format = format;
+			let output = pipeline.process_bytes(&source).unwrap();
+			image::load_from_memory(&output).unwrap();
+
+			// EXIF keeps the fields of the source besides the ones set
+			let blocks = Blocks::read(&output);
+			let exif = ::exif::Reader::new()
+				.read_raw(blocks.exif.unwrap())
+				.unwrap();
+			let ascii = |tag| {
+				exif.get_field(tag, ::exif::In::PRIMARY)
+					.unwrap()
+					.display_value()
+					.to_string()
+			};
+			assert_eq!("\"Example & Co\"", ascii(::exif::Tag::Copyright));
+			assert_eq!("\"Joe Bloggs\"", ascii(::exif::Tag::Artist));
+			assert_eq!("\"Canon\"", ascii(::exif::Tag::Make));
+
+			// The copyright of the source is replaced rather than repeated
+			let packet = blocks.xmp.unwrap();
+			assert_eq!(2, packet.matches("dc:rights>").count());
+			assert_eq!(
+				Some("Example &amp; Co"),
+				xmp::property(&packet, "dc:rights")
+			);
+			assert_eq!(Some("Joe Bloggs"), xmp::property(&packet, "dc:creator"));
+			assert_eq!(Some("Example"), xmp::property(&packet, "photoshop:Credit"));
+			assert_eq!(Some("render"), xmp::property(&packet, "ex:Source"));
+			assert!(packet.contains(r#"xmlns:ex="https://example.com/ns/""#));
+		}
+
+		let pipeline: Pipeline = toml::from_str(&config(r#""other:Source" = "render""#)).unwrap();
+		assert!(pipeline.process_bytes(&source).is_err());
+	}
+
+	#[test]
+	fn plans_oriented_sources() {
+		let source = source();
+		let orientation = read_metadata_from_bytes(&source).unwrap().orientation;
+		let info = read_info(Cursor::new(&source))
+			.unwrap()
+			.with_orientation(orientation);
+		assert_eq!((30, 20), info.dimensions());
+
+		for auto_orient in [true, false] {
+			let pipeline: Pipeline = toml::from_str(&format!(
+				r#"
+				out_format = "png"
+				auto_orient = {auto_orient}
+				operations = [
+					{{ auto-orient = {{}} }},
+					{{ crop-gravity = {{ width = {{ pixel = {{ pixels = 15 }} }}, height = {{ pixel = {{ pixels = 40 }} }} }} }},
+				]
+				"#
+			))
+			.unwrap();
+
+			let steps = pipeline.plan(info).unwrap();
+			let output =
+				image::load_from_memory(&pipeline.process_bytes(&source).unwrap()).unwrap();
+			assert_eq!(Some((20, 30)), steps[0].dimensions);
+			assert_eq!(Some(output.dimensions()), steps[1].dimensions);
+			assert_eq!(
+				output.dimensions(),
+				pipeline
+					.output_dimensions(info.dimensions(), orientation)
+					.unwrap()
+			);
+		}
+	}
+}
@@ -0,0 +1,274 @@
+use crate::{metadata::xmp, Error};
+use ::exif::{In, Reader, Tag, Value};
+use image::{DynamicImage, ImageFormat};
+use serde::Serialize;
+use std::io::Cursor;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, path::Path};
+
+/// EXIF orientation, describing the transformation needed to display the image upright.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Orientation {
+	Normal,
+	FlipHorizontal,
+	Rotate180,
+	FlipVertical,
+	Transpose,
+	Rotate90,
+	Transverse,
+	Rotate270,
+}
+
+impl Orientation {
+	pub fn from_exif(value: u32) -> Option<Self> {
+		match value {
+			1 => Some(Self::Normal),
+			2 => Some(Self::FlipHorizontal),
+			3 => Some(Self::Rotate180),
+			4 => Some(Self::FlipVertical),
+			5 => Some(Self::Transpose),
+			6 => Some(Self::Rotate90),
+			7 => Some(Self::Transverse),
+			8 => Some(Self::Rotate270),
+			_ => None,
+		}
+	}
+
+	/// Transforms an image stored with this orientation so it displays upright.
+	pub fn apply(self, image: DynamicImage) -> DynamicImage {
+		match self {
+			Self::Normal => image,
+			Self::FlipHorizontal => image.fliph(),
+			Self::Rotate180 => image.rotate180(),
+			Self::FlipVertical => image.flipv(),
+			Self::Transpose => image.rotate90().fliph(),
+			Self::Rotate90 => image.rotate90(),
+			Self::Transverse => image.rotate90().flipv(),
+			Self::Rotate270 => image.rotate270(),
+		}
+	}
+
+	/// Whether applying the orientation swaps the width and height.
+	pub fn swaps_dimensions(self) -> bool {
+		matches!(
+			self,
+			Self::Transpose | Self::Rotate90 | Self::Transverse | Self::Rotate270
+		)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DateTime {
+	pub year: u16,
+	pub month: u8,
+	pub day: u8,
+	pub hour: u8,
+	pub minute: u8,
+	pub second: u8,
+}
+
+impl DateTime {
+	/// Parses the date portion of an ISO 8601 timestamp as used by XMP, e.g. `2023-06-01T12:30:00`.
+	fn from_iso8601(value: &str) -> Option<Self> {
+		fn number<T: std::str::FromStr>(value: &str, range: std::ops::Range<usize>) -> Option<T> {
+			value.get(range)?.parse().ok()
+		}
+
+		Some(Self {
+			year: number(value, 0..4)?,
+			month: number(value, 5..7)?,
+			day: number(value, 8..10)?,
+			hour: number(value, 11..13).unwrap_or(0),
+			minute: number(value, 14..16).unwrap_or(0),
+			second: number(value, 17..19).unwrap_or(0),
+		})
+	}
+}
+
+impl From<::exif::DateTime> for DateTime {
+	fn from(value: ::exif::DateTime) -> Self {
+		Self {
+			year: value.year,
+			month: value.month,
+			day: value.day,
+			hour: value.hour,
+			minute: value.minute,
+			second: value.second,
+		}
+	}
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Camera {
+	pub make: Option<String>,
+	pub model: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct GpsPosition {
+	/// Degrees north of the equator, negative for the southern hemisphere
+	pub latitude: f64,
+	/// Degrees east of the prime meridian, negative for the western hemisphere
+	pub longitude: f64,
+	/// Meters above sea level
+	pub altitude: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ImageMetadata {
+	pub capture_date: Option<DateTime>,
+	pub camera: Option<Camera>,
+	pub lens: Option<String>,
+	pub gps: Option<GpsPosition>,
+	pub orientation: Option<Orientation>,
+}
+
+/// Reads EXIF and XMP metadata from an image file.
+///
+/// EXIF fields take precedence; XMP is used to fill in anything the EXIF block does not provide.
+/// Images without any metadata produce an empty [`ImageMetadata`] rather than an error.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_metadata<P: AsRef<Path>>(path: P) -> Result<ImageMetadata, Error> {
+	read_metadata_from_bytes(&fs::read(path)?)
+}
+
+pub fn read_metadata_from_bytes(bytes: &[u8]) -> Result<ImageMetadata, Error> {
+	let has_exif_container = matches!(
+		image::guess_format(bytes),
+		Ok(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Tiff | ImageFormat::WebP)
+	);
+
+	let mut metadata = if has_exif_container {
+		match Reader::new().read_from_container(&mut Cursor::new(bytes)) {
+			Ok(exif) => from_exif(&exif),
+			Err(::exif::Error::NotFound(_)) => ImageMetadata::default(),
+			Err(err) => return Err(err.into()),
+		}
+	} else {
+		ImageMetadata::default()
+	};
+
+	if let Some(packet) = xmp::find_packet(bytes) {
+		merge_xmp(&mut metadata, packet);
+	}
+
+	Ok(metadata)
+}
+
+fn from_exif(exif: &::exif::Exif) -> ImageMetadata {
+	let ascii = |tag| match exif.get_field(tag, In::PRIMARY).map(|field| &field.value) {
+		Some(Value::Ascii(values)) => values
+			.first()
+			.map(|value| {
+				String::from_utf8_lossy(value)
+					.trim_matches(['\0', ' '])
+					.to_string()
+			})
+			.filter(|value| !value.is_empty()),
+		_ => None,
+	};
+
+	let capture_date =
+		[Tag::DateTimeOriginal, Tag::DateTime]
+			.into_iter()
+			.find_map(
+				|tag| match exif.get_field(tag, In::PRIMARY).map(|field| &field.value) {
+					Some(Value::Ascii(values)) => values
+						.first()
+						.and_then(|value| ::exif::DateTime::from_ascii(value).ok())
+						.map(DateTime::from),
+					_ => None,
+				},
+			);
+
+	let make = ascii(Tag::Make);
+	let model = ascii(Tag::Model);
+	let camera = (make.is_some() || model.is_some()).then_some(Camera { make, model });
+
+	let orientation = exif
+		.get_field(Tag::Orientation, In::PRIMARY)
+		.and_then(|field| field.value.get_uint(0))
+		.and_then(Orientation::from_exif);
+
+	ImageMetadata {
+		capture_date,
+		camera,
+		lens: ascii(Tag::LensModel),
+		gps: gps_position(exif),
+		orientation,
+	}
+}
+
+fn gps_position(exif: &::exif::Exif) -> Option<GpsPosition> {
+	let coordinate = |tag, reference_tag, negative: &[u8]| {
+		let degrees = match &exif.get_field(tag, In::PRIMARY)?.value {
+			Value::Rational(values) if values.len() >= 3 => {
+				values[0].to_f64() + values[1].to_f64() / 60.0 + values[2].to_f64() / 3600.0
+			}
+			_ => return None,
+		};
+
+		match &exif.get_field(reference_tag, In::PRIMARY)?.value {
+			Value::Ascii(values) if values.first().map(Vec::as_slice) == Some(negative) => {
+				Some(-degrees)
+			}
+			_ => Some(degrees),
+		}
+	};
+
+	let latitude = coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, b"S")?;
+	let longitude = coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, b"W")?;
+
+	let altitude = match exif
+		.get_field(Tag::GPSAltitude, In::PRIMARY)
+		.map(|field| &field.value)
+	{
+		Some(Value::Rational(values)) if !values.is_empty() => {
+			let altitude = values[0].to_f64();
+			let below_sea_level = exif
+				.get_field(Tag::GPSAltitudeRef, In::PRIMARY)
+				.and_then(|field| field.value.get_uint(0))
+				== Some(1);
+			Some(if below_sea_level { -altitude } else { altitude })
+		}
+		_ => None,
+	};
+
+	Some(GpsPosition {
+		latitude,
+		longitude,
+		altitude,
+	})
+}
+
+fn merge_xmp(metadata: &mut ImageMetadata, packet: &str) {
+	if metadata.capture_date.is_none() {
+		metadata.capture_date = ["exif:DateTimeOriginal", "xmp:CreateDate"]
+			.into_iter()
+			.find_map(|name| xmp::property(packet, name).and_then(DateTime::from_iso8601));
+	}
+
+	if metadata.camera.is_none() {
+		let make = xmp::property(packet, "tiff:Make").map(String::from);
+		let model = xmp::property(packet, "tiff:Model").map(String::from);
+		metadata.camera = (make.is_some() || model.is_some()).then_some(Camera { make, model });
+	}
+
+	if metadata.lens.is_none() {
+		metadata.lens = ["exifEX:LensModel", "aux:Lens"]
+			.into_iter()
+			.find_map(|name| xmp::property(packet, name))
+			.map(String::from);
+	}
+
+	if metadata.orientation.is_none() {
+		metadata.orientation = xmp::property(packet, "tiff:Orientation")
+			.and_then(|value| value.parse().ok())
+			.and_then(Orientation::from_exif);
+	}
+}
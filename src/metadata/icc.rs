@@ -0,0 +1,285 @@
+use crate::Error;
+use image::{
+	codecs::{jpeg::JpegDecoder, png::PngDecoder, tiff::TiffDecoder, webp::WebPDecoder},
+	io::Reader as ImageReader,
+	ImageDecoder, ImageFormat,
+};
+use serde::Serialize;
+use std::io::Cursor;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, path::Path};
+
+const HEADER_SIZE: usize = 128;
+
+/// Color space of the pixel data described by an ICC profile.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorSpace {
+	Rgb,
+	Gray,
+	Cmyk,
+	Lab,
+	Xyz,
+	Other(String),
+}
+
+impl ColorSpace {
+	fn from_signature(signature: &[u8]) -> Self {
+		match signature {
+			b"RGB " => Self::Rgb,
+			b"GRAY" => Self::Gray,
+			b"CMYK" => Self::Cmyk,
+			b"Lab " => Self::Lab,
+			b"XYZ " => Self::Xyz,
+			other => Self::Other(String::from_utf8_lossy(other).trim().to_string()),
+		}
+	}
+
+	fn channels(&self) -> Option<u8> {
+		match self {
+			Self::Gray => Some(1),
+			Self::Rgb | Self::Lab | Self::Xyz => Some(3),
+			Self::Cmyk => Some(4),
+			Self::Other(_) => None,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ColorProfile {
+	/// Profile description, e.g. `sRGB IEC61966-2.1` or `Display P3`
+	pub name: Option<String>,
+	pub color_space: ColorSpace,
+	/// Gamma of the profile's tone response curve, when it is a simple power function
+	pub gamma: Option<f32>,
+	/// Whether the decoder converted pixel data out of the profile's color space, e.g. CMYK JPEGs
+	/// which are decoded to RGB. The profile itself is never applied to pixel data.
+	pub converted: bool,
+	/// Size of the embedded profile in bytes
+	pub size: usize,
+}
+
+/// Reads the embedded ICC profile of an image file, if it has one.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_color_profile<P: AsRef<Path>>(path: P) -> Result<Option<ColorProfile>, Error> {
+	read_color_profile_from_bytes(&fs::read(path)?)
+}
+
+pub fn read_color_profile_from_bytes(bytes: &[u8]) -> Result<Option<ColorProfile>, Error> {
+	Ok(read_profile(bytes)?
+		.and_then(|(profile, decoded_channels)| parse_profile(&profile, decoded_channels)))
+}
+
+/// The embedded ICC profile of a JPEG, PNG, TIFF or WebP file, with the number of color channels
+/// its pixels decode to.
+pub(crate) fn read_profile(bytes: &[u8]) -> Result<Option<(Vec<u8>, u8)>, Error> {
+	let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+	let format = reader.format();
+	let reader = reader.into_inner();
+
+	let profile = match format {
+		Some(ImageFormat::Jpeg) => embedded_profile(JpegDecoder::new(reader)?),
+		Some(ImageFormat::Png) => embedded_profile(PngDecoder::new(reader)?),
+		Some(ImageFormat::Tiff) => embedded_profile(TiffDecoder::new(reader)?),
+		Some(ImageFormat::WebP) => embedded_profile(WebPDecoder::new(reader)?),
+		_ => None,
+	};
+
+	Ok(profile)
+}
+
+fn embedded_profile<'a, D: ImageDecoder<'a>>(mut decoder: D) -> Option<(Vec<u8>, u8)> {
+	let color_type = decoder.color_type();
+	let channels = color_type.channel_count() - u8::from(color_type.has_alpha());
+	decoder.icc_profile().map(|profile| (profile, channels))
+}
+
+pub(crate) fn parse_profile(profile: &[u8], decoded_channels: u8) -> Option<ColorProfile> {
+	if profile.len() < HEADER_SIZE + 4 {
+		return None;
+	}
+
+	let color_space = ColorSpace::from_signature(&profile[16..20]);
+	let converted = color_space
+		.channels()
+		.is_some_and(|channels| channels != decoded_channels);
+
+	let name = tag(profile, b"desc").and_then(description);
+	let gamma = tag(profile, b"rTRC")
+		.or_else(|| tag(profile, b"kTRC"))
+		.and_then(curve_gamma);
+
+	Some(ColorProfile {
+		name,
+		color_space,
+		gamma,
+		converted,
+		size: profile.len(),
+	})
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+	Some(u32::from_be_bytes(
+		bytes.get(offset..offset + 4)?.try_into().ok()?,
+	))
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+	Some(u16::from_be_bytes(
+		bytes.get(offset..offset + 2)?.try_into().ok()?,
+	))
+}
+
+/// Looks up a tag's data in the profile's tag table.
+fn tag<'a>(profile: &'a [u8], signature: &[u8; 4]) -> Option<&'a [u8]> {
+	// The count is untrusted, so only look at entries which fit in the profile
+	let count = (read_u32(profile, HEADER_SIZE)? as usize)
+		.min(profile.len().saturating_sub(HEADER_SIZE + 4) / 12);
+	(0..count).find_map(|index| {
+		let entry = HEADER_SIZE + 4 + index * 12;
+		if profile.get(entry..entry + 4)? != signature {
+			return None;
+		}
+		let offset = read_u32(profile, entry + 4)? as usize;
+		let size = read_u32(profile, entry + 8)? as usize;
+		profile.get(offset..offset.checked_add(size)?)
+	})
+}
+
+fn description(data: &[u8]) -> Option<String> {
+	let description = match data.get(0..4)? {
+		// ICC v2 textDescriptionType
+		b"desc" => {
+			let length = read_u32(data, 8)? as usize;
+			let text = data.get(12..length.checked_add(12)?)?;
+			String::from_utf8_lossy(text).to_string()
+		}
+		// ICC v4 multiLocalizedUnicodeType, using the first record
+		b"mluc" => {
+			let length = read_u32(data, 20)? as usize;
+			let offset = read_u32(data, 24)? as usize;
+			let text = data
+				.get(offset..offset.checked_add(length)?)?
+				.chunks_exact(2)
+				.map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+				.collect::<Vec<_>>();
+			String::from_utf16_lossy(&text)
+		}
+		_ => return None,
+	};
+
+	let description = description.trim_end_matches('\0').trim().to_string();
+	(!description.is_empty()).then_some(description)
+}
+
+fn curve_gamma(data: &[u8]) -> Option<f32> {
+	match data.get(0..4)? {
+		b"curv" => match read_u32(data, 8)? {
+			0 => Some(1.0),
+			1 => Some(read_u16(data, 12)? as f32 / 256.0),
+			// Sampled curves have no single gamma value
+			_ => None,
+		},
+		b"para" => Some(read_u32(data, 12)? as f32 / 65536.0),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_profile, ColorSpace};
+
+	fn profile(color_space: &[u8; 4], tags: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+		let mut profile = vec![0u8; 128];
+		profile[16..20].copy_from_slice(color_space);
+		profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+
+		let mut offset = 128 + 4 + tags.len() * 12;
+		let mut data = Vec::new();
+		for (signature, tag) in tags {
+			profile.extend_from_slice(*signature);
+			profile.extend_from_slice(&(offset as u32).to_be_bytes());
+			profile.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+			offset += tag.len();
+			data.extend_from_slice(tag);
+		}
+		profile.extend(data);
+		profile
+	}
+
+	fn text_description(text: &str) -> Vec<u8> {
+		let mut tag = b"desc\0\0\0\0".to_vec();
+		tag.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+		tag.extend_from_slice(text.as_bytes());
+		tag.push(0);
+		tag
+	}
+
+	fn gamma_curve(gamma: u16) -> Vec<u8> {
+		let mut tag = b"curv\0\0\0\0".to_vec();
+		tag.extend_from_slice(&1u32.to_be_bytes());
+		tag.extend_from_slice(&gamma.to_be_bytes());
+		tag
+	}
+
+	#[test]
+	fn parse_rgb_profile() {
+		let bytes = profile(
+			b"RGB ",
+			&[
+				(b"desc", text_description("Adobe RGB (1998)")),
+				(b"rTRC", gamma_curve(563)),
+			],
+		);
+
+		let profile = parse_profile(&bytes, 3).unwrap();
+		assert_eq!(Some("Adobe RGB (1998)".to_string()), profile.name);
+		assert_eq!(ColorSpace::Rgb, profile.color_space);
+		assert_eq!(Some(563.0 / 256.0), profile.gamma);
+		assert!(!profile.converted);
+	}
+
+	#[test]
+	fn parse_cmyk_profile_decoded_as_rgb() {
+		let bytes = profile(b"CMYK", &[]);
+
+		let profile = parse_profile(&bytes, 3).unwrap();
+		assert_eq!(ColorSpace::Cmyk, profile.color_space);
+		assert_eq!(None, profile.name);
+		assert!(profile.converted);
+	}
+
+	#[test]
+	fn parse_truncated_profile() {
+		assert!(parse_profile(&[0u8; 64], 3).is_none());
+	}
+
+	#[test]
+	fn parse_description_out_of_bounds() {
+		let mut mluc = b"mluc\0\0\0\0".to_vec();
+		mluc.extend_from_slice(&1u32.to_be_bytes());
+		mluc.extend_from_slice(&12u32.to_be_bytes());
+		mluc.extend_from_slice(b"enUS");
+		mluc.extend_from_slice(&u32::MAX.to_be_bytes());
+		mluc.extend_from_slice(&u32::MAX.to_be_bytes());
+
+		let mut desc = text_description("sRGB");
+		desc[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+
+		for tag in [mluc, desc] {
+			let bytes = profile(b"RGB ", &[(b"desc", tag)]);
+			assert_eq!(None, parse_profile(&bytes, 3).unwrap().name);
+		}
+	}
+
+	#[test]
+	fn parse_oversized_tag_count() {
+		let mut bytes = profile(b"RGB ", &[(b"rTRC", gamma_curve(563))]);
+		bytes[128..132].copy_from_slice(&u32::MAX.to_be_bytes());
+
+		let profile = parse_profile(&bytes, 3).unwrap();
+		assert_eq!(None, profile.name);
+		assert_eq!(Some(563.0 / 256.0), profile.gamma);
+	}
+}
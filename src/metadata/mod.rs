@@ -0,0 +1,25 @@
+mod animation;
+mod embed;
+mod exif;
+mod icc;
+mod xmp;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::animation::read_animation_info;
+pub(crate) use self::animation::{decode_frames, frame_delay};
+pub use self::animation::{read_animation_info_from_bytes, AnimationInfo, LoopCount};
+pub use self::embed::{copy_metadata, OutputMetadata};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::exif::read_metadata;
+pub use self::exif::{
+	read_metadata_from_bytes, Camera, DateTime, GpsPosition, ImageMetadata, Orientation,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::icc::read_color_profile;
+pub use self::icc::{read_color_profile_from_bytes, ColorProfile, ColorSpace};
+
+pub(crate) fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+	haystack
+		.windows(needle.len())
+		.position(|window| window == needle)
+}
@@ -0,0 +1,156 @@
+use crate::metadata::find_bytes;
+
+const XMP_START: &[u8] = b"<x:xmpmeta";
+const XMP_END: &[u8] = b"</x:xmpmeta>";
+
+/// Locates an XMP packet anywhere in the raw bytes of a file.
+///
+/// XMP is stored as plain XML in every container we read (JPEG APP1, PNG iTXt, WebP and TIFF), so
+/// there is no need to parse the container itself.
+pub(crate) fn find_packet(bytes: &[u8]) -> Option<&str> {
+	let start = find_bytes(bytes, XMP_START)?;
+	let end = start + find_bytes(&bytes[start..], XMP_END)? + XMP_END.len();
+	std::str::from_utf8(&bytes[start..end]).ok()
+}
+
+/// Reads a simple property from an XMP packet, written either as an attribute
+/// (`tiff:Make="Canon"`) or as an element (`<tiff:Make>Canon</tiff:Make>`).
+pub(crate) fn property<'a>(packet: &'a str, name: &str) -> Option<&'a str> {
+	let attribute = format!("{name}=\"");
+	if let Some(start) = packet.find(&attribute) {
+		let value = &packet[start + attribute.len()..];
+		return value.find('"').map(|end| value[..end].trim());
+	}
+
+	let open = format!("<{name}>");
+	let close = format!("</{name}>");
+	let start = packet.find(&open)? + open.len();
+	let end = start + packet[start..].find(&close)?;
+	let value = packet[start..end].trim();
+
+	// Values such as `dc:rights` are wrapped in an `rdf:Alt` container
+	match value.find("<rdf:li") {
+		Some(item) => {
+			let item = &value[item..];
+			let start = item.find('>')? + 1;
+			let end = item.find("</rdf:li>")?;
+			Some(item[start..end].trim())
+		}
+		None => Some(value),
+	}
+}
+
+/// Writes properties into `packet`, replacing any it already has, or into a new packet. Values
+/// are XML content, and `namespaces` are the URIs of the prefixes of the names.
+pub(crate) fn set_properties(
+	packet: Option<String>,
+	properties: &[(String, String)],
+	namespaces: &[(&str, &str)],
+) -> String {
+	// Properties go into a description of their own, which declares their namespaces
+	let mut description = String::from(r#"<rdf:Description rdf:about="""#);
+	for (prefix, namespace) in namespaces {
+		description.push_str(&format!(r#" xmlns:{prefix}="{}""#, escape(namespace)));
+	}
+	description.push('>');
+	for (name, value) in properties {
+		description.push_str(&format!("<{name}>{value}</{name}>"));
+	}
+	description.push_str("</rdf:Description>");
+
+	if let Some(mut packet) = packet {
+		for (name, _) in properties {
+			remove_property(&mut packet, name);
+		}
+		if let Some(start) = packet.find("<rdf:RDF") {
+			if let Some(end) = packet[start..].find('>') {
+				packet.insert_str(start + end + 1, &description);
+				return packet;
+			}
+		}
+	}
+	format!(
+		r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">{description}</rdf:RDF></x:xmpmeta>"#
+	)
+}
+
+/// Removes a property written as an attribute or as an element, as [`property`] reads them.
+fn remove_property(packet: &mut String, name: &str) {
+	let attribute = format!("{name}=\"");
+	if let Some(start) = packet
+		.find(&attribute)
+		.filter(|&start| packet[..start].ends_with(char::is_whitespace))
+	{
+		let value = start + attribute.len();
+		if let Some(end) = packet[value..].find('"') {
+			packet.replace_range(start..value + end + 1, "");
+		}
+	}
+
+	let open = format!("<{name}");
+	let close = format!("</{name}>");
+	let Some(start) = packet
+		.find(&open)
+		.filter(|&start| packet[start + open.len()..].starts_with(['>', '/', ' ']))
+	else {
+		return;
+	};
+	let Some(tag_end) = packet[start..].find('>').map(|end| start + end) else {
+		return;
+	};
+	let end = match packet[..tag_end].ends_with('/') {
+		true => Some(tag_end + 1),
+		false => packet[tag_end..]
+			.find(&close)
+			.map(|end| tag_end + end + close.len()),
+	};
+	if let Some(end) = end {
+		packet.replace_range(start..end, "");
+	}
+}
+
+/// Escapes text for XML content and attribute values.
+pub(crate) fn escape(text: &str) -> String {
+	text.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{find_packet, property};
+
+	const PACKET: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description tiff:Make="Canon" xmp:CreateDate="2023-06-01T12:30:00"><tiff:Model>EOS R5</tiff:Model><dc:rights><rdf:Alt><rdf:li xml:lang="x-default">Jane Doe</rdf:li></rdf:Alt></dc:rights></rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+	#[test]
+	fn find_packet_in_container() {
+		let bytes = [
+			b"\xff\xd8garbage".as_slice(),
+			PACKET.as_bytes(),
+			b"\xff\xd9",
+		]
+		.concat();
+		assert_eq!(Some(PACKET), find_packet(&bytes));
+	}
+
+	#[test]
+	fn property_from_attribute() {
+		assert_eq!(Some("Canon"), property(PACKET, "tiff:Make"));
+	}
+
+	#[test]
+	fn property_from_element() {
+		assert_eq!(Some("EOS R5"), property(PACKET, "tiff:Model"));
+	}
+
+	#[test]
+	fn property_from_alt_container() {
+		assert_eq!(Some("Jane Doe"), property(PACKET, "dc:rights"));
+	}
+
+	#[test]
+	fn missing_property() {
+		assert_eq!(None, property(PACKET, "aux:Lens"));
+	}
+}
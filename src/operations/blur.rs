@@ -0,0 +1,263 @@
+use crate::{check_cancelled, OperationError, PercentageUnit, PixelUnit, Process, Unit};
+use image::{
+	imageops, DynamicImage, GenericImage, GenericImageView, ImageBuffer, Pixel, Primitive,
+};
+use num::{NumCast, ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+/// Number of blur strengths blended between, from sharp to the full sigma.
+const LEVELS: usize = 4;
+
+/// Gaussian blur of `image` like [`DynamicImage::blur`], done in bands of rows so that
+/// cancellation is checked as it goes. Each band is blurred with enough rows around it to cover
+/// the kernel, which reaches `2 * sigma` rows away, so the result is the same as a single pass.
+pub(crate) fn blur(image: &DynamicImage, sigma: f32) -> Result<DynamicImage, OperationError> {
+	Ok(match image {
+		DynamicImage::ImageLuma8(buffer) => DynamicImage::ImageLuma8(blur_bands(buffer, sigma)?),
+		DynamicImage::ImageLumaA8(buffer) => DynamicImage::ImageLumaA8(blur_bands(buffer, sigma)?),
+		DynamicImage::ImageRgb8(buffer) => DynamicImage::ImageRgb8(blur_bands(buffer, sigma)?),
+		DynamicImage::ImageRgba8(buffer) => DynamicImage::ImageRgba8(blur_bands(buffer, sigma)?),
+		DynamicImage::ImageLuma16(buffer) => DynamicImage::ImageLuma16(blur_bands(buffer, sigma)?),
+		DynamicImage::ImageLumaA16(buffer) => {
+			DynamicImage::ImageLumaA16(blur_bands(buffer, sigma)?)
+		}
+		DynamicImage::ImageRgb16(buffer) => DynamicImage::ImageRgb16(blur_bands(buffer, sigma)?),
+		DynamicImage::ImageRgba16(buffer) => DynamicImage::ImageRgba16(blur_bands(buffer, sigma)?),
+		DynamicImage::ImageRgb32F(buffer) => DynamicImage::ImageRgb32F(blur_bands(buffer, sigma)?),
+		DynamicImage::ImageRgba32F(buffer) => {
+			DynamicImage::ImageRgba32F(blur_bands(buffer, sigma)?)
+		}
+		image => {
+			check_cancelled()?;
+			image.blur(sigma)
+		}
+	})
+}
+
+fn blur_bands<P>(
+	buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+	sigma: f32,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, OperationError>
+where
+	P: Pixel + 'static,
+	P::Subpixel: 'static,
+{
+	let (width, height) = buffer.dimensions();
+	// `imageops::blur` treats sigmas which aren't positive as 1
+	let sigma_rows = if sigma > 0.0 { sigma } else { 1.0 };
+	let margin = (2.0 * sigma_rows).ceil().min(height as f32) as u32 + 1;
+	let band = (4 * margin).max(64);
+
+	let mut output = ImageBuffer::new(width, height);
+	for top in (0..height).step_by(band as usize) {
+		check_cancelled()?;
+		let rows = band.min(height - top);
+		let start = top.saturating_sub(margin);
+		let end = (top + rows + margin).min(height);
+
+		let blurred = imageops::blur(&*buffer.view(0, start, width, end - start), sigma);
+		output
+			.copy_from(&*blurred.view(0, top - start, width, rows), 0, top)
+			.map_err(|error| OperationError::new(format!("Unable to blur: {error}")))?;
+	}
+
+	Ok(output)
+}
+
+/// Blends rows of equally sized buffers, where `weight` picks a position between the first buffer
+/// at 0 and the last at 1 for each row.
+fn blend_rows<P, F>(
+	levels: Vec<ImageBuffer<P, Vec<P::Subpixel>>>,
+	weight: F,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+	P: Pixel,
+	F: Fn(u32) -> f32,
+{
+	let max = P::Subpixel::DEFAULT_MAX_VALUE.to_f32().unwrap_or(1.0);
+	let offset = if max > 1.0 { 0.5 } else { 0.0 };
+	let last = levels.len() - 1;
+
+	let mut output = levels[0].clone();
+	for (y, row) in output.enumerate_rows_mut() {
+		let position = weight(y).clamp(0.0, 1.0) * last as f32;
+		let low = (position.floor() as usize).min(last);
+		let high = (low + 1).min(last);
+		let t = position - low as f32;
+
+		for (x, _, pixel) in row {
+			let from = levels[low].get_pixel(x, y).channels();
+			let to = levels[high].get_pixel(x, y).channels();
+			for ((channel, from), to) in pixel.channels_mut().iter_mut().zip(from).zip(to) {
+				let from = from.to_f32().unwrap_or_default();
+				let to = to.to_f32().unwrap_or_default();
+				*channel = NumCast::from((from + (to - from) * t).clamp(0.0, max) + offset)
+					.unwrap_or(P::Subpixel::DEFAULT_MIN_VALUE);
+			}
+		}
+	}
+
+	output
+}
+
+/// Keeps a horizontal band sharp and blurs progressively towards the top and bottom, like a
+/// tilt-shift lens. The band is centered on `position` and `band` high, and the blur reaches
+/// `sigma` at `falloff` beyond the band. Percentages are of the image height.
+///
+/// ```toml
+/// [[operations]]
+/// tilt-shift = { position = { percentage = { percentage = 0.6 } }, band = { percentage = { percentage = 0.2 } }, sigma = 8.0 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TiltShift {
+	/// Defaults to the middle of the image
+	#[serde(default = "TiltShift::default_position")]
+	pub position: Unit,
+	pub band: Unit,
+	/// Defaults to the distance from the band to the furthest edge
+	#[serde(default)]
+	pub falloff: Option<Unit>,
+	pub sigma: f32,
+}
+
+impl TiltShift {
+	fn default_position() -> Unit {
+		Unit::Percentage(PercentageUnit {
+			percentage: 0.5,
+			of: None,
+		})
+	}
+}
+
+impl Process for TiltShift {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if self.sigma.is_nan() || self.sigma < 0.0 {
+			return Err(OperationError::new(format!(
+				"Tilt-shift sigma must not be negative, got {}",
+				self.sigma
+			)));
+		}
+		if self.sigma == 0.0 {
+			return Ok(image);
+		}
+
+		let size = image.dimensions();
+		let height = PixelUnit::from(size.1);
+		let center = self.position.as_pixel(height, size).pixels as f32;
+		let half_band = self.band.as_pixel(height, size).pixels as f32 / 2.0;
+		let falloff = match &self.falloff {
+			Some(falloff) => falloff.as_pixel(height, size).pixels as f32,
+			None => (center - half_band).max(height.pixels as f32 - center - half_band),
+		}
+		.max(1.0);
+
+		let weight = |y: u32| ((y as f32 + 0.5 - center).abs() - half_band).max(0.0) / falloff;
+
+		let mut levels = vec![image];
+		for level in 1..=LEVELS {
+			check_cancelled()?;
+			let sigma = self.sigma * level as f32 / LEVELS as f32;
+			levels.push(blur(&levels[0], sigma)?);
+		}
+
+		let image = match &levels[0] {
+			DynamicImage::ImageLuma8(_) => DynamicImage::ImageLuma8(blend_rows(
+				levels.into_iter().map(DynamicImage::into_luma8).collect(),
+				weight,
+			)),
+			DynamicImage::ImageLumaA8(_) => DynamicImage::ImageLumaA8(blend_rows(
+				levels
+					.into_iter()
+					.map(DynamicImage::into_luma_alpha8)
+					.collect(),
+				weight,
+			)),
+			DynamicImage::ImageRgb8(_) => DynamicImage::ImageRgb8(blend_rows(
+				levels.into_iter().map(DynamicImage::into_rgb8).collect(),
+				weight,
+			)),
+			DynamicImage::ImageRgba8(_) => DynamicImage::ImageRgba8(blend_rows(
+				levels.into_iter().map(DynamicImage::into_rgba8).collect(),
+				weight,
+			)),
+			DynamicImage::ImageLuma16(_) => DynamicImage::ImageLuma16(blend_rows(
+				levels.into_iter().map(DynamicImage::into_luma16).collect(),
+				weight,
+			)),
+			DynamicImage::ImageLumaA16(_) => DynamicImage::ImageLumaA16(blend_rows(
+				levels
+					.into_iter()
+					.map(DynamicImage::into_luma_alpha16)
+					.collect(),
+				weight,
+			)),
+			DynamicImage::ImageRgb16(_) => DynamicImage::ImageRgb16(blend_rows(
+				levels.into_iter().map(DynamicImage::into_rgb16).collect(),
+				weight,
+			)),
+			DynamicImage::ImageRgba16(_) => DynamicImage::ImageRgba16(blend_rows(
+				levels.into_iter().map(DynamicImage::into_rgba16).collect(),
+				weight,
+			)),
+			DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgb32F(blend_rows(
+				levels.into_iter().map(DynamicImage::into_rgb32f).collect(),
+				weight,
+			)),
+			_ => DynamicImage::ImageRgba32F(blend_rows(
+				levels.into_iter().map(DynamicImage::into_rgba32f).collect(),
+				weight,
+			)),
+		};
+
+		Ok(image)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{blur, TiltShift};
+	use crate::{PercentageUnit, Process, Unit};
+	use image::{DynamicImage, GenericImageView, GrayImage, Luma, Rgb, RgbImage};
+
+	#[test]
+	fn blurs_in_bands_like_a_single_pass() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(9, 300, |x, y| {
+			Rgb([
+				(x * 28) as u8,
+				(y * 7 % 256) as u8,
+				((x + y) % 2 * 255) as u8,
+			])
+		}));
+		for sigma in [0.0, 0.7, 3.0, 40.0] {
+			assert_eq!(image.blur(sigma), blur(&image, sigma).unwrap());
+		}
+	}
+
+	#[test]
+	fn band_stays_sharp() {
+		let image = DynamicImage::ImageLuma8(GrayImage::from_fn(40, 40, |x, _| {
+			Luma([if x % 2 == 0 { 0 } else { 255 }])
+		}));
+		let shifted = TiltShift {
+			position: Unit::Percentage(PercentageUnit {
+				percentage: 0.5,
+				of: None,
+			}),
+			band: Unit::Percentage(PercentageUnit {
+				percentage: 0.2,
+				of: None,
+			}),
+			falloff: None,
+			sigma: 4.0,
+		}
+		.process(image.clone())
+		.unwrap();
+
+		for x in 0..40 {
+			assert_eq!(image.get_pixel(x, 20), shifted.get_pixel(x, 20));
+		}
+		let edge = shifted.get_pixel(0, 0)[0];
+		assert!(edge > 60, "expected the top to be blurred, got {edge}");
+	}
+}
@@ -0,0 +1,530 @@
+use crate::{Color, Gravity, ImageInfo, OperationError, PixelUnit, Process, Unit};
+use image::{
+	imageops, DynamicImage, GenericImage, GenericImageView, ImageBuffer, Luma, Rgb, Rgba,
+	Rgba32FImage,
+};
+use serde::{Deserialize, Serialize};
+
+/// Places `image` at `(x, y)` on a `width` by `height` canvas filled with `color`, keeping the bit
+/// depth of the image. An alpha channel is added when either the image or the color has one.
+pub(crate) fn place_on_canvas(
+	image: &DynamicImage,
+	width: u32,
+	height: u32,
+	x: u32,
+	y: u32,
+	color: Color,
+) -> Result<DynamicImage, OperationError> {
+	let alpha = !color.is_opaque() || image.color().has_alpha();
+	let rgba = [color.r, color.g, color.b, color.a];
+
+	let canvas = match (image, alpha) {
+		(
+			DynamicImage::ImageLuma16(_)
+			| DynamicImage::ImageLumaA16(_)
+			| DynamicImage::ImageRgb16(_)
+			| DynamicImage::ImageRgba16(_),
+			true,
+		) => {
+			let mut canvas =
+				ImageBuffer::from_pixel(width, height, Rgba(rgba.map(|c| c as u16 * 257)));
+			canvas
+				.copy_from(&image.to_rgba16(), x, y)
+				.map_err(copy_error)?;
+			DynamicImage::ImageRgba16(canvas)
+		}
+		(
+			DynamicImage::ImageLuma16(_)
+			| DynamicImage::ImageLumaA16(_)
+			| DynamicImage::ImageRgb16(_)
+			| DynamicImage::ImageRgba16(_),
+			false,
+		) => {
+			let [r, g, b, _] = rgba.map(|c| c as u16 * 257);
+			let mut canvas = ImageBuffer::from_pixel(width, height, Rgb([r, g, b]));
+			canvas
+				.copy_from(&image.to_rgb16(), x, y)
+				.map_err(copy_error)?;
+			DynamicImage::ImageRgb16(canvas)
+		}
+		(DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_), true) => {
+			let mut canvas =
+				ImageBuffer::from_pixel(width, height, Rgba(rgba.map(|c| c as f32 / 255.0)));
+			canvas
+				.copy_from(&image.to_rgba32f(), x, y)
+				.map_err(copy_error)?;
+			DynamicImage::ImageRgba32F(canvas)
+		}
+		(DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_), false) => {
+			let [r, g, b, _] = rgba.map(|c| c as f32 / 255.0);
+			let mut canvas = ImageBuffer::from_pixel(width, height, Rgb([r, g, b]));
+			canvas
+				.copy_from(&image.to_rgb32f(), x, y)
+				.map_err(copy_error)?;
+			DynamicImage::ImageRgb32F(canvas)
+		}
+		(_, true) => {
+			let mut canvas = ImageBuffer::from_pixel(width, height, Rgba(rgba));
+			canvas
+				.copy_from(&image.to_rgba8(), x, y)
+				.map_err(copy_error)?;
+			DynamicImage::ImageRgba8(canvas)
+		}
+		(_, false) => {
+			let mut canvas =
+				ImageBuffer::from_pixel(width, height, Rgb([color.r, color.g, color.b]));
+			canvas
+				.copy_from(&image.to_rgb8(), x, y)
+				.map_err(copy_error)?;
+			DynamicImage::ImageRgb8(canvas)
+		}
+	};
+
+	Ok(canvas)
+}
+
+fn copy_error(error: image::ImageError) -> OperationError {
+	OperationError::new(format!("Unable to place image on canvas: {error}"))
+}
+
+/// Extends the canvas on each side, filling the new area with `color`. Percentages of the top and
+/// bottom are of the image height, and of the left and right of the image width.
+///
+/// ```toml
+/// [[operations]]
+/// pad = { top = { pixel = { pixels = 10 } }, bottom = { pixel = { pixels = 10 } }, color = "#ffffff" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Pad {
+	#[serde(default = "Pad::none")]
+	pub top: Unit,
+	#[serde(default = "Pad::none")]
+	pub right: Unit,
+	#[serde(default = "Pad::none")]
+	pub bottom: Unit,
+	#[serde(default = "Pad::none")]
+	pub left: Unit,
+	/// Defaults to transparent
+	#[serde(default)]
+	pub color: Color,
+}
+
+impl Pad {
+	fn none() -> Unit {
+		Unit::Pixel(PixelUnit::from(0))
+	}
+
+	/// Left and top of the image on the canvas, and the canvas width and height.
+	fn layout(&self, width: u32, height: u32) -> Result<[u32; 4], OperationError> {
+		let pixels = |unit: &Unit, dimension: u32| {
+			unit.as_pixel(PixelUnit::from(dimension), (width, height))
+				.pixels
+		};
+
+		let top = pixels(&self.top, height);
+		let right = pixels(&self.right, width);
+		let bottom = pixels(&self.bottom, height);
+		let left = pixels(&self.left, width);
+
+		let out_width = width
+			.checked_add(left)
+			.and_then(|width| width.checked_add(right));
+		let out_height = height
+			.checked_add(top)
+			.and_then(|height| height.checked_add(bottom));
+		let (Some(out_width), Some(out_height)) = (out_width, out_height) else {
+			return Err(OperationError::new(format!(
+				"Padding is too large for operation {self:?}"
+			)));
+		};
+
+		Ok([left, top, out_width, out_height])
+	}
+}
+
+impl Process for Pad {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let [left, top, width, height] = self.layout(image.width(), image.height())?;
+		place_on_canvas(&image, width, height, left, top, self.color)
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		let [_, _, width, height] = self.layout(image.width, image.height)?;
+		Ok(Some((width, height)))
+	}
+}
+
+/// Grows the canvas to `width` by `height`, placing the image by `gravity` and filling the new area
+/// with `color`. Percentages are of the image width and height. A size smaller than the image
+/// leaves that side as it is.
+///
+/// ```toml
+/// [[operations]]
+/// extend = { width = { pixel = { pixels = 1200 } }, height = { pixel = { pixels = 1200 } }, gravity = "center", color = "white" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Extend {
+	pub width: Unit,
+	pub height: Unit,
+	/// Defaults to center
+	#[serde(default = "Extend::default_gravity")]
+	pub gravity: Gravity,
+	/// Defaults to transparent
+	#[serde(default)]
+	pub color: Color,
+}
+
+impl Extend {
+	fn default_gravity() -> Gravity {
+		Gravity::Center
+	}
+
+	fn size(&self, width: u32, height: u32) -> (u32, u32) {
+		let out_width = self
+			.width
+			.as_pixel(PixelUnit::from(width), (width, height))
+			.pixels
+			.max(width);
+		let out_height = self
+			.height
+			.as_pixel(PixelUnit::from(height), (width, height))
+			.pixels
+			.max(height);
+		(out_width, out_height)
+	}
+}
+
+impl Process for Extend {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (width, height) = image.dimensions();
+		let (out_width, out_height) = self.size(width, height);
+
+		let (x, y) = self
+			.gravity
+			.position((out_width, out_height), (width, height), (0, 0));
+		place_on_canvas(
+			&image, out_width, out_height, x as u32, y as u32, self.color,
+		)
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		Ok(Some(self.size(image.width, image.height)))
+	}
+}
+
+/// Letterboxes or pillarboxes the image to the aspect ratio `ratio` without scaling it, adding the
+/// least area filled with `color` needed.
+///
+/// ```toml
+/// [[operations]]
+/// pad-to-aspect = { ratio = [1, 1], color = "white" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PadToAspect {
+	/// Width to height, such as `[4, 5]`
+	pub ratio: (u32, u32),
+	/// Defaults to transparent
+	#[serde(default)]
+	pub color: Color,
+	/// Defaults to center
+	#[serde(default = "Extend::default_gravity")]
+	pub gravity: Gravity,
+}
+
+impl PadToAspect {
+	fn size(&self, width: u32, height: u32) -> Result<(u32, u32), OperationError> {
+		let (ratio_width, ratio_height) = self.ratio;
+		if ratio_width == 0 || ratio_height == 0 {
+			return Err(OperationError::new(format!(
+				"Aspect ratio must not be zero, got {ratio_width}:{ratio_height}"
+			)));
+		}
+
+		let (w, h, rw, rh) = (
+			width as u64,
+			height as u64,
+			ratio_width as u64,
+			ratio_height as u64,
+		);
+		let (out_width, out_height) = if w * rh > h * rw {
+			(w, (w * rh).div_ceil(rw))
+		} else {
+			((h * rw).div_ceil(rh), h)
+		};
+		let (Ok(out_width), Ok(out_height)) = (u32::try_from(out_width), u32::try_from(out_height))
+		else {
+			return Err(OperationError::new(format!(
+				"Padded image is too large for operation {self:?}"
+			)));
+		};
+
+		Ok((out_width, out_height))
+	}
+}
+
+impl Process for PadToAspect {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (width, height) = image.dimensions();
+		let (out_width, out_height) = self.size(width, height)?;
+		let (x, y) = self
+			.gravity
+			.position((out_width, out_height), (width, height), (0, 0));
+		place_on_canvas(
+			&image, out_width, out_height, x as u32, y as u32, self.color,
+		)
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		self.size(image.width, image.height).map(Some)
+	}
+}
+
+/// Converts an RGBA float canvas back to the bit depth of `like`, dropping the alpha channel unless
+/// `alpha` is set.
+pub(crate) fn with_depth_of(
+	canvas: Rgba32FImage,
+	like: &DynamicImage,
+	alpha: bool,
+) -> DynamicImage {
+	let canvas = DynamicImage::ImageRgba32F(canvas);
+	match (like, alpha) {
+		(
+			DynamicImage::ImageLuma16(_)
+			| DynamicImage::ImageLumaA16(_)
+			| DynamicImage::ImageRgb16(_)
+			| DynamicImage::ImageRgba16(_),
+			true,
+		) => DynamicImage::ImageRgba16(canvas.into_rgba16()),
+		(
+			DynamicImage::ImageLuma16(_)
+			| DynamicImage::ImageLumaA16(_)
+			| DynamicImage::ImageRgb16(_)
+			| DynamicImage::ImageRgba16(_),
+			false,
+		) => DynamicImage::ImageRgb16(canvas.into_rgb16()),
+		(DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_), true) => canvas,
+		(DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_), false) => {
+			DynamicImage::ImageRgb32F(canvas.into_rgb32f())
+		}
+		(_, true) => DynamicImage::ImageRgba8(canvas.into_rgba8()),
+		(_, false) => DynamicImage::ImageRgb8(canvas.into_rgb8()),
+	}
+}
+
+/// Composites a blurred shadow of the image's alpha silhouette behind it, offset right and down by
+/// `x` and `y`. The canvas grows to fit the offset and the blur. Percentages of `x` are of the
+/// image width, and of `y` of the image height.
+///
+/// ```toml
+/// [[operations]]
+/// drop-shadow = { x = { pixel = { pixels = 8 } }, y = { pixel = { pixels = 8 } }, sigma = 6.0, opacity = 0.6 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DropShadow {
+	pub x: Unit,
+	pub y: Unit,
+	pub sigma: f32,
+	/// Defaults to black
+	#[serde(default = "DropShadow::default_color")]
+	pub color: Color,
+	/// Between 0 and 1, defaults to 0.5
+	#[serde(default = "DropShadow::default_opacity")]
+	pub opacity: f32,
+}
+
+impl DropShadow {
+	fn default_color() -> Color {
+		Color::BLACK
+	}
+
+	fn default_opacity() -> f32 {
+		0.5
+	}
+
+	/// Offset of the shadow, margin left for its blur, and the canvas width and height.
+	fn layout(&self, width: u32, height: u32) -> Result<[u32; 5], OperationError> {
+		if !(0.0..=1.0).contains(&self.opacity) {
+			return Err(OperationError::new(format!(
+				"Drop shadow opacity must be between 0 and 1, got {}",
+				self.opacity
+			)));
+		}
+		if self.sigma.is_nan() || self.sigma < 0.0 {
+			return Err(OperationError::new(format!(
+				"Drop shadow sigma must not be negative, got {}",
+				self.sigma
+			)));
+		}
+
+		let x = self
+			.x
+			.as_pixel(PixelUnit::from(width), (width, height))
+			.pixels;
+		let y = self
+			.y
+			.as_pixel(PixelUnit::from(height), (width, height))
+			.pixels;
+		let margin = (self.sigma * 3.0).ceil() as u32;
+
+		let out_width = width
+			.checked_add(x)
+			.and_then(|width| width.checked_add(margin.checked_mul(2)?));
+		let out_height = height
+			.checked_add(y)
+			.and_then(|height| height.checked_add(margin.checked_mul(2)?));
+		let (Some(out_width), Some(out_height)) = (out_width, out_height) else {
+			return Err(OperationError::new(format!(
+				"Shadow is too large for operation {self:?}"
+			)));
+		};
+
+		Ok([x, y, margin, out_width, out_height])
+	}
+}
+
+impl Process for DropShadow {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let [x, y, margin, out_width, out_height] = self.layout(image.width(), image.height())?;
+		let source = image.to_rgba32f();
+		let mut silhouette = ImageBuffer::from_pixel(out_width, out_height, Luma([0.0f32]));
+		for (px, py, pixel) in source.enumerate_pixels() {
+			silhouette.put_pixel(margin + x + px, margin + y + py, Luma([pixel[3]]));
+		}
+		if self.sigma > 0.0 {
+			silhouette = imageops::blur(&silhouette, self.sigma);
+		}
+
+		let [r, g, b, a] = [self.color.r, self.color.g, self.color.b, self.color.a]
+			.map(|channel| channel as f32 / 255.0);
+		let opacity = a * self.opacity;
+		let mut canvas = Rgba32FImage::from_fn(out_width, out_height, |px, py| {
+			let coverage = silhouette.get_pixel(px, py)[0].clamp(0.0, 1.0);
+			Rgba([r, g, b, coverage * opacity])
+		});
+		imageops::overlay(&mut canvas, &source, margin as i64, margin as i64);
+
+		Ok(with_depth_of(canvas, &image, true))
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		let [_, _, _, width, height] = self.layout(image.width, image.height)?;
+		Ok(Some((width, height)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{DropShadow, Extend, Pad, PadToAspect};
+	use crate::{Color, Gravity, PercentageUnit, PixelUnit, Process, Unit};
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+	#[test]
+	fn parse_colors() {
+		assert_eq!(Ok(Color::rgba(255, 0, 0, 255)), "#f00".parse());
+		assert_eq!(Ok(Color::rgba(18, 52, 86, 255)), "#123456".parse());
+		assert_eq!(Ok(Color::rgba(18, 52, 86, 120)), "#12345678".parse());
+		assert_eq!(Ok(Color::TRANSPARENT), "Transparent".parse::<Color>());
+		assert!("123456".parse::<Color>().is_err());
+		assert!("#12345".parse::<Color>().is_err());
+		assert_eq!("#12345678", String::from(Color::rgba(18, 52, 86, 120)));
+	}
+
+	#[test]
+	fn pad_sides() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 10, Rgb([10, 20, 30])));
+		let pad = Pad {
+			top: Unit::Pixel(PixelUnit::from(2)),
+			right: Unit::Percentage(PercentageUnit::try_from(0.5).unwrap()),
+			bottom: Unit::Pixel(PixelUnit::from(0)),
+			left: Unit::Pixel(PixelUnit::from(3)),
+			color: Color::WHITE,
+		};
+
+		let padded = pad.process(image).unwrap();
+		assert_eq!((33, 12), padded.dimensions());
+		assert!(!padded.color().has_alpha());
+		assert_eq!([255, 255, 255, 255], padded.get_pixel(0, 0).0);
+		assert_eq!([10, 20, 30, 255], padded.get_pixel(3, 2).0);
+		assert_eq!([255, 255, 255, 255], padded.get_pixel(23, 2).0);
+	}
+
+	#[test]
+	fn extend_by_gravity() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 10, Rgb([10, 20, 30])));
+		let extend = |width, gravity| Extend {
+			width: Unit::Pixel(PixelUnit::from(width)),
+			height: Unit::Pixel(PixelUnit::from(20)),
+			gravity,
+			color: Color::WHITE,
+		};
+
+		let centered = extend(40, Gravity::Center).process(image.clone()).unwrap();
+		assert_eq!((40, 20), centered.dimensions());
+		assert!(!centered.color().has_alpha());
+		assert_eq!([255, 255, 255, 255], centered.get_pixel(9, 4).0);
+		assert_eq!([10, 20, 30, 255], centered.get_pixel(10, 5).0);
+		assert_eq!([10, 20, 30, 255], centered.get_pixel(29, 14).0);
+		assert_eq!([255, 255, 255, 255], centered.get_pixel(30, 15).0);
+
+		// A smaller width leaves that side as it is
+		let cornered = extend(5, Gravity::BottomRight).process(image).unwrap();
+		assert_eq!((20, 20), cornered.dimensions());
+		assert_eq!([255, 255, 255, 255], cornered.get_pixel(0, 9).0);
+		assert_eq!([10, 20, 30, 255], cornered.get_pixel(0, 10).0);
+	}
+
+	#[test]
+	fn pad_to_aspect() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 10, Rgb([10, 20, 30])));
+		let pad = |ratio| PadToAspect {
+			ratio,
+			color: Color::WHITE,
+			gravity: crate::Gravity::Center,
+		};
+
+		let square = pad((1, 1)).process(image.clone()).unwrap();
+		assert_eq!((20, 20), square.dimensions());
+		assert_eq!([10, 20, 30, 255], square.get_pixel(0, 5).0);
+		assert_eq!([255, 255, 255, 255], square.get_pixel(0, 4).0);
+
+		let portrait = pad((4, 5)).process(image.clone()).unwrap();
+		assert_eq!((20, 25), portrait.dimensions());
+		let wide = pad((3, 1)).process(image).unwrap();
+		assert_eq!((30, 10), wide.dimensions());
+	}
+
+	#[test]
+	fn drop_shadow_sits_behind_the_image() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(10, 10, Rgb([255, 255, 255])));
+		let shadow = DropShadow {
+			x: Unit::Pixel(PixelUnit::from(4)),
+			y: Unit::Pixel(PixelUnit::from(4)),
+			sigma: 0.0,
+			color: Color::BLACK,
+			opacity: 1.0,
+		};
+
+		let shadowed = shadow.process(image).unwrap();
+		assert_eq!((14, 14), shadowed.dimensions());
+		assert_eq!([255, 255, 255, 255], shadowed.get_pixel(5, 5).0);
+		assert_eq!([0, 0, 0, 255], shadowed.get_pixel(12, 12).0);
+		assert_eq!(0, shadowed.get_pixel(12, 1)[3]);
+	}
+
+	#[test]
+	fn drop_shadow_too_large() {
+		let image = DynamicImage::ImageRgb8(RgbImage::new(10, 10));
+		let shadow = DropShadow {
+			x: Unit::Pixel(PixelUnit::from(4)),
+			y: Unit::Pixel(PixelUnit::from(4)),
+			sigma: 1e9,
+			color: Color::BLACK,
+			opacity: 1.0,
+		};
+		assert!(shadow.process(image).is_err());
+	}
+}
@@ -0,0 +1,301 @@
+use super::canvas::with_depth_of;
+use crate::{check_cancelled, ImageInfo, OperationError, PixelUnit, Process, Unit};
+use image::{DynamicImage, GenericImageView, Rgba, Rgba32FImage};
+use serde::{Deserialize, Serialize};
+
+/// Rows of pixels being carved, along with their luminance. Seams always run top to bottom, so
+/// horizontal seams are carved from the transposed image.
+struct Grid {
+	width: usize,
+	height: usize,
+	pixels: Vec<[f32; 4]>,
+	luminance: Vec<f32>,
+}
+
+impl Grid {
+	fn new(image: &Rgba32FImage, transpose: bool) -> Self {
+		let (width, height) = image.dimensions();
+		let (width, height) = if transpose {
+			(height, width)
+		} else {
+			(width, height)
+		};
+		let pixels: Vec<[f32; 4]> = (0..height)
+			.flat_map(|y| (0..width).map(move |x| (x, y)))
+			.map(|(x, y)| {
+				let (x, y) = if transpose { (y, x) } else { (x, y) };
+				image.get_pixel(x, y).0
+			})
+			.collect();
+		let luminance = pixels
+			.iter()
+			.map(|[r, g, b, a]| (0.2126 * r + 0.7152 * g + 0.0722 * b) * a)
+			.collect();
+
+		Self {
+			width: width as usize,
+			height: height as usize,
+			pixels,
+			luminance,
+		}
+	}
+
+	fn into_image(self, transpose: bool) -> Rgba32FImage {
+		let (width, height) = (self.width as u32, self.height as u32);
+		if transpose {
+			Rgba32FImage::from_fn(height, width, |x, y| {
+				Rgba(self.pixels[x as usize * self.width + y as usize])
+			})
+		} else {
+			Rgba32FImage::from_fn(width, height, |x, y| {
+				Rgba(self.pixels[y as usize * self.width + x as usize])
+			})
+		}
+	}
+
+	/// Gradient magnitude of the luminance, which is low in flat areas a seam can pass through
+	/// unnoticed.
+	fn energy(&self) -> Vec<f32> {
+		let (width, height) = (self.width, self.height);
+		let at = |x: usize, y: usize| self.luminance[y * width + x];
+		(0..height)
+			.flat_map(|y| (0..width).map(move |x| (x, y)))
+			.map(|(x, y)| {
+				let dx = at((x + 1).min(width - 1), y) - at(x.saturating_sub(1), y);
+				let dy = at(x, (y + 1).min(height - 1)) - at(x, y.saturating_sub(1));
+				dx.abs() + dy.abs()
+			})
+			.collect()
+	}
+
+	/// Column of the lowest energy seam in each row, found by dynamic programming.
+	fn seam(&self) -> Vec<usize> {
+		let width = self.width;
+		let mut cost = self.energy();
+		for y in 1..self.height {
+			for x in 0..width {
+				let above = &cost[(y - 1) * width..y * width];
+				let lowest = above[x.saturating_sub(1)..(x + 2).min(width)]
+					.iter()
+					.copied()
+					.fold(f32::MAX, f32::min);
+				cost[y * width + x] += lowest;
+			}
+		}
+
+		let last = &cost[(self.height - 1) * width..];
+		let mut x = (0..width)
+			.min_by(|&a, &b| last[a].total_cmp(&last[b]))
+			.unwrap_or(0);
+		let mut seam = vec![0; self.height];
+		for y in (0..self.height).rev() {
+			seam[y] = x;
+			if y > 0 {
+				let above = &cost[(y - 1) * width..y * width];
+				x = (x.saturating_sub(1)..(x + 2).min(width))
+					.min_by(|&a, &b| above[a].total_cmp(&above[b]))
+					.unwrap_or(x);
+			}
+		}
+
+		seam
+	}
+
+	fn remove<T: Copy>(values: &mut Vec<T>, width: usize, seam: &[usize]) {
+		let mut row = 0;
+		let mut index = 0;
+		values.retain(|_| {
+			let keep = index - row * width != seam[row];
+			index += 1;
+			if index % width == 0 {
+				row += 1;
+			}
+			keep
+		});
+	}
+
+	fn remove_seam(&mut self, seam: &[usize]) {
+		Self::remove(&mut self.pixels, self.width, seam);
+		Self::remove(&mut self.luminance, self.width, seam);
+		self.width -= 1;
+	}
+
+	/// Removes `count` seams, or duplicates that many when negative.
+	fn carve(&mut self, count: i64) -> Result<(), OperationError> {
+		if count > 0 {
+			for _ in 0..count {
+				check_cancelled()?;
+				let seam = self.seam();
+				self.remove_seam(&seam);
+			}
+			return Ok(());
+		}
+
+		// Inserting the same lowest seam over and over would only stretch it, so the seams to
+		// duplicate are found by removing them from a copy. At most half the width is added at a
+		// time for the same reason.
+		let mut remaining = count.unsigned_abs() as usize;
+		while remaining > 0 {
+			let count = remaining.min((self.width / 2).max(1));
+			let mut copy = Self {
+				width: self.width,
+				height: self.height,
+				pixels: Vec::new(),
+				luminance: self.luminance.clone(),
+			};
+			let mut columns: Vec<usize> = (0..self.width * self.height)
+				.map(|index| index % self.width)
+				.collect();
+			let mut seams = vec![Vec::with_capacity(count); self.height];
+			for _ in 0..count {
+				check_cancelled()?;
+				let seam = copy.seam();
+				for (y, &x) in seam.iter().enumerate() {
+					seams[y].push(columns[y * copy.width + x]);
+				}
+				Self::remove(&mut columns, copy.width, &seam);
+				Self::remove(&mut copy.luminance, copy.width, &seam);
+				copy.width -= 1;
+			}
+
+			let width = self.width;
+			let mut pixels = Vec::with_capacity((width + count) * self.height);
+			let mut luminance = Vec::with_capacity(pixels.capacity());
+			for (y, seams) in seams.iter_mut().enumerate() {
+				seams.sort_unstable();
+				let mut seams = seams.iter().peekable();
+				for x in 0..width {
+					let index = y * width + x;
+					pixels.push(self.pixels[index]);
+					luminance.push(self.luminance[index]);
+					while seams.next_if_eq(&&x).is_some() {
+						// Averages with the next pixel so the new seam blends in
+						let next = y * width + (x + 1).min(width - 1);
+						let mut pixel = self.pixels[index];
+						for (channel, next) in pixel.iter_mut().zip(self.pixels[next]) {
+							*channel = (*channel + next) / 2.0;
+						}
+						pixels.push(pixel);
+						luminance.push((self.luminance[index] + self.luminance[next]) / 2.0);
+					}
+				}
+			}
+
+			self.pixels = pixels;
+			self.luminance = luminance;
+			self.width += count;
+			remaining -= count;
+		}
+
+		Ok(())
+	}
+}
+
+/// Seams can't be carved down to nothing, so both dimensions of the target need to be nonzero.
+pub(crate) fn check_size(width: u32, height: u32) -> Result<(), OperationError> {
+	if width == 0 || height == 0 {
+		return Err(OperationError::new(format!(
+			"Cannot seam carve to {width}x{height}"
+		)));
+	}
+
+	Ok(())
+}
+
+/// Resizes to exactly `width` by `height` by removing or duplicating the lowest energy seams of
+/// pixels, keeping detailed areas intact. Width is carved before height.
+pub(crate) fn seam_carve(
+	image: &DynamicImage,
+	width: u32,
+	height: u32,
+) -> Result<DynamicImage, OperationError> {
+	let (current_width, current_height) = image.dimensions();
+	if (width, height) == (current_width, current_height) {
+		return Ok(image.clone());
+	}
+
+	let mut rgba = image.to_rgba32f();
+	if width != current_width {
+		let mut grid = Grid::new(&rgba, false);
+		grid.carve(current_width as i64 - width as i64)?;
+		rgba = grid.into_image(false);
+	}
+	if height != current_height {
+		let mut grid = Grid::new(&rgba, true);
+		grid.carve(current_height as i64 - height as i64)?;
+		rgba = grid.into_image(true);
+	}
+
+	Ok(with_depth_of(rgba, image, image.color().has_alpha()))
+}
+
+/// Content-aware resize, which removes or inserts seams of pixels through the least detailed
+/// parts of the image rather than scaling or cropping. This keeps the subject intact through
+/// aggressive aspect ratio changes, but gets slow for large changes to large images.
+///
+/// ```toml
+/// [[operations]]
+/// seam-carve = { width = { percentage = { percentage = 0.7 } }, height = { pixel = { pixels = 480 } } }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SeamCarve {
+	pub width: Unit,
+	pub height: Unit,
+}
+
+impl SeamCarve {
+	fn size(&self, width: u32, height: u32) -> Result<(u32, u32), OperationError> {
+		let pixels = |unit: &Unit, dimension: u32| {
+			unit.as_pixel(PixelUnit::from(dimension), (width, height))
+				.pixels
+		};
+		let width = pixels(&self.width, width);
+		let height = pixels(&self.height, height);
+		check_size(width, height)?;
+
+		Ok((width, height))
+	}
+}
+
+impl Process for SeamCarve {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (width, height) = self.size(image.width(), image.height())?;
+		seam_carve(&image, width, height)
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		self.size(image.width, image.height).map(Some)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::seam_carve;
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+	#[test]
+	fn carving_keeps_detail() {
+		// A flat image with a single bright column, which carving should step around
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(20, 10, |x, _| {
+			if x == 12 {
+				Rgb([255, 255, 255])
+			} else {
+				Rgb([0, 0, 0])
+			}
+		}));
+
+		let narrower = seam_carve(&image, 12, 10).unwrap();
+		assert_eq!((12, 10), narrower.dimensions());
+		let bright = |image: &DynamicImage| {
+			(0..image.width())
+				.filter(|&x| image.get_pixel(x, 5)[0] == 255)
+				.count()
+		};
+		assert_eq!(1, bright(&narrower));
+
+		let wider = seam_carve(&image, 30, 8).unwrap();
+		assert_eq!((30, 8), wider.dimensions());
+		assert_eq!(1, bright(&wider));
+	}
+}
@@ -0,0 +1,758 @@
+use crate::{simd, Color, OperationError, Process};
+use image::{DynamicImage, ImageBuffer, Pixel, Primitive};
+use num::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+/// Applies `f` to the normalized RGB channels of every pixel, keeping the alpha channel, color
+/// type and bit depth. Gray pixels are passed as equal channels and keep the luminance of the
+/// result, so operations which add color convert grayscale images with [`with_color`] first.
+pub(crate) fn map_rgb<F>(image: DynamicImage, f: F) -> DynamicImage
+where
+	F: FnMut([f32; 3]) -> [f32; 3],
+{
+	match image {
+		DynamicImage::ImageLuma8(buffer) => DynamicImage::ImageLuma8(map_buffer(buffer, f)),
+		DynamicImage::ImageLumaA8(buffer) => DynamicImage::ImageLumaA8(map_buffer(buffer, f)),
+		DynamicImage::ImageLuma16(buffer) => DynamicImage::ImageLuma16(map_buffer(buffer, f)),
+		DynamicImage::ImageLumaA16(buffer) => DynamicImage::ImageLumaA16(map_buffer(buffer, f)),
+		DynamicImage::ImageRgb8(buffer) => DynamicImage::ImageRgb8(map_buffer(buffer, f)),
+		DynamicImage::ImageRgba8(buffer) => DynamicImage::ImageRgba8(map_buffer(buffer, f)),
+		DynamicImage::ImageRgb16(buffer) => DynamicImage::ImageRgb16(map_buffer(buffer, f)),
+		DynamicImage::ImageRgba16(buffer) => DynamicImage::ImageRgba16(map_buffer(buffer, f)),
+		DynamicImage::ImageRgb32F(buffer) => DynamicImage::ImageRgb32F(map_buffer(buffer, f)),
+		DynamicImage::ImageRgba32F(buffer) => DynamicImage::ImageRgba32F(map_buffer(buffer, f)),
+		image => map_rgb(DynamicImage::ImageRgba32F(image.into_rgba32f()), f),
+	}
+}
+
+/// Applies `f` to each normalized RGB channel of every pixel on its own, with the index of the
+/// channel, in the same way as [`map_rgb`]. 8-bit RGB images are mapped through a lookup table
+/// per channel.
+pub(crate) fn map_channels<F>(image: DynamicImage, f: F) -> DynamicImage
+where
+	F: Fn(usize, f32) -> f32,
+{
+	let tables = || {
+		[0, 1, 2].map(|channel| {
+			std::array::from_fn(|sample| {
+				(f(channel, sample as f32 / 255.0).clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+			})
+		})
+	};
+
+	match image {
+		DynamicImage::ImageRgb8(mut buffer) => {
+			simd::apply_lut(&mut buffer, 3, &tables());
+			DynamicImage::ImageRgb8(buffer)
+		}
+		DynamicImage::ImageRgba8(mut buffer) => {
+			simd::apply_lut(&mut buffer, 4, &tables());
+			DynamicImage::ImageRgba8(buffer)
+		}
+		image => map_rgb(image, |rgb| [0, 1, 2].map(|i| f(i, rgb[i]))),
+	}
+}
+
+/// Converts grayscale images to RGB of the same bit depth, for operations which add color.
+pub(crate) fn with_color(image: DynamicImage) -> DynamicImage {
+	match image {
+		DynamicImage::ImageLuma8(_) => DynamicImage::ImageRgb8(image.into_rgb8()),
+		DynamicImage::ImageLumaA8(_) => DynamicImage::ImageRgba8(image.into_rgba8()),
+		DynamicImage::ImageLuma16(_) => DynamicImage::ImageRgb16(image.into_rgb16()),
+		DynamicImage::ImageLumaA16(_) => DynamicImage::ImageRgba16(image.into_rgba16()),
+		image => image,
+	}
+}
+
+fn map_buffer<P, F>(
+	mut buffer: ImageBuffer<P, Vec<P::Subpixel>>,
+	mut f: F,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+	P: Pixel,
+	F: FnMut([f32; 3]) -> [f32; 3],
+{
+	let max = P::Subpixel::DEFAULT_MAX_VALUE.to_f32().unwrap_or(1.0);
+	// Integer channels are rounded to the nearest value rather than truncated
+	let offset = if max > 1.0 { 0.5 } else { 0.0 };
+
+	let gray = P::CHANNEL_COUNT <= 2;
+
+	for pixel in buffer.pixels_mut() {
+		let channels = pixel.channels_mut();
+		let rgb = if gray {
+			[channels[0].to_f32().unwrap_or_default() / max; 3]
+		} else {
+			[0, 1, 2].map(|i| channels[i].to_f32().unwrap_or_default() / max)
+		};
+
+		let [r, g, b] = f(rgb);
+		let values = if gray {
+			&[0.2126 * r + 0.7152 * g + 0.0722 * b][..]
+		} else {
+			&[r, g, b][..]
+		};
+		for (channel, &value) in channels.iter_mut().zip(values) {
+			*channel = num::NumCast::from(value.clamp(0.0, 1.0) * max + offset)
+				.unwrap_or(P::Subpixel::DEFAULT_MIN_VALUE);
+		}
+	}
+
+	buffer
+}
+
+/// Converts normalized RGB to hue in degrees, saturation and lightness.
+pub(crate) fn rgb_to_hsl([r, g, b]: [f32; 3]) -> [f32; 3] {
+	let max = r.max(g).max(b);
+	let min = r.min(g).min(b);
+	let lightness = (max + min) / 2.0;
+	let delta = max - min;
+
+	if delta == 0.0 {
+		return [0.0, 0.0, lightness];
+	}
+
+	let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+	let hue = if max == r {
+		60.0 * ((g - b) / delta).rem_euclid(6.0)
+	} else if max == g {
+		60.0 * ((b - r) / delta + 2.0)
+	} else {
+		60.0 * ((r - g) / delta + 4.0)
+	};
+
+	[hue, saturation, lightness]
+}
+
+/// Converts hue in degrees, saturation and lightness to normalized RGB.
+pub(crate) fn hsl_to_rgb([hue, saturation, lightness]: [f32; 3]) -> [f32; 3] {
+	let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+	let h = hue.rem_euclid(360.0) / 60.0;
+	let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+	let m = lightness - chroma / 2.0;
+
+	let [r, g, b] = match h as u32 {
+		0 => [chroma, x, 0.0],
+		1 => [x, chroma, 0.0],
+		2 => [0.0, chroma, x],
+		3 => [0.0, x, chroma],
+		4 => [x, 0.0, chroma],
+		_ => [chroma, 0.0, x],
+	};
+
+	[r + m, g + m, b + m]
+}
+
+/// Increases or decreases color saturation by a percentage, e.g. `50` for 50% more saturated
+/// colors, or `-100` to remove all color.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Saturation {
+	pub percentage: f32,
+}
+
+impl Process for Saturation {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !self.percentage.is_finite() {
+			return Err(OperationError::new(format!(
+				"Saturation must be finite, got {}%",
+				self.percentage
+			)));
+		}
+		if self.percentage < -100.0 {
+			return Err(OperationError::new(format!(
+				"Saturation cannot be reduced by more than 100%, got {}%",
+				self.percentage
+			)));
+		}
+
+		let factor = 1.0 + self.percentage / 100.0;
+		Ok(map_rgb(image, |rgb| {
+			let [hue, saturation, lightness] = rgb_to_hsl(rgb);
+			hsl_to_rgb([hue, (saturation * factor).min(1.0), lightness])
+		}))
+	}
+}
+
+/// Sepia toning, blended with the original colors by `intensity` between 0 and 1.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Sepia {
+	#[serde(default = "Sepia::default_intensity")]
+	pub intensity: f32,
+}
+
+impl Sepia {
+	fn default_intensity() -> f32 {
+		1.0
+	}
+}
+
+impl Process for Sepia {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !(0.0..=1.0).contains(&self.intensity) {
+			return Err(OperationError::new(format!(
+				"Sepia intensity must be between 0 and 1, got {}",
+				self.intensity
+			)));
+		}
+
+		let intensity = self.intensity;
+		Ok(map_rgb(with_color(image), |[r, g, b]| {
+			let sepia = [
+				0.393 * r + 0.769 * g + 0.189 * b,
+				0.349 * r + 0.686 * g + 0.168 * b,
+				0.272 * r + 0.534 * g + 0.131 * b,
+			];
+			let original = [r, g, b];
+			[0, 1, 2].map(|i| original[i] + (sepia[i] - original[i]) * intensity)
+		}))
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlendMode {
+	/// Replaces the image color
+	Normal,
+	/// Darkens the image by the color, leaving white areas the tint color
+	#[default]
+	Multiply,
+	/// Lightens the image by the color, leaving black areas the tint color
+	Screen,
+	/// Multiplies dark areas and screens light areas
+	Overlay,
+	/// Takes the hue and saturation of the color, keeping the image lightness
+	Color,
+}
+
+impl BlendMode {
+	fn blend(self, base: [f32; 3], tint: [f32; 3]) -> [f32; 3] {
+		let channel = |f: fn(f32, f32) -> f32| [0, 1, 2].map(|i| f(base[i], tint[i]));
+		match self {
+			Self::Normal => tint,
+			Self::Multiply => channel(|base, tint| base * tint),
+			Self::Screen => channel(|base, tint| 1.0 - (1.0 - base) * (1.0 - tint)),
+			Self::Overlay => channel(|base, tint| {
+				if base < 0.5 {
+					2.0 * base * tint
+				} else {
+					1.0 - 2.0 * (1.0 - base) * (1.0 - tint)
+				}
+			}),
+			Self::Color => {
+				let [hue, saturation, _] = rgb_to_hsl(tint);
+				let [_, _, lightness] = rgb_to_hsl(base);
+				hsl_to_rgb([hue, saturation, lightness])
+			}
+		}
+	}
+}
+
+/// Blends a color over the image with a blend mode, mixed with the original colors by `strength`
+/// between 0 and 1. The alpha of the color scales the strength.
+///
+/// ```toml
+/// [[operations]]
+/// grayscale = {}
+///
+/// [[operations]]
+/// tint = { color = "#1e90ff", mode = "screen", strength = 0.8 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Tint {
+	pub color: Color,
+	/// Defaults to multiply
+	#[serde(default)]
+	pub mode: BlendMode,
+	#[serde(default = "Tint::default_strength")]
+	pub strength: f32,
+}
+
+impl Tint {
+	fn default_strength() -> f32 {
+		1.0
+	}
+}
+
+impl Process for Tint {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !(0.0..=1.0).contains(&self.strength) {
+			return Err(OperationError::new(format!(
+				"Tint strength must be between 0 and 1, got {}",
+				self.strength
+			)));
+		}
+
+		let tint = [self.color.r, self.color.g, self.color.b].map(|channel| channel as f32 / 255.0);
+		let strength = self.strength * self.color.a as f32 / 255.0;
+		let mode = self.mode;
+		let image = with_color(image);
+		if mode == BlendMode::Color {
+			return Ok(map_rgb(image, |rgb| {
+				let blended = mode.blend(rgb, tint);
+				[0, 1, 2].map(|i| rgb[i] + (blended[i] - rgb[i]) * strength)
+			}));
+		}
+
+		// Other modes blend each channel on its own
+		Ok(map_channels(image, |i, value| {
+			let mut rgb = [0.0; 3];
+			rgb[i] = value;
+			value + (mode.blend(rgb, tint)[i] - value) * strength
+		}))
+	}
+}
+
+/// Remaps luminance through a gradient of `[position, color]` stops, with positions between 0 and
+/// 1 in ascending order. Two stops make a duotone. The alpha of the colors blends the gradient with
+/// the original colors.
+///
+/// ```toml
+/// [[operations]]
+/// gradient-map = { stops = [[0.0, "#1a0033"], [0.5, "#e6007e"], [1.0, "#ffe600"]] }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct GradientMap {
+	pub stops: Vec<(f32, Color)>,
+}
+
+impl GradientMap {
+	fn validate(&self) -> Result<(), OperationError> {
+		if self.stops.is_empty() {
+			return Err(OperationError::new(
+				"Gradient maps need at least one stop".to_string(),
+			));
+		}
+		if self
+			.stops
+			.iter()
+			.any(|(position, _)| !(0.0..=1.0).contains(position))
+		{
+			return Err(OperationError::new(format!(
+				"Gradient stop positions must be between 0 and 1 in {:?}",
+				self.stops
+			)));
+		}
+		if self.stops.windows(2).any(|pair| pair[1].0 < pair[0].0) {
+			return Err(OperationError::new(format!(
+				"Gradient stops must be in ascending order in {:?}",
+				self.stops
+			)));
+		}
+
+		Ok(())
+	}
+}
+
+impl Process for GradientMap {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		self.validate()?;
+
+		let stops: Vec<(f32, [f32; 4])> = self
+			.stops
+			.iter()
+			.map(|(position, color)| {
+				let rgba = [color.r, color.g, color.b, color.a].map(|c| c as f32 / 255.0);
+				(*position, rgba)
+			})
+			.collect();
+		let (first, last) = (stops[0], stops[stops.len() - 1]);
+
+		Ok(map_rgb(with_color(image), |rgb| {
+			let luminance = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+			let color = if luminance <= first.0 {
+				first.1
+			} else if luminance >= last.0 {
+				last.1
+			} else {
+				let next = stops.partition_point(|(position, _)| *position <= luminance);
+				let ((from, low), (to, high)) = (stops[next - 1], stops[next]);
+				let t = (luminance - from) / (to - from);
+				[0, 1, 2, 3].map(|i| low[i] + (high[i] - low[i]) * t)
+			};
+			[0, 1, 2].map(|i| rgb[i] + (color[i] - rgb[i]) * color[3])
+		}))
+	}
+}
+
+/// Inverts pixels lighter than `threshold`, on a 0–255 scale. Pixels are compared by luminance
+/// unless `per_channel` is set, in which case each channel is compared and inverted on its own.
+///
+/// ```toml
+/// [[operations]]
+/// solarize = { threshold = 128 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Solarize {
+	pub threshold: u8,
+	#[serde(default)]
+	pub per_channel: bool,
+}
+
+impl Process for Solarize {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let threshold = self.threshold as f32 / 255.0;
+
+		if self.per_channel {
+			return Ok(map_channels(image, |_, value| {
+				if value > threshold {
+					1.0 - value
+				} else {
+					value
+				}
+			}));
+		}
+
+		Ok(map_rgb(image, |rgb| {
+			let luminance = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+			if luminance > threshold {
+				rgb.map(|value| 1.0 - value)
+			} else {
+				rgb
+			}
+		}))
+	}
+}
+
+/// Converts an sRGB encoded channel to linear light.
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+	if value <= 0.04045 {
+		value / 12.92
+	} else {
+		((value + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+/// Converts a linear light channel to sRGB encoding.
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+	if value <= 0.0031308 {
+		value * 12.92
+	} else {
+		1.055 * value.max(0.0).powf(1.0 / 2.4) - 0.055
+	}
+}
+
+/// Corrects color casts by scaling the channels in linear light, either by manual `temperature`
+/// and `tint` sliders between -100 and 100, or automatically.
+///
+/// ```toml
+/// [[operations]]
+/// white-balance = { manual = { temperature = 20, tint = -5 } }
+///
+/// [[operations]]
+/// white-balance = "gray-world"
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WhiteBalance {
+	/// Positive temperatures are warmer and negative ones cooler. Positive tints are more magenta
+	/// and negative ones more green.
+	Manual {
+		temperature: f32,
+		#[serde(default)]
+		tint: f32,
+	},
+	/// Assumes the average color of the image is neutral gray
+	GrayWorld,
+	/// Assumes the brightest color of the image, ignoring the brightest 1% of each channel, is white
+	WhitePatch,
+}
+
+impl WhiteBalance {
+	/// Gains for the linear red, green and blue channels.
+	fn gains(&self, image: &DynamicImage) -> Result<[f32; 3], OperationError> {
+		let gains = match self {
+			Self::Manual { temperature, tint } => {
+				if [temperature, tint]
+					.iter()
+					.any(|value| !(-100.0..=100.0).contains(*value))
+				{
+					return Err(OperationError::new(format!(
+						"White balance temperature and tint must be between -100 and 100, got {temperature} and {tint}"
+					)));
+				}
+
+				let (temperature, tint) = (temperature / 200.0, tint / 200.0);
+				let gains = [
+					2f32.powf(temperature),
+					2f32.powf(-tint),
+					2f32.powf(-temperature),
+				];
+				// Keep the overall brightness the same
+				let luminance = 0.2126 * gains[0] + 0.7152 * gains[1] + 0.0722 * gains[2];
+				gains.map(|gain| gain / luminance)
+			}
+			Self::GrayWorld => {
+				let mut sums = [0f64; 3];
+				for pixel in image.to_rgb32f().pixels() {
+					for (sum, value) in sums.iter_mut().zip(pixel.0) {
+						*sum += srgb_to_linear(value) as f64;
+					}
+				}
+				let gray = 0.2126 * sums[0] + 0.7152 * sums[1] + 0.0722 * sums[2];
+				sums.map(|sum| if sum > 0.0 { (gray / sum) as f32 } else { 1.0 })
+			}
+			Self::WhitePatch => {
+				let mut histograms = [[0u32; 256]; 3];
+				let rgb = image.to_rgb8();
+				for pixel in rgb.pixels() {
+					for (histogram, value) in histograms.iter_mut().zip(pixel.0) {
+						histogram[value as usize] += 1;
+					}
+				}
+				let clipped = rgb.pixels().len() as u32 / 100;
+				let whites = histograms.map(|histogram| {
+					let mut count = 0;
+					let bin = histogram
+						.iter()
+						.rev()
+						.position(|&bin| {
+							count += bin;
+							count > clipped
+						})
+						.map_or(255, |from_top| 255 - from_top);
+					srgb_to_linear(bin as f32 / 255.0)
+				});
+				let white = whites.iter().copied().fold(0.0, f32::max);
+				whites.map(|channel| if channel > 0.0 { white / channel } else { 1.0 })
+			}
+		};
+
+		Ok(gains)
+	}
+}
+
+impl Process for WhiteBalance {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let gains = self.gains(&image)?;
+		// Gray images only stay gray with equal gains
+		let image = match gains {
+			[r, g, b] if r == g && g == b => image,
+			_ => with_color(image),
+		};
+
+		Ok(map_rgb(image, |rgb| {
+			[0, 1, 2].map(|i| linear_to_srgb(srgb_to_linear(rgb[i]) * gains[i]))
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		hsl_to_rgb, linear_to_srgb, rgb_to_hsl, srgb_to_linear, BlendMode, GradientMap, Saturation,
+		Sepia, Solarize, Tint, WhiteBalance,
+	};
+	use crate::{Color, Process};
+	use image::{ColorType, DynamicImage, GenericImageView, GrayAlphaImage, LumaA, Rgb, RgbImage};
+
+	#[test]
+	fn hsl_round_trip() {
+		for rgb in [
+			[1.0, 0.0, 0.0],
+			[0.2, 0.6, 0.4],
+			[0.5, 0.5, 0.5],
+			[0.1, 0.2, 0.9],
+			[0.9, 0.1, 0.7],
+		] {
+			let result = hsl_to_rgb(rgb_to_hsl(rgb));
+			for (expected, actual) in rgb.iter().zip(result) {
+				assert!(
+					(expected - actual).abs() < 1e-5,
+					"{rgb:?} became {result:?}"
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn rgb_to_hsl_pure_colors() {
+		assert_eq!([0.0, 1.0, 0.5], rgb_to_hsl([1.0, 0.0, 0.0]));
+		assert_eq!([120.0, 1.0, 0.5], rgb_to_hsl([0.0, 1.0, 0.0]));
+		assert_eq!([240.0, 1.0, 0.5], rgb_to_hsl([0.0, 0.0, 1.0]));
+	}
+
+	#[test]
+	fn srgb_round_trip() {
+		for value in [0.0, 0.02, 0.2, 0.5, 0.9, 1.0] {
+			assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+		}
+	}
+
+	#[test]
+	fn gray_world_neutralizes_a_cast() {
+		let image = RgbImage::from_fn(8, 8, |x, y| {
+			let value = (x * 16 + y * 8) as u8 + 40;
+			Rgb([value.saturating_add(30), value, value / 2 + 20])
+		});
+		let balanced = WhiteBalance::GrayWorld
+			.process(DynamicImage::ImageRgb8(image))
+			.unwrap();
+
+		let means = (0..3).map(|channel| {
+			balanced
+				.pixels()
+				.map(|(_, _, pixel)| pixel[channel] as f32)
+				.sum::<f32>()
+				/ 64.0
+		});
+		let means: Vec<f32> = means.collect();
+		assert!((means[0] - means[1]).abs() < 8.0, "{means:?}");
+		assert!((means[2] - means[1]).abs() < 8.0, "{means:?}");
+	}
+
+	/// Processes a single pixel of an 8-bit RGB image.
+	fn recolor(operation: impl Process, rgb: [u8; 3]) -> [u8; 3] {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb(rgb)));
+		operation
+			.process(image)
+			.unwrap()
+			.into_rgb8()
+			.get_pixel(0, 0)
+			.0
+	}
+
+	#[test]
+	fn desaturates_to_gray() {
+		let desaturate = || Saturation { percentage: -100.0 };
+		// Gray takes the HSL lightness of the color
+		assert_eq!([125, 125, 125], recolor(desaturate(), [200, 50, 100]));
+		// There is no color in gray to saturate
+		assert_eq!(
+			[80, 80, 80],
+			recolor(Saturation { percentage: 100.0 }, [80, 80, 80])
+		);
+
+		for percentage in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, -101.0] {
+			let image = DynamicImage::ImageRgb8(RgbImage::new(1, 1));
+			assert!(Saturation { percentage }.process(image).is_err());
+		}
+	}
+
+	#[test]
+	fn keeps_gray_images_gray() {
+		let gray = || DynamicImage::ImageLumaA8(GrayAlphaImage::from_pixel(2, 2, LumaA([100, 40])));
+
+		let saturated = Saturation { percentage: 50.0 }.process(gray()).unwrap();
+		assert_eq!(ColorType::La8, saturated.color());
+		assert_eq!(
+			[100, 40],
+			saturated.as_luma_alpha8().unwrap().get_pixel(0, 0).0
+		);
+
+		// Toning adds color, so it needs RGB
+		let toned = Sepia { intensity: 1.0 }.process(gray()).unwrap();
+		assert_eq!(ColorType::Rgba8, toned.color());
+		assert_eq!([135, 120, 94, 40], toned.get_pixel(0, 0).0);
+	}
+
+	#[test]
+	fn sepia_tones() {
+		assert_eq!(
+			[135, 120, 94],
+			recolor(Sepia { intensity: 1.0 }, [100, 100, 100])
+		);
+		// Channels clip at white
+		assert_eq!(
+			[255, 255, 239],
+			recolor(Sepia { intensity: 1.0 }, [255, 255, 255])
+		);
+		assert_eq!(
+			[100, 50, 20],
+			recolor(Sepia { intensity: 0.0 }, [100, 50, 20])
+		);
+	}
+
+	#[test]
+	fn tints_with_blend_modes() {
+		let tint = |mode, strength| Tint {
+			color: Color::rgba(255, 128, 0, 255),
+			mode,
+			strength,
+		};
+
+		assert_eq!(
+			[255, 128, 0],
+			recolor(tint(BlendMode::Multiply, 1.0), [255, 255, 255])
+		);
+		assert_eq!(
+			[128, 64, 0],
+			recolor(tint(BlendMode::Multiply, 1.0), [128, 128, 128])
+		);
+		assert_eq!(
+			[255, 128, 0],
+			recolor(tint(BlendMode::Screen, 1.0), [0, 0, 0])
+		);
+		assert_eq!(
+			[255, 255, 255],
+			recolor(tint(BlendMode::Screen, 1.0), [255, 255, 255])
+		);
+		assert_eq!(
+			[255, 128, 0],
+			recolor(tint(BlendMode::Normal, 1.0), [20, 40, 60])
+		);
+		// Color keeps the lightness of the image
+		assert_eq!(
+			[128, 128, 128],
+			recolor(tint(BlendMode::Color, 0.0), [128, 128, 128])
+		);
+		assert_eq!(
+			[255, 255, 255],
+			recolor(tint(BlendMode::Color, 1.0), [255, 255, 255])
+		);
+		assert_eq!(
+			[255, 192, 128],
+			recolor(tint(BlendMode::Normal, 0.5), [255, 255, 255])
+		);
+	}
+
+	#[test]
+	fn maps_luminance_through_gradients() {
+		let duotone = || GradientMap {
+			stops: vec![
+				(0.0, Color::rgba(0, 0, 64, 255)),
+				(1.0, Color::rgba(255, 0, 64, 255)),
+			],
+		};
+		assert_eq!([0, 0, 64], recolor(duotone(), [0, 0, 0]));
+		assert_eq!([255, 0, 64], recolor(duotone(), [255, 255, 255]));
+		assert_eq!([128, 0, 64], recolor(duotone(), [128, 128, 128]));
+
+		// Luminance outside of the stops takes the nearest stop
+		let stops = || GradientMap {
+			stops: vec![
+				(0.25, Color::rgba(255, 0, 0, 255)),
+				(0.5, Color::rgba(0, 255, 0, 255)),
+				(0.75, Color::rgba(0, 0, 255, 255)),
+			],
+		};
+		assert_eq!([255, 0, 0], recolor(stops(), [10, 10, 10]));
+		// Just past the middle stop, towards blue
+		assert_eq!([0, 253, 2], recolor(stops(), [128, 128, 128]));
+		assert_eq!([0, 0, 255], recolor(stops(), [240, 240, 240]));
+
+		let unordered = GradientMap {
+			stops: vec![(1.0, Color::WHITE), (0.0, Color::BLACK)],
+		};
+		assert!(unordered.process(DynamicImage::new_rgb8(1, 1)).is_err());
+	}
+
+	#[test]
+	fn solarizes_above_the_threshold() {
+		let solarize = |per_channel| Solarize {
+			threshold: 128,
+			per_channel,
+		};
+
+		assert_eq!([55, 55, 55], recolor(solarize(false), [200, 200, 200]));
+		assert_eq!([128, 128, 128], recolor(solarize(false), [128, 128, 128]));
+		// Luminance decides for the whole pixel
+		assert_eq!([55, 95, 55], recolor(solarize(false), [200, 160, 200]));
+		assert_eq!([200, 60, 60], recolor(solarize(false), [200, 60, 60]));
+		assert_eq!([55, 60, 0], recolor(solarize(true), [200, 60, 255]));
+	}
+}
@@ -0,0 +1,184 @@
+use crate::{check_cancelled, ImageInfo, Operation, OperationError, Process};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+/// How a property of the image is compared against a value.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Comparison {
+	GreaterThan(f32),
+	AtLeast(f32),
+	LessThan(f32),
+	AtMost(f32),
+	Equals(f32),
+}
+
+impl Comparison {
+	fn matches(self, value: f32) -> bool {
+		match self {
+			Self::GreaterThan(threshold) => value > threshold,
+			Self::AtLeast(threshold) => value >= threshold,
+			Self::LessThan(threshold) => value < threshold,
+			Self::AtMost(threshold) => value <= threshold,
+			Self::Equals(threshold) => (value - threshold).abs() < 1e-3,
+		}
+	}
+}
+
+/// A check against the image as it is when the [`Conditional`] is reached.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Condition {
+	/// Width in pixels
+	Width(Comparison),
+	/// Height in pixels
+	Height(Comparison),
+	/// Width divided by height
+	AspectRatio(Comparison),
+	/// Bits per channel, such as 8 or 16
+	BitDepth(Comparison),
+	HasAlpha,
+	Grayscale,
+	Landscape,
+	Portrait,
+	All(Vec<Condition>),
+	Any(Vec<Condition>),
+	Not(Box<Condition>),
+}
+
+impl Condition {
+	pub fn matches(&self, image: &DynamicImage) -> bool {
+		self.matches_info(&ImageInfo::of(image))
+	}
+
+	/// Checks the condition against the size and color type of an image.
+	pub fn matches_info(&self, image: &ImageInfo) -> bool {
+		let ImageInfo {
+			width,
+			height,
+			color_type: color,
+			..
+		} = *image;
+		match self {
+			Self::Width(comparison) => comparison.matches(width as f32),
+			Self::Height(comparison) => comparison.matches(height as f32),
+			Self::AspectRatio(comparison) => {
+				comparison.matches(width as f32 / height.max(1) as f32)
+			}
+			Self::BitDepth(comparison) => {
+				let depth = color.bits_per_pixel() / color.channel_count() as u16;
+				comparison.matches(depth as f32)
+			}
+			Self::HasAlpha => color.has_alpha(),
+			Self::Grayscale => !color.has_color(),
+			Self::Landscape => width > height,
+			Self::Portrait => height > width,
+			Self::All(conditions) => conditions
+				.iter()
+				.all(|condition| condition.matches_info(image)),
+			Self::Any(conditions) => conditions
+				.iter()
+				.any(|condition| condition.matches_info(image)),
+			Self::Not(condition) => !condition.matches_info(image),
+		}
+	}
+}
+
+/// Runs `then` when the condition holds for the image, and `else` otherwise, so that one config
+/// can handle differently sized or colored inputs.
+///
+/// ```toml
+/// [[operations]]
+/// conditional = { if = { width = { greater-than = 2000 } }, then = [{ scale = { factor = 0.5 } }] }
+///
+/// [[operations]]
+/// conditional = { if = { all = ["grayscale", "portrait"] }, then = [{ rotate-degrees = { angle = 90.0 } }] }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Conditional {
+	#[serde(rename = "if")]
+	pub condition: Condition,
+	#[serde(default)]
+	pub then: Vec<Operation>,
+	#[serde(default, rename = "else", skip_serializing_if = "Vec::is_empty")]
+	pub otherwise: Vec<Operation>,
+}
+
+impl Conditional {
+	fn branch(&self, image: &ImageInfo) -> &[Operation] {
+		if self.condition.matches_info(image) {
+			&self.then
+		} else {
+			&self.otherwise
+		}
+	}
+}
+
+impl Process for Conditional {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let operations = self.branch(&ImageInfo::of(&image));
+
+		operations.iter().try_fold(image, |image, operation| {
+			check_cancelled()?;
+			operation.get_process().process(image)
+		})
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		let mut dimensions = Some(image.dimensions());
+		for operation in self.branch(&image) {
+			let Some((width, height)) = dimensions else {
+				break;
+			};
+			let image = ImageInfo::new(width, height, image.color_type);
+			dimensions = operation.get_process().plan(image)?;
+		}
+
+		Ok(dimensions)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Comparison, Condition, Conditional};
+	use crate::Operation;
+	use image::{DynamicImage, GenericImageView, RgbImage, RgbaImage};
+	use serde::Deserialize;
+
+	#[derive(Deserialize)]
+	struct Config {
+		operations: Vec<Operation>,
+	}
+
+	#[test]
+	fn conditions() {
+		let image = DynamicImage::ImageRgba8(RgbaImage::new(40, 20));
+		let condition = |toml: &str| {
+			toml::from_str::<Conditional>(&format!("if = {toml}"))
+				.unwrap()
+				.condition
+		};
+
+		assert!(condition("{ width = { greater-than = 30 } }").matches(&image));
+		assert!(!condition("{ aspect-ratio = { less-than = 1 } }").matches(&image));
+		assert!(condition(r#"{ all = ["has-alpha", "landscape"] }"#).matches(&image));
+		assert!(condition(r#"{ not = "grayscale" }"#).matches(&image));
+		assert!(!Condition::BitDepth(Comparison::Equals(16.0)).matches(&image));
+	}
+
+	#[test]
+	fn runs_one_branch() {
+		let config: Config = toml::from_str(
+			r#"
+			[[operations]]
+			conditional = { if = "portrait", then = [{ flip = "vertical" }], else = [{ scale = { factor = 0.5 } }] }
+			"#,
+		)
+		.unwrap();
+
+		let image = DynamicImage::ImageRgb8(RgbImage::new(40, 20));
+		let processed = config.operations[0].get_process().process(image).unwrap();
+		assert_eq!((20, 10), processed.dimensions());
+	}
+}
@@ -1,16 +1,17 @@
-use crate::{Coordinate, OperationError, PixelUnit, Process};
+use super::edges::{luminance, magnitude, sobel};
+use crate::{Color, Coordinate, Gravity, ImageInfo, OperationError, PixelUnit, Process, Unit};
 
 use image::{DynamicImage, GenericImageView};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Crop {
 	pub from: Coordinate,
 	pub to: CropOrigin,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum CropOrigin {
 	Minimum(Coordinate),
@@ -26,59 +27,396 @@ impl CropOrigin {
 		width: PixelUnit,
 		height: PixelUnit,
 	) -> (PixelUnit, PixelUnit) {
+		let size = (width.pixels, height.pixels);
 		match self {
-			Self::Minimum(coordinate) => {
-				(coordinate.x.as_pixel(width), coordinate.y.as_pixel(height))
-			}
+			Self::Minimum(coordinate) => (
+				coordinate.x.as_pixel(width, size),
+				coordinate.y.as_pixel(height, size),
+			),
 			Self::Maximum(coordinate) => {
-				let x = width - coordinate.x.as_pixel(width);
-				let y = height - coordinate.y.as_pixel(height);
-				(x, y)
+				let x = width
+					.pixels
+					.saturating_sub(coordinate.x.as_pixel(width, size).pixels);
+				let y = height
+					.pixels
+					.saturating_sub(coordinate.y.as_pixel(height, size).pixels);
+				(x.into(), y.into())
 			}
 			Self::CropStart(coordinate) => {
-				let x = x + coordinate.x.as_pixel(width);
-				let y = y + coordinate.y.as_pixel(height);
-				(x, y)
+				let x = x
+					.pixels
+					.saturating_add(coordinate.x.as_pixel(width, size).pixels);
+				let y = y
+					.pixels
+					.saturating_add(coordinate.y.as_pixel(height, size).pixels);
+				(x.into(), y.into())
 			}
 		}
 	}
 }
 
-impl Process for Crop {
-	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
-		let (width, height) = image.dimensions();
+impl Crop {
+	/// Left, top, width and height of the crop within a `width` by `height` image.
+	pub(crate) fn bounds(&self, width: u32, height: u32) -> Result<[u32; 4], OperationError> {
+		let (image_width, image_height) = (width, height);
 		let width = PixelUnit::from(width);
 		let height = PixelUnit::from(height);
 
-		let left = self.from.x.as_pixel(height);
-		let top = self.from.y.as_pixel(width);
+		let size = (width.pixels, height.pixels);
+		let left = self.from.x.as_pixel(width, size);
+		let top = self.from.y.as_pixel(height, size);
 
 		let (right, bottom) = self.to.as_pixel_coordinate(left, top, width, height);
 
-		if bottom < top {
+		let Some(crop_height) = bottom.pixels.checked_sub(top.pixels) else {
 			return Err(OperationError::new(format!(
 				"Bottom cannot be less than top for crop operation {self:?}"
 			)));
-		}
+		};
 
-		if right < left {
+		let Some(crop_width) = right.pixels.checked_sub(left.pixels) else {
 			return Err(OperationError::new(format!(
 				"Right cannot be less than left for crop operation {self:?}"
 			)));
+		};
+
+		let bounds = clamp_bounds(
+			[left.into(), top.into(), crop_width, crop_height],
+			(image_width, image_height),
+		);
+		if bounds[2] == 0 || bounds[3] == 0 {
+			return Err(empty_crop(self, image_width, image_height));
+		}
+
+		Ok(bounds)
+	}
+}
+
+fn empty_crop(operation: &impl std::fmt::Debug, width: u32, height: u32) -> OperationError {
+	OperationError::new(format!(
+		"Crop operation {operation:?} is empty within a {width}x{height} image"
+	))
+}
+
+/// Limits crop bounds to the image like [`DynamicImage::crop_imm`] does.
+pub(crate) fn clamp_bounds([x, y, width, height]: [u32; 4], image: (u32, u32)) -> [u32; 4] {
+	let x = x.min(image.0);
+	let y = y.min(image.1);
+	[x, y, width.min(image.0 - x), height.min(image.1 - y)]
+}
+
+impl Process for Crop {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let [left, top, width, height] = self.bounds(image.width(), image.height())?;
+		Ok(image.crop_imm(left, top, width, height))
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		let [_, _, width, height] = self.bounds(image.width, image.height)?;
+		Ok(Some((width, height)))
+	}
+}
+
+/// Crops to `width` by `height`, anchored within the image by `gravity` and moved away from the
+/// anchored edges by `offset`. Percentages are of the image width and height, and a size larger
+/// than the image keeps that dimension as it is.
+///
+/// ```toml
+/// [[operations]]
+/// crop-gravity = { width = { pixel = { pixels = 800 } }, height = { pixel = { pixels = 600 } }, gravity = "center" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CropGravity {
+	pub width: Unit,
+	pub height: Unit,
+	/// Defaults to center
+	#[serde(default = "CropGravity::default_gravity")]
+	pub gravity: Gravity,
+	#[serde(default)]
+	pub offset: Coordinate,
+}
+
+impl CropGravity {
+	pub(crate) fn default_gravity() -> Gravity {
+		Gravity::Center
+	}
+
+	/// Left, top, width and height of the crop within a `width` by `height` image.
+	pub(crate) fn bounds(&self, width: u32, height: u32) -> Result<[u32; 4], OperationError> {
+		let pixels = |unit: &Unit, dimension: u32| {
+			unit.as_pixel(PixelUnit::from(dimension), (width, height))
+				.pixels
+		};
+		let window = (
+			pixels(&self.width, width).min(width),
+			pixels(&self.height, height).min(height),
+		);
+		if window.0 == 0 || window.1 == 0 {
+			return Err(empty_crop(self, width, height));
+		}
+		let offset = (
+			pixels(&self.offset.x, width),
+			pixels(&self.offset.y, height),
+		);
+
+		let (x, y) = self.gravity.position((width, height), window, offset);
+		let x = x.clamp(0, (width - window.0) as i64) as u32;
+		let y = y.clamp(0, (height - window.1) as i64) as u32;
+		Ok([x, y, window.0, window.1])
+	}
+}
+
+impl Process for CropGravity {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let [x, y, width, height] = self.bounds(image.width(), image.height())?;
+		Ok(image.crop_imm(x, y, width, height))
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		let [_, _, width, height] = self.bounds(image.width, image.height)?;
+		Ok(Some((width, height)))
+	}
+}
+
+/// The largest `width` by `height` window with the aspect ratio `ratio` that fits in an image.
+pub(crate) fn aspect_window(
+	width: u32,
+	height: u32,
+	ratio: (u32, u32),
+) -> Result<(u32, u32), OperationError> {
+	if ratio.0 == 0 || ratio.1 == 0 {
+		return Err(OperationError::new(format!(
+			"Aspect ratio must not be zero, got {}:{}",
+			ratio.0, ratio.1
+		)));
+	}
+
+	let (width, height, ratio_width, ratio_height) =
+		(width as u64, height as u64, ratio.0 as u64, ratio.1 as u64);
+	let window = if width * ratio_height > height * ratio_width {
+		(
+			(height * ratio_width + ratio_height / 2) / ratio_height,
+			height,
+		)
+	} else {
+		(
+			width,
+			(width * ratio_height + ratio_width / 2) / ratio_width,
+		)
+	};
+
+	Ok(((window.0 as u32).max(1), (window.1 as u32).max(1)))
+}
+
+/// Crops to the largest window of aspect ratio `ratio`, anchored within the image by `gravity`.
+///
+/// ```toml
+/// [[operations]]
+/// crop-to-aspect = { ratio = [16, 9], gravity = "top" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CropToAspect {
+	/// Width to height, such as `[4, 5]`
+	pub ratio: (u32, u32),
+	/// Defaults to center
+	#[serde(default = "CropGravity::default_gravity")]
+	pub gravity: Gravity,
+}
+
+impl CropToAspect {
+	/// Left, top, width and height of the crop within a `width` by `height` image.
+	pub(crate) fn bounds(&self, width: u32, height: u32) -> Result<[u32; 4], OperationError> {
+		let window = aspect_window(width, height, self.ratio)?;
+		let (x, y) = self.gravity.position((width, height), window, (0, 0));
+		Ok([x as u32, y as u32, window.0, window.1])
+	}
+}
+
+impl Process for CropToAspect {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let [x, y, width, height] = self.bounds(image.width(), image.height())?;
+		Ok(image.crop_imm(x, y, width, height))
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		aspect_window(image.width, image.height, self.ratio).map(Some)
+	}
+}
+
+/// Crops to the largest window of aspect ratio `ratio`, positioned over the most detailed part of
+/// the image. Detail is measured by edges and saturation, with a preference for keeping it near
+/// the middle of the crop, so subjects stay in frame without giving coordinates.
+///
+/// ```toml
+/// [[operations]]
+/// smart-crop = { ratio = [1, 1] }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SmartCrop {
+	/// Width to height, such as `[16, 9]`
+	pub ratio: (u32, u32),
+}
+
+/// Detail is scored on a copy no larger than this, which is plenty to place a crop.
+const SALIENCY_SIZE: u32 = 256;
+
+/// Offset along the free axis of the crop window which covers the most detail.
+pub(crate) fn salient_offset(image: &DynamicImage, window: (u32, u32)) -> (u32, u32) {
+	let (width, height) = image.dimensions();
+	let horizontal = window.0 < width;
+	if window.0 >= width && window.1 >= height {
+		return (0, 0);
+	}
+
+	let small = image.thumbnail(SALIENCY_SIZE, SALIENCY_SIZE);
+	let scale = small.width() as f32 / width as f32;
+	let plane = luminance(&small);
+	let (gx, gy) = sobel(&plane);
+	let edges = magnitude(&gx, &gy);
+	let rgb = small.to_rgb32f();
+
+	// Saliency summed across the free axis
+	let length = if horizontal {
+		small.width()
+	} else {
+		small.height()
+	};
+	let mut profile = vec![0.0f32; length as usize];
+	for (x, y, pixel) in rgb.enumerate_pixels() {
+		let max = pixel.0.iter().copied().fold(0.0, f32::max);
+		let min = pixel.0.iter().copied().fold(1.0, f32::min);
+		let value = edges.get_pixel(x, y)[0] + 0.25 * (max - min);
+		profile[if horizontal { x } else { y } as usize] += value;
+	}
+
+	let span = if horizontal { window.0 } else { window.1 };
+	let span = ((span as f32 * scale).round() as usize).clamp(1, profile.len());
+	let weight = |k: usize| {
+		let position = (k as f32 + 0.5) / span as f32 * 2.0 - 1.0;
+		1.0 - 0.5 * position.abs()
+	};
+	let best = (0..=profile.len() - span)
+		.map(|offset| {
+			let score: f32 = profile[offset..offset + span]
+				.iter()
+				.enumerate()
+				.map(|(k, value)| value * weight(k))
+				.sum();
+			(offset, score)
+		})
+		.max_by(|a, b| a.1.total_cmp(&b.1))
+		.map_or(0, |(offset, _)| offset);
+
+	let offset = (best as f32 / scale).round() as u32;
+	if horizontal {
+		(offset.min(width - window.0), 0)
+	} else {
+		(0, offset.min(height - window.1))
+	}
+}
+
+impl Process for SmartCrop {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (width, height) = image.dimensions();
+		let window = aspect_window(width, height, self.ratio)?;
+		let (x, y) = salient_offset(&image, window);
+
+		Ok(image.crop_imm(x, y, window.0, window.1))
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		aspect_window(image.width, image.height, self.ratio).map(Some)
+	}
+}
+
+/// Removes borders of a uniform color from every side of the image, such as the margins of scans
+/// and screenshots. `tolerance` is how far, between 0 and 1, a channel can be from the border
+/// color while still counting as border. Images which are entirely border are left as they are.
+///
+/// ```toml
+/// [[operations]]
+/// trim = { tolerance = 0.05 }
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Trim {
+	/// Defaults to the color of the top left pixel
+	#[serde(default)]
+	pub color: Option<Color>,
+	#[serde(default)]
+	pub tolerance: f32,
+}
+
+impl Trim {
+	fn check_tolerance(&self) -> Result<(), OperationError> {
+		if !(0.0..=1.0).contains(&self.tolerance) {
+			return Err(OperationError::new(format!(
+				"Trim tolerance must be between 0 and 1, got {}",
+				self.tolerance
+			)));
+		}
+
+		Ok(())
+	}
+}
+
+impl Process for Trim {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		self.check_tolerance()?;
+
+		let rgba = image.to_rgba32f();
+		let (width, height) = rgba.dimensions();
+		if width == 0 || height == 0 {
+			return Ok(image);
 		}
+		let border = match self.color {
+			Some(color) => [color.r, color.g, color.b, color.a].map(|c| c as f32 / 255.0),
+			None => rgba.get_pixel(0, 0).0,
+		};
+		// Allows for rounding when comparing against an 8 bit color
+		let tolerance = self.tolerance + 1e-4;
+		let is_border = |x: u32, y: u32| {
+			rgba.get_pixel(x, y)
+				.0
+				.iter()
+				.zip(border)
+				.all(|(value, border)| (value - border).abs() <= tolerance)
+		};
+		let row = |y: u32| (0..width).all(|x| is_border(x, y));
+		let column = |x: u32, top: u32, bottom: u32| (top..bottom).all(|y| is_border(x, y));
+
+		let Some(top) = (0..height).find(|&y| !row(y)) else {
+			return Ok(image);
+		};
+		let bottom = (top..height).rev().find(|&y| !row(y)).unwrap_or(top) + 1;
+		let left = (0..width).find(|&x| !column(x, top, bottom)).unwrap_or(0);
+		let right = (left..width)
+			.rev()
+			.find(|&x| !column(x, top, bottom))
+			.unwrap_or(left)
+			+ 1;
 
-		Ok(image.crop_imm(
-			left.into(),
-			top.into(),
-			(right - left).into(),
-			(bottom - top).into(),
-		))
+		Ok(image.crop_imm(left, top, right - left, bottom - top))
+	}
+
+	/// The borders are only known once the pixels are.
+	fn plan(&self, _image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		self.check_tolerance()?;
+		Ok(None)
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use crate::{operations::crop::CropOrigin, Coordinate, Unit};
+	use super::{aspect_window, Crop, CropGravity, CropToAspect, SmartCrop, Trim};
+	use crate::{
+		operations::crop::CropOrigin, Coordinate, Gravity, ImageInfo, PercentageAxis,
+		PercentageUnit, Process, Unit, UnitExpr,
+	};
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
 
 	const CANVAS_WIDTH: u32 = 100;
 	const CANVAS_HEIGHT: u32 = 100;
@@ -137,6 +475,48 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn crop_origin_as_pixel_coordinate_minimum_from_end() {
+		let crop_origin = CropOrigin::Minimum(Coordinate {
+			x: Unit::FromEnd(10.into()),
+			y: Unit::FromEnd(120.into()),
+		});
+
+		assert_eq!(
+			(90.into(), 0.into()),
+			crop_origin.as_pixel_coordinate(
+				5.into(),
+				5.into(),
+				CANVAS_WIDTH.into(),
+				CANVAS_HEIGHT.into(),
+			)
+		);
+	}
+
+	#[test]
+	fn crop_origin_as_pixel_coordinate_minimum_expr() {
+		let crop_origin = CropOrigin::Minimum(Coordinate {
+			x: Unit::Expr(Box::new(UnitExpr::Subtract(
+				Unit::Percentage(0.5.try_into().unwrap()),
+				Unit::Pixel(16.into()),
+			))),
+			y: Unit::Expr(Box::new(UnitExpr::Min(
+				Unit::Pixel(120.into()),
+				Unit::FromEnd(30.into()),
+			))),
+		});
+
+		assert_eq!(
+			(34.into(), 70.into()),
+			crop_origin.as_pixel_coordinate(
+				5.into(),
+				5.into(),
+				CANVAS_WIDTH.into(),
+				CANVAS_HEIGHT.into(),
+			)
+		);
+	}
+
 	#[test]
 	fn crop_origin_as_pixel_coordinate_maximum_pixel() {
 		let crop_origin = CropOrigin::Maximum(Coordinate {
@@ -244,4 +624,200 @@ mod tests {
 			)
 		);
 	}
+
+	#[test]
+	fn aspect_windows() {
+		assert_eq!((100, 100), aspect_window(300, 100, (1, 1)).unwrap());
+		assert_eq!((300, 169), aspect_window(300, 200, (16, 9)).unwrap());
+		assert!(aspect_window(300, 200, (0, 9)).is_err());
+	}
+
+	#[test]
+	fn smart_crop_finds_detail() {
+		// Flat gray with a checkerboard towards the right
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(300, 100, |x, y| {
+			if (200..260).contains(&x) && (20..80).contains(&y) && (x / 4 + y / 4) % 2 == 0 {
+				Rgb([255, 255, 255])
+			} else {
+				Rgb([128, 128, 128])
+			}
+		}));
+
+		let crop = SmartCrop { ratio: (1, 1) };
+		let cropped = crop.process(image.clone()).unwrap();
+		assert_eq!((100, 100), cropped.dimensions());
+		assert_eq!(image.get_pixel(200, 20), cropped.get_pixel(20, 20));
+	}
+
+	#[test]
+	fn trim_borders() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 30, |x, y| {
+			if (5..20).contains(&x) && (10..12).contains(&y) {
+				Rgb([0, 0, 0])
+			} else if x < 3 {
+				Rgb([250, 250, 250])
+			} else {
+				Rgb([255, 255, 255])
+			}
+		}));
+
+		let trimmed = Trim::default().process(image.clone()).unwrap();
+		assert_eq!((37, 30), trimmed.dimensions());
+
+		let trim = Trim {
+			color: None,
+			tolerance: 0.05,
+		};
+		let trimmed = trim.process(image).unwrap();
+		assert_eq!((15, 2), trimmed.dimensions());
+	}
+
+	#[test]
+	fn crop_by_gravity() {
+		let image =
+			DynamicImage::ImageRgb8(RgbImage::from_fn(40, 20, |x, y| Rgb([x as u8, y as u8, 0])));
+		let crop = |gravity| CropGravity {
+			width: Unit::Pixel(10.into()),
+			height: Unit::Percentage(0.5.try_into().unwrap()),
+			gravity,
+			offset: Coordinate {
+				x: Unit::Pixel(2.into()),
+				y: Unit::Pixel(0.into()),
+			},
+		};
+
+		let cropped = crop(Gravity::Center).process(image.clone()).unwrap();
+		assert_eq!((10, 10), cropped.dimensions());
+		assert_eq!([17, 5, 0, 255], cropped.get_pixel(0, 0).0);
+
+		let cropped = crop(Gravity::BottomRight).process(image).unwrap();
+		assert_eq!([28, 10, 0, 255], cropped.get_pixel(0, 0).0);
+	}
+
+	#[test]
+	fn empty_gravity_crops_are_errors() {
+		let image = DynamicImage::ImageRgb8(RgbImage::new(40, 20));
+		let crop = |width: u32, height: u32| CropGravity {
+			width: Unit::Pixel(width.into()),
+			height: Unit::Pixel(height.into()),
+			gravity: Gravity::Center,
+			offset: Coordinate::default(),
+		};
+
+		for crop in [crop(0, 10), crop(10, 0)] {
+			let planned = crop.plan(ImageInfo::of(&image)).unwrap_err();
+			let processed = crop.process(image.clone()).unwrap_err();
+			assert_eq!(planned.message, processed.message);
+			assert!(processed.message.ends_with("is empty within a 40x20 image"));
+		}
+	}
+
+	#[test]
+	fn crops_to_aspect_by_gravity() {
+		let image =
+			DynamicImage::ImageRgb8(RgbImage::from_fn(40, 20, |x, y| Rgb([x as u8, y as u8, 0])));
+		let crop = |ratio, gravity| {
+			CropToAspect { ratio, gravity }
+				.process(image.clone())
+				.unwrap()
+		};
+
+		let right = crop((1, 1), Gravity::Right);
+		assert_eq!((20, 20), right.dimensions());
+		assert_eq!([20, 0, 0, 255], right.get_pixel(0, 0).0);
+
+		let centered = crop((1, 1), Gravity::Center);
+		assert_eq!([10, 0, 0, 255], centered.get_pixel(0, 0).0);
+
+		let bottom = crop((4, 1), Gravity::Bottom);
+		assert_eq!((40, 10), bottom.dimensions());
+		assert_eq!([0, 10, 0, 255], bottom.get_pixel(0, 0).0);
+
+		assert!(CropToAspect {
+			ratio: (0, 1),
+			gravity: Gravity::Center,
+		}
+		.process(image.clone())
+		.is_err());
+	}
+
+	#[test]
+	fn crop_percentages_follow_their_axis() {
+		let image =
+			DynamicImage::ImageRgb8(RgbImage::from_fn(40, 20, |x, y| Rgb([x as u8, y as u8, 0])));
+		let crop = |x: PercentageUnit, y: PercentageUnit| Crop {
+			from: Coordinate {
+				x: Unit::Percentage(x),
+				y: Unit::Percentage(y),
+			},
+			to: CropOrigin::Maximum(Coordinate {
+				x: Unit::Pixel(0.into()),
+				y: Unit::Pixel(0.into()),
+			}),
+		};
+		let half: PercentageUnit = 0.5.try_into().unwrap();
+
+		let cropped = crop(half, half).process(image.clone()).unwrap();
+		assert_eq!((20, 10), cropped.dimensions());
+		assert_eq!([20, 10, 0, 255], cropped.get_pixel(0, 0).0);
+
+		// Both offsets are half of the shorter side
+		let shorter = half.of(PercentageAxis::Shorter);
+		let cropped = crop(shorter, shorter).process(image).unwrap();
+		assert_eq!((30, 10), cropped.dimensions());
+	}
+
+	#[test]
+	fn empty_crops_are_errors() {
+		let image = DynamicImage::ImageRgb8(RgbImage::new(40, 30));
+		let crop = Crop {
+			from: Coordinate {
+				x: Unit::FromEnd(4.into()),
+				y: Unit::Pixel(0.into()),
+			},
+			to: CropOrigin::Maximum(Coordinate {
+				x: Unit::Pixel(4.into()),
+				y: Unit::Pixel(0.into()),
+			}),
+		};
+		assert!(crop.plan(ImageInfo::of(&image)).is_err());
+		assert!(crop.process(image.clone()).is_err());
+
+		// Starting past the right edge
+		let crop = Crop {
+			from: Coordinate {
+				x: Unit::Pixel(50.into()),
+				y: Unit::Pixel(0.into()),
+			},
+			to: CropOrigin::CropStart(Coordinate {
+				x: Unit::Pixel(10.into()),
+				y: Unit::Pixel(10.into()),
+			}),
+		};
+		assert!(crop.process(image).is_err());
+	}
+
+	#[test]
+	fn crop_origin_maximum_past_the_image() {
+		let crop_origin = CropOrigin::Maximum(Coordinate {
+			x: Unit::Pixel(120.into()),
+			y: Unit::FromEnd(10.into()),
+		});
+
+		assert_eq!(
+			(0.into(), 10.into()),
+			crop_origin.as_pixel_coordinate(
+				5.into(),
+				5.into(),
+				CANVAS_WIDTH.into(),
+				CANVAS_HEIGHT.into(),
+			)
+		);
+
+		let crop = Crop {
+			from: Coordinate::default(),
+			to: crop_origin,
+		};
+		assert!(crop.bounds(CANVAS_WIDTH, CANVAS_HEIGHT).is_err());
+	}
 }
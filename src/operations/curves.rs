@@ -0,0 +1,197 @@
+use super::color::{map_channels, with_color};
+use crate::{OperationError, Process};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+/// Tone curves defined by `[input, output]` control points on a 0–255 scale, interpolated with a
+/// monotone cubic spline. The `rgb` curve applies to every channel before the per-channel curves.
+/// Channels without control points are left unchanged.
+///
+/// ```toml
+/// [[operations]]
+/// curves = { rgb = [[0, 0], [64, 48], [192, 208], [255, 255]], blue = [[0, 16], [255, 240]] }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Curves {
+	#[serde(default)]
+	pub rgb: Vec<(f32, f32)>,
+	#[serde(default)]
+	pub red: Vec<(f32, f32)>,
+	#[serde(default)]
+	pub green: Vec<(f32, f32)>,
+	#[serde(default)]
+	pub blue: Vec<(f32, f32)>,
+}
+
+impl Process for Curves {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let rgb = ToneCurve::new(&self.rgb)?;
+		let channels = [
+			ToneCurve::new(&self.red)?,
+			ToneCurve::new(&self.green)?,
+			ToneCurve::new(&self.blue)?,
+		];
+
+		// Curves for single channels add color to grayscale images
+		let image = if [&self.red, &self.green, &self.blue]
+			.iter()
+			.all(|points| points.is_empty())
+		{
+			image
+		} else {
+			with_color(image)
+		};
+
+		Ok(map_channels(image, |i, value| {
+			channels[i].evaluate(rgb.evaluate(value))
+		}))
+	}
+}
+
+/// A monotone cubic spline through normalized control points, using the Fritsch–Carlson method
+/// so that the curve never overshoots between points.
+#[derive(Debug)]
+struct ToneCurve {
+	points: Vec<(f32, f32)>,
+	tangents: Vec<f32>,
+}
+
+impl ToneCurve {
+	fn new(points: &[(f32, f32)]) -> Result<Self, OperationError> {
+		if points.is_empty() {
+			return Ok(Self {
+				points: vec![(0.0, 0.0), (1.0, 1.0)],
+				tangents: vec![1.0, 1.0],
+			});
+		}
+
+		if points.len() < 2 {
+			return Err(OperationError::new(
+				"Curves need at least two control points".to_string(),
+			));
+		}
+
+		if let Some(point) = points.iter().find(|(input, output)| {
+			!(0.0..=255.0).contains(input) || !(0.0..=255.0).contains(output)
+		}) {
+			return Err(OperationError::new(format!(
+				"Curve control point {point:?} is outside of 0–255"
+			)));
+		}
+
+		let mut points = points
+			.iter()
+			.map(|(input, output)| (input / 255.0, output / 255.0))
+			.collect::<Vec<_>>();
+		points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+		if points.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+			return Err(OperationError::new(
+				"Curve control points must have distinct inputs".to_string(),
+			));
+		}
+
+		let slopes = points
+			.windows(2)
+			.map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+			.collect::<Vec<_>>();
+
+		let mut tangents = Vec::with_capacity(points.len());
+		tangents.push(slopes[0]);
+		for pair in slopes.windows(2) {
+			tangents.push(if pair[0] * pair[1] <= 0.0 {
+				0.0
+			} else {
+				(pair[0] + pair[1]) / 2.0
+			});
+		}
+		tangents.push(slopes[slopes.len() - 1]);
+
+		// Limit the tangents so that each segment stays monotone
+		for (i, slope) in slopes.iter().enumerate() {
+			if *slope == 0.0 {
+				tangents[i] = 0.0;
+				tangents[i + 1] = 0.0;
+				continue;
+			}
+
+			let alpha = tangents[i] / slope;
+			let beta = tangents[i + 1] / slope;
+			let magnitude = alpha.hypot(beta);
+			if magnitude > 3.0 {
+				let tau = 3.0 / magnitude;
+				tangents[i] = tau * alpha * slope;
+				tangents[i + 1] = tau * beta * slope;
+			}
+		}
+
+		Ok(Self { points, tangents })
+	}
+
+	fn evaluate(&self, x: f32) -> f32 {
+		let first = self.points[0];
+		let last = self.points[self.points.len() - 1];
+		if x <= first.0 {
+			return first.1;
+		}
+		if x >= last.0 {
+			return last.1;
+		}
+
+		let i = self.points.partition_point(|point| point.0 <= x) - 1;
+		let (x0, y0) = self.points[i];
+		let (x1, y1) = self.points[i + 1];
+		let h = x1 - x0;
+		let t = (x - x0) / h;
+		let t2 = t * t;
+		let t3 = t2 * t;
+
+		(2.0 * t3 - 3.0 * t2 + 1.0) * y0
+			+ (t3 - 2.0 * t2 + t) * h * self.tangents[i]
+			+ (-2.0 * t3 + 3.0 * t2) * y1
+			+ (t3 - t2) * h * self.tangents[i + 1]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::ToneCurve;
+
+	#[test]
+	fn passes_through_control_points() {
+		let curve =
+			ToneCurve::new(&[(0.0, 0.0), (64.0, 32.0), (192.0, 224.0), (255.0, 255.0)]).unwrap();
+
+		for (input, output) in [(0.0, 0.0), (64.0, 32.0), (192.0, 224.0), (255.0, 255.0)] {
+			assert!((curve.evaluate(input / 255.0) * 255.0 - output).abs() < 1e-3);
+		}
+	}
+
+	#[test]
+	fn monotone_between_points() {
+		let curve =
+			ToneCurve::new(&[(0.0, 0.0), (100.0, 200.0), (110.0, 205.0), (255.0, 255.0)]).unwrap();
+
+		let values = (0..=255)
+			.map(|x| curve.evaluate(x as f32 / 255.0))
+			.collect::<Vec<_>>();
+		assert!(values.windows(2).all(|pair| pair[0] <= pair[1] + 1e-6));
+		assert!(values.iter().all(|value| (0.0..=1.0).contains(value)));
+	}
+
+	#[test]
+	fn clamps_outside_of_points() {
+		let curve = ToneCurve::new(&[(32.0, 16.0), (224.0, 240.0)]).unwrap();
+
+		assert_eq!(16.0 / 255.0, curve.evaluate(0.0));
+		assert_eq!(240.0 / 255.0, curve.evaluate(1.0));
+	}
+
+	#[test]
+	fn invalid_points() {
+		assert!(ToneCurve::new(&[(0.0, 0.0)]).is_err());
+		assert!(ToneCurve::new(&[(0.0, 0.0), (0.0, 255.0)]).is_err());
+		assert!(ToneCurve::new(&[(0.0, 0.0), (300.0, 255.0)]).is_err());
+	}
+}
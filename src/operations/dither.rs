@@ -0,0 +1,178 @@
+use crate::{OperationError, Process};
+use color_quant::NeuQuant;
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Reduces the image to a palette of at most `colors` colors, dithering to hide banding. Useful
+/// before encoding GIFs or 8-bit PNGs.
+///
+/// ```toml
+/// [[operations]]
+/// dither = { algorithm = "floyd-steinberg", colors = 16 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Dither {
+	pub algorithm: DitherAlgorithm,
+	/// Between 2 and 256
+	pub colors: u16,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DitherAlgorithm {
+	/// Error diffusion to the 4 following neighbours
+	FloydSteinberg,
+	/// Error diffusion to 6 neighbours, dropping a quarter of the error for higher contrast
+	Atkinson,
+	/// Ordered dithering with an 8x8 Bayer matrix
+	Bayer,
+}
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+	[0, 32, 8, 40, 2, 34, 10, 42],
+	[48, 16, 56, 24, 50, 18, 58, 26],
+	[12, 44, 4, 36, 14, 46, 6, 38],
+	[60, 28, 52, 20, 62, 30, 54, 22],
+	[3, 35, 11, 43, 1, 33, 9, 41],
+	[51, 19, 59, 27, 49, 17, 57, 25],
+	[15, 47, 7, 39, 13, 45, 5, 37],
+	[63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+impl Process for Dither {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !(2..=256).contains(&self.colors) {
+			return Err(OperationError::new(format!(
+				"Dithering needs between 2 and 256 colors, got {}",
+				self.colors
+			)));
+		}
+
+		let mut image = image.into_rgba8();
+		let palette = NeuQuant::new(10, self.colors as usize, image.as_raw());
+
+		match self.algorithm {
+			DitherAlgorithm::FloydSteinberg => diffuse(
+				&mut image,
+				&palette,
+				&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)],
+				16.0,
+			),
+			DitherAlgorithm::Atkinson => diffuse(
+				&mut image,
+				&palette,
+				&[
+					(1, 0, 1.0),
+					(2, 0, 1.0),
+					(-1, 1, 1.0),
+					(0, 1, 1.0),
+					(1, 1, 1.0),
+					(0, 2, 1.0),
+				],
+				8.0,
+			),
+			DitherAlgorithm::Bayer => ordered(&mut image, &palette, self.colors),
+		}
+
+		Ok(DynamicImage::ImageRgba8(image))
+	}
+}
+
+fn nearest(palette: &NeuQuant, pixel: [u8; 4]) -> [u8; 4] {
+	palette.lookup(palette.index_of(&pixel)).unwrap_or(pixel)
+}
+
+/// Error diffusion, distributing the quantization error of each pixel to the neighbours at
+/// `(dx, dy, weight)` with the weights divided by `divisor`.
+fn diffuse(
+	image: &mut RgbaImage,
+	palette: &NeuQuant,
+	neighbours: &[(i64, i64, f32)],
+	divisor: f32,
+) {
+	let (width, height) = image.dimensions();
+	let mut errors = vec![[0.0f32; 3]; width as usize * height as usize];
+
+	for y in 0..height {
+		for x in 0..width {
+			let index = (y * width + x) as usize;
+			let pixel = image.get_pixel(x, y).0;
+
+			let mut wanted = pixel;
+			for channel in 0..3 {
+				wanted[channel] = (pixel[channel] as f32 + errors[index][channel])
+					.round()
+					.clamp(0.0, 255.0) as u8;
+			}
+
+			let mut quantized = nearest(palette, wanted);
+			quantized[3] = pixel[3];
+			image.put_pixel(x, y, Rgba(quantized));
+
+			for (dx, dy, weight) in neighbours {
+				let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+				if nx < 0 || nx >= width as i64 || ny >= height as i64 {
+					continue;
+				}
+
+				let neighbour = &mut errors[(ny * width as i64 + nx) as usize];
+				for channel in 0..3 {
+					let error = wanted[channel] as f32 - quantized[channel] as f32;
+					neighbour[channel] += error * weight / divisor;
+				}
+			}
+		}
+	}
+}
+
+/// Ordered dithering, offsetting each pixel by a threshold from the Bayer matrix scaled to the
+/// spacing between colors in the palette.
+fn ordered(image: &mut RgbaImage, palette: &NeuQuant, colors: u16) {
+	let spread = 255.0 / (colors as f32).cbrt();
+
+	for (x, y, pixel) in image.enumerate_pixels_mut() {
+		let threshold = BAYER_8X8[y as usize % 8][x as usize % 8] as f32 / 64.0 - 0.5;
+
+		let mut wanted = pixel.0;
+		for channel in 0..3 {
+			wanted[channel] = (pixel[channel] as f32 + threshold * spread)
+				.round()
+				.clamp(0.0, 255.0) as u8;
+		}
+
+		let mut quantized = nearest(palette, wanted);
+		quantized[3] = pixel[3];
+		*pixel = Rgba(quantized);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Dither, DitherAlgorithm};
+	use crate::Process;
+	use image::{DynamicImage, Rgba, RgbaImage};
+	use std::collections::HashSet;
+
+	#[test]
+	fn limits_colors() {
+		let image = RgbaImage::from_fn(64, 64, |x, y| {
+			Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, 255])
+		});
+
+		for algorithm in [
+			DitherAlgorithm::FloydSteinberg,
+			DitherAlgorithm::Atkinson,
+			DitherAlgorithm::Bayer,
+		] {
+			for colors in [2, 16] {
+				let dithered = Dither { algorithm, colors }
+					.process(DynamicImage::ImageRgba8(image.clone()))
+					.unwrap()
+					.into_rgba8();
+				let unique = dithered.pixels().collect::<HashSet<_>>();
+				assert!(unique.len() <= colors as usize, "{algorithm:?} {colors}");
+			}
+		}
+	}
+}
@@ -0,0 +1,280 @@
+use super::overlay::composite;
+use crate::{Color, OperationError, Process};
+use image::{imageops, DynamicImage, ImageBuffer, Luma, Rgb, Rgba, Rgba32FImage};
+use serde::{Deserialize, Serialize};
+
+pub(crate) type Plane = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EdgeMethod {
+	/// Gradient magnitude of a 3x3 Sobel operator
+	Sobel,
+	/// Thin edges from the Canny detector. Gradients above `high` are edges, and those above `low`
+	/// are edges when they connect to one. Both are between 0 and 1.
+	Canny {
+		low: f32,
+		high: f32,
+		/// Blur applied before finding gradients, defaults to 1.4
+		#[serde(default = "EdgeMethod::default_sigma")]
+		sigma: f32,
+	},
+}
+
+impl EdgeMethod {
+	fn default_sigma() -> f32 {
+		1.4
+	}
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EdgeOutput {
+	/// A grayscale image with white edges on black
+	#[default]
+	Map,
+	/// The original image with edges drawn over it in a color
+	Overlay(Color),
+}
+
+/// Finds edges in the luminance of the image.
+///
+/// ```toml
+/// [[operations]]
+/// edge-detect = { method = "sobel" }
+///
+/// [[operations]]
+/// edge-detect = { method = { canny = { low = 0.1, high = 0.3 } }, output = { overlay = "#ff0000" } }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct EdgeDetect {
+	pub method: EdgeMethod,
+	#[serde(default)]
+	pub output: EdgeOutput,
+}
+
+pub(crate) fn luminance(image: &DynamicImage) -> Plane {
+	let rgb = image.to_rgb32f();
+	Plane::from_fn(rgb.width(), rgb.height(), |x, y| {
+		let [r, g, b] = rgb.get_pixel(x, y).0;
+		Luma([0.2126 * r + 0.7152 * g + 0.0722 * b])
+	})
+}
+
+/// Horizontal and vertical Sobel gradients, scaled so a step from black to white is 1.
+pub(crate) fn sobel(plane: &Plane) -> (Plane, Plane) {
+	let (width, height) = plane.dimensions();
+	let at = |x: u32, y: u32, dx: i64, dy: i64| {
+		let x = (x as i64 + dx).clamp(0, width as i64 - 1) as u32;
+		let y = (y as i64 + dy).clamp(0, height as i64 - 1) as u32;
+		plane.get_pixel(x, y)[0]
+	};
+
+	let gx = Plane::from_fn(width, height, |x, y| {
+		let value = at(x, y, 1, -1) + 2.0 * at(x, y, 1, 0) + at(x, y, 1, 1)
+			- at(x, y, -1, -1)
+			- 2.0 * at(x, y, -1, 0)
+			- at(x, y, -1, 1);
+		Luma([value / 4.0])
+	});
+	let gy = Plane::from_fn(width, height, |x, y| {
+		let value = at(x, y, -1, 1) + 2.0 * at(x, y, 0, 1) + at(x, y, 1, 1)
+			- at(x, y, -1, -1)
+			- 2.0 * at(x, y, 0, -1)
+			- at(x, y, 1, -1);
+		Luma([value / 4.0])
+	});
+
+	(gx, gy)
+}
+
+pub(crate) fn magnitude(gx: &Plane, gy: &Plane) -> Plane {
+	Plane::from_fn(gx.width(), gx.height(), |x, y| {
+		Luma([gx.get_pixel(x, y)[0].hypot(gy.get_pixel(x, y)[0]).min(1.0)])
+	})
+}
+
+fn canny(plane: &Plane, low: f32, high: f32, sigma: f32) -> Plane {
+	let blurred = if sigma > 0.0 {
+		imageops::blur(plane, sigma)
+	} else {
+		plane.clone()
+	};
+	let (gx, gy) = sobel(&blurred);
+	let magnitude = magnitude(&gx, &gy);
+	let (width, height) = magnitude.dimensions();
+
+	// Keep only gradients which are the largest across the edge
+	let thin = Plane::from_fn(width, height, |x, y| {
+		let value = magnitude.get_pixel(x, y)[0];
+		if value < low {
+			return Luma([0.0]);
+		}
+
+		let angle = gy.get_pixel(x, y)[0]
+			.atan2(gx.get_pixel(x, y)[0])
+			.to_degrees()
+			.rem_euclid(180.0);
+		let (dx, dy) = match angle {
+			a if !(22.5..157.5).contains(&a) => (1, 0),
+			a if a < 67.5 => (1, 1),
+			a if a < 112.5 => (0, 1),
+			_ => (-1, 1),
+		};
+		let neighbour = |sign: i64| {
+			let nx = x as i64 + dx * sign;
+			let ny = y as i64 + dy * sign;
+			if (0..width as i64).contains(&nx) && (0..height as i64).contains(&ny) {
+				magnitude.get_pixel(nx as u32, ny as u32)[0]
+			} else {
+				0.0
+			}
+		};
+
+		if value >= neighbour(1) && value >= neighbour(-1) {
+			Luma([value])
+		} else {
+			Luma([0.0])
+		}
+	});
+
+	// Hysteresis, following weak edges out from strong ones
+	let mut edges = Plane::new(width, height);
+	let mut stack: Vec<(u32, u32)> = thin
+		.enumerate_pixels()
+		.filter(|(_, _, value)| value[0] >= high)
+		.map(|(x, y, _)| (x, y))
+		.collect();
+	while let Some((x, y)) = stack.pop() {
+		if edges.get_pixel(x, y)[0] > 0.0 {
+			continue;
+		}
+		edges.put_pixel(x, y, Luma([1.0]));
+
+		for dy in -1..=1i64 {
+			for dx in -1..=1i64 {
+				let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+				if (0..width as i64).contains(&nx)
+					&& (0..height as i64).contains(&ny)
+					&& thin.get_pixel(nx as u32, ny as u32)[0] >= low
+					&& edges.get_pixel(nx as u32, ny as u32)[0] == 0.0
+				{
+					stack.push((nx as u32, ny as u32));
+				}
+			}
+		}
+	}
+
+	edges
+}
+
+impl Process for EdgeDetect {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let plane = luminance(&image);
+		let edges = match self.method {
+			EdgeMethod::Sobel => {
+				let (gx, gy) = sobel(&plane);
+				magnitude(&gx, &gy)
+			}
+			EdgeMethod::Canny { low, high, sigma } => {
+				if !(0.0..=1.0).contains(&low) || !(0.0..=1.0).contains(&high) || low > high {
+					return Err(OperationError::new(format!(
+						"Canny thresholds must be between 0 and 1 with low no greater than high, got {low} and {high}"
+					)));
+				}
+				if sigma.is_nan() || sigma < 0.0 {
+					return Err(OperationError::new(format!(
+						"Canny sigma must not be negative, got {sigma}"
+					)));
+				}
+				canny(&plane, low, high, sigma)
+			}
+		};
+		drop(plane);
+
+		let image = match &self.output {
+			EdgeOutput::Map => match image {
+				DynamicImage::ImageLuma16(_)
+				| DynamicImage::ImageLumaA16(_)
+				| DynamicImage::ImageRgb16(_)
+				| DynamicImage::ImageRgba16(_) => DynamicImage::ImageLuma16(ImageBuffer::from_fn(
+					edges.width(),
+					edges.height(),
+					|x, y| Luma([(edges.get_pixel(x, y)[0] * 65535.0 + 0.5) as u16]),
+				)),
+				DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+					DynamicImage::ImageRgb32F(ImageBuffer::from_fn(
+						edges.width(),
+						edges.height(),
+						|x, y| Rgb([edges.get_pixel(x, y)[0]; 3]),
+					))
+				}
+				_ => DynamicImage::ImageLuma8(ImageBuffer::from_fn(
+					edges.width(),
+					edges.height(),
+					|x, y| Luma([(edges.get_pixel(x, y)[0] * 255.0 + 0.5) as u8]),
+				)),
+			},
+			EdgeOutput::Overlay(color) => {
+				let [r, g, b, a] = [color.r, color.g, color.b, color.a].map(|c| c as f32 / 255.0);
+				let layer = Rgba32FImage::from_fn(edges.width(), edges.height(), |x, y| {
+					Rgba([r, g, b, a * edges.get_pixel(x, y)[0]])
+				});
+				composite(image, &DynamicImage::ImageRgba32F(layer), 0, 0)
+			}
+		};
+
+		Ok(image)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{EdgeDetect, EdgeMethod, EdgeOutput};
+	use crate::Process;
+	use image::{DynamicImage, GenericImageView, GrayImage, Luma};
+
+	fn square() -> DynamicImage {
+		DynamicImage::ImageLuma8(GrayImage::from_fn(20, 20, |x, y| {
+			if (5..15).contains(&x) && (5..15).contains(&y) {
+				Luma([255])
+			} else {
+				Luma([0])
+			}
+		}))
+	}
+
+	#[test]
+	fn sobel_finds_the_outline() {
+		let edges = EdgeDetect {
+			method: EdgeMethod::Sobel,
+			output: EdgeOutput::Map,
+		}
+		.process(square())
+		.unwrap();
+
+		assert_eq!(0, edges.get_pixel(10, 10)[0]);
+		assert_eq!(0, edges.get_pixel(1, 1)[0]);
+		assert!(edges.get_pixel(5, 10)[0] > 128);
+	}
+
+	#[test]
+	fn canny_edges_are_thin() {
+		let edges = EdgeDetect {
+			method: EdgeMethod::Canny {
+				low: 0.1,
+				high: 0.3,
+				sigma: 1.0,
+			},
+			output: EdgeOutput::Map,
+		}
+		.process(square())
+		.unwrap();
+
+		let row: Vec<u8> = (0..20).map(|x| edges.get_pixel(x, 10)[0]).collect();
+		let lit = row.iter().filter(|&&value| value == 255).count();
+		assert!((2..=4).contains(&lit), "{row:?}");
+		assert_eq!(0, edges.get_pixel(10, 10)[0]);
+	}
+}
@@ -0,0 +1,157 @@
+use super::crop::{aspect_window, salient_offset};
+use crate::{ImageInfo, OperationError, Process};
+use image::{DynamicImage, GenericImageView};
+use rustface::{Model, Rectangle};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs::File, io::BufReader, path::PathBuf, sync::OnceLock};
+
+/// Crops to the largest window of aspect ratio `ratio` centered on the faces in the image. When
+/// no face is found, the window is placed like [`SmartCrop`](super::SmartCrop) would.
+///
+/// `model` is a SeetaFace frontal face detection model, such as `seeta_fd_frontal_v1.0.bin` from
+/// the rustface repository. It is read the first time the operation is processed and reused
+/// afterwards.
+///
+/// ```toml
+/// [[operations]]
+/// face-crop = { model = "models/seeta_fd_frontal_v1.0.bin", ratio = [4, 5] }
+/// ```
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FaceCrop {
+	pub model: PathBuf,
+	/// Width to height, such as `[1, 1]`
+	pub ratio: (u32, u32),
+	/// Smallest face to look for in pixels, defaults to 40. Larger sizes are faster.
+	#[serde(default = "FaceCrop::default_min_face_size")]
+	pub min_face_size: u32,
+	/// Detection confidence faces need to reach, defaults to 2.0
+	#[serde(default = "FaceCrop::default_threshold")]
+	pub threshold: f64,
+	#[serde(skip)]
+	loaded_model: OnceLock<Model>,
+}
+
+impl fmt::Debug for FaceCrop {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("FaceCrop")
+			.field("model", &self.model)
+			.field("ratio", &self.ratio)
+			.field("min_face_size", &self.min_face_size)
+			.field("threshold", &self.threshold)
+			.finish_non_exhaustive()
+	}
+}
+
+impl FaceCrop {
+	pub fn new(model: impl Into<PathBuf>, ratio: (u32, u32)) -> Self {
+		Self {
+			model: model.into(),
+			ratio,
+			min_face_size: Self::default_min_face_size(),
+			threshold: Self::default_threshold(),
+			loaded_model: OnceLock::new(),
+		}
+	}
+
+	fn default_min_face_size() -> u32 {
+		40
+	}
+
+	fn default_threshold() -> f64 {
+		2.0
+	}
+
+	fn model(&self) -> Result<&Model, OperationError> {
+		if let Some(model) = self.loaded_model.get() {
+			return Ok(model);
+		}
+
+		let model = File::open(&self.model)
+			.and_then(|file| rustface::read_model(BufReader::new(file)))
+			.map_err(|error| {
+				OperationError::new(format!(
+					"Cannot read face detection model {}: {error}",
+					self.model.display()
+				))
+			})?;
+		Ok(self.loaded_model.get_or_init(|| model))
+	}
+
+	fn faces(&self, image: &DynamicImage) -> Result<Vec<Rectangle>, OperationError> {
+		let mut detector = rustface::create_detector_with_model(self.model()?.clone());
+		detector.set_min_face_size(self.min_face_size.max(20));
+		detector.set_score_thresh(self.threshold);
+
+		let gray = image.to_luma8();
+		let data = rustface::ImageData::new(gray.as_raw(), gray.width(), gray.height());
+		Ok(detector
+			.detect(&data)
+			.into_iter()
+			.map(|face| *face.bbox())
+			.collect())
+	}
+}
+
+/// Offset of the `window` which centers the bounds of `faces`, kept inside the image.
+fn centered_offset(image: (u32, u32), window: (u32, u32), faces: &[Rectangle]) -> (u32, u32) {
+	let left = faces.iter().map(|face| face.x() as i64).min().unwrap_or(0);
+	let top = faces.iter().map(|face| face.y() as i64).min().unwrap_or(0);
+	let right = faces
+		.iter()
+		.map(|face| face.x() as i64 + face.width() as i64)
+		.max()
+		.unwrap_or(0);
+	let bottom = faces
+		.iter()
+		.map(|face| face.y() as i64 + face.height() as i64)
+		.max()
+		.unwrap_or(0);
+
+	let place = |low: i64, high: i64, window: u32, length: u32| {
+		((low + high - window as i64) / 2).clamp(0, (length - window) as i64) as u32
+	};
+	(
+		place(left, right, window.0, image.0),
+		place(top, bottom, window.1, image.1),
+	)
+}
+
+impl Process for FaceCrop {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (width, height) = image.dimensions();
+		let window = aspect_window(width, height, self.ratio)?;
+
+		let faces = self.faces(&image)?;
+		let (x, y) = if faces.is_empty() {
+			salient_offset(&image, window)
+		} else {
+			centered_offset((width, height), window, &faces)
+		};
+
+		Ok(image.crop_imm(x, y, window.0, window.1))
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		aspect_window(image.width, image.height, self.ratio).map(Some)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::centered_offset;
+	use rustface::Rectangle;
+
+	#[test]
+	fn centers_on_faces() {
+		let faces = [
+			Rectangle::new(300, 40, 50, 50),
+			Rectangle::new(400, 60, 50, 50),
+		];
+		assert_eq!((275, 0), centered_offset((600, 200), (200, 200), &faces));
+
+		// Kept inside the image near the edges
+		let faces = [Rectangle::new(560, 40, 40, 40)];
+		assert_eq!((400, 0), centered_offset((600, 200), (200, 200), &faces));
+	}
+}
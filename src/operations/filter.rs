@@ -0,0 +1,898 @@
+use crate::{check_cancelled, OperationError, Process};
+use image::{DynamicImage, ImageBuffer, Pixel, Primitive};
+use num::{NumCast, ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+/// A neighbourhood filter which can be applied to buffers of any pixel type. Slow filters check
+/// for cancellation between rows.
+trait Filter {
+	fn filter<P: Pixel>(
+		&self,
+		buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+	) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, OperationError>;
+}
+
+fn apply_filter<F: Filter>(
+	image: &DynamicImage,
+	filter: &F,
+) -> Result<DynamicImage, OperationError> {
+	Ok(match image {
+		DynamicImage::ImageLuma8(buffer) => DynamicImage::ImageLuma8(filter.filter(buffer)?),
+		DynamicImage::ImageLumaA8(buffer) => DynamicImage::ImageLumaA8(filter.filter(buffer)?),
+		DynamicImage::ImageRgb8(buffer) => DynamicImage::ImageRgb8(filter.filter(buffer)?),
+		DynamicImage::ImageRgba8(buffer) => DynamicImage::ImageRgba8(filter.filter(buffer)?),
+		DynamicImage::ImageLuma16(buffer) => DynamicImage::ImageLuma16(filter.filter(buffer)?),
+		DynamicImage::ImageLumaA16(buffer) => DynamicImage::ImageLumaA16(filter.filter(buffer)?),
+		DynamicImage::ImageRgb16(buffer) => DynamicImage::ImageRgb16(filter.filter(buffer)?),
+		DynamicImage::ImageRgba16(buffer) => DynamicImage::ImageRgba16(filter.filter(buffer)?),
+		DynamicImage::ImageRgb32F(buffer) => DynamicImage::ImageRgb32F(filter.filter(buffer)?),
+		DynamicImage::ImageRgba32F(buffer) => DynamicImage::ImageRgba32F(filter.filter(buffer)?),
+		image => DynamicImage::ImageRgba32F(filter.filter(&image.to_rgba32f())?),
+	})
+}
+
+/// Number of channels holding color, leaving the alpha channel untouched by filters.
+fn color_channels<P: Pixel>() -> usize {
+	match P::CHANNEL_COUNT {
+		2 | 4 => P::CHANNEL_COUNT as usize - 1,
+		count => count as usize,
+	}
+}
+
+fn max_value<S: Primitive>() -> f32 {
+	S::DEFAULT_MAX_VALUE.to_f32().unwrap_or(1.0)
+}
+
+/// Converts a filtered value back to a channel, clamping it and rounding integer channels.
+fn to_subpixel<S: Primitive>(value: f32) -> S {
+	let max = max_value::<S>();
+	let offset = if max > 1.0 { 0.5 } else { 0.0 };
+	NumCast::from(value.clamp(0.0, max) + offset).unwrap_or(S::DEFAULT_MIN_VALUE)
+}
+
+/// Offsets a coordinate, clamping it to the edge of the image.
+#[inline]
+fn clamp_offset(position: u32, offset: i64, size: u32) -> u32 {
+	(position as i64 + offset).clamp(0, size as i64 - 1) as u32
+}
+
+/// Convolves the color channels with a square kernel. Each result is divided by `divisor` and
+/// `offset` is added to it. Edges are extended to fill the kernel.
+///
+/// ```toml
+/// [[operations]]
+/// convolve = { kernel = { preset = "emboss" } }
+///
+/// [[operations]]
+/// convolve = { kernel = { custom = [[1, 2, 1], [2, 4, 2], [1, 2, 1]] } }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Convolve {
+	pub kernel: Kernel,
+	/// Defaults to the sum of the kernel, or 1 when the kernel sums to 0
+	#[serde(default)]
+	pub divisor: Option<f32>,
+	/// Added to each channel after dividing, on a 0–255 scale
+	#[serde(default)]
+	pub offset: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kernel {
+	Preset(KernelPreset),
+	/// Rows of an odd-sized square kernel
+	Custom(Vec<Vec<f32>>),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KernelPreset {
+	Sharpen,
+	Emboss,
+	EdgeDetect,
+	BoxBlur,
+}
+
+impl KernelPreset {
+	fn weights(self) -> [f32; 9] {
+		match self {
+			Self::Sharpen => [0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0],
+			Self::Emboss => [-2.0, -1.0, 0.0, -1.0, 1.0, 1.0, 0.0, 1.0, 2.0],
+			Self::EdgeDetect => [-1.0, -1.0, -1.0, -1.0, 8.0, -1.0, -1.0, -1.0, -1.0],
+			Self::BoxBlur => [1.0; 9],
+		}
+	}
+}
+
+impl Kernel {
+	/// Returns the weights in row-major order and the size of the kernel.
+	pub(crate) fn weights(&self) -> Result<(Vec<f32>, usize), OperationError> {
+		match self {
+			Self::Preset(preset) => Ok((preset.weights().to_vec(), 3)),
+			Self::Custom(rows) => {
+				let size = rows.len();
+				if size < 3 || size % 2 == 0 {
+					return Err(OperationError::new(format!(
+						"Kernels must have an odd size of at least 3, got {size}"
+					)));
+				}
+				if rows.iter().any(|row| row.len() != size) {
+					return Err(OperationError::new(format!(
+						"Kernels must be square, expected {size} values in each row"
+					)));
+				}
+
+				Ok((rows.concat(), size))
+			}
+		}
+	}
+}
+
+impl Convolve {
+	/// The filter for the kernel, with the divisor and offset resolved.
+	pub(crate) fn convolution(&self) -> Result<Convolution, OperationError> {
+		let (weights, size) = self.kernel.weights()?;
+		let divisor = match self.divisor {
+			Some(0.0) => return Err(OperationError::new("Divisor cannot be 0".to_string())),
+			Some(divisor) => divisor,
+			None => match weights.iter().sum::<f32>() {
+				0.0 => 1.0,
+				sum => sum,
+			},
+		};
+
+		Ok(Convolution {
+			weights,
+			size,
+			divisor,
+			offset: self.offset / 255.0,
+		})
+	}
+}
+
+impl Process for Convolve {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		apply_filter(&image, &self.convolution()?)
+	}
+}
+
+/// Sharpens with a 3x3 kernel of preset strength, or a custom kernel.
+///
+/// ```toml
+/// [[operations]]
+/// sharpen = "medium"
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sharpen {
+	Light,
+	Medium,
+	Strong,
+	/// Rows of an odd-sized square kernel, divided by its sum
+	Custom(Vec<Vec<f32>>),
+}
+
+impl Process for Sharpen {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let amount = match self {
+			Self::Light => 0.25,
+			Self::Medium => 0.5,
+			Self::Strong => 1.0,
+			Self::Custom(rows) => {
+				return Convolve {
+					kernel: Kernel::Custom(rows.clone()),
+					divisor: None,
+					offset: 0.0,
+				}
+				.process(image)
+			}
+		};
+
+		let weights = vec![
+			0.0,
+			-amount,
+			0.0,
+			-amount,
+			1.0 + 4.0 * amount,
+			-amount,
+			0.0,
+			-amount,
+			0.0,
+		];
+
+		apply_filter(
+			&image,
+			&Convolution {
+				weights,
+				size: 3,
+				divisor: 1.0,
+				offset: 0.0,
+			},
+		)
+	}
+}
+
+pub(crate) struct Convolution {
+	pub weights: Vec<f32>,
+	pub size: usize,
+	pub divisor: f32,
+	/// Normalized to the range of the channel type
+	pub offset: f32,
+}
+
+impl Filter for Convolution {
+	fn filter<P: Pixel>(
+		&self,
+		buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+	) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, OperationError> {
+		let (width, height) = buffer.dimensions();
+		let channels = color_channels::<P>();
+		let offset = self.offset * max_value::<P::Subpixel>();
+		let radius = (self.size / 2) as i64;
+
+		let mut output = buffer.clone();
+		for (x, y, pixel) in output.enumerate_pixels_mut() {
+			let mut sums = [0.0f32; 4];
+			for (i, weight) in self.weights.iter().enumerate() {
+				if *weight == 0.0 {
+					continue;
+				}
+
+				let dx = (i % self.size) as i64 - radius;
+				let dy = (i / self.size) as i64 - radius;
+				let source =
+					buffer.get_pixel(clamp_offset(x, dx, width), clamp_offset(y, dy, height));
+				for (sum, channel) in sums.iter_mut().zip(&source.channels()[..channels]) {
+					*sum += channel.to_f32().unwrap_or_default() * weight;
+				}
+			}
+
+			for (channel, sum) in pixel.channels_mut()[..channels].iter_mut().zip(sums) {
+				*channel = to_subpixel(sum / self.divisor + offset);
+			}
+		}
+
+		Ok(output)
+	}
+}
+
+/// Replaces each color channel with the median of the surrounding `(2 * radius + 1)²` pixels,
+/// removing salt-and-pepper noise while keeping edges.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct MedianFilter {
+	pub radius: u32,
+}
+
+impl Process for MedianFilter {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if self.radius == 0 {
+			return Ok(image);
+		}
+
+		apply_filter(
+			&image,
+			&Median {
+				radius: self.radius,
+			},
+		)
+	}
+}
+
+/// Median filtering with a histogram of the window which slides along each row, updating the
+/// median incrementally as in Huang's algorithm.
+struct Median {
+	radius: u32,
+}
+
+impl Filter for Median {
+	fn filter<P: Pixel>(
+		&self,
+		buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+	) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, OperationError> {
+		let (width, height) = buffer.dimensions();
+		if width == 0 || height == 0 {
+			return Ok(buffer.clone());
+		}
+
+		// Larger windows would only repeat the edges of the image further
+		let radius = self.radius.min(width.max(height)) as u64;
+		let half = (2 * radius + 1)
+			.checked_mul(2 * radius + 1)
+			.map(|size| size / 2)
+			.ok_or_else(|| OperationError::new(format!("Median radius {radius} is too large")))?;
+		let radius = radius as i64;
+
+		// 8-bit channels map directly to bins, other types are quantized to 16 bits
+		let max = max_value::<P::Subpixel>();
+		let levels = if max == 255.0 { 256 } else { 65536 };
+		let scale = (levels - 1) as f32 / max;
+		let bin = |x: u32, y: u32, channel: usize| {
+			let value = buffer.get_pixel(x, y).channels()[channel]
+				.to_f32()
+				.unwrap_or_default();
+			((value * scale).round() as usize).min(levels - 1)
+		};
+
+		let mut output = buffer.clone();
+		let mut histogram = vec![0u64; levels];
+
+		for channel in 0..color_channels::<P>() {
+			for y in 0..height {
+				check_cancelled()?;
+				histogram.fill(0);
+				for dy in -radius..=radius {
+					for dx in -radius..=radius {
+						let x = clamp_offset(0, dx, width);
+						histogram[bin(x, clamp_offset(y, dy, height), channel)] += 1;
+					}
+				}
+
+				let mut median = 0;
+				// Number of values in the window below the median bin
+				let mut below = 0;
+
+				for x in 0..width {
+					if x > 0 {
+						let removed_x = clamp_offset(x, -radius - 1, width);
+						let added_x = clamp_offset(x, radius, width);
+						for dy in -radius..=radius {
+							let row = clamp_offset(y, dy, height);
+
+							let removed = bin(removed_x, row, channel);
+							histogram[removed] -= 1;
+							if removed < median {
+								below -= 1;
+							}
+
+							let added = bin(added_x, row, channel);
+							histogram[added] += 1;
+							if added < median {
+								below += 1;
+							}
+						}
+					}
+
+					while below > half {
+						median -= 1;
+						below -= histogram[median];
+					}
+					while below + histogram[median] <= half {
+						below += histogram[median];
+						median += 1;
+					}
+
+					output.get_pixel_mut(x, y).channels_mut()[channel] =
+						to_subpixel(median as f32 / scale);
+				}
+			}
+		}
+
+		Ok(output)
+	}
+}
+
+/// Edge-preserving smoothing, averaging nearby pixels weighted by both their distance and how
+/// similar their colors are.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct BilateralFilter {
+	/// Standard deviation of the distance weighting in pixels
+	pub spatial_sigma: f32,
+	/// Standard deviation of the color difference weighting, on a 0–255 scale
+	pub range_sigma: f32,
+}
+
+impl Process for BilateralFilter {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if self.spatial_sigma <= 0.0 || self.range_sigma <= 0.0 {
+			return Err(OperationError::new(format!(
+				"Bilateral filter sigmas must be positive, got {} and {}",
+				self.spatial_sigma, self.range_sigma
+			)));
+		}
+
+		let radius = (2.0 * self.spatial_sigma).ceil() as i64;
+		let size = (2 * radius + 1) as usize;
+		let spatial_weights = (0..size * size)
+			.map(|i| {
+				let dx = (i % size) as i64 - radius;
+				let dy = (i / size) as i64 - radius;
+				let distance = (dx * dx + dy * dy) as f32;
+				(-distance / (2.0 * self.spatial_sigma * self.spatial_sigma)).exp()
+			})
+			.collect();
+
+		apply_filter(
+			&image,
+			&Bilateral {
+				radius,
+				spatial_weights,
+				range_sigma: self.range_sigma / 255.0,
+			},
+		)
+	}
+}
+
+struct Bilateral {
+	radius: i64,
+	/// Precomputed distance weights for the window in row-major order
+	spatial_weights: Vec<f32>,
+	/// Normalized to the range of the channel type
+	range_sigma: f32,
+}
+
+impl Filter for Bilateral {
+	fn filter<P: Pixel>(
+		&self,
+		buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+	) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, OperationError> {
+		let (width, height) = buffer.dimensions();
+		let channels = color_channels::<P>();
+		let size = (2 * self.radius + 1) as usize;
+		let range_sigma = self.range_sigma * max_value::<P::Subpixel>();
+		let range_denominator = 2.0 * range_sigma * range_sigma;
+
+		let values = |x: u32, y: u32| {
+			let mut values = [0.0f32; 4];
+			for (value, channel) in values
+				.iter_mut()
+				.zip(&buffer.get_pixel(x, y).channels()[..channels])
+			{
+				*value = channel.to_f32().unwrap_or_default();
+			}
+			values
+		};
+
+		let mut output = buffer.clone();
+		for (y, row) in output.enumerate_rows_mut() {
+			check_cancelled()?;
+			for (x, _, pixel) in row {
+				let center = values(x, y);
+				let mut sums = [0.0f32; 4];
+				let mut total_weight = 0.0;
+
+				for (i, spatial_weight) in self.spatial_weights.iter().enumerate() {
+					let dx = (i % size) as i64 - self.radius;
+					let dy = (i / size) as i64 - self.radius;
+					let neighbour = values(clamp_offset(x, dx, width), clamp_offset(y, dy, height));
+
+					let difference = center
+						.iter()
+						.zip(neighbour)
+						.map(|(a, b)| (a - b) * (a - b))
+						.sum::<f32>();
+					let weight = spatial_weight * (-difference / range_denominator).exp();
+
+					for (sum, value) in sums.iter_mut().zip(neighbour) {
+						*sum += value * weight;
+					}
+					total_weight += weight;
+				}
+
+				for (channel, sum) in pixel.channels_mut()[..channels].iter_mut().zip(sums) {
+					*channel = to_subpixel(sum / total_weight);
+				}
+			}
+		}
+
+		Ok(output)
+	}
+}
+
+/// Oil painting effect using a Kuwahara filter. Each pixel takes the average color of whichever
+/// of the four `radius` sized quadrants around it has the least variation in intensity, which
+/// flattens detail while keeping edges sharp. Colors are then reduced to `levels` steps per
+/// channel, between 2 and 256.
+///
+/// ```toml
+/// [[operations]]
+/// oil-paint = { radius = 4, levels = 20 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct OilPaint {
+	pub radius: u32,
+	pub levels: u16,
+}
+
+impl Process for OilPaint {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !(2..=256).contains(&self.levels) {
+			return Err(OperationError::new(format!(
+				"Oil paint levels must be between 2 and 256, got {}",
+				self.levels
+			)));
+		}
+		if self.radius == 0 {
+			return Ok(image);
+		}
+
+		apply_filter(
+			&image,
+			&Kuwahara {
+				radius: self.radius as i64,
+				levels: self.levels,
+			},
+		)
+	}
+}
+
+struct Kuwahara {
+	radius: i64,
+	levels: u16,
+}
+
+impl Filter for Kuwahara {
+	fn filter<P: Pixel>(
+		&self,
+		buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+	) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, OperationError> {
+		let (width, height) = buffer.dimensions();
+		let channels = color_channels::<P>();
+		let max = max_value::<P::Subpixel>();
+		let step = max / (self.levels - 1) as f32;
+		let count = ((self.radius + 1) * (self.radius + 1)) as f32;
+
+		let mut output = buffer.clone();
+		for (y, row) in output.enumerate_rows_mut() {
+			check_cancelled()?;
+			for (x, _, pixel) in row {
+				let mut best = (f32::INFINITY, [0.0f32; 4]);
+
+				// Quadrants share the row and column through the pixel
+				for (sx, sy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+					let mut sums = [0.0f32; 4];
+					let mut intensity_sum = 0.0;
+					let mut intensity_squares = 0.0;
+
+					for dy in 0..=self.radius {
+						for dx in 0..=self.radius {
+							let neighbour = buffer
+								.get_pixel(
+									clamp_offset(x, dx * sx, width),
+									clamp_offset(y, dy * sy, height),
+								)
+								.channels();
+							let mut intensity = 0.0;
+							for (sum, channel) in sums.iter_mut().zip(&neighbour[..channels]) {
+								let value = channel.to_f32().unwrap_or_default();
+								*sum += value;
+								intensity += value;
+							}
+							intensity /= channels as f32;
+							intensity_sum += intensity;
+							intensity_squares += intensity * intensity;
+						}
+					}
+
+					let mean = intensity_sum / count;
+					let variance = intensity_squares / count - mean * mean;
+					if variance < best.0 {
+						best = (variance, sums);
+					}
+				}
+
+				for (channel, sum) in pixel.channels_mut()[..channels].iter_mut().zip(best.1) {
+					*channel = to_subpixel((sum / count / step).round() * step);
+				}
+			}
+		}
+
+		Ok(output)
+	}
+}
+
+/// Shifts the red channel outwards from the center and the blue channel inwards, like a lens with
+/// lateral chromatic aberration. `amount` is the shift in pixels at the corners, and shrinks
+/// towards the center.
+///
+/// ```toml
+/// [[operations]]
+/// chromatic-aberration = { amount = 3.0 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ChromaticAberration {
+	pub amount: f32,
+}
+
+impl Process for ChromaticAberration {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !self.amount.is_finite() {
+			return Err(OperationError::new(format!(
+				"Chromatic aberration amount must be finite, got {}",
+				self.amount
+			)));
+		}
+		if self.amount == 0.0 || !image.color().has_color() {
+			return Ok(image);
+		}
+
+		apply_filter(
+			&image,
+			&Aberration {
+				amount: self.amount,
+			},
+		)
+	}
+}
+
+struct Aberration {
+	amount: f32,
+}
+
+impl Filter for Aberration {
+	fn filter<P: Pixel>(
+		&self,
+		buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+	) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, OperationError> {
+		let (width, height) = buffer.dimensions();
+		if width == 0 || height == 0 {
+			return Ok(buffer.clone());
+		}
+		let (center_x, center_y) = (width as f32 / 2.0, height as f32 / 2.0);
+		let scale = self.amount / center_x.hypot(center_y);
+
+		// Bilinear sample of one channel, clamped to the edges
+		let sample = |x: f32, y: f32, channel: usize| {
+			let x = x.clamp(0.0, (width - 1) as f32);
+			let y = y.clamp(0.0, (height - 1) as f32);
+			let (left, top) = (x.floor() as u32, y.floor() as u32);
+			let (right, bottom) = ((left + 1).min(width - 1), (top + 1).min(height - 1));
+			let (fx, fy) = (x - left as f32, y - top as f32);
+			let at = |x, y| {
+				buffer.get_pixel(x, y).channels()[channel]
+					.to_f32()
+					.unwrap_or_default()
+			};
+			let upper = at(left, top) * (1.0 - fx) + at(right, top) * fx;
+			let lower = at(left, bottom) * (1.0 - fx) + at(right, bottom) * fx;
+			upper * (1.0 - fy) + lower * fy
+		};
+
+		let mut output = buffer.clone();
+		for (x, y, pixel) in output.enumerate_pixels_mut() {
+			let dx = x as f32 + 0.5 - center_x;
+			let dy = y as f32 + 0.5 - center_y;
+			let channels = pixel.channels_mut();
+			for (channel, direction) in [(0, 1.0), (2, -1.0)] {
+				// Sampling nearer the center spreads the channel outwards
+				let factor = 1.0 - direction * scale;
+				channels[channel] = to_subpixel(sample(
+					center_x + dx * factor - 0.5,
+					center_y + dy * factor - 0.5,
+					channel,
+				));
+			}
+		}
+
+		Ok(output)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		BilateralFilter, ChromaticAberration, Convolve, Kernel, KernelPreset, MedianFilter,
+		OilPaint, Sharpen,
+	};
+	use crate::Process;
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage, Rgba, RgbaImage};
+
+	fn gradient() -> DynamicImage {
+		DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, y| {
+			Rgba([(x * 30) as u8, (y * 30) as u8, 128, 200])
+		}))
+	}
+
+	#[test]
+	fn identity_kernel() {
+		let convolve = Convolve {
+			kernel: Kernel::Custom(vec![
+				vec![0.0, 0.0, 0.0, 0.0, 0.0],
+				vec![0.0, 0.0, 0.0, 0.0, 0.0],
+				vec![0.0, 0.0, 1.0, 0.0, 0.0],
+				vec![0.0, 0.0, 0.0, 0.0, 0.0],
+				vec![0.0, 0.0, 0.0, 0.0, 0.0],
+			]),
+			divisor: None,
+			offset: 0.0,
+		};
+
+		assert_eq!(gradient(), convolve.process(gradient()).unwrap());
+	}
+
+	#[test]
+	fn sharpens_edges() {
+		let edge = || {
+			DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 4, |x, _| match x {
+				0..=3 => Rgba([100, 100, 100, 255]),
+				_ => Rgba([150, 150, 150, 255]),
+			}))
+		};
+		let sharpened = |sharpen: Sharpen| sharpen.process(edge()).unwrap().into_rgba8();
+
+		// Each side of the edge moves away from the other by the preset amount times the step
+		let medium = sharpened(Sharpen::Medium);
+		assert_eq!(Rgba([75, 75, 75, 255]), *medium.get_pixel(3, 1));
+		assert_eq!(Rgba([175, 175, 175, 255]), *medium.get_pixel(4, 1));
+		assert_eq!(Rgba([100, 100, 100, 255]), *medium.get_pixel(1, 1));
+		assert_eq!(Rgba([150, 150, 150, 255]), *medium.get_pixel(6, 2));
+		assert_eq!(88, sharpened(Sharpen::Light).get_pixel(3, 1).0[0]);
+		assert_eq!(50, sharpened(Sharpen::Strong).get_pixel(3, 1).0[0]);
+	}
+
+	#[test]
+	fn preserves_alpha() {
+		let convolve = Convolve {
+			kernel: Kernel::Preset(KernelPreset::EdgeDetect),
+			divisor: None,
+			offset: 0.0,
+		};
+
+		let image = convolve.process(gradient()).unwrap().into_rgba8();
+		assert!(image.pixels().all(|pixel| pixel[3] == 200));
+	}
+
+	#[test]
+	fn invalid_kernels() {
+		for rows in [
+			vec![vec![1.0; 2]; 2],
+			vec![vec![1.0; 3], vec![1.0; 3], vec![1.0; 2]],
+		] {
+			let convolve = Convolve {
+				kernel: Kernel::Custom(rows),
+				divisor: None,
+				offset: 0.0,
+			};
+			assert!(convolve.process(gradient()).is_err());
+		}
+	}
+
+	#[test]
+	fn median_removes_outliers() {
+		let mut image = RgbaImage::from_pixel(9, 9, Rgba([100, 150, 200, 255]));
+		image.put_pixel(4, 4, Rgba([255, 0, 255, 255]));
+		image.put_pixel(0, 0, Rgba([0, 255, 0, 255]));
+
+		let filtered = MedianFilter { radius: 1 }
+			.process(DynamicImage::ImageRgba8(image))
+			.unwrap()
+			.into_rgba8();
+		assert!(filtered
+			.pixels()
+			.all(|pixel| *pixel == Rgba([100, 150, 200, 255])));
+	}
+
+	#[test]
+	fn median_of_gradient() {
+		let image = DynamicImage::ImageLuma8(image::GrayImage::from_fn(5, 1, |x, _| {
+			image::Luma([[10, 50, 20, 40, 30][x as usize]])
+		}));
+
+		let filtered = MedianFilter { radius: 1 }
+			.process(image)
+			.unwrap()
+			.into_luma8();
+		// Windows are 3x3 with the single row repeated, and the edges extended
+		assert_eq!(&[10, 20, 40, 30, 30], filtered.as_raw().as_slice());
+	}
+
+	#[test]
+	fn median_of_empty_image() {
+		let image = DynamicImage::ImageRgb8(RgbImage::new(0, 5));
+		let filtered = MedianFilter { radius: 1 }.process(image).unwrap();
+		assert_eq!((0, 5), filtered.dimensions());
+	}
+
+	#[test]
+	fn median_with_huge_radius() {
+		let image = DynamicImage::ImageLuma8(image::GrayImage::from_fn(5, 1, |x, _| {
+			image::Luma([[10, 50, 20, 40, 30][x as usize]])
+		}));
+
+		let filtered = MedianFilter {
+			radius: 4_000_000_000,
+		}
+		.process(image.clone())
+		.unwrap();
+		let expected = MedianFilter { radius: 5 }.process(image).unwrap();
+		assert_eq!(expected.as_bytes(), filtered.as_bytes());
+	}
+
+	#[test]
+	fn bilateral_preserves_edges() {
+		let image = DynamicImage::ImageLuma8(image::GrayImage::from_fn(10, 10, |x, _| {
+			image::Luma([if x < 5 { 20 } else { 230 }])
+		}));
+
+		let filtered = BilateralFilter {
+			spatial_sigma: 2.0,
+			range_sigma: 20.0,
+		}
+		.process(image)
+		.unwrap()
+		.into_luma8();
+
+		assert_eq!(20, filtered.get_pixel(4, 5)[0]);
+		assert_eq!(230, filtered.get_pixel(5, 5)[0]);
+	}
+
+	#[test]
+	fn oil_paint_preserves_edges() {
+		let mut image =
+			image::GrayImage::from_fn(10, 10, |x, _| image::Luma([if x < 5 { 20 } else { 230 }]));
+		image.put_pixel(2, 5, image::Luma([120]));
+
+		let filtered = OilPaint {
+			radius: 2,
+			levels: 256,
+		}
+		.process(DynamicImage::ImageLuma8(image))
+		.unwrap()
+		.into_luma8();
+
+		// The speck is in every quadrant so it is averaged into the flattest, and the edge stays put
+		assert_eq!(31, filtered.get_pixel(2, 5)[0]);
+		assert_eq!(20, filtered.get_pixel(2, 7)[0]);
+		assert_eq!(20, filtered.get_pixel(4, 1)[0]);
+		assert_eq!(230, filtered.get_pixel(5, 1)[0]);
+	}
+
+	#[test]
+	fn oil_paint_levels() {
+		let image =
+			DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([100, 150, 200, 80])));
+
+		let filtered = OilPaint {
+			radius: 1,
+			levels: 3,
+		}
+		.process(image)
+		.unwrap()
+		.into_rgba8();
+
+		// Channels snap to 0, 128 or 255 and alpha is untouched
+		assert_eq!([128, 128, 255, 80], filtered.get_pixel(1, 1).0);
+
+		for levels in [1, 257] {
+			assert!(OilPaint { radius: 1, levels }
+				.process(DynamicImage::new_rgb8(1, 1))
+				.is_err());
+		}
+	}
+
+	#[test]
+	fn chromatic_aberration_shifts_red_out_and_blue_in() {
+		// White on the right, from a little past the center
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 40, |x, _| match x {
+			30.. => Rgb([255, 255, 255]),
+			_ => Rgb([0, 0, 0]),
+		}));
+		let shifted = ChromaticAberration { amount: 3.0 }
+			.process(image.clone())
+			.unwrap();
+
+		// The red edge moves away from the center and the blue edge towards it
+		assert_eq!([0, 255, 255, 255], shifted.get_pixel(30, 20).0);
+		assert_eq!([0, 0, 255, 255], shifted.get_pixel(29, 20).0);
+		assert_eq!([255, 255, 255, 255], shifted.get_pixel(33, 20).0);
+		assert_eq!(image.get_pixel(20, 20), shifted.get_pixel(20, 20));
+
+		let amount = |amount| ChromaticAberration { amount };
+		assert_eq!(image, amount(0.0).process(image.clone()).unwrap());
+		assert!(amount(f32::NAN).process(image).is_err());
+	}
+
+	#[test]
+	fn chromatic_aberration_of_empty_images() {
+		for (width, height) in [(0, 0), (0, 5), (5, 0)] {
+			let image = DynamicImage::ImageRgb8(RgbImage::new(width, height));
+			let shifted = ChromaticAberration { amount: 3.0 }.process(image).unwrap();
+			assert_eq!((width, height), shifted.dimensions());
+		}
+	}
+}
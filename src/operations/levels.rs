@@ -0,0 +1,283 @@
+use super::color::map_rgb;
+use crate::{check_cancelled, OperationError, Process};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+const BINS: usize = 256;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoContrastMode {
+	/// Stretches each channel on its own, which also corrects color casts
+	PerChannel,
+	/// Stretches every channel by the same amount, based on luminance, which keeps the colors
+	#[default]
+	Luminance,
+}
+
+/// Stretches the histogram so the darkest and lightest values reach black and white. `clip` is the
+/// fraction of values, between 0 and 0.5, ignored at each end of the histogram so a few outliers
+/// don't prevent the stretch.
+///
+/// ```toml
+/// [[operations]]
+/// auto-contrast = { clip = 0.01, mode = "per-channel" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct AutoContrast {
+	/// Defaults to 0.005
+	#[serde(default = "AutoContrast::default_clip")]
+	pub clip: f32,
+	/// Defaults to luminance
+	#[serde(default)]
+	pub mode: AutoContrastMode,
+}
+
+impl AutoContrast {
+	fn default_clip() -> f32 {
+		0.005
+	}
+}
+
+/// Black and white points of a histogram, ignoring `clip` values at each end.
+fn levels(histogram: &[u64; BINS], clip: f32) -> Option<(f32, f32)> {
+	let total: u64 = histogram.iter().sum();
+	let clipped = (total as f64 * clip as f64) as u64;
+
+	let mut count = 0;
+	let low = histogram.iter().position(|&bin| {
+		count += bin;
+		count > clipped
+	})?;
+	count = 0;
+	let from_top = histogram.iter().rev().position(|&bin| {
+		count += bin;
+		count > clipped
+	})?;
+	let high = BINS - 1 - from_top;
+
+	(high > low).then(|| (low as f32 / 255.0, high as f32 / 255.0))
+}
+
+fn bin(value: f32) -> usize {
+	(value.clamp(0.0, 1.0) * 255.0).round() as usize
+}
+
+impl Process for AutoContrast {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !(0.0..0.5).contains(&self.clip) {
+			return Err(OperationError::new(format!(
+				"Auto contrast clip must be at least 0 and less than 0.5, got {}",
+				self.clip
+			)));
+		}
+
+		let rgb = image.to_rgb32f();
+		let levels = match self.mode {
+			AutoContrastMode::PerChannel => {
+				let mut histograms = [[0u64; BINS]; 3];
+				for pixel in rgb.pixels() {
+					for (histogram, value) in histograms.iter_mut().zip(pixel.0) {
+						histogram[bin(value)] += 1;
+					}
+				}
+				histograms.map(|histogram| levels(&histogram, self.clip))
+			}
+			AutoContrastMode::Luminance => {
+				let mut histogram = [0u64; BINS];
+				for pixel in rgb.pixels() {
+					let [r, g, b] = pixel.0;
+					histogram[bin(0.2126 * r + 0.7152 * g + 0.0722 * b)] += 1;
+				}
+				[levels(&histogram, self.clip); 3]
+			}
+		};
+		drop(rgb);
+
+		if levels.iter().all(Option::is_none) {
+			return Ok(image);
+		}
+
+		Ok(map_rgb(image, |pixel| {
+			[0, 1, 2].map(|i| match levels[i] {
+				Some((low, high)) => (pixel[i] - low) / (high - low),
+				None => pixel[i],
+			})
+		}))
+	}
+}
+
+/// Contrast limited adaptive histogram equalization of luminance. The image is split into tiles of
+/// about `tile_size` pixels which are equalized separately and blended together. `clip_limit`
+/// limits each histogram bin to that multiple of the average bin, so noise in flat areas isn't
+/// amplified.
+///
+/// ```toml
+/// [[operations]]
+/// clahe = { tile_size = 64, clip_limit = 2.0 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Clahe {
+	/// Defaults to 64
+	#[serde(default = "Clahe::default_tile_size")]
+	pub tile_size: u32,
+	/// Defaults to 2
+	#[serde(default = "Clahe::default_clip_limit")]
+	pub clip_limit: f32,
+}
+
+impl Clahe {
+	fn default_tile_size() -> u32 {
+		64
+	}
+
+	fn default_clip_limit() -> f32 {
+		2.0
+	}
+
+	/// Clipped and equalized mapping of luminance levels for a histogram.
+	fn mapping(&self, mut histogram: [u32; BINS]) -> [f32; BINS] {
+		let total: u32 = histogram.iter().sum();
+		let limit = ((self.clip_limit * total as f32 / BINS as f32) as u32).max(1);
+
+		let mut excess = 0;
+		for bin in histogram.iter_mut() {
+			if *bin > limit {
+				excess += *bin - limit;
+				*bin = limit;
+			}
+		}
+		let (share, remainder) = (excess / BINS as u32, (excess % BINS as u32) as usize);
+		for (index, bin) in histogram.iter_mut().enumerate() {
+			*bin += share + u32::from(index < remainder);
+		}
+
+		let mut mapping = [0.0; BINS];
+		let mut cumulative = 0;
+		for (level, bin) in mapping.iter_mut().zip(histogram) {
+			cumulative += bin;
+			*level = cumulative as f32 / total.max(1) as f32;
+		}
+		mapping
+	}
+}
+
+impl Process for Clahe {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if self.tile_size == 0 || self.clip_limit < 1.0 {
+			return Err(OperationError::new(format!(
+				"CLAHE needs a positive tile size and a clip limit of at least 1, got {} and {}",
+				self.tile_size, self.clip_limit
+			)));
+		}
+
+		let rgb = image.to_rgb32f();
+		let (width, height) = rgb.dimensions();
+		let luminance: Vec<f32> = rgb
+			.pixels()
+			.map(|pixel| {
+				let [r, g, b] = pixel.0;
+				0.2126 * r + 0.7152 * g + 0.0722 * b
+			})
+			.collect();
+		drop(rgb);
+
+		let tiles_x = width.div_ceil(self.tile_size).max(1) as usize;
+		let tiles_y = height.div_ceil(self.tile_size).max(1) as usize;
+		let tile_width = width as f32 / tiles_x as f32;
+		let tile_height = height as f32 / tiles_y as f32;
+
+		let rows = || luminance.chunks(width.max(1) as usize).enumerate();
+		let mut histograms = vec![[0u32; BINS]; tiles_x * tiles_y];
+		for (y, row) in rows() {
+			check_cancelled()?;
+			let tile_y = ((y as f32 / tile_height) as usize).min(tiles_y - 1);
+			for (x, value) in row.iter().enumerate() {
+				let tile_x = ((x as f32 / tile_width) as usize).min(tiles_x - 1);
+				histograms[tile_y * tiles_x + tile_x][bin(*value)] += 1;
+			}
+		}
+		let mappings: Vec<[f32; BINS]> = histograms
+			.into_iter()
+			.map(|histogram| self.mapping(histogram))
+			.collect();
+
+		// Position between the centers of the surrounding tiles along one axis
+		let neighbours = |position: usize, size: f32, tiles: usize| {
+			let tile = ((position as f32 + 0.5) / size - 0.5).clamp(0.0, (tiles - 1) as f32);
+			let low = tile.floor() as usize;
+			(low, (low + 1).min(tiles - 1), tile - low as f32)
+		};
+
+		// Equalized luminance of each pixel, interpolated between the mappings of the nearest tiles
+		let mut equalized = Vec::with_capacity(luminance.len());
+		for (y, row) in rows() {
+			check_cancelled()?;
+			let (top, bottom, fy) = neighbours(y, tile_height, tiles_y);
+			for (x, value) in row.iter().enumerate() {
+				let (left, right, fx) = neighbours(x, tile_width, tiles_x);
+				let level = bin(*value);
+				let at = |tile_x: usize, tile_y: usize| mappings[tile_y * tiles_x + tile_x][level];
+				let upper = at(left, top) * (1.0 - fx) + at(right, top) * fx;
+				let lower = at(left, bottom) * (1.0 - fx) + at(right, bottom) * fx;
+				equalized.push(upper * (1.0 - fy) + lower * fy);
+			}
+		}
+
+		let mut index = 0;
+		Ok(map_rgb(image, |pixel| {
+			let (value, equalized) = (luminance[index], equalized[index]);
+			index += 1;
+
+			if value > 0.0 {
+				pixel.map(|channel| channel * equalized / value)
+			} else {
+				[equalized; 3]
+			}
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{AutoContrast, AutoContrastMode, Clahe};
+	use crate::Process;
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+	#[test]
+	fn stretches_to_full_range() {
+		let image = RgbImage::from_fn(16, 1, |x, _| {
+			let value = 100 + x as u8 * 4;
+			Rgb([value, value, value / 2])
+		});
+		let stretched = AutoContrast {
+			clip: 0.0,
+			mode: AutoContrastMode::PerChannel,
+		}
+		.process(DynamicImage::ImageRgb8(image))
+		.unwrap();
+
+		assert_eq!([0, 0, 0, 255], stretched.get_pixel(0, 0).0);
+		assert_eq!([255, 255, 255, 255], stretched.get_pixel(15, 0).0);
+	}
+
+	#[test]
+	fn clahe_spreads_a_narrow_range() {
+		let image = DynamicImage::ImageLuma8(image::GrayImage::from_fn(64, 64, |x, y| {
+			image::Luma([100 + ((x + y) % 20) as u8])
+		}));
+		let equalized = Clahe {
+			tile_size: 32,
+			clip_limit: 4.0,
+		}
+		.process(image)
+		.unwrap()
+		.to_luma8();
+
+		let min = equalized.pixels().map(|pixel| pixel[0]).min().unwrap();
+		let max = equalized.pixels().map(|pixel| pixel[0]).max().unwrap();
+		assert!(max - min > 60, "range {min}..{max}");
+	}
+}
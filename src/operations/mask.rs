@@ -0,0 +1,246 @@
+use crate::{Color, OperationError, PixelUnit, Process, Unit};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel, Primitive};
+use num::{NumCast, ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+/// Converts the image to RGBA at its current bit depth and multiplies its alpha channel by the
+/// coverage, between 0 and 1, returned for each pixel.
+pub(crate) fn mask_alpha<F: Fn(u32, u32) -> f32>(image: DynamicImage, coverage: F) -> DynamicImage {
+	match image {
+		DynamicImage::ImageLuma8(_)
+		| DynamicImage::ImageLumaA8(_)
+		| DynamicImage::ImageRgb8(_)
+		| DynamicImage::ImageRgba8(_) => {
+			DynamicImage::ImageRgba8(scale_alpha(image.into_rgba8(), coverage))
+		}
+		DynamicImage::ImageLuma16(_)
+		| DynamicImage::ImageLumaA16(_)
+		| DynamicImage::ImageRgb16(_)
+		| DynamicImage::ImageRgba16(_) => {
+			DynamicImage::ImageRgba16(scale_alpha(image.into_rgba16(), coverage))
+		}
+		image => DynamicImage::ImageRgba32F(scale_alpha(image.into_rgba32f(), coverage)),
+	}
+}
+
+fn scale_alpha<P: Pixel, F: Fn(u32, u32) -> f32>(
+	mut buffer: ImageBuffer<P, Vec<P::Subpixel>>,
+	coverage: F,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+	let alpha_channel = P::CHANNEL_COUNT as usize - 1;
+	let offset = if P::Subpixel::DEFAULT_MAX_VALUE.to_f32().unwrap_or(1.0) > 1.0 {
+		0.5
+	} else {
+		0.0
+	};
+
+	for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+		let coverage = coverage(x, y);
+		if coverage >= 1.0 {
+			continue;
+		}
+		let channels = pixel.channels_mut();
+		let alpha = channels[alpha_channel].to_f32().unwrap_or(0.0) * coverage.max(0.0);
+		channels[alpha_channel] =
+			NumCast::from(alpha + offset).unwrap_or(P::Subpixel::DEFAULT_MIN_VALUE);
+	}
+
+	buffer
+}
+
+/// Coverage of the pixel at `(x, y)` by a circle, antialiased across the pixel on its edge.
+#[inline]
+fn circle_coverage(x: u32, y: u32, center_x: f32, center_y: f32, radius: f32) -> f32 {
+	let dx = x as f32 + 0.5 - center_x;
+	let dy = y as f32 + 0.5 - center_y;
+	(radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0)
+}
+
+/// Makes the corners of the image transparent, either rounding each corner by a radius or masking
+/// everything outside of the largest circle centered on the image. Percentages of the radius are
+/// of the shorter side.
+///
+/// ```toml
+/// [[operations]]
+/// round-corners = { radius = { pixel = { pixels = 16 } } }
+///
+/// [[operations]]
+/// round-corners = "circle"
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RoundCorners {
+	Radius(Unit),
+	Circle,
+}
+
+impl Process for RoundCorners {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (width, height) = image.dimensions();
+		let shortest = width.min(height);
+		let (w, h) = (width as f32, height as f32);
+
+		let image = match self {
+			Self::Circle => {
+				let radius = shortest as f32 / 2.0;
+				mask_alpha(image, |x, y| {
+					circle_coverage(x, y, w / 2.0, h / 2.0, radius)
+				})
+			}
+			Self::Radius(radius) => {
+				let radius = radius
+					.as_pixel(PixelUnit::from(shortest), (width, height))
+					.pixels;
+				if radius == 0 {
+					return Ok(image);
+				}
+				let radius = radius.min(shortest / 2) as f32;
+
+				mask_alpha(image, |x, y| {
+					let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+					let center_x = if px < radius {
+						radius
+					} else if px > w - radius {
+						w - radius
+					} else {
+						return 1.0;
+					};
+					let center_y = if py < radius {
+						radius
+					} else if py > h - radius {
+						h - radius
+					} else {
+						return 1.0;
+					};
+					circle_coverage(x, y, center_x, center_y, radius)
+				})
+			}
+		};
+
+		Ok(image)
+	}
+}
+
+/// Blue and red difference chroma of normalized RGB. Leaving out luma makes uneven lighting on a
+/// backdrop matter less.
+fn chroma([r, g, b]: [f32; 3]) -> [f32; 2] {
+	let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+	[(b - luma) * 0.564, (r - luma) * 0.713]
+}
+
+/// Makes pixels close in chroma to `color` transparent. Pixels within `tolerance` of it become
+/// fully transparent, and those up to `softness` further away fade back to opaque. Both are
+/// between 0 and 1.
+///
+/// ```toml
+/// [[operations]]
+/// chroma-key = { color = "#00ff00", tolerance = 0.2, softness = 0.1 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ChromaKey {
+	pub color: Color,
+	#[serde(default = "ChromaKey::default_tolerance")]
+	pub tolerance: f32,
+	#[serde(default = "ChromaKey::default_softness")]
+	pub softness: f32,
+}
+
+impl ChromaKey {
+	fn default_tolerance() -> f32 {
+		0.2
+	}
+
+	fn default_softness() -> f32 {
+		0.1
+	}
+}
+
+impl Process for ChromaKey {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !(0.0..=1.0).contains(&self.tolerance) || !(0.0..=1.0).contains(&self.softness) {
+			return Err(OperationError::new(format!(
+				"Chroma key tolerance and softness must be between 0 and 1, got {} and {}",
+				self.tolerance, self.softness
+			)));
+		}
+
+		let key = chroma([self.color.r, self.color.g, self.color.b].map(|c| c as f32 / 255.0));
+		// The largest possible chroma distance, between green and magenta
+		let scale = {
+			let [cb, cr] = chroma([0.0, 1.0, 0.0]);
+			2.0 * cb.hypot(cr)
+		};
+		let (tolerance, softness) = (self.tolerance, self.softness);
+
+		let rgb = image.to_rgb32f();
+		let coverage = |x, y| {
+			let [cb, cr] = chroma(rgb.get_pixel(x, y).0);
+			let distance = (cb - key[0]).hypot(cr - key[1]) / scale;
+			if distance <= tolerance {
+				0.0
+			} else if distance >= tolerance + softness {
+				1.0
+			} else {
+				(distance - tolerance) / softness
+			}
+		};
+
+		Ok(mask_alpha(image, coverage))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ChromaKey, RoundCorners};
+	use crate::{Color, PixelUnit, Process, Unit};
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+	#[test]
+	fn round_corners_are_transparent() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 20, Rgb([200, 100, 50])));
+		let rounded = RoundCorners::Radius(Unit::Pixel(PixelUnit::from(8)))
+			.process(image)
+			.unwrap();
+
+		assert_eq!(0, rounded.get_pixel(0, 0)[3]);
+		assert_eq!(0, rounded.get_pixel(39, 19)[3]);
+		assert_eq!(255, rounded.get_pixel(20, 0)[3]);
+		assert_eq!(255, rounded.get_pixel(0, 10)[3]);
+		let edge = rounded.get_pixel(2, 2)[3];
+		assert!(
+			edge > 0 && edge < 255,
+			"expected a partially covered edge, got {edge}"
+		);
+	}
+
+	#[test]
+	fn circle_masks_outside_the_circle() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 20, Rgb([200, 100, 50])));
+		let circle = RoundCorners::Circle.process(image).unwrap();
+
+		assert_eq!(0, circle.get_pixel(5, 10)[3]);
+		assert_eq!(255, circle.get_pixel(20, 10)[3]);
+		assert_eq!(0, circle.get_pixel(11, 1)[3]);
+	}
+
+	#[test]
+	fn chroma_key_removes_the_backdrop() {
+		let image = RgbImage::from_fn(4, 1, |x, _| match x {
+			0 => Rgb([0, 255, 0]),
+			1 => Rgb([40, 220, 50]),
+			_ => Rgb([200, 60, 90]),
+		});
+		let keyed = ChromaKey {
+			color: Color::rgba(0, 255, 0, 255),
+			tolerance: 0.2,
+			softness: 0.1,
+		}
+		.process(DynamicImage::ImageRgb8(image))
+		.unwrap();
+
+		assert_eq!(0, keyed.get_pixel(0, 0)[3]);
+		assert_eq!(0, keyed.get_pixel(1, 0)[3]);
+		assert_eq!(255, keyed.get_pixel(2, 0)[3]);
+	}
+}
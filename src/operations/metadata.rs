@@ -0,0 +1,104 @@
+use crate::{OperationError, Process};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// XMP prefixes which can be used without declaring their namespace.
+const NAMESPACES: &[(&str, &str)] = &[
+	("dc", "http://purl.org/dc/elements/1.1/"),
+	("xmp", "http://ns.adobe.com/xap/1.0/"),
+	("xmpRights", "http://ns.adobe.com/xap/1.0/rights/"),
+	("photoshop", "http://ns.adobe.com/photoshop/1.0/"),
+	(
+		"Iptc4xmpCore",
+		"http://iptc.org/std/Iptc4xmpCore/1.0/xmlns/",
+	),
+	("plus", "http://ns.useplus.org/ldf/xmp/1.0/"),
+];
+
+/// Metadata of the source which `strip-metadata` can keep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetadataField {
+	Copyright,
+	Artist,
+	Orientation,
+	CaptureDate,
+	/// Camera make and model, and the lens
+	Camera,
+	/// The embedded ICC profile
+	ColorProfile,
+}
+
+/// Leaves the EXIF, XMP, IPTC and ICC metadata of the source out of the output, except for the
+/// fields in `keep`. Kept fields are written as EXIF and an ICC profile, whether or not
+/// `keep_metadata` is set, and replace the copy it would otherwise make. The last `strip-metadata`
+/// in a pipeline applies.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct StripMetadata {
+	#[serde(default)]
+	pub keep: Vec<MetadataField>,
+}
+
+impl Process for StripMetadata {
+	/// Metadata is written by the pipeline after encoding, so there's nothing to do to the pixels.
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		Ok(image)
+	}
+}
+
+/// Writes attribution into the output, over whatever metadata it keeps of the source, even after
+/// `strip-metadata`. Copyright, artist and description are written as both EXIF and XMP, and
+/// `xmp` holds further XMP properties by their prefixed name, such as `photoshop:Credit`.
+/// Prefixes other than `dc`, `xmp`, `xmpRights`, `photoshop`, `Iptc4xmpCore` and `plus` need their
+/// namespace URI in `namespaces`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SetMetadata {
+	pub copyright: Option<String>,
+	pub artist: Option<String>,
+	pub description: Option<String>,
+	#[serde(default)]
+	pub xmp: BTreeMap<String, String>,
+	/// Namespace URIs by prefix
+	#[serde(default)]
+	pub namespaces: BTreeMap<String, String>,
+}
+
+impl SetMetadata {
+	/// Namespace URI of an XMP prefix, either declared or a common one.
+	pub(crate) fn namespace(&self, prefix: &str) -> Option<&str> {
+		self.namespaces.get(prefix).map(String::as_str).or_else(|| {
+			NAMESPACES
+				.iter()
+				.find(|(known, _)| *known == prefix)
+				.map(|(_, namespace)| *namespace)
+		})
+	}
+}
+
+impl Process for SetMetadata {
+	/// Metadata is written by the pipeline after encoding, so this only checks that the XMP
+	/// properties have valid names with a known namespace.
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let is_name = |name: &str| {
+			name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+				&& name
+					.chars()
+					.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+		};
+		for name in self.xmp.keys() {
+			match name.split_once(':') {
+				Some((prefix, local))
+					if is_name(prefix) && is_name(local) && self.namespace(prefix).is_some() => {}
+				_ => {
+					return Err(OperationError::new(format!(
+						"XMP property `{name}` needs a prefix with a known namespace"
+					)))
+				}
+			}
+		}
+		Ok(image)
+	}
+}
@@ -1,15 +1,66 @@
+mod blur;
+mod canvas;
+mod carve;
+mod color;
+mod conditional;
 mod crop;
+mod curves;
+mod dither;
+mod edges;
+#[cfg(feature = "face-detect")]
+mod face;
+mod filter;
+mod levels;
+mod mask;
+mod metadata;
+mod noise;
+mod overlay;
+#[cfg(feature = "plugins")]
+mod plugin;
+#[cfg(feature = "qr")]
+mod qr_code;
 mod resize;
+mod stats;
+#[cfg(feature = "text")]
+mod text;
+mod transform;
 
 use image::DynamicImage;
 use serde::{Deserialize, Serialize};
 
-use crate::{OperationError, Process};
+use crate::{simd, ImageInfo, OperationError, Process};
 
-pub use crop::Crop;
-pub use resize::Resize;
+pub use blur::TiltShift;
+pub use canvas::{DropShadow, Extend, Pad, PadToAspect};
+pub use carve::SeamCarve;
+pub use color::{BlendMode, GradientMap, Saturation, Sepia, Solarize, Tint, WhiteBalance};
+pub use conditional::{Comparison, Condition, Conditional};
+pub use crop::{Crop, CropGravity, CropOrigin, CropToAspect, SmartCrop, Trim};
+pub use curves::Curves;
+pub use dither::{Dither, DitherAlgorithm};
+pub use edges::{EdgeDetect, EdgeMethod, EdgeOutput};
+#[cfg(feature = "face-detect")]
+pub use face::FaceCrop;
+pub use filter::{
+	BilateralFilter, ChromaticAberration, Convolve, Kernel, KernelPreset, MedianFilter, OilPaint,
+	Sharpen,
+};
+pub use levels::{AutoContrast, AutoContrastMode, Clahe};
+pub use mask::{ChromaKey, RoundCorners};
+pub use metadata::{MetadataField, SetMetadata, StripMetadata};
+pub use noise::{Noise, NoiseKind};
+pub use overlay::Overlay;
+#[cfg(feature = "plugins")]
+pub use plugin::Plugin;
+#[cfg(feature = "qr")]
+pub use qr_code::{QrCode, QrCodeAction};
+pub use resize::{CropMode, FilterType, Resize, Scale, Thumbnail};
+pub use stats::{HistogramSummary, ImageStats, Stats};
+#[cfg(feature = "text")]
+pub use text::{DrawText, TextAlign, TextShadow, TextStroke};
+pub use transform::{Perspective, PerspectiveDirection, RotateDegrees, Shear};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Grayscale {}
 
@@ -19,7 +70,26 @@ impl Process for Grayscale {
 	}
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Rotates and flips the image upright using the EXIF orientation of the source file. Images
+/// without an orientation are left as they are. Only the first orientation in a pipeline has an
+/// effect, including the one applied by `auto_orient`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct AutoOrient {}
+
+impl Process for AutoOrient {
+	/// The orientation is read from the source by the pipeline, so on its own this has nothing to
+	/// apply.
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		Ok(image)
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		Ok(Some(image.upright().dimensions()))
+	}
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Blur {
 	pub sigma: f32,
@@ -27,11 +97,11 @@ pub struct Blur {
 
 impl Process for Blur {
 	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
-		Ok(image.blur(self.sigma))
+		blur::blur(&image, self.sigma)
 	}
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum AdjustBrightness {
 	Darken(u16),
@@ -45,13 +115,74 @@ impl Process for AdjustBrightness {
 			Self::Brighten(value) => *value as i32,
 		};
 
-		Ok(image.brighten(value))
+		let image = match image {
+			DynamicImage::ImageLuma8(mut buffer) => {
+				simd::brighten(&mut buffer, 1, value);
+				DynamicImage::ImageLuma8(buffer)
+			}
+			DynamicImage::ImageLumaA8(mut buffer) => {
+				simd::brighten(&mut buffer, 2, value);
+				DynamicImage::ImageLumaA8(buffer)
+			}
+			DynamicImage::ImageRgb8(mut buffer) => {
+				simd::brighten(&mut buffer, 3, value);
+				DynamicImage::ImageRgb8(buffer)
+			}
+			DynamicImage::ImageRgba8(mut buffer) => {
+				simd::brighten(&mut buffer, 4, value);
+				DynamicImage::ImageRgba8(buffer)
+			}
+			image => image.brighten(value),
+		};
+
+		Ok(image)
+	}
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Flip {
+	/// Mirror left to right
+	Horizontal,
+	/// Mirror top to bottom
+	Vertical,
+	/// Mirror along both axes, the same as rotating by 180 degrees
+	Both,
+}
+
+impl Process for Flip {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let image = match self {
+			Self::Horizontal => image.fliph(),
+			Self::Vertical => image.flipv(),
+			Self::Both => image.rotate180(),
+		};
+
+		Ok(image)
+	}
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct HueRotate {
+	/// Degrees to rotate the hue by, normalized into 0–360
+	pub degrees: i32,
+}
+
+impl Process for HueRotate {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		// Gray has no hue to rotate
+		if !image.color().has_color() {
+			return Ok(image);
+		}
+
+		Ok(image.huerotate(self.degrees.rem_euclid(360)))
 	}
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub struct Invert;
+pub struct Invert {}
 
 impl Process for Invert {
 	fn process(&self, mut image: DynamicImage) -> Result<DynamicImage, OperationError> {
@@ -60,7 +191,7 @@ impl Process for Invert {
 	}
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Unsharpen {
 	pub sigma: f32,
@@ -73,7 +204,125 @@ impl Process for Unsharpen {
 	}
 }
 
-// TODO next filter3x3
-// TODO - include predefined kernels for sharpening and shit?
-// See: https://programmathically.com/understanding-convolutional-filters-and-convolutional-kernels/
-// Or use guassian and box kernels for blur, and maybe the sharpen filter for `Sharpen`
+#[cfg(test)]
+mod tests {
+	use super::{AutoOrient, HueRotate, Invert, Unsharpen};
+	use crate::{process_bytes, Operation, Pipeline, Process};
+	use image::{
+		DynamicImage, GenericImageView, GrayImage, ImageOutputFormat, Luma, Rgb, RgbImage,
+	};
+	use serde::Deserialize;
+	use std::io::Cursor;
+
+	#[derive(Deserialize)]
+	struct Config {
+		operations: Vec<Operation>,
+	}
+
+	/// A 16x8 JPEG, red on the left and blue on the right, tagged with an EXIF `orientation`.
+	fn oriented_jpeg(orientation: u16) -> Vec<u8> {
+		let image = RgbImage::from_fn(16, 8, |x, _| match x {
+			0..=7 => Rgb([255, 0, 0]),
+			_ => Rgb([0, 0, 255]),
+		});
+		let mut jpeg = Cursor::new(Vec::new());
+		DynamicImage::ImageRgb8(image)
+			.write_to(&mut jpeg, ImageOutputFormat::Jpeg(90))
+			.unwrap();
+		let jpeg = jpeg.into_inner();
+
+		// A big-endian TIFF header and an IFD with only the orientation
+		let exif = [
+			b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".as_slice(),
+			&orientation.to_be_bytes(),
+			&[0; 6],
+		]
+		.concat();
+		let length = (exif.len() as u16 + 2).to_be_bytes();
+		[&jpeg[..2], &[0xff, 0xe1], &length, &exif, &jpeg[2..]].concat()
+	}
+
+	#[test]
+	fn auto_orients_from_exif() {
+		let jpeg = oriented_jpeg(6);
+		let auto_orient = || Operation::AutoOrient(AutoOrient {});
+
+		assert_eq!(
+			(16, 8),
+			process_bytes(&jpeg, Vec::new()).unwrap().dimensions()
+		);
+		// Turned a quarter clockwise, with red at the top. Only the first one has an effect.
+		let upright = process_bytes(&jpeg, vec![auto_orient(), auto_orient()]).unwrap();
+		assert_eq!((8, 16), upright.dimensions());
+		assert!(upright.get_pixel(4, 2).0[0] > 200);
+		assert!(upright.get_pixel(4, 13).0[2] > 200);
+
+		let pipeline: Pipeline =
+			serde_json::from_str(r#"{ "out_format": "png", "auto_orient": true }"#).unwrap();
+		let output = image::load_from_memory(&pipeline.process_bytes(&jpeg).unwrap()).unwrap();
+		assert_eq!((8, 16), output.dimensions());
+	}
+
+	#[test]
+	fn deserialize_invert_and_unsharpen() {
+		let config: Config = toml::from_str(
+			r#"
+			[[operations]]
+			invert = {}
+
+			[[operations]]
+			unsharpen = { sigma = 1.5, threshold = 4 }
+			"#,
+		)
+		.unwrap();
+
+		assert!(matches!(config.operations[0], Operation::Invert(Invert {})));
+		assert!(matches!(
+			config.operations[1],
+			Operation::Unsharpen(Unsharpen {
+				sigma,
+				threshold: 4
+			}) if sigma == 1.5
+		));
+	}
+
+	#[test]
+	fn invert_and_unsharpen_round_trip() {
+		let operations = vec![
+			Operation::Invert(Invert {}),
+			Operation::Unsharpen(Unsharpen {
+				sigma: 2.0,
+				threshold: 10,
+			}),
+		];
+
+		let json = serde_json::to_string(&operations).unwrap();
+		assert_eq!(
+			r#"[{"invert":{}},{"unsharpen":{"sigma":2.0,"threshold":10}}]"#,
+			json
+		);
+
+		let operations: Vec<Operation> = serde_json::from_str(&json).unwrap();
+		assert!(matches!(operations[0], Operation::Invert(_)));
+		assert!(matches!(
+			operations[1],
+			Operation::Unsharpen(Unsharpen { threshold: 10, .. })
+		));
+	}
+
+	#[test]
+	fn hue_rotate_keeps_gray_images() {
+		let image = DynamicImage::ImageLuma8(GrayImage::from_pixel(2, 2, Luma([90])));
+		let rotated = HueRotate { degrees: 120 }.process(image).unwrap();
+		assert_eq!(&[90; 4], rotated.as_luma8().unwrap().as_raw().as_slice());
+	}
+
+	#[test]
+	fn hue_rotate_normalizes_degrees() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb([200, 40, 40])));
+		let forward = HueRotate { degrees: 120 }.process(image.clone()).unwrap();
+		let backward = HueRotate { degrees: -240 }.process(image.clone()).unwrap();
+		assert_eq!(image.huerotate(120).as_bytes(), forward.as_bytes());
+		assert_eq!(forward.as_bytes(), backward.as_bytes());
+	}
+}
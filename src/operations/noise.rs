@@ -0,0 +1,101 @@
+use super::color::{map_rgb, with_color};
+use crate::{OperationError, Process};
+use image::DynamicImage;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::hash_map::RandomState,
+	f32::consts::TAU,
+	hash::{BuildHasher, Hasher},
+};
+
+/// Adds random noise to the color channels. The same `seed` always produces the same noise.
+///
+/// ```toml
+/// [[operations]]
+/// noise = { kind = "gaussian", amount = 0.05, seed = 7 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Noise {
+	pub kind: NoiseKind,
+	/// Between 0 and 1, see [`NoiseKind`]
+	pub amount: f32,
+	#[serde(default)]
+	pub seed: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NoiseKind {
+	/// Normally distributed noise with a standard deviation of `amount` of the channel range
+	Gaussian,
+	/// Sets a fraction of `amount` pixels to black or white
+	SaltAndPepper,
+}
+
+impl Process for Noise {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !(0.0..=1.0).contains(&self.amount) {
+			return Err(OperationError::new(format!(
+				"Noise amount must be between 0 and 1, got {}",
+				self.amount
+			)));
+		}
+
+		let seed = self
+			.seed
+			.unwrap_or_else(|| RandomState::new().build_hasher().finish());
+		let mut rng = ChaCha8Rng::seed_from_u64(seed);
+		let amount = self.amount;
+
+		let image = match self.kind {
+			NoiseKind::Gaussian => map_rgb(with_color(image), |rgb| {
+				rgb.map(|channel| channel + gaussian(&mut rng) * amount)
+			}),
+			NoiseKind::SaltAndPepper => map_rgb(image, |rgb| {
+				if rng.gen::<f32>() < amount {
+					[if rng.gen() { 1.0 } else { 0.0 }; 3]
+				} else {
+					rgb
+				}
+			}),
+		};
+
+		Ok(image)
+	}
+}
+
+/// Samples the standard normal distribution with the Box–Muller transform.
+fn gaussian<R: Rng>(rng: &mut R) -> f32 {
+	let u1 = 1.0 - rng.gen::<f32>();
+	let u2 = rng.gen::<f32>();
+	(-2.0 * u1.ln()).sqrt() * (TAU * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Noise, NoiseKind};
+	use crate::Process;
+	use image::{DynamicImage, Rgb, RgbImage};
+
+	fn noise(kind: NoiseKind, seed: u64) -> DynamicImage {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 16, Rgb([128, 128, 128])));
+		Noise {
+			kind,
+			amount: 0.2,
+			seed: Some(seed),
+		}
+		.process(image)
+		.unwrap()
+	}
+
+	#[test]
+	fn seeded_noise_is_deterministic() {
+		for kind in [NoiseKind::Gaussian, NoiseKind::SaltAndPepper] {
+			assert_eq!(noise(kind, 1), noise(kind, 1));
+			assert_ne!(noise(kind, 1), noise(kind, 2));
+		}
+	}
+}
@@ -0,0 +1,236 @@
+use super::mask::mask_alpha;
+use crate::{Coordinate, Gravity, OperationError, PixelUnit, Process, Unit};
+use image::{imageops, DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::OnceLock};
+
+/// Composites `overlay` onto `image` with its top left corner at `(x, y)`, keeping the bit depth of
+/// the image. Opaque images stay opaque.
+pub(crate) fn composite(
+	image: DynamicImage,
+	overlay: &DynamicImage,
+	x: i64,
+	y: i64,
+) -> DynamicImage {
+	let opaque = !image.color().has_alpha();
+	let image = match image {
+		DynamicImage::ImageLuma16(_)
+		| DynamicImage::ImageLumaA16(_)
+		| DynamicImage::ImageRgb16(_)
+		| DynamicImage::ImageRgba16(_) => {
+			let mut base = image.into_rgba16();
+			imageops::overlay(&mut base, &overlay.to_rgba16(), x, y);
+			DynamicImage::ImageRgba16(base)
+		}
+		DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+			let mut base = image.into_rgba32f();
+			imageops::overlay(&mut base, &overlay.to_rgba32f(), x, y);
+			DynamicImage::ImageRgba32F(base)
+		}
+		image => {
+			let mut base = image.into_rgba8();
+			imageops::overlay(&mut base, &overlay.to_rgba8(), x, y);
+			DynamicImage::ImageRgba8(base)
+		}
+	};
+
+	match (opaque, image) {
+		(true, DynamicImage::ImageRgba16(base)) => {
+			DynamicImage::ImageRgb16(DynamicImage::ImageRgba16(base).into_rgb16())
+		}
+		(true, DynamicImage::ImageRgba32F(base)) => {
+			DynamicImage::ImageRgb32F(DynamicImage::ImageRgba32F(base).into_rgb32f())
+		}
+		(true, image) => DynamicImage::ImageRgb8(image.into_rgb8()),
+		(false, image) => image,
+	}
+}
+
+/// Composites another image, such as a watermark, onto the working image. The overlay is anchored
+/// by `gravity` and moved away from the anchored edges by `offset`. Percentages of the offset and
+/// `width` are of the working image.
+///
+/// The overlay file is read the first time the operation is processed and reused afterwards.
+///
+/// ```toml
+/// [[operations]]
+/// overlay = { path = "logo.png", gravity = "bottom-right", offset = { x = { pixel = { pixels = 16 } }, y = { pixel = { pixels = 16 } } }, opacity = 0.8, width = { percentage = { percentage = 0.2 } } }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Overlay {
+	pub path: PathBuf,
+	#[serde(default)]
+	pub gravity: Gravity,
+	#[serde(default)]
+	pub offset: Coordinate,
+	/// Between 0 and 1, defaults to 1
+	#[serde(default = "Overlay::default_opacity")]
+	pub opacity: f32,
+	/// Scales the overlay to this width, keeping its aspect ratio
+	#[serde(default)]
+	pub width: Option<Unit>,
+	#[serde(skip)]
+	source: OnceLock<DynamicImage>,
+}
+
+impl Overlay {
+	pub fn new(path: impl Into<PathBuf>) -> Self {
+		Self {
+			path: path.into(),
+			gravity: Gravity::default(),
+			offset: Coordinate::default(),
+			opacity: Self::default_opacity(),
+			width: None,
+			source: OnceLock::new(),
+		}
+	}
+
+	fn default_opacity() -> f32 {
+		1.0
+	}
+
+	fn source(&self) -> Result<&DynamicImage, OperationError> {
+		if let Some(source) = self.source.get() {
+			return Ok(source);
+		}
+
+		let source = image::open(&self.path).map_err(|error| {
+			OperationError::new(format!(
+				"Unable to read overlay {}: {error}",
+				self.path.display()
+			))
+		})?;
+
+		Ok(self.source.get_or_init(|| source))
+	}
+}
+
+impl Process for Overlay {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		if !(0.0..=1.0).contains(&self.opacity) {
+			return Err(OperationError::new(format!(
+				"Overlay opacity must be between 0 and 1, got {}",
+				self.opacity
+			)));
+		}
+
+		let (width, height) = image.dimensions();
+		let mut overlay = self.source()?.clone();
+		if let Some(overlay_width) = &self.width {
+			let overlay_width = overlay_width
+				.as_pixel(PixelUnit::from(width), (width, height))
+				.pixels
+				.max(1);
+			overlay = overlay.resize(overlay_width, u32::MAX, imageops::FilterType::Lanczos3);
+		}
+		if self.opacity < 1.0 {
+			let opacity = self.opacity;
+			overlay = mask_alpha(overlay, |_, _| opacity);
+		}
+
+		let offset = (
+			self.offset
+				.x
+				.as_pixel(PixelUnit::from(width), (width, height))
+				.pixels,
+			self.offset
+				.y
+				.as_pixel(PixelUnit::from(height), (width, height))
+				.pixels,
+		);
+		let (x, y) = self
+			.gravity
+			.position((width, height), overlay.dimensions(), offset);
+
+		Ok(composite(image, &overlay, x, y))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Overlay;
+	use crate::{Gravity, Process};
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage, Rgba, RgbaImage};
+
+	/// An overlay of `overlay` without reading a file.
+	fn overlay(overlay: DynamicImage, gravity: Gravity, opacity: f32) -> Overlay {
+		let operation = Overlay {
+			gravity,
+			opacity,
+			..Overlay::new("unused.png")
+		};
+		operation.source.set(overlay).unwrap();
+		operation
+	}
+
+	#[test]
+	fn gravity_positions() {
+		let position = |gravity: Gravity| gravity.position((100, 80), (20, 10), (4, 2));
+		assert_eq!((4, 2), position(Gravity::TopLeft));
+		assert_eq!((44, 2), position(Gravity::Top));
+		assert_eq!((76, 2), position(Gravity::TopRight));
+		assert_eq!((4, 37), position(Gravity::Left));
+		assert_eq!((44, 37), position(Gravity::Center));
+		assert_eq!((76, 37), position(Gravity::Right));
+		assert_eq!((4, 68), position(Gravity::BottomLeft));
+		assert_eq!((44, 68), position(Gravity::Bottom));
+		assert_eq!((76, 68), position(Gravity::BottomRight));
+
+		// Items larger than the area start before it
+		assert_eq!(
+			(-10, -5),
+			Gravity::Center.position((10, 10), (30, 20), (0, 0))
+		);
+		assert_eq!(
+			(-20, -10),
+			Gravity::BottomRight.position((10, 10), (30, 20), (0, 0))
+		);
+	}
+
+	#[test]
+	fn places_overlay() {
+		let base = DynamicImage::ImageRgb8(RgbImage::new(10, 8));
+		let white = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 3, Rgb([255, 255, 255])));
+
+		let result = overlay(white, Gravity::BottomRight, 1.0)
+			.process(base)
+			.unwrap();
+		assert!(matches!(result, DynamicImage::ImageRgb8(_)));
+		let white = |x: u32, y: u32| result.get_pixel(x, y).0 == [255, 255, 255, 255];
+		assert!(white(6, 5) && white(9, 7));
+		assert!(!white(5, 7) && !white(9, 4));
+	}
+
+	#[test]
+	fn blends_alpha() {
+		let base = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+		// Half transparent on the left, opaque on the right
+		let red = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, _| {
+			Rgba([255, 0, 0, if x < 2 { 128 } else { 255 }])
+		}));
+
+		let result = overlay(red.clone(), Gravity::TopLeft, 1.0)
+			.process(base.clone())
+			.unwrap();
+		assert_eq!([128, 0, 0, 255], result.get_pixel(0, 0).0);
+		assert_eq!([255, 0, 0, 255], result.get_pixel(3, 0).0);
+
+		// Opacity scales the alpha of the overlay
+		let result = overlay(red, Gravity::TopLeft, 0.5).process(base).unwrap();
+		let faded = result.get_pixel(3, 0).0[0];
+		assert!((126..=129).contains(&faded), "{faded}");
+	}
+
+	#[test]
+	fn clips_larger_overlays() {
+		let base = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+		let large =
+			DynamicImage::ImageRgb8(RgbImage::from_fn(12, 12, |x, y| Rgb([x as u8, y as u8, 0])));
+
+		let result = overlay(large, Gravity::Center, 1.0).process(base).unwrap();
+		assert_eq!((4, 4), result.dimensions());
+		assert_eq!([4, 4, 0, 255], result.get_pixel(0, 0).0);
+		assert_eq!([7, 7, 0, 255], result.get_pixel(3, 3).0);
+	}
+}
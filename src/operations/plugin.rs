@@ -0,0 +1,24 @@
+use crate::{plugins, ImageInfo, OperationError, Process};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+/// Runs an operation registered by a loaded plugin, see [`crate::plugins`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Plugin {
+	pub name: String,
+	/// Passed to the plugin encoded as JSON
+	#[serde(default)]
+	pub params: serde_json::Value,
+}
+
+impl Process for Plugin {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		plugins::process(&self.name, &self.params.to_string(), image)
+	}
+
+	/// Plugins can return any size.
+	fn plan(&self, _image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		Ok(None)
+	}
+}
@@ -0,0 +1,66 @@
+use crate::{analysis::detect_qr_codes, ImageInfo, OperationError, Process};
+use image::{imageops, DynamicImage};
+use serde::{Deserialize, Serialize};
+
+/// Finds QR codes in the image and crops to or blurs them. Images without QR codes are left
+/// unchanged.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct QrCode {
+	pub action: QrCodeAction,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QrCodeAction {
+	/// Crop to the area containing all detected codes
+	Crop,
+	/// Blur each detected code, e.g. for privacy
+	Blur { sigma: f32 },
+}
+
+impl Process for QrCode {
+	fn process(&self, mut image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let regions = detect_qr_codes(&image, false);
+		if regions.is_empty() {
+			return Ok(image);
+		}
+
+		match self.action {
+			QrCodeAction::Crop => {
+				let left = regions.iter().map(|region| region.x).min().unwrap_or(0);
+				let top = regions.iter().map(|region| region.y).min().unwrap_or(0);
+				let right = regions
+					.iter()
+					.map(|region| region.x + region.width)
+					.max()
+					.unwrap_or(0);
+				let bottom = regions
+					.iter()
+					.map(|region| region.y + region.height)
+					.max()
+					.unwrap_or(0);
+
+				Ok(image.crop_imm(left, top, right - left, bottom - top))
+			}
+			QrCodeAction::Blur { sigma } => {
+				for region in regions {
+					let blurred = image
+						.crop_imm(region.x, region.y, region.width, region.height)
+						.blur(sigma);
+					imageops::replace(&mut image, &blurred, region.x as i64, region.y as i64);
+				}
+
+				Ok(image)
+			}
+		}
+	}
+
+	/// Cropping depends on where the codes are found.
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		Ok(match self.action {
+			QrCodeAction::Crop => None,
+			QrCodeAction::Blur { .. } => Some(image.dimensions()),
+		})
+	}
+}
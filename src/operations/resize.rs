@@ -1,20 +1,26 @@
-use crate::{OperationError, PixelUnit, Process, Unit};
+use super::carve::{check_size, seam_carve};
+use crate::{ImageInfo, OperationError, PixelUnit, Process, Unit};
 use image::{DynamicImage, GenericImageView};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Resize {
-	pub width: Unit,
-	pub height: Unit,
+	/// Follows the aspect ratio of the image from `height` when left out
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub width: Option<Unit>,
+	/// Follows the aspect ratio of the image from `width` when left out
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub height: Option<Unit>,
 	pub filter: FilterType,
 	pub crop_mode: CropMode,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum FilterType {
 	/// Nearest Neighbor
+	#[default]
 	Nearest,
 	/// Linear Filter
 	Triangle,
@@ -26,12 +32,6 @@ pub enum FilterType {
 	Lanczos3,
 }
 
-impl Default for FilterType {
-	fn default() -> Self {
-		Self::Nearest
-	}
-}
-
 impl From<FilterType> for image::imageops::FilterType {
 	fn from(filter: FilterType) -> Self {
 		match filter {
@@ -44,38 +44,445 @@ impl From<FilterType> for image::imageops::FilterType {
 	}
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum CropMode {
+	/// Fits inside the size, keeping the aspect ratio
+	#[serde(alias = "fit-inside")]
 	Preserve,
 	Fill,
 	Exact,
+	/// Reaches the exact size by removing or inserting seams through the least detailed parts of
+	/// the image, like [`SeamCarve`](super::SeamCarve)
+	SeamCarve,
+	/// Like `preserve`, but images which already fit are left as they are rather than upscaled
+	ShrinkOnly,
+	/// Like `preserve`, but only upscales images which fit inside the size
+	EnlargeOnly,
+}
+
+/// Resizes `image` to exactly `width` by `height`, cropping the middle out of it first when
+/// `fill`ing so that it keeps its aspect ratio.
+fn resize_exact(
+	image: DynamicImage,
+	width: u32,
+	height: u32,
+	filter: FilterType,
+	fill: bool,
+) -> DynamicImage {
+	#[cfg(feature = "fast-resize")]
+	if let Some(resized) = fast_resize(&image, width, height, filter, fill) {
+		return resized;
+	}
+
+	if fill {
+		image.resize_to_fill(width, height, filter.into())
+	} else {
+		image.resize_exact(width, height, filter.into())
+	}
+}
+
+/// Resamples 8 bit images with fast_image_resize, whose SIMD convolution is several times quicker
+/// than `image::imageops` for the larger filters. Other images, and empty ones, are left for
+/// `image` to resize.
+#[cfg(feature = "fast-resize")]
+fn fast_resize(
+	image: &DynamicImage,
+	width: u32,
+	height: u32,
+	filter: FilterType,
+	fill: bool,
+) -> Option<DynamicImage> {
+	use fast_image_resize::{
+		images::{Image, ImageRef},
+		FilterType as Filter, PixelType, ResizeAlg, ResizeOptions, Resizer,
+	};
+	use image::ImageBuffer;
+
+	let pixel_type = match image {
+		DynamicImage::ImageLuma8(_) => PixelType::U8,
+		DynamicImage::ImageLumaA8(_) => PixelType::U8x2,
+		DynamicImage::ImageRgb8(_) => PixelType::U8x3,
+		DynamicImage::ImageRgba8(_) => PixelType::U8x4,
+		_ => return None,
+	};
+	if image.width() == 0 || image.height() == 0 || width == 0 || height == 0 {
+		return None;
+	}
+
+	let algorithm = match filter {
+		FilterType::Nearest => ResizeAlg::Nearest,
+		FilterType::Triangle => ResizeAlg::Convolution(Filter::Bilinear),
+		FilterType::CatmullRom => ResizeAlg::Convolution(Filter::CatmullRom),
+		FilterType::Gaussian => ResizeAlg::Convolution(Filter::Gaussian),
+		FilterType::Lanczos3 => ResizeAlg::Convolution(Filter::Lanczos3),
+	};
+	// Alpha isn't premultiplied, the same as `image::imageops`
+	let mut options = ResizeOptions::new().resize_alg(algorithm).use_alpha(false);
+	if fill {
+		options = options.fit_into_destination(None);
+	}
+
+	let source = ImageRef::new(image.width(), image.height(), image.as_bytes(), pixel_type).ok()?;
+	let mut resized = Image::new(width, height, pixel_type);
+	Resizer::new()
+		.resize(&source, &mut resized, &options)
+		.ok()?;
+
+	let buffer = resized.into_vec();
+	Some(match pixel_type {
+		PixelType::U8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, buffer)?),
+		PixelType::U8x2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, buffer)?),
+		PixelType::U8x3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, buffer)?),
+		_ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, buffer)?),
+	})
+}
+
+/// Size of a `width` by `height` image scaled to fit inside `target_width` by `target_height`,
+/// matching [`DynamicImage::resize`].
+pub(crate) fn fit_inside(
+	(width, height): (u32, u32),
+	(target_width, target_height): (u32, u32),
+) -> (u32, u32) {
+	let ratio = (target_width as f64 / width as f64).min(target_height as f64 / height as f64);
+	let fitted = |dimension: u32| ((dimension as f64 * ratio).round() as u64).max(1);
+	let (fitted_width, fitted_height) = (fitted(width), fitted(height));
+
+	if fitted_width > u32::MAX as u64 {
+		let ratio = u32::MAX as f64 / width as f64;
+		(u32::MAX, ((height as f64 * ratio).round() as u32).max(1))
+	} else if fitted_height > u32::MAX as u64 {
+		let ratio = u32::MAX as f64 / height as f64;
+		(((width as f64 * ratio).round() as u32).max(1), u32::MAX)
+	} else {
+		(fitted_width as u32, fitted_height as u32)
+	}
+}
+
+impl Resize {
+	/// Target size in pixels for an image of `width` by `height`.
+	pub(crate) fn size(&self, width: u32, height: u32) -> Result<(u32, u32), OperationError> {
+		let pixels = |unit: &Unit, dimension: u32| {
+			unit.as_pixel(PixelUnit::from(dimension), (width, height))
+				.pixels
+		};
+		// Rounded, and at least a pixel so that extreme aspect ratios don't collapse
+		let follow = |target: u32, from: u32, to: u32| {
+			((target as f64 * to as f64 / from as f64).round() as u32).max(1)
+		};
+
+		match (&self.width, &self.height) {
+			(Some(target_width), Some(target_height)) => {
+				Ok((pixels(target_width, width), pixels(target_height, height)))
+			}
+			(Some(target_width), None) => {
+				let target_width = pixels(target_width, width);
+				Ok((target_width, follow(target_width, width, height)))
+			}
+			(None, Some(target_height)) => {
+				let target_height = pixels(target_height, height);
+				Ok((follow(target_height, height, width), target_height))
+			}
+			(None, None) => Err(OperationError::new(
+				"Resize needs at least one of width and height".to_string(),
+			)),
+		}
+	}
 }
 
 impl Process for Resize {
 	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
 		let (width, height) = image.dimensions();
-		let width = PixelUnit::from(width);
-		let height = PixelUnit::from(height);
+		let (target_width, target_height) = self.size(width, height)?;
+
+		// How much fitting inside the size would scale the image, for the one way modes
+		let fit_scale = || {
+			let scale_x = target_width as f64 / width as f64;
+			let scale_y = target_height as f64 / height as f64;
+			scale_x.min(scale_y)
+		};
+
+		// Like `DynamicImage::resize`, which leaves images already at the size alone
+		let preserve = |image: DynamicImage| {
+			if (target_width, target_height) == (width, height) {
+				return image;
+			}
+			let (width, height) = fit_inside((width, height), (target_width, target_height));
+			resize_exact(image, width, height, self.filter, false)
+		};
 
 		let image = match self.crop_mode {
-			CropMode::Preserve => image.resize(
-				self.width.as_pixel(width).pixels,
-				self.height.as_pixel(height).pixels,
-				self.filter.into(),
-			),
-			CropMode::Exact => image.resize_exact(
-				self.width.as_pixel(width).pixels,
-				self.height.as_pixel(height).pixels,
-				self.filter.into(),
-			),
-			CropMode::Fill => image.resize_to_fill(
-				self.width.as_pixel(width).pixels,
-				self.height.as_pixel(height).pixels,
-				self.filter.into(),
-			),
+			CropMode::Preserve => preserve(image),
+			CropMode::Exact => resize_exact(image, target_width, target_height, self.filter, false),
+			CropMode::Fill => resize_exact(image, target_width, target_height, self.filter, true),
+			CropMode::ShrinkOnly if fit_scale() >= 1.0 => image,
+			CropMode::EnlargeOnly if fit_scale() <= 1.0 => image,
+			CropMode::ShrinkOnly | CropMode::EnlargeOnly => preserve(image),
+			CropMode::SeamCarve => {
+				check_size(target_width, target_height)?;
+				seam_carve(&image, target_width, target_height)?
+			}
 		};
 
 		Ok(image)
 	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		let dimensions = image.dimensions();
+		let target = self.size(image.width, image.height)?;
+		if matches!(self.crop_mode, CropMode::SeamCarve) {
+			check_size(target.0, target.1)?;
+		}
+		if target == dimensions {
+			return Ok(Some(dimensions));
+		}
+
+		let fit_scale =
+			(target.0 as f64 / image.width as f64).min(target.1 as f64 / image.height as f64);
+		Ok(Some(match self.crop_mode {
+			CropMode::Exact | CropMode::Fill | CropMode::SeamCarve => target,
+			CropMode::ShrinkOnly if fit_scale >= 1.0 => dimensions,
+			CropMode::EnlargeOnly if fit_scale <= 1.0 => dimensions,
+			CropMode::Preserve | CropMode::ShrinkOnly | CropMode::EnlargeOnly => {
+				fit_inside(dimensions, target)
+			}
+		}))
+	}
+}
+
+/// Resizes to fit within `width` by `height`, keeping the aspect ratio, with a fast box-style
+/// filter. Much quicker than [`Resize`] for downscaling large images, at slightly lower quality,
+/// which makes it a good fit for previews. With `exact` the aspect ratio is ignored.
+///
+/// ```toml
+/// [[operations]]
+/// thumbnail = { width = { pixel = { pixels = 320 } }, height = { pixel = { pixels = 320 } } }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Thumbnail {
+	pub width: Unit,
+	pub height: Unit,
+	#[serde(default)]
+	pub exact: bool,
+}
+
+impl Thumbnail {
+	fn size(&self, width: u32, height: u32) -> Result<(u32, u32), OperationError> {
+		let pixels = |unit: &Unit, dimension: u32| {
+			unit.as_pixel(PixelUnit::from(dimension), (width, height))
+				.pixels
+		};
+		let width = pixels(&self.width, width);
+		let height = pixels(&self.height, height);
+		if width == 0 || height == 0 {
+			return Err(OperationError::new(format!(
+				"Cannot create a {width}x{height} thumbnail"
+			)));
+		}
+
+		Ok((width, height))
+	}
+}
+
+impl Process for Thumbnail {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (width, height) = self.size(image.width(), image.height())?;
+		Ok(if self.exact {
+			image.thumbnail_exact(width, height)
+		} else {
+			image.thumbnail(width, height)
+		})
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		let size = self.size(image.width, image.height)?;
+		Ok(Some(if self.exact {
+			size
+		} else {
+			fit_inside(image.dimensions(), size)
+		}))
+	}
+}
+
+/// Resizes both dimensions by `factor`, so `2.0` doubles the size and `0.5` halves it.
+///
+/// ```toml
+/// [[operations]]
+/// scale = { factor = 0.5 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Scale {
+	pub factor: f32,
+	/// Defaults to lanczos3
+	#[serde(default = "Scale::default_filter")]
+	pub filter: FilterType,
+}
+
+impl Scale {
+	fn default_filter() -> FilterType {
+		FilterType::Lanczos3
+	}
+
+	fn size(&self, width: u32, height: u32) -> Result<(u32, u32), OperationError> {
+		if !self.factor.is_finite() || self.factor <= 0.0 {
+			return Err(OperationError::new(format!(
+				"Scale factor must be greater than 0, got {}",
+				self.factor
+			)));
+		}
+
+		let scale = |dimension: u32| {
+			let scaled = (dimension as f64 * self.factor as f64).round();
+			if scaled > u32::MAX as f64 {
+				Err(OperationError::new(format!(
+					"Scaling by {} is too large for a {width}x{height} image",
+					self.factor
+				)))
+			} else {
+				Ok((scaled as u32).max(1))
+			}
+		};
+
+		Ok((scale(width)?, scale(height)?))
+	}
+}
+
+impl Process for Scale {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (width, height) = self.size(image.width(), image.height())?;
+		Ok(resize_exact(image, width, height, self.filter, false))
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		self.size(image.width, image.height).map(Some)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{CropMode, FilterType, Resize, Scale, Thumbnail};
+	use crate::{ImageInfo, PixelUnit, Process, Unit};
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+	#[test]
+	fn one_way_modes() {
+		let resize = |crop_mode, size: u32| {
+			Resize {
+				width: Some(Unit::Pixel(PixelUnit::from(size))),
+				height: Some(Unit::Pixel(PixelUnit::from(size))),
+				filter: FilterType::Triangle,
+				crop_mode,
+			}
+			.process(DynamicImage::ImageRgb8(RgbImage::new(40, 20)))
+			.unwrap()
+			.dimensions()
+		};
+
+		assert_eq!((40, 20), resize(CropMode::ShrinkOnly, 100));
+		assert_eq!((20, 10), resize(CropMode::ShrinkOnly, 20));
+		assert_eq!((100, 50), resize(CropMode::EnlargeOnly, 100));
+		assert_eq!((40, 20), resize(CropMode::EnlargeOnly, 20));
+	}
+
+	#[test]
+	fn fills_from_the_middle() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 20, |x, _| match x {
+			0..=19 => Rgb([255, 0, 0]),
+			_ => Rgb([0, 0, 255]),
+		}));
+		let filled = Resize {
+			width: Some(Unit::Pixel(PixelUnit::from(10))),
+			height: Some(Unit::Pixel(PixelUnit::from(10))),
+			filter: FilterType::Triangle,
+			crop_mode: CropMode::Fill,
+		}
+		.process(image)
+		.unwrap();
+
+		assert_eq!((10, 10), filled.dimensions());
+		assert_eq!([255, 0, 0, 255], filled.get_pixel(0, 5).0);
+		assert_eq!([0, 0, 255, 255], filled.get_pixel(9, 5).0);
+	}
+
+	#[test]
+	fn thumbnails() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 20, |x, _| match x {
+			0..=19 => Rgb([255, 0, 0]),
+			_ => Rgb([0, 0, 255]),
+		}));
+		let thumbnail = |width: u32, height: u32, exact| Thumbnail {
+			width: Unit::Pixel(PixelUnit::from(width)),
+			height: Unit::Pixel(PixelUnit::from(height)),
+			exact,
+		};
+
+		let fitted = thumbnail(10, 10, false).process(image.clone()).unwrap();
+		assert_eq!((10, 5), fitted.dimensions());
+		assert_eq!([255, 0, 0, 255], fitted.get_pixel(0, 2).0);
+		assert_eq!([0, 0, 255, 255], fitted.get_pixel(9, 2).0);
+
+		let exact = thumbnail(10, 10, true).process(image.clone()).unwrap();
+		assert_eq!((10, 10), exact.dimensions());
+
+		assert!(thumbnail(0, 10, false).process(image).is_err());
+	}
+
+	#[test]
+	fn follows_aspect_ratio() {
+		let resize = Resize {
+			width: None,
+			height: Some(Unit::Pixel(PixelUnit::from(15))),
+			filter: FilterType::Triangle,
+			crop_mode: CropMode::Exact,
+		};
+		assert_eq!((30, 15), resize.size(40, 20).unwrap());
+		assert_eq!((1, 15), resize.size(1, 1000).unwrap());
+
+		let resize = Resize {
+			width: None,
+			height: None,
+			..resize
+		};
+		assert!(resize.size(40, 20).is_err());
+	}
+
+	#[test]
+	fn scales_by_factor() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 20, Rgb([10, 20, 30])));
+		let scale = |factor| Scale {
+			factor,
+			filter: FilterType::Triangle,
+		};
+
+		let doubled = scale(2.0).process(image.clone()).unwrap();
+		assert_eq!((80, 40), doubled.dimensions());
+		assert_eq!([10, 20, 30, 255], doubled.get_pixel(40, 20).0);
+		assert_eq!(
+			(10, 5),
+			scale(0.25).process(image.clone()).unwrap().dimensions()
+		);
+		assert_eq!(
+			(1, 1),
+			scale(0.001).process(image.clone()).unwrap().dimensions()
+		);
+
+		for invalid in [0.0, -1.0, f32::NAN, f32::INFINITY, 1e30] {
+			assert!(scale(invalid).process(image.clone()).is_err());
+		}
+	}
+
+	#[test]
+	fn seam_carving_to_nothing() {
+		let resize = Resize {
+			width: Some(Unit::Pixel(PixelUnit::from(0))),
+			height: Some(Unit::Pixel(PixelUnit::from(5))),
+			filter: FilterType::Nearest,
+			crop_mode: CropMode::SeamCarve,
+		};
+		let image = DynamicImage::ImageRgb8(RgbImage::new(40, 20));
+		assert!(resize.plan(ImageInfo::of(&image)).is_err());
+		assert!(resize.process(image).is_err());
+	}
 }
@@ -0,0 +1,146 @@
+use crate::{OperationError, Process};
+use image::{DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+
+/// Records statistics about the image at its position in the pipeline, without modifying it.
+///
+/// Snapshots are collected into the [`ProcessingReport`](crate::ProcessingReport).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Stats {
+	/// Name identifying the snapshot in the report
+	pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct HistogramSummary {
+	pub min: u8,
+	pub max: u8,
+	pub mean: f32,
+	pub median: u8,
+	pub std_dev: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ImageStats {
+	pub label: Option<String>,
+	/// Index of the operation in the pipeline
+	pub position: usize,
+	pub width: u32,
+	pub height: u32,
+	pub color_type: String,
+	/// Summary of the luminance histogram
+	pub histogram: HistogramSummary,
+	/// Mean RGBA color
+	pub mean_color: [f32; 4],
+}
+
+impl Stats {
+	pub fn snapshot(&self, position: usize, image: &DynamicImage) -> ImageStats {
+		let (width, height) = image.dimensions();
+		let pixel_count = (width as u64 * height as u64).max(1) as f64;
+
+		let mut histogram = [0u64; 256];
+		for pixel in image.to_luma8().pixels() {
+			histogram[pixel.0[0] as usize] += 1;
+		}
+
+		let mut totals = [0u64; 4];
+		for pixel in image.to_rgba8().pixels() {
+			for (total, value) in totals.iter_mut().zip(pixel.0) {
+				*total += value as u64;
+			}
+		}
+
+		ImageStats {
+			label: self.label.clone(),
+			position,
+			width,
+			height,
+			color_type: format!("{:?}", image.color()),
+			histogram: summarize(&histogram, pixel_count),
+			mean_color: totals.map(|total| (total as f64 / pixel_count) as f32),
+		}
+	}
+}
+
+fn summarize(histogram: &[u64; 256], pixel_count: f64) -> HistogramSummary {
+	let occupied = || (0..=255u8).filter(|value| histogram[*value as usize] > 0);
+
+	let mean = histogram
+		.iter()
+		.enumerate()
+		.map(|(value, count)| value as f64 * *count as f64)
+		.sum::<f64>()
+		/ pixel_count;
+
+	let variance = histogram
+		.iter()
+		.enumerate()
+		.map(|(value, count)| (value as f64 - mean).powi(2) * *count as f64)
+		.sum::<f64>()
+		/ pixel_count;
+
+	let mut seen = 0;
+	let median = (0..=255u8)
+		.find(|value| {
+			seen += histogram[*value as usize];
+			seen as f64 >= pixel_count / 2.0
+		})
+		.unwrap_or(0);
+
+	HistogramSummary {
+		min: occupied().next().unwrap_or(0),
+		max: occupied().next_back().unwrap_or(0),
+		mean: mean as f32,
+		median,
+		std_dev: variance.sqrt() as f32,
+	}
+}
+
+impl Process for Stats {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		Ok(image)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Stats;
+	use image::{DynamicImage, Rgb, RgbImage};
+
+	#[test]
+	fn snapshots_the_image() {
+		// Left half black and right half white
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(4, 2, |x, _| {
+			if x < 2 {
+				Rgb([0, 0, 0])
+			} else {
+				Rgb([255, 255, 255])
+			}
+		}));
+		let stats = Stats {
+			label: Some("source".to_string()),
+		}
+		.snapshot(3, &image);
+
+		assert_eq!(Some("source".to_string()), stats.label);
+		assert_eq!((3, 4, 2), (stats.position, stats.width, stats.height));
+		assert_eq!("Rgb8", stats.color_type);
+		assert_eq!((0, 255), (stats.histogram.min, stats.histogram.max));
+		assert_eq!(127.5, stats.histogram.mean);
+		assert_eq!(0, stats.histogram.median);
+		assert_eq!(127.5, stats.histogram.std_dev);
+		assert_eq!([127.5, 127.5, 127.5, 255.0], stats.mean_color);
+	}
+
+	#[test]
+	fn snapshots_empty_images() {
+		let stats = Stats::default().snapshot(0, &DynamicImage::ImageRgb8(RgbImage::new(0, 0)));
+		assert_eq!((0, 0), (stats.histogram.min, stats.histogram.max));
+		assert_eq!(0.0, stats.histogram.mean);
+		assert_eq!([0.0; 4], stats.mean_color);
+	}
+}
@@ -0,0 +1,339 @@
+use super::overlay::composite;
+use crate::{Color, Coordinate, Gravity, OperationError, PixelUnit, Process, Unit};
+use ab_glyph::{point, Font, FontArc, GlyphId, PxScale, ScaleFont};
+use image::{
+	imageops, DynamicImage, GenericImageView, ImageBuffer, Luma, Pixel, Rgba, Rgba32FImage,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::OnceLock};
+
+/// DejaVu Sans, used when no font file is given. See `fonts/LICENSE-DejaVu`.
+static DEFAULT_FONT: &[u8] = include_bytes!("../../fonts/DejaVuSans.ttf");
+
+type Mask = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TextAlign {
+	#[default]
+	Left,
+	Center,
+	Right,
+}
+
+/// An outline drawn around each glyph.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TextStroke {
+	pub width: u32,
+	/// Defaults to black
+	#[serde(default = "default_color")]
+	pub color: Color,
+}
+
+/// A shadow of the text, offset right and down. Percentages of `x` and `y` are of the font size.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TextShadow {
+	pub x: Unit,
+	pub y: Unit,
+	#[serde(default)]
+	pub sigma: f32,
+	/// Defaults to black
+	#[serde(default = "default_color")]
+	pub color: Color,
+}
+
+fn default_color() -> Color {
+	Color::BLACK
+}
+
+/// Draws text onto the image. Lines are split on newlines and aligned with each other by `align`,
+/// and the block of text is anchored by `gravity` and moved away from the anchored edges by
+/// `offset`. Percentages of `size` are of the image height.
+///
+/// `font` is a TrueType or OpenType file, read the first time the operation is processed and
+/// reused afterwards. DejaVu Sans is used when it is not set.
+///
+/// ```toml
+/// [[operations]]
+/// draw-text = { text = "Hello", size = { percentage = { percentage = 0.1 } }, color = "#ffffff", gravity = "bottom", offset = { x = { pixel = { pixels = 0 } }, y = { pixel = { pixels = 24 } } }, stroke = { width = 2 } }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DrawText {
+	pub text: String,
+	#[serde(default)]
+	pub font: Option<PathBuf>,
+	pub size: Unit,
+	/// Defaults to black
+	#[serde(default = "default_color")]
+	pub color: Color,
+	#[serde(default)]
+	pub gravity: Gravity,
+	#[serde(default)]
+	pub offset: Coordinate,
+	#[serde(default)]
+	pub align: TextAlign,
+	#[serde(default)]
+	pub stroke: Option<TextStroke>,
+	#[serde(default)]
+	pub shadow: Option<TextShadow>,
+	#[serde(skip)]
+	loaded_font: OnceLock<FontArc>,
+}
+
+impl DrawText {
+	pub fn new(text: impl Into<String>, size: Unit) -> Self {
+		Self {
+			text: text.into(),
+			font: None,
+			size,
+			color: default_color(),
+			gravity: Gravity::default(),
+			offset: Coordinate::default(),
+			align: TextAlign::default(),
+			stroke: None,
+			shadow: None,
+			loaded_font: OnceLock::new(),
+		}
+	}
+
+	fn font(&self) -> Result<&FontArc, OperationError> {
+		if let Some(font) = self.loaded_font.get() {
+			return Ok(font);
+		}
+
+		let font = match &self.font {
+			None => FontArc::try_from_slice(DEFAULT_FONT)
+				.map_err(|error| OperationError::new(format!("Invalid default font: {error}")))?,
+			Some(path) => {
+				let data = std::fs::read(path).map_err(|error| {
+					OperationError::new(format!("Unable to read font {}: {error}", path.display()))
+				})?;
+				FontArc::try_from_vec(data).map_err(|error| {
+					OperationError::new(format!("Invalid font {}: {error}", path.display()))
+				})?
+			}
+		};
+
+		Ok(self.loaded_font.get_or_init(|| font))
+	}
+
+	/// Rasterizes the glyph coverage of the text, padded by `padding` on each side.
+	fn rasterize(&self, font: &FontArc, size: f32, padding: u32) -> Mask {
+		let scale = PxScale::from(size);
+		let scaled = font.as_scaled(scale);
+		let lines: Vec<&str> = self.text.lines().collect();
+
+		let line_width = |line: &str| {
+			let mut previous: Option<GlyphId> = None;
+			line.chars().fold(0.0, |width, c| {
+				let id = font.glyph_id(c);
+				let kern = previous.map_or(0.0, |previous| scaled.kern(previous, id));
+				previous = Some(id);
+				width + kern + scaled.h_advance(id)
+			})
+		};
+		let widths: Vec<f32> = lines.iter().map(|line| line_width(line)).collect();
+		let block_width = widths.iter().copied().fold(0.0f32, f32::max);
+		let line_height = scaled.height() + scaled.line_gap();
+		let block_height = line_height * lines.len().saturating_sub(1) as f32 + scaled.height();
+
+		let mut mask = Mask::new(
+			block_width.ceil() as u32 + padding * 2,
+			block_height.ceil() as u32 + padding * 2,
+		);
+		let (mask_width, mask_height) = mask.dimensions();
+
+		for (index, (line, width)) in lines.iter().zip(widths).enumerate() {
+			let mut caret = padding as f32
+				+ match self.align {
+					TextAlign::Left => 0.0,
+					TextAlign::Center => (block_width - width) / 2.0,
+					TextAlign::Right => block_width - width,
+				};
+			let baseline = padding as f32 + index as f32 * line_height + scaled.ascent();
+
+			let mut previous: Option<GlyphId> = None;
+			for c in line.chars() {
+				let id = font.glyph_id(c);
+				if let Some(previous) = previous {
+					caret += scaled.kern(previous, id);
+				}
+				previous = Some(id);
+
+				let glyph = id.with_scale_and_position(scale, point(caret, baseline));
+				caret += scaled.h_advance(id);
+
+				let Some(outlined) = font.outline_glyph(glyph) else {
+					continue;
+				};
+				let bounds = outlined.px_bounds();
+				outlined.draw(|x, y, coverage| {
+					let x = bounds.min.x as i64 + x as i64;
+					let y = bounds.min.y as i64 + y as i64;
+					if (0..mask_width as i64).contains(&x) && (0..mask_height as i64).contains(&y) {
+						let pixel = mask.get_pixel_mut(x as u32, y as u32);
+						pixel[0] = pixel[0].max(coverage);
+					}
+				});
+			}
+		}
+
+		mask
+	}
+}
+
+/// Grows the coverage of a mask by `radius` pixels in every direction.
+fn dilate(mask: &Mask, radius: u32) -> Mask {
+	let radius = radius as i64;
+	let (width, height) = mask.dimensions();
+	let offsets: Vec<(i64, i64)> = (-radius..=radius)
+		.flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+		.filter(|(dx, dy)| dx * dx + dy * dy <= radius * radius)
+		.collect();
+
+	Mask::from_fn(width, height, |x, y| {
+		let coverage = offsets
+			.iter()
+			.filter_map(|(dx, dy)| {
+				let (x, y) = (x as i64 + dx, y as i64 + dy);
+				((0..width as i64).contains(&x) && (0..height as i64).contains(&y))
+					.then(|| mask.get_pixel(x as u32, y as u32)[0])
+			})
+			.fold(0.0f32, f32::max);
+		Luma([coverage])
+	})
+}
+
+/// Blends `color` onto the layer through the coverage of `mask`, placed at `(x, y)`.
+fn paint(layer: &mut Rgba32FImage, mask: &Mask, x: u32, y: u32, color: Color) {
+	let [r, g, b, a] = [color.r, color.g, color.b, color.a].map(|channel| channel as f32 / 255.0);
+	for (mx, my, coverage) in mask.enumerate_pixels() {
+		let coverage = coverage[0].clamp(0.0, 1.0);
+		if coverage > 0.0 {
+			layer
+				.get_pixel_mut(x + mx, y + my)
+				.blend(&Rgba([r, g, b, a * coverage]));
+		}
+	}
+}
+
+impl Process for DrawText {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (width, height) = image.dimensions();
+		let size = self
+			.size
+			.as_pixel(PixelUnit::from(height), (width, height))
+			.pixels;
+		if self.text.trim().is_empty() || size == 0 {
+			return Ok(image);
+		}
+
+		let font = self.font()?;
+		let stroke_width = self.stroke.as_ref().map_or(0, |stroke| stroke.width);
+		let fill = self.rasterize(font, size as f32, stroke_width);
+		let stroke = self
+			.stroke
+			.as_ref()
+			.map(|stroke| (dilate(&fill, stroke.width), stroke.color));
+		let (mask_width, mask_height) = fill.dimensions();
+
+		// The layer holds the text and its shadow, so it grows to fit the shadow's offset and blur.
+		let (shadow_x, shadow_y, margin) = match &self.shadow {
+			Some(shadow) => {
+				if shadow.sigma.is_nan() || shadow.sigma < 0.0 {
+					return Err(OperationError::new(format!(
+						"Text shadow sigma must not be negative, got {}",
+						shadow.sigma
+					)));
+				}
+				(
+					shadow
+						.x
+						.as_pixel(PixelUnit::from(size), (width, height))
+						.pixels,
+					shadow
+						.y
+						.as_pixel(PixelUnit::from(size), (width, height))
+						.pixels,
+					(shadow.sigma * 3.0).ceil() as u32,
+				)
+			}
+			None => (0, 0, 0),
+		};
+		let left = margin.saturating_sub(shadow_x);
+		let top = margin.saturating_sub(shadow_y);
+		let layer_width = left + mask_width + shadow_x + margin;
+		let layer_height = top + mask_height + shadow_y + margin;
+		let mut layer = Rgba32FImage::new(layer_width, layer_height);
+
+		if let Some(shadow) = &self.shadow {
+			let silhouette = stroke.as_ref().map_or(&fill, |(stroke, _)| stroke);
+			let mut shadow_mask = Mask::new(layer_width, layer_height);
+			imageops::replace(
+				&mut shadow_mask,
+				silhouette,
+				(left + shadow_x) as i64,
+				(top + shadow_y) as i64,
+			);
+			if shadow.sigma > 0.0 {
+				shadow_mask = imageops::blur(&shadow_mask, shadow.sigma);
+			}
+			paint(&mut layer, &shadow_mask, 0, 0, shadow.color);
+		}
+		if let Some((stroke, color)) = &stroke {
+			paint(&mut layer, stroke, left, top, *color);
+		}
+		paint(&mut layer, &fill, left, top, self.color);
+
+		let offset = (
+			self.offset
+				.x
+				.as_pixel(PixelUnit::from(width), (width, height))
+				.pixels,
+			self.offset
+				.y
+				.as_pixel(PixelUnit::from(height), (width, height))
+				.pixels,
+		);
+		let (x, y) = self
+			.gravity
+			.position((width, height), (mask_width, mask_height), offset);
+
+		Ok(composite(
+			image,
+			&DynamicImage::ImageRgba32F(layer),
+			x - left as i64,
+			y - top as i64,
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::DrawText;
+	use crate::{Color, Gravity, PixelUnit, Process, Unit};
+	use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+	#[test]
+	fn draws_text_within_the_image() {
+		let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(120, 40, Rgb([0, 0, 0])));
+		let mut text = DrawText::new("Hi", Unit::Pixel(PixelUnit::from(24)));
+		text.color = Color::WHITE;
+		text.gravity = Gravity::Center;
+
+		let drawn = text.process(image).unwrap();
+		assert_eq!((120, 40), drawn.dimensions());
+		assert!(!drawn.color().has_alpha());
+
+		let lit = drawn
+			.to_rgb8()
+			.pixels()
+			.filter(|pixel| pixel[0] > 128)
+			.count();
+		assert!(lit > 20, "expected text to be drawn, {lit} pixels lit");
+		assert_eq!([0, 0, 0, 255], drawn.get_pixel(2, 2).0);
+	}
+}
@@ -0,0 +1,520 @@
+use super::{canvas::with_depth_of, resize::FilterType};
+use crate::{Color, Coordinate, ImageInfo, OperationError, PixelUnit, Process};
+use image::{DynamicImage, GenericImageView, Rgba, Rgba32FImage};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// Weight of a sample `distance` pixels away for a resampling filter, and the filter's radius.
+fn kernel(filter: FilterType) -> (fn(f32) -> f32, i64) {
+	fn sinc(x: f32) -> f32 {
+		if x == 0.0 {
+			1.0
+		} else {
+			(PI * x).sin() / (PI * x)
+		}
+	}
+
+	match filter {
+		FilterType::Nearest | FilterType::Triangle => (|x| (1.0 - x.abs()).max(0.0), 1),
+		FilterType::CatmullRom => (
+			|x| {
+				let x = x.abs();
+				if x < 1.0 {
+					1.5 * x * x * x - 2.5 * x * x + 1.0
+				} else if x < 2.0 {
+					-0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+				} else {
+					0.0
+				}
+			},
+			2,
+		),
+		FilterType::Gaussian => (|x| (-2.0 * x * x).exp(), 2),
+		FilterType::Lanczos3 => (
+			|x| {
+				if x.abs() < 3.0 {
+					sinc(x) * sinc(x / 3.0)
+				} else {
+					0.0
+				}
+			},
+			3,
+		),
+	}
+}
+
+/// Resamples `image` onto a `width` by `height` canvas. `source` maps the center of each output
+/// pixel to a position in the image, where pixel centers are at half pixels. Anything outside the
+/// image is `background`.
+pub(crate) fn warp<F>(
+	image: &DynamicImage,
+	width: u32,
+	height: u32,
+	source: F,
+	filter: FilterType,
+	background: Color,
+) -> DynamicImage
+where
+	F: Fn(f32, f32) -> (f32, f32),
+{
+	let alpha = image.color().has_alpha() || !background.is_opaque();
+
+	// Premultiplied so transparent pixels don't bleed their color into edges
+	let mut input = image.to_rgba32f();
+	for pixel in input.pixels_mut() {
+		let a = pixel[3];
+		for channel in &mut pixel.0[..3] {
+			*channel *= a;
+		}
+	}
+	let background = {
+		let [r, g, b, a] =
+			[background.r, background.g, background.b, background.a].map(|c| c as f32 / 255.0);
+		[r * a, g * a, b * a, a]
+	};
+	let (in_width, in_height) = (input.width() as i64, input.height() as i64);
+	let at = |x: i64, y: i64| {
+		if (0..in_width).contains(&x) && (0..in_height).contains(&y) {
+			input.get_pixel(x as u32, y as u32).0
+		} else {
+			background
+		}
+	};
+	let (weight, radius) = kernel(filter);
+
+	let output = Rgba32FImage::from_fn(width, height, |x, y| {
+		let (sx, sy) = source(x as f32 + 0.5, y as f32 + 0.5);
+		let (u, v) = (sx - 0.5, sy - 0.5);
+
+		let value = if filter == FilterType::Nearest {
+			at(sx.floor() as i64, sy.floor() as i64)
+		} else {
+			let (left, top) = (u.floor() as i64, v.floor() as i64);
+			let mut sum = [0.0f32; 4];
+			let mut total = 0.0;
+			for j in top - radius + 1..=top + radius {
+				let wy = weight(v - j as f32);
+				for i in left - radius + 1..=left + radius {
+					let w = wy * weight(u - i as f32);
+					for (sum, value) in sum.iter_mut().zip(at(i, j)) {
+						*sum += value * w;
+					}
+					total += w;
+				}
+			}
+			sum.map(|sum| if total != 0.0 { sum / total } else { 0.0 })
+		};
+
+		let a = value[3].clamp(0.0, 1.0);
+		if a > 0.0 {
+			Rgba([
+				(value[0] / a).clamp(0.0, 1.0),
+				(value[1] / a).clamp(0.0, 1.0),
+				(value[2] / a).clamp(0.0, 1.0),
+				a,
+			])
+		} else {
+			Rgba([0.0; 4])
+		}
+	});
+
+	with_depth_of(output, image, alpha)
+}
+
+/// Size of the canvas [`warp_affine`] transforms a `width` by `height` image onto.
+fn affine_size((width, height): (u32, u32), [a, b, c, d]: [f32; 4], expand: bool) -> (u32, u32) {
+	if !expand {
+		return (width, height);
+	}
+
+	let (width, height) = (width as f32, height as f32);
+	let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+		.map(|(x, y): (f32, f32)| (x * width / 2.0, y * height / 2.0))
+		.map(|(x, y)| (a * x + b * y, c * x + d * y));
+	let extent = |axis: fn(&(f32, f32)) -> f32| {
+		let (min, max) = corners
+			.iter()
+			.map(axis)
+			.fold((f32::MAX, f32::MIN), |(min, max), v| {
+				(min.min(v), max.max(v))
+			});
+		// Tolerates rounding error so exact quarter turns don't gain a pixel
+		((max - min - 1e-3).ceil() as u32).max(1)
+	};
+	(extent(|corner| corner.0), extent(|corner| corner.1))
+}
+
+/// Applies the linear transform `[a, b, c, d]`, mapping `(x, y)` to `(a x + b y, c x + d y)`,
+/// about the center of the image. With `expand` the canvas grows or shrinks to the bounds of the
+/// transformed image, otherwise it keeps the original size. The transform must be invertible.
+pub(crate) fn warp_affine(
+	image: &DynamicImage,
+	[a, b, c, d]: [f32; 4],
+	expand: bool,
+	filter: FilterType,
+	background: Color,
+) -> DynamicImage {
+	let (width, height) = (image.width() as f32, image.height() as f32);
+	let (out_width, out_height) = affine_size(image.dimensions(), [a, b, c, d], expand);
+
+	let det = a * d - b * c;
+	let inverse = [d / det, -b / det, -c / det, a / det];
+	let (out_cx, out_cy) = (out_width as f32 / 2.0, out_height as f32 / 2.0);
+	warp(
+		image,
+		out_width,
+		out_height,
+		|x, y| {
+			let (x, y) = (x - out_cx, y - out_cy);
+			(
+				inverse[0] * x + inverse[1] * y + width / 2.0,
+				inverse[2] * x + inverse[3] * y + height / 2.0,
+			)
+		},
+		filter,
+		background,
+	)
+}
+
+/// Solves for the projective transform mapping each of `from` to the matching point of `to`.
+fn homography(from: [(f32, f32); 4], to: [(f32, f32); 4]) -> Option<[f64; 9]> {
+	let mut rows = [[0.0f64; 9]; 8];
+	for (i, ((x, y), (u, v))) in from.into_iter().zip(to).enumerate() {
+		let (x, y, u, v) = (x as f64, y as f64, u as f64, v as f64);
+		rows[i * 2] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+		rows[i * 2 + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+	}
+
+	// Gaussian elimination with partial pivoting
+	for column in 0..8 {
+		let pivot =
+			(column..8).max_by(|&a, &b| rows[a][column].abs().total_cmp(&rows[b][column].abs()))?;
+		if rows[pivot][column].abs() < 1e-10 {
+			return None;
+		}
+		rows.swap(column, pivot);
+		let pivot_row = rows[column];
+		for (index, row) in rows.iter_mut().enumerate() {
+			if index != column {
+				let factor = row[column] / pivot_row[column];
+				for (value, pivot) in row.iter_mut().zip(pivot_row).skip(column) {
+					*value -= factor * pivot;
+				}
+			}
+		}
+	}
+
+	let h: Vec<f64> = (0..8).map(|i| rows[i][8] / rows[i][i]).collect();
+	Some([h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], 1.0])
+}
+
+fn project(h: &[f64; 9], x: f32, y: f32) -> (f32, f32) {
+	let (x, y) = (x as f64, y as f64);
+	let w = h[6] * x + h[7] * y + h[8];
+	(
+		((h[0] * x + h[1] * y + h[2]) / w) as f32,
+		((h[3] * x + h[4] * y + h[5]) / w) as f32,
+	)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PerspectiveDirection {
+	/// Straightens the quadrilateral into a rectangle the size of its longest edges
+	#[default]
+	ToRectangle,
+	/// Distorts the whole image into the quadrilateral, keeping the image size
+	FromRectangle,
+}
+
+/// Perspective warp between the quadrilateral given by `corners`, clockwise from the top left,
+/// and a rectangle. Straightening is useful for deskewing photos of documents and whiteboards.
+/// Percentages of the corners are of the image width and height.
+///
+/// ```toml
+/// [[operations]]
+/// [operations.perspective]
+/// corners = [
+///     { x = { pixel = { pixels = 40 } }, y = { pixel = { pixels = 12 } } },
+///     { x = { pixel = { pixels = 610 } }, y = { pixel = { pixels = 30 } } },
+///     { x = { pixel = { pixels = 630 } }, y = { pixel = { pixels = 460 } } },
+///     { x = { pixel = { pixels = 18 } }, y = { pixel = { pixels = 440 } } },
+/// ]
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Perspective {
+	pub corners: [Coordinate; 4],
+	#[serde(default)]
+	pub direction: PerspectiveDirection,
+	/// Defaults to triangle
+	#[serde(default = "default_filter")]
+	pub filter: FilterType,
+	/// Fills areas outside the image, defaults to transparent
+	#[serde(default)]
+	pub background: Color,
+}
+
+fn default_filter() -> FilterType {
+	FilterType::Triangle
+}
+
+impl Perspective {
+	/// Transform mapping output positions back to a `width` by `height` source, and the size of
+	/// the output.
+	fn layout(&self, width: u32, height: u32) -> Result<([f64; 9], (u32, u32)), OperationError> {
+		let quad = self.corners.each_ref().map(|corner| {
+			(
+				corner
+					.x
+					.as_pixel(PixelUnit::from(width), (width, height))
+					.pixels as f32,
+				corner
+					.y
+					.as_pixel(PixelUnit::from(height), (width, height))
+					.pixels as f32,
+			)
+		});
+		let distance = |(ax, ay): (f32, f32), (bx, by): (f32, f32)| (ax - bx).hypot(ay - by);
+
+		let (out_width, out_height) = match self.direction {
+			PerspectiveDirection::ToRectangle => (
+				distance(quad[0], quad[1]).max(distance(quad[3], quad[2])),
+				distance(quad[0], quad[3]).max(distance(quad[1], quad[2])),
+			),
+			PerspectiveDirection::FromRectangle => (width as f32, height as f32),
+		};
+		let rectangle =
+			|width: f32, height: f32| [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)];
+
+		// Maps output positions back to the source
+		let h = match self.direction {
+			PerspectiveDirection::ToRectangle => homography(rectangle(out_width, out_height), quad),
+			PerspectiveDirection::FromRectangle => {
+				homography(quad, rectangle(width as f32, height as f32))
+			}
+		}
+		.ok_or_else(|| {
+			OperationError::new(format!(
+				"Corners must form a quadrilateral for operation {self:?}"
+			))
+		})?;
+
+		let size = (
+			(out_width.round() as u32).max(1),
+			(out_height.round() as u32).max(1),
+		);
+		Ok((h, size))
+	}
+}
+
+impl Process for Perspective {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		let (h, (width, height)) = self.layout(image.width(), image.height())?;
+		Ok(warp(
+			&image,
+			width,
+			height,
+			|x, y| project(&h, x, y),
+			self.filter,
+			self.background,
+		))
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		let (_, size) = self.layout(image.width, image.height)?;
+		Ok(Some(size))
+	}
+}
+
+/// Rotates the image clockwise by any angle, resampling with `filter`. Negative angles rotate
+/// counter-clockwise. For exact quarter turns, prefer `auto-orient` or `flip`, which don't
+/// resample.
+///
+/// ```toml
+/// [[operations]]
+/// rotate-degrees = { angle = 12.5, filter = "catmull-rom", expand = true }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RotateDegrees {
+	pub angle: f32,
+	/// Defaults to triangle
+	#[serde(default = "default_filter")]
+	pub filter: FilterType,
+	/// Grows the canvas to fit the rotated image instead of cropping it to the original size
+	#[serde(default)]
+	pub expand: bool,
+	/// Fills the uncovered corners, defaults to transparent
+	#[serde(default)]
+	pub background: Color,
+}
+
+impl RotateDegrees {
+	/// The rotation as a linear transform, or `None` for whole turns.
+	fn transform(&self) -> Result<Option<[f32; 4]>, OperationError> {
+		if !self.angle.is_finite() {
+			return Err(OperationError::new(format!(
+				"Angle must be a finite number, got {}",
+				self.angle
+			)));
+		}
+		if self.angle.rem_euclid(360.0) == 0.0 {
+			return Ok(None);
+		}
+
+		let (sin, cos) = self.angle.to_radians().sin_cos();
+		Ok(Some([cos, -sin, sin, cos]))
+	}
+}
+
+impl Process for RotateDegrees {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		Ok(match self.transform()? {
+			Some(transform) => {
+				warp_affine(&image, transform, self.expand, self.filter, self.background)
+			}
+			None => image,
+		})
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		Ok(Some(match self.transform()? {
+			Some(transform) => affine_size(image.dimensions(), transform, self.expand),
+			None => image.dimensions(),
+		}))
+	}
+}
+
+/// Skews the image by shifting rows horizontally by `x` times their distance from the center and
+/// columns vertically by `y` times theirs. A factor of 1 is a 45 degree slant.
+///
+/// ```toml
+/// [[operations]]
+/// shear = { x = 0.25, expand = true, background = "white" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Shear {
+	#[serde(default)]
+	pub x: f32,
+	#[serde(default)]
+	pub y: f32,
+	/// Defaults to triangle
+	#[serde(default = "default_filter")]
+	pub filter: FilterType,
+	/// Grows the canvas to fit the sheared image instead of cropping it to the original size
+	#[serde(default)]
+	pub expand: bool,
+	/// Fills areas outside the image, defaults to transparent
+	#[serde(default)]
+	pub background: Color,
+}
+
+impl Shear {
+	/// The shear as a linear transform, or `None` when it has no effect.
+	fn transform(&self) -> Result<Option<[f32; 4]>, OperationError> {
+		if !self.x.is_finite() || !self.y.is_finite() {
+			return Err(OperationError::new(format!(
+				"Shear factors must be finite numbers, got {} and {}",
+				self.x, self.y
+			)));
+		}
+		if (1.0 - self.x * self.y).abs() < 1e-6 {
+			return Err(OperationError::new(format!(
+				"Shear factors {} and {} collapse the image into a line",
+				self.x, self.y
+			)));
+		}
+		if self.x == 0.0 && self.y == 0.0 {
+			return Ok(None);
+		}
+
+		Ok(Some([1.0, self.x, self.y, 1.0]))
+	}
+}
+
+impl Process for Shear {
+	fn process(&self, image: DynamicImage) -> Result<DynamicImage, OperationError> {
+		Ok(match self.transform()? {
+			Some(transform) => {
+				warp_affine(&image, transform, self.expand, self.filter, self.background)
+			}
+			None => image,
+		})
+	}
+
+	fn plan(&self, image: ImageInfo) -> Result<Option<(u32, u32)>, OperationError> {
+		Ok(Some(match self.transform()? {
+			Some(transform) => affine_size(image.dimensions(), transform, self.expand),
+			None => image.dimensions(),
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{homography, project, RotateDegrees, Shear};
+	use crate::{Color, Process};
+	use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+	#[test]
+	fn homography_maps_corners() {
+		let from = [(0.0, 0.0), (100.0, 0.0), (100.0, 50.0), (0.0, 50.0)];
+		let to = [(10.0, 5.0), (90.0, 12.0), (95.0, 60.0), (4.0, 48.0)];
+		let h = homography(from, to).unwrap();
+
+		for (point, expected) in from.into_iter().zip(to) {
+			let (x, y) = project(&h, point.0, point.1);
+			assert!((x - expected.0).abs() < 1e-3 && (y - expected.1).abs() < 1e-3);
+		}
+		assert!(homography([(0.0, 0.0); 4], to).is_none());
+	}
+
+	#[test]
+	fn rotate_degrees() {
+		let mut image = RgbaImage::from_pixel(40, 20, Rgba([255, 255, 255, 255]));
+		image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+		let image = DynamicImage::ImageRgba8(image);
+		let rotate = |angle, expand| {
+			RotateDegrees {
+				angle,
+				filter: super::FilterType::Nearest,
+				expand,
+				background: Color::BLACK,
+			}
+			.process(image.clone())
+			.unwrap()
+		};
+
+		// A quarter turn clockwise moves the top left corner to the top right
+		let rotated = rotate(90.0, true);
+		assert_eq!((20, 40), rotated.dimensions());
+		assert_eq!(Rgba([255, 0, 0, 255]), rotated.get_pixel(19, 0));
+
+		let cropped = rotate(30.0, false);
+		assert_eq!((40, 20), cropped.dimensions());
+		assert_eq!(Rgba([0, 0, 0, 255]), cropped.get_pixel(0, 0));
+		assert_eq!(Rgba([255, 255, 255, 255]), cropped.get_pixel(20, 10));
+	}
+
+	#[test]
+	fn shear_expands_to_fit() {
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 20, Rgba([255; 4])));
+		let shear = |x, y| Shear {
+			x,
+			y,
+			filter: super::FilterType::Nearest,
+			expand: true,
+			background: Color::TRANSPARENT,
+		};
+
+		let sheared = shear(0.5, 0.0).process(image.clone()).unwrap();
+		assert_eq!((50, 20), sheared.dimensions());
+		assert_eq!(255, sheared.get_pixel(0, 0)[3]);
+		assert_eq!(0, sheared.get_pixel(0, 19)[3]);
+		assert_eq!(0, sheared.get_pixel(49, 0)[3]);
+
+		assert!(shear(2.0, 0.5).process(image).is_err());
+	}
+}
@@ -0,0 +1,345 @@
+//! Rewrites operations into fewer, cheaper ones for a known source size, which mostly pays off for
+//! machine-generated pipelines.
+//!
+//! - Consecutive crops are merged into one crop.
+//! - Consecutive resizes which don't crop, resample the same way and both shrink the image collapse
+//!   into a single resize to the final size. Shrinking and then enlarging is left alone, as it is
+//!   how images are pixelated or softened.
+//! - Operations with no effect, such as a crop of the whole image or a resize to the same size,
+//!   are dropped.
+//! - A blur followed by an even downscale with a smoothing filter is moved after it, with the blur
+//!   scaled to match. Nearest neighbor downscales alias, which the blur would no longer smooth.
+//!
+//! Collapsed resizes only resample once, so their output can differ slightly from resampling in
+//! steps.
+
+use crate::{
+	operations::{Blur, Crop, CropMode, CropOrigin, FilterType, Resize, Thumbnail},
+	plan::ImageInfo,
+	Coordinate, Operation, PixelUnit, Unit,
+};
+
+/// How far apart the horizontal and vertical scale of a resize can be while still counting as even.
+const EVEN_SCALE_TOLERANCE: f64 = 0.01;
+
+/// An operation along with the image before it.
+struct Entry {
+	operation: Operation,
+	input: ImageInfo,
+}
+
+/// Resampling of an operation which only resizes the image.
+#[derive(Clone, Copy, PartialEq)]
+enum Resampling {
+	Filter(FilterType),
+	Thumbnail,
+}
+
+/// Optimizes `operations` for a source described by `image`. Operations after one whose result
+/// depends on the pixels, such as `trim`, are left as they are.
+pub fn optimize(operations: Vec<Operation>, image: ImageInfo) -> Vec<Operation> {
+	let mut entries: Vec<Entry> = Vec::new();
+	let mut operations = operations.into_iter();
+	let mut input = image;
+
+	for operation in operations.by_ref() {
+		let Ok(Some(output)) = operation.get_process().plan(input) else {
+			// The size is unknown from here on, or processing will fail anyway
+			entries.push(Entry { operation, input });
+			break;
+		};
+
+		let next = input.after(&operation, output);
+		push(&mut entries, operation, input, output);
+		input = next;
+	}
+
+	entries
+		.into_iter()
+		.map(|entry| entry.operation)
+		.chain(operations)
+		.collect()
+}
+
+fn push(entries: &mut Vec<Entry>, operation: Operation, input: ImageInfo, output: (u32, u32)) {
+	let previous = entries.last();
+
+	if let (Some(previous), Some(bounds)) = (previous, crop_bounds(&operation, input)) {
+		if let Some(previous_bounds) = crop_bounds(&previous.operation, previous.input) {
+			let [x, y, _, _] = previous_bounds;
+			let [inner_x, inner_y, width, height] = bounds;
+			let input = previous.input;
+			entries.pop();
+			return push(
+				entries,
+				Operation::Crop(pixel_crop([x + inner_x, y + inner_y, width, height])),
+				input,
+				output,
+			);
+		}
+	}
+
+	if let Some(resampling) = resampling_of(&operation) {
+		match previous {
+			Some(previous)
+				if resampling_of(&previous.operation) == Some(resampling)
+					&& shrinks_in_steps(
+						previous.input.dimensions(),
+						input.dimensions(),
+						output,
+					) =>
+			{
+				let input = entries.pop().map_or(input, |previous| previous.input);
+				return push(entries, resize(resampling, output), input, output);
+			}
+			Some(Entry {
+				operation: Operation::Blur(blur),
+				..
+			}) if resampling.smooths() => {
+				if let Some(scale) = even_downscale(input.dimensions(), output) {
+					let sigma = blur.sigma * scale as f32;
+					entries.pop();
+					push(entries, operation, input, output);
+					let input = ImageInfo::new(output.0, output.1, input.color_type);
+					return push(entries, Operation::Blur(Blur { sigma }), input, output);
+				}
+			}
+			_ => {}
+		}
+	}
+
+	if !has_no_effect(&operation, input, output) {
+		entries.push(Entry { operation, input });
+	}
+}
+
+/// Left, top, width and height of operations which only crop.
+pub(crate) fn crop_bounds(operation: &Operation, image: ImageInfo) -> Option<[u32; 4]> {
+	let (width, height) = image.dimensions();
+	match operation {
+		Operation::Crop(crop) => crop.bounds(width, height).ok(),
+		Operation::CropGravity(crop) => crop.bounds(width, height).ok(),
+		Operation::CropToAspect(crop) => crop.bounds(width, height).ok(),
+		_ => None,
+	}
+}
+
+fn pixel_crop([x, y, width, height]: [u32; 4]) -> Crop {
+	let pixels = |pixels: u32| Unit::Pixel(PixelUnit::from(pixels));
+	Crop {
+		from: Coordinate {
+			x: pixels(x),
+			y: pixels(y),
+		},
+		to: CropOrigin::CropStart(Coordinate {
+			x: pixels(width),
+			y: pixels(height),
+		}),
+	}
+}
+
+/// Resampling of operations which resize without cropping.
+fn resampling_of(operation: &Operation) -> Option<Resampling> {
+	match operation {
+		Operation::Resize(Resize {
+			filter,
+			crop_mode:
+				CropMode::Preserve | CropMode::Exact | CropMode::ShrinkOnly | CropMode::EnlargeOnly,
+			..
+		}) => Some(Resampling::Filter(*filter)),
+		Operation::Scale(scale) => Some(Resampling::Filter(scale.filter)),
+		Operation::Thumbnail(_) => Some(Resampling::Thumbnail),
+		_ => None,
+	}
+}
+
+impl Resampling {
+	/// Whether downscaling averages pixels, rather than picking one of them and aliasing.
+	fn smooths(self) -> bool {
+		matches!(
+			self,
+			Resampling::Filter(
+				FilterType::Triangle
+					| FilterType::CatmullRom
+					| FilterType::Gaussian
+					| FilterType::Lanczos3
+			)
+		)
+	}
+}
+
+fn resize(resampling: Resampling, (width, height): (u32, u32)) -> Operation {
+	let width = Unit::Pixel(PixelUnit::from(width));
+	let height = Unit::Pixel(PixelUnit::from(height));
+	match resampling {
+		Resampling::Filter(filter) => Operation::Resize(Resize {
+			width: Some(width),
+			height: Some(height),
+			filter,
+			crop_mode: CropMode::Exact,
+		}),
+		Resampling::Thumbnail => Operation::Thumbnail(Thumbnail {
+			width,
+			height,
+			exact: true,
+		}),
+	}
+}
+
+/// Whether resizing from `input` to `middle` and then on to `output` never enlarges either
+/// dimension, so that resizing straight to `output` loses no detail the steps would keep.
+fn shrinks_in_steps(input: (u32, u32), middle: (u32, u32), output: (u32, u32)) -> bool {
+	middle.0 <= input.0 && middle.1 <= input.1 && output.0 <= middle.0 && output.1 <= middle.1
+}
+
+/// The scale of a resize which shrinks both dimensions by about the same amount.
+fn even_downscale(input: (u32, u32), output: (u32, u32)) -> Option<f64> {
+	let scale_x = output.0 as f64 / input.0 as f64;
+	let scale_y = output.1 as f64 / input.1 as f64;
+	let even = (scale_x - scale_y).abs() <= EVEN_SCALE_TOLERANCE * scale_x.max(scale_y);
+	(even && scale_x < 1.0).then_some((scale_x + scale_y) / 2.0)
+}
+
+fn has_no_effect(operation: &Operation, input: ImageInfo, output: (u32, u32)) -> bool {
+	let same_size = output == input.dimensions();
+	match operation {
+		Operation::Crop(_) | Operation::CropGravity(_) | Operation::CropToAspect(_) => same_size,
+		operation if resampling_of(operation).is_some() => same_size,
+		Operation::RotateDegrees(rotate) => rotate.angle.rem_euclid(360.0) == 0.0,
+		Operation::Shear(shear) => shear.x == 0.0 && shear.y == 0.0,
+		Operation::Blur(blur) => blur.sigma == 0.0,
+		_ => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::optimize;
+	use crate::{plan::ImageInfo, Operation};
+	use image::{ColorType, DynamicImage, GenericImageView, Rgb, RgbImage};
+	use serde::Deserialize;
+
+	#[derive(Deserialize)]
+	struct Config {
+		operations: Vec<Operation>,
+	}
+
+	#[test]
+	fn fuses_operations() {
+		let config: Config = toml::from_str(
+			r#"
+			[[operations]]
+			crop-gravity = { width = { pixel = { pixels = 100 } }, height = { pixel = { pixels = 80 } }, gravity = "bottom-right" }
+
+			[[operations]]
+			crop-to-aspect = { ratio = [1, 1], gravity = "left" }
+
+			[[operations]]
+			rotate-degrees = { angle = 360.0 }
+
+			[[operations]]
+			blur = { sigma = 4.0 }
+
+			[[operations]]
+			scale = { factor = 0.5 }
+
+			[[operations]]
+			resize = { width = { pixel = { pixels = 20 } }, filter = "lanczos3", crop_mode = "preserve" }
+
+			[[operations]]
+			resize = { width = { percentage = { percentage = 1.0 } }, height = { percentage = { percentage = 1.0 } }, filter = "nearest", crop_mode = "exact" }
+			"#,
+		)
+		.unwrap();
+
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(160, 120, |x, y| {
+			Rgb([x as u8, y as u8, 0])
+		}));
+		let optimized = optimize(config.operations, ImageInfo::new(160, 120, ColorType::Rgb8));
+		let names: Vec<String> = optimized.iter().map(Operation::name).collect();
+		assert_eq!(vec!["crop", "resize", "blur"], names);
+
+		let Operation::Blur(blur) = &optimized[2] else {
+			unreachable!();
+		};
+		assert_eq!(1.0, blur.sigma);
+
+		// Both crops are anchored to the bottom right corner of the source
+		let cropped = optimized[0].get_process().process(image).unwrap();
+		assert_eq!((80, 80), cropped.dimensions());
+		assert_eq!([60, 40, 0, 255], cropped.get_pixel(0, 0).0);
+	}
+
+	fn optimized_names(operations: &str) -> Vec<String> {
+		let config: Config = toml::from_str(operations).unwrap();
+		optimize(config.operations, ImageInfo::new(160, 120, ColorType::Rgb8))
+			.iter()
+			.map(Operation::name)
+			.collect()
+	}
+
+	#[test]
+	fn keeps_blur_before_nearest_downscale() {
+		let names = optimized_names(
+			r#"
+			[[operations]]
+			blur = { sigma = 4.0 }
+
+			[[operations]]
+			scale = { factor = 0.5, filter = "nearest" }
+			"#,
+		);
+		assert_eq!(vec!["blur", "scale"], names);
+	}
+
+	#[test]
+	fn keeps_resizes_which_resample_differently() {
+		// Pixelation, which scaling back up with a smoothing filter would blur away
+		let names = optimized_names(
+			r#"
+			[[operations]]
+			scale = { factor = 0.1, filter = "nearest" }
+
+			[[operations]]
+			scale = { factor = 10.0, filter = "triangle" }
+			"#,
+		);
+		assert_eq!(vec!["scale", "scale"], names);
+
+		let names = optimized_names(
+			r#"
+			[[operations]]
+			thumbnail = { width = { pixel = { pixels = 80 } }, height = { pixel = { pixels = 60 } } }
+
+			[[operations]]
+			scale = { factor = 0.5 }
+			"#,
+		);
+		assert_eq!(vec!["thumbnail", "scale"], names);
+	}
+
+	#[test]
+	fn keeps_downscale_before_upscale() {
+		let names = optimized_names(
+			r#"
+			[[operations]]
+			scale = { factor = 0.1, filter = "triangle" }
+
+			[[operations]]
+			scale = { factor = 10.0, filter = "triangle" }
+			"#,
+		);
+		assert_eq!(vec!["scale", "scale"], names);
+
+		let names = optimized_names(
+			r#"
+			[[operations]]
+			scale = { factor = 0.5, filter = "triangle" }
+
+			[[operations]]
+			scale = { factor = 0.5, filter = "triangle" }
+			"#,
+		);
+		assert_eq!(vec!["resize"], names);
+	}
+}
@@ -0,0 +1,181 @@
+//! `${name}` placeholders in configs, filled in from parameters so that one config can serve
+//! many jobs.
+//!
+//! A string which is only a placeholder is replaced with the parameter as a TOML value, so
+//! `pixels = "${width}"` with `width=300` becomes the number 300. When the config does not accept
+//! those values, such as `label = "${label}"` with `label=2024`, the parameters are used as text
+//! instead. Quoting a parameter as a TOML string, e.g. `label='"2024"'`, always keeps it as text.
+//! Placeholders within longer strings are replaced with the parameter as text.
+
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use thiserror::Error;
+use toml::Value;
+
+#[derive(Debug, Error)]
+pub enum ParamError {
+	#[error("No value for parameter `{0}`")]
+	Missing(String),
+
+	#[error("Unterminated placeholder in `{0}`")]
+	Unterminated(String),
+
+	#[error(transparent)]
+	Toml(#[from] toml::de::Error),
+}
+
+/// Parses a TOML config after replacing its placeholders with `params`.
+pub fn from_toml<T: DeserializeOwned>(
+	toml: &str,
+	params: &HashMap<String, String>,
+) -> Result<T, ParamError> {
+	let value: Value = toml::from_str(toml)?;
+
+	let mut typed = value.clone();
+	fill(&mut typed, params, true)?;
+	typed.try_into().or_else(|err| {
+		let mut text = value;
+		fill(&mut text, params, false)?;
+		text.try_into().map_err(|_| err.into())
+	})
+}
+
+/// Replaces the placeholders in every string within `value`.
+pub fn substitute(value: &mut Value, params: &HashMap<String, String>) -> Result<(), ParamError> {
+	fill(value, params, true)
+}
+
+/// Replaces placeholders, parsing strings which are only a placeholder as TOML values when `typed`
+/// is set.
+fn fill(
+	value: &mut Value,
+	params: &HashMap<String, String>,
+	typed: bool,
+) -> Result<(), ParamError> {
+	match value {
+		Value::String(string) => {
+			if let Some(name) = whole_placeholder(string).filter(|_| typed) {
+				let param = params
+					.get(name)
+					.ok_or_else(|| ParamError::Missing(name.to_string()))?;
+				*value = parse_param(param);
+			} else {
+				*string = interpolate(string, params)?;
+			}
+		}
+		Value::Array(values) => {
+			for value in values.iter_mut() {
+				fill(value, params, typed)?;
+			}
+		}
+		Value::Table(table) => {
+			for (_, value) in table.iter_mut() {
+				fill(value, params, typed)?;
+			}
+		}
+		_ => {}
+	}
+
+	Ok(())
+}
+
+fn whole_placeholder(string: &str) -> Option<&str> {
+	let name = string.strip_prefix("${")?.strip_suffix('}')?;
+	(!name.contains(['$', '{', '}'])).then_some(name)
+}
+
+/// A parameter as a number, boolean, array or table when it reads as one, and as a string
+/// otherwise.
+fn parse_param(param: &str) -> Value {
+	toml::from_str::<toml::Table>(&format!("value = {param}"))
+		.ok()
+		.and_then(|mut table| table.remove("value"))
+		.unwrap_or_else(|| Value::String(param.to_string()))
+}
+
+fn interpolate(string: &str, params: &HashMap<String, String>) -> Result<String, ParamError> {
+	let mut result = String::with_capacity(string.len());
+	let mut rest = string;
+	while let Some(start) = rest.find("${") {
+		result.push_str(&rest[..start]);
+		let end = rest[start..]
+			.find('}')
+			.ok_or_else(|| ParamError::Unterminated(string.to_string()))?;
+		let name = &rest[start + 2..start + end];
+		let param = params
+			.get(name)
+			.ok_or_else(|| ParamError::Missing(name.to_string()))?;
+		result.push_str(param);
+		rest = &rest[start + end + 1..];
+	}
+	result.push_str(rest);
+
+	Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{from_toml, ParamError};
+	use crate::{ImageOutputFormat, Pipeline};
+	use std::collections::HashMap;
+
+	#[test]
+	fn substitutes_params() {
+		let params = HashMap::from([
+			("width".to_string(), "300".to_string()),
+			("quality".to_string(), "80".to_string()),
+			("name".to_string(), "hero".to_string()),
+		]);
+		let pipeline: Pipeline = from_toml(
+			r#"
+			out_format = { jpeg = { quality = "${quality}" } }
+
+			[[operations]]
+			stats = { label = "${name}-${width}" }
+
+			[[operations]]
+			thumbnail = { width = { pixel = { pixels = "${width}" } }, height = { pixel = { pixels = 200 } } }
+			"#,
+			&params,
+		)
+		.unwrap();
+
+		assert_eq!(ImageOutputFormat::Jpeg { quality: 80 }, pipeline.out_format);
+		let json = serde_json::to_string(&pipeline.operations).unwrap();
+		assert!(json.contains(r#""label":"hero-300""#));
+		assert!(json.contains(r#""width":{"pixel":{"pixels":300}}"#));
+
+		let missing = from_toml::<Pipeline>(r#"out_format = "${format}""#, &HashMap::new());
+		assert!(matches!(missing, Err(ParamError::Missing(name)) if name == "format"));
+	}
+
+	#[test]
+	fn keeps_numeric_text_params() {
+		let stats = |params: &HashMap<String, String>, config: &str| {
+			let pipeline: Pipeline = from_toml(config, params).unwrap();
+			serde_json::to_string(&pipeline.operations).unwrap()
+		};
+		let label = r#"
+			out_format = "png"
+
+			[[operations]]
+			stats = { label = "${label}" }
+			"#;
+
+		let params = HashMap::from([("label".to_string(), "2024".to_string())]);
+		assert!(stats(&params, label).contains(r#""label":"2024""#));
+
+		// Quoted parameters stay text alongside numeric ones
+		let params = HashMap::from([
+			("label".to_string(), r#""2024""#.to_string()),
+			("width".to_string(), "300".to_string()),
+		]);
+		let thumbnail = r#"
+			[[operations]]
+			thumbnail = { width = { pixel = { pixels = "${width}" } }, height = { pixel = { pixels = 200 } } }
+			"#;
+		let json = stats(&params, &format!("{label}{thumbnail}"));
+		assert!(json.contains(r#""label":"2024""#));
+		assert!(json.contains(r#""pixels":300"#));
+	}
+}
@@ -0,0 +1,115 @@
+//! Renders a page of a PDF as the source image, enabled with the `pdf` feature.
+//!
+//! Sources starting with a PDF header are rendered at the page and resolution of
+//! [`ProcessOptions::pdf`] instead of being decoded as images, for thumbnails of documents. Pages
+//! are rendered with pdfium, which is loaded from the system libraries at runtime, e.g.
+//! `libpdfium.so` on Linux.
+
+use crate::{Error, ProcessOptions};
+use image::{
+	error::{DecodingError, ImageFormatHint},
+	DynamicImage, ImageError,
+};
+use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, PoisonError};
+
+/// PDF page sizes are in points, of which there are 72 to an inch
+const POINTS_PER_INCH: f32 = 72.0;
+
+/// Which page of a PDF source to render, and at what resolution.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "snake_case")]
+pub struct PdfOptions {
+	/// Index of the page, from 0
+	pub page: u16,
+	/// Pixels per inch. At 72 there is a pixel for each point of the page.
+	pub dpi: f32,
+}
+
+impl Default for PdfOptions {
+	fn default() -> Self {
+		Self {
+			page: 0,
+			dpi: POINTS_PER_INCH,
+		}
+	}
+}
+
+/// Whether `bytes` start with a PDF header.
+pub fn is_pdf(bytes: &[u8]) -> bool {
+	bytes.starts_with(b"%PDF-")
+}
+
+/// Renders the page of the PDF in `bytes` chosen by [`ProcessOptions::pdf`], after checking the
+/// size it renders at against the limits of `options`.
+pub(crate) fn render(bytes: &[u8], options: &ProcessOptions) -> Result<DynamicImage, Error> {
+	let PdfOptions { page, dpi } = options.pdf;
+	if !(dpi > 0.0 && dpi.is_finite()) {
+		return Err(pdf_error(format!(
+			"PDF resolution must be above 0, got {dpi} dpi"
+		)));
+	}
+
+	// pdfium is set up and torn down with each instance, so only one can be used at a time
+	static PDFIUM: Mutex<()> = Mutex::new(());
+	let _lock = PDFIUM.lock().unwrap_or_else(PoisonError::into_inner);
+	let pdfium = Pdfium::new(
+		Pdfium::bind_to_system_library()
+			.map_err(|error| pdf_error(format!("Cannot load pdfium: {error}")))?,
+	);
+
+	let document = pdfium
+		.load_pdf_from_byte_slice(bytes, None)
+		.map_err(|error| pdf_error(format!("Cannot open PDF: {error}")))?;
+	let pages = document.pages();
+	let page = pages.get(page).map_err(|_| {
+		pdf_error(format!(
+			"Cannot render page {page} of a PDF with {} pages",
+			pages.len()
+		))
+	})?;
+
+	let scale = dpi / POINTS_PER_INCH;
+	let size = |points: f32| (points * scale).round().clamp(1.0, i32::MAX as f32) as u32;
+	let (width, height) = (size(page.width().value), size(page.height().value));
+	options.limits.check(width, height)?;
+	options.check_memory(|| width as u64 * height as u64 * 4)?;
+
+	let bitmap = page
+		.render_with_config(&PdfRenderConfig::new().set_target_size(width as i32, height as i32))
+		.map_err(|error| pdf_error(format!("Cannot render PDF page: {error}")))?;
+	Ok(bitmap.as_image())
+}
+
+fn pdf_error(message: String) -> Error {
+	ImageError::Decoding(DecodingError::new(
+		ImageFormatHint::Name("PDF".to_string()),
+		message,
+	))
+	.into()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_pdf, render, PdfOptions};
+	use crate::ProcessOptions;
+
+	#[test]
+	fn detects_pdfs() {
+		assert!(is_pdf(b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n"));
+		assert!(!is_pdf(b"\x89PNG\r\n\x1a\n"));
+		assert!(!is_pdf(b"%PD"));
+	}
+
+	#[test]
+	fn rejects_invalid_resolutions() {
+		for dpi in [0.0, -72.0, f32::NAN, f32::INFINITY] {
+			let options = ProcessOptions {
+				pdf: PdfOptions { page: 0, dpi },
+				..Default::default()
+			};
+			assert!(render(b"%PDF-1.7", &options).is_err());
+		}
+	}
+}
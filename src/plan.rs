@@ -0,0 +1,186 @@
+//! Working out what a pipeline does to the size of an image without decoding it, to check
+//! configs against real inputs before spending time on pixels.
+
+use crate::{metadata::Orientation, Error, Operation};
+use image::{
+	codecs::{
+		bmp::BmpDecoder, gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder, tiff::TiffDecoder,
+		webp::WebPDecoder,
+	},
+	io::Reader as ImageReader,
+	ColorType, DynamicImage, GenericImageView, ImageDecoder, ImageFormat,
+};
+use std::io::{BufRead, Seek};
+
+/// Size and color type of an image, which is all operations need to work out their output size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageInfo {
+	pub width: u32,
+	pub height: u32,
+	pub color_type: ColorType,
+	/// EXIF orientation of the source, until an `auto-orient` operation applies it
+	pub orientation: Option<Orientation>,
+}
+
+impl ImageInfo {
+	pub fn new(width: u32, height: u32, color_type: ColorType) -> Self {
+		Self {
+			width,
+			height,
+			color_type,
+			orientation: None,
+		}
+	}
+
+	pub fn with_orientation(self, orientation: Option<Orientation>) -> Self {
+		Self {
+			orientation,
+			..self
+		}
+	}
+
+	/// The image once its orientation is applied, with the width and height swapped for the
+	/// orientations which rotate by 90 degrees.
+	pub fn upright(self) -> Self {
+		match self.orientation {
+			Some(orientation) if orientation.swaps_dimensions() => {
+				Self::new(self.height, self.width, self.color_type)
+			}
+			_ => Self::new(self.width, self.height, self.color_type),
+		}
+	}
+
+	/// The image after `operation` resizes it to `dimensions`. The orientation is kept until it is
+	/// applied by `auto-orient`.
+	pub(crate) fn after(self, operation: &Operation, (width, height): (u32, u32)) -> Self {
+		let orientation = match operation {
+			Operation::AutoOrient(_) => None,
+			_ => self.orientation,
+		};
+		Self::new(width, height, self.color_type).with_orientation(orientation)
+	}
+
+	pub fn of(image: &DynamicImage) -> Self {
+		let (width, height) = image.dimensions();
+		Self::new(width, height, image.color())
+	}
+
+	pub fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	/// Size of the decoded pixels in bytes.
+	pub fn bytes(&self) -> u64 {
+		self.width as u64 * self.height as u64 * self.color_type.bytes_per_pixel() as u64
+	}
+
+	fn of_decoder<'a>(decoder: impl ImageDecoder<'a>) -> Self {
+		let (width, height) = decoder.dimensions();
+		Self::new(width, height, decoder.color_type())
+	}
+}
+
+/// Reads the size and color type of an image from its header. Only formats without a header
+/// decoder here are decoded in full.
+pub fn read_info<R: BufRead + Seek>(reader: R) -> Result<ImageInfo, Error> {
+	let reader = ImageReader::new(reader).with_guessed_format()?;
+	let info = match reader.format() {
+		Some(ImageFormat::Png) => ImageInfo::of_decoder(PngDecoder::new(reader.into_inner())?),
+		Some(ImageFormat::Jpeg) => ImageInfo::of_decoder(JpegDecoder::new(reader.into_inner())?),
+		Some(ImageFormat::Gif) => ImageInfo::of_decoder(GifDecoder::new(reader.into_inner())?),
+		Some(ImageFormat::WebP) => ImageInfo::of_decoder(WebPDecoder::new(reader.into_inner())?),
+		Some(ImageFormat::Tiff) => ImageInfo::of_decoder(TiffDecoder::new(reader.into_inner())?),
+		Some(ImageFormat::Bmp) => ImageInfo::of_decoder(BmpDecoder::new(reader.into_inner())?),
+		_ => ImageInfo::of(&reader.decode()?),
+	};
+
+	Ok(info)
+}
+
+/// An operation of a plan with the dimensions of its result, which are unknown from the first
+/// operation whose result depends on the pixels, such as `trim`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+	pub operation: String,
+	pub dimensions: Option<(u32, u32)>,
+}
+
+/// Plans `operations` for an image described by `info`. Color types are taken to stay as they
+/// are, so conditions on color are checked against the source.
+pub fn plan(operations: &[Operation], info: ImageInfo) -> Result<Vec<Step>, Error> {
+	let mut current = Some(info);
+	operations
+		.iter()
+		.map(|operation| {
+			if let Some(info) = current {
+				current = operation
+					.get_process()
+					.plan(info)?
+					.map(|dimensions| info.after(operation, dimensions));
+			}
+
+			Ok(Step {
+				operation: operation.name(),
+				dimensions: current.map(|info| info.dimensions()),
+			})
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{plan, ImageInfo};
+	use crate::Operation;
+	use image::{DynamicImage, GenericImageView, RgbImage};
+	use serde::Deserialize;
+
+	#[derive(Deserialize)]
+	struct Config {
+		operations: Vec<Operation>,
+	}
+
+	#[test]
+	fn plans_match_processing() {
+		let config: Config = toml::from_str(
+			r#"
+			[[operations]]
+			resize = { width = { pixel = { pixels = 90 } }, filter = "nearest", crop_mode = "preserve" }
+
+			[[operations]]
+			pad = { top = { pixel = { pixels = 7 } }, left = { percentage = { percentage = 0.1 } } }
+
+			[[operations]]
+			conditional = { if = "landscape", then = [{ crop-to-aspect = { ratio = [4, 3] } }] }
+
+			[[operations]]
+			rotate-degrees = { angle = 20.0, expand = true }
+
+			[[operations]]
+			drop-shadow = { x = { pixel = { pixels = 3 } }, y = { pixel = { pixels = 2 } }, sigma = 1.5 }
+
+			[[operations]]
+			thumbnail = { width = { pixel = { pixels = 40 } }, height = { pixel = { pixels = 40 } } }
+
+			[[operations]]
+			trim = {}
+
+			[[operations]]
+			scale = { factor = 2.0 }
+			"#,
+		)
+		.unwrap();
+
+		let mut image = DynamicImage::ImageRgb8(RgbImage::new(120, 50));
+		let steps = plan(&config.operations, ImageInfo::of(&image)).unwrap();
+		for (operation, step) in config.operations.iter().zip(&steps) {
+			if let Some(dimensions) = step.dimensions {
+				image = operation.get_process().process(image).unwrap();
+				assert_eq!(dimensions, image.dimensions(), "{}", step.operation);
+			}
+		}
+
+		// Trimming depends on the pixels, and so does everything after it
+		assert_eq!(None, steps[6].dimensions);
+		assert_eq!(None, steps[7].dimensions);
+	}
+}
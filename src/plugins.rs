@@ -0,0 +1,366 @@
+//! Operations loaded from shared libraries at runtime, enabled with the `plugins` feature.
+//!
+//! A plugin exports `imageless_plugin_declare`, returning a [`PluginDeclaration`] which lists the
+//! operations it provides. `include/imageless_plugin.h` declares the same interface for plugins
+//! written in C. Once loaded, operations are used in configs through
+//! [`Plugin`](crate::operations::Plugin):
+//!
+//! ```toml
+//! [[operations]]
+//! plugin = { name = "watermark", params = { text = "ACME" } }
+//! ```
+//!
+//! Images are passed to plugins as 8-bit RGBA.
+
+use crate::OperationError;
+use image::{DynamicImage, RgbaImage};
+use libloading::Library;
+use std::{
+	collections::HashMap,
+	ffi::{c_char, c_int, c_void, CStr, CString},
+	fs, io,
+	path::Path,
+	ptr, slice,
+	sync::{Arc, OnceLock, RwLock},
+};
+use thiserror::Error;
+
+/// Version of the plugin interface. Plugins declaring a different version are rejected.
+pub const ABI_VERSION: u32 = 1;
+
+const DECLARE_SYMBOL: &[u8] = b"imageless_plugin_declare\0";
+
+/// An RGBA8 image with `width * height * 4` bytes of pixel data.
+#[repr(C)]
+pub struct PluginImage {
+	pub width: u32,
+	pub height: u32,
+	pub data: *mut u8,
+}
+
+/// An operation provided by a plugin.
+#[repr(C)]
+pub struct PluginOperation {
+	/// Name of the operation in configs
+	pub name: *const c_char,
+	/// Processes the image in place, with the parameters from the config encoded as JSON.
+	///
+	/// A plugin which changes the dimensions replaces `data` with its own allocation, which is
+	/// released with `free`. On failure a non-zero status is returned and `error` may be set to a
+	/// message which is also released with `free`.
+	pub process: unsafe extern "C" fn(
+		params: *const c_char,
+		image: *mut PluginImage,
+		error: *mut *mut c_char,
+	) -> c_int,
+	pub free: unsafe extern "C" fn(ptr: *mut c_void),
+}
+
+/// Returned by a plugin's `imageless_plugin_declare` function.
+#[repr(C)]
+pub struct PluginDeclaration {
+	pub abi_version: u32,
+	pub operation_count: usize,
+	pub operations: *const PluginOperation,
+}
+
+#[derive(Error, Debug)]
+pub enum PluginError {
+	#[error("Failed to load plugin")]
+	Load(#[from] libloading::Error),
+
+	#[error("Plugin interface version {0} is not supported")]
+	AbiVersion(u32),
+
+	#[error("Invalid plugin declaration")]
+	InvalidDeclaration,
+
+	#[error("Operation `{0}` is already registered")]
+	DuplicateOperation(String),
+
+	#[error("IO error")]
+	IoError(#[from] io::Error),
+}
+
+struct LoadedOperation {
+	process: unsafe extern "C" fn(*const c_char, *mut PluginImage, *mut *mut c_char) -> c_int,
+	free: unsafe extern "C" fn(*mut c_void),
+	// Keeps the library loaded for as long as its operations are registered
+	_library: Arc<Library>,
+}
+
+fn registry() -> &'static RwLock<HashMap<String, LoadedOperation>> {
+	static REGISTRY: OnceLock<RwLock<HashMap<String, LoadedOperation>>> = OnceLock::new();
+	REGISTRY.get_or_init(Default::default)
+}
+
+/// Loads a plugin and registers its operations, returning their names.
+///
+/// # Safety
+///
+/// Loading a library runs its initialisation code, and the plugin must implement the interface
+/// described by [`PluginDeclaration`] correctly.
+pub unsafe fn load_plugin<P: AsRef<Path>>(path: P) -> Result<Vec<String>, PluginError> {
+	let library = Arc::new(Library::new(path.as_ref())?);
+
+	let declare =
+		library.get::<unsafe extern "C" fn() -> *const PluginDeclaration>(DECLARE_SYMBOL)?;
+	let declaration = declare().as_ref().ok_or(PluginError::InvalidDeclaration)?;
+	if declaration.abi_version != ABI_VERSION {
+		return Err(PluginError::AbiVersion(declaration.abi_version));
+	}
+	if declaration.operations.is_null() {
+		return Err(PluginError::InvalidDeclaration);
+	}
+
+	let operations = slice::from_raw_parts(declaration.operations, declaration.operation_count)
+		.iter()
+		.map(|operation| {
+			if operation.name.is_null() {
+				return Err(PluginError::InvalidDeclaration);
+			}
+			let name = CStr::from_ptr(operation.name)
+				.to_str()
+				.map_err(|_| PluginError::InvalidDeclaration)?;
+
+			Ok((name.to_string(), operation.process, operation.free))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let mut registry = registry().write().unwrap_or_else(|err| err.into_inner());
+	if let Some((name, ..)) = operations
+		.iter()
+		.find(|(name, ..)| registry.contains_key(name))
+	{
+		return Err(PluginError::DuplicateOperation(name.clone()));
+	}
+
+	let names = operations.iter().map(|(name, ..)| name.clone()).collect();
+	for (name, process, free) in operations {
+		registry.insert(
+			name,
+			LoadedOperation {
+				process,
+				free,
+				_library: library.clone(),
+			},
+		);
+	}
+
+	Ok(names)
+}
+
+/// Loads every shared library in a directory with [`load_plugin`], returning the names of the
+/// registered operations.
+///
+/// # Safety
+///
+/// See [`load_plugin`].
+pub unsafe fn load_plugin_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<String>, PluginError> {
+	let mut paths = fs::read_dir(dir)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<Result<Vec<_>, _>>()?;
+	paths.retain(|path| {
+		path.is_file()
+			&& path
+				.extension()
+				.is_some_and(|extension| extension == std::env::consts::DLL_EXTENSION)
+	});
+	paths.sort();
+
+	let mut names = Vec::new();
+	for path in paths {
+		names.extend(load_plugin(path)?);
+	}
+
+	Ok(names)
+}
+
+/// Names of the operations registered by loaded plugins.
+pub fn registered_operations() -> Vec<String> {
+	let registry = registry().read().unwrap_or_else(|err| err.into_inner());
+	let mut names = registry.keys().cloned().collect::<Vec<_>>();
+	names.sort();
+	names
+}
+
+pub(crate) fn process(
+	name: &str,
+	params: &str,
+	image: DynamicImage,
+) -> Result<DynamicImage, OperationError> {
+	let registry = registry().read().unwrap_or_else(|err| err.into_inner());
+	let operation = registry
+		.get(name)
+		.ok_or_else(|| OperationError::new(format!("Unknown plugin operation `{name}`")))?;
+	let params = CString::new(params)
+		.map_err(|_| OperationError::new("Plugin parameters contain a NUL byte".to_string()))?;
+
+	let mut buffer = image.into_rgba8();
+	let (width, height) = buffer.dimensions();
+	let data = buffer.as_mut_ptr();
+	let mut plugin_image = PluginImage {
+		width,
+		height,
+		data,
+	};
+	let mut error = ptr::null_mut();
+
+	// SAFETY: the buffer holds `width * height * 4` bytes for the duration of the call
+	let status = unsafe { (operation.process)(params.as_ptr(), &mut plugin_image, &mut error) };
+
+	if status != 0 {
+		let message = if error.is_null() {
+			format!("Plugin operation `{name}` failed with status {status}")
+		} else {
+			// SAFETY: the plugin set `error` to a NUL-terminated string it allocated
+			unsafe {
+				let message = CStr::from_ptr(error).to_string_lossy().into_owned();
+				(operation.free)(error.cast());
+				message
+			}
+		};
+		return Err(OperationError::new(message));
+	}
+
+	if plugin_image.data == data {
+		if (plugin_image.width, plugin_image.height) != (width, height) {
+			return Err(OperationError::new(format!(
+				"Plugin operation `{name}` changed the dimensions without replacing the data"
+			)));
+		}
+		return Ok(DynamicImage::ImageRgba8(buffer));
+	}
+
+	let len = plugin_image.width as usize * plugin_image.height as usize * 4;
+	// SAFETY: a plugin replacing the data provides `width * height * 4` bytes which it allocated
+	let data = unsafe {
+		let data = slice::from_raw_parts(plugin_image.data, len).to_vec();
+		(operation.free)(plugin_image.data.cast());
+		data
+	};
+
+	RgbaImage::from_raw(plugin_image.width, plugin_image.height, data)
+		.map(DynamicImage::ImageRgba8)
+		.ok_or_else(|| OperationError::new(format!("Plugin operation `{name}` returned no image")))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::{registered_operations, registry, LoadedOperation, PluginImage};
+	use crate::{operations::Plugin, Process};
+	use image::{DynamicImage, Rgba, RgbaImage};
+	use libloading::os::unix::Library;
+	use std::{
+		ffi::{c_char, c_int, c_void, CStr, CString},
+		slice,
+		sync::Arc,
+	};
+
+	/// Registers `process` as if a plugin had been loaded, with the test binary as the library.
+	fn register(
+		name: &str,
+		process: unsafe extern "C" fn(*const c_char, *mut PluginImage, *mut *mut c_char) -> c_int,
+		free: unsafe extern "C" fn(*mut c_void),
+	) {
+		let mut registry = registry().write().unwrap();
+		registry.insert(
+			name.to_string(),
+			LoadedOperation {
+				process,
+				free,
+				_library: Arc::new(Library::this().into()),
+			},
+		);
+	}
+
+	unsafe extern "C" fn invert(
+		_params: *const c_char,
+		image: *mut PluginImage,
+		_error: *mut *mut c_char,
+	) -> c_int {
+		let image = &mut *image;
+		let len = image.width as usize * image.height as usize * 4;
+		for (index, sample) in slice::from_raw_parts_mut(image.data, len)
+			.iter_mut()
+			.enumerate()
+		{
+			if index % 4 != 3 {
+				*sample = 255 - *sample;
+			}
+		}
+		0
+	}
+
+	/// Replaces the image with a single gray pixel of the `value` parameter.
+	unsafe extern "C" fn dot(
+		params: *const c_char,
+		image: *mut PluginImage,
+		_error: *mut *mut c_char,
+	) -> c_int {
+		let params: serde_json::Value =
+			serde_json::from_slice(CStr::from_ptr(params).to_bytes()).unwrap();
+		let value = params["value"].as_u64().unwrap() as u8;
+		let image = &mut *image;
+		image.width = 1;
+		image.height = 1;
+		image.data = Box::into_raw(Box::new([value, value, value, 255])).cast();
+		0
+	}
+
+	unsafe extern "C" fn free_dot(ptr: *mut c_void) {
+		drop(Box::from_raw(ptr.cast::<[u8; 4]>()));
+	}
+
+	unsafe extern "C" fn fail(
+		_params: *const c_char,
+		_image: *mut PluginImage,
+		error: *mut *mut c_char,
+	) -> c_int {
+		*error = CString::new("Out of ink").unwrap().into_raw();
+		3
+	}
+
+	unsafe extern "C" fn free_string(ptr: *mut c_void) {
+		drop(CString::from_raw(ptr.cast()));
+	}
+
+	#[test]
+	fn runs_plugin_operations() {
+		register("test-invert", invert, free_string);
+		register("test-dot", dot, free_dot);
+		register("test-fail", fail, free_string);
+		let names = registered_operations();
+		assert!(["test-dot", "test-fail", "test-invert"]
+			.iter()
+			.all(|name| names.contains(&name.to_string())));
+
+		let plugin = |name: &str, params| Plugin {
+			name: name.to_string(),
+			params,
+		};
+		let image =
+			|| DynamicImage::ImageRgba8(RgbaImage::from_pixel(3, 2, Rgba([10, 20, 30, 128])));
+
+		let inverted = plugin("test-invert", serde_json::Value::Null)
+			.process(image())
+			.unwrap()
+			.into_rgba8();
+		assert_eq!((3, 2), inverted.dimensions());
+		assert_eq!(&Rgba([245, 235, 225, 128]), inverted.get_pixel(2, 1));
+
+		let dot = plugin("test-dot", serde_json::json!({ "value": 7 }))
+			.process(image())
+			.unwrap()
+			.into_rgba8();
+		assert_eq!(RgbaImage::from_pixel(1, 1, Rgba([7, 7, 7, 255])), dot);
+
+		let error = plugin("test-fail", serde_json::Value::Null)
+			.process(image())
+			.unwrap_err();
+		assert_eq!("Out of ink", error.message);
+		assert!(plugin("test-missing", serde_json::Value::Null)
+			.process(image())
+			.is_err());
+	}
+}
@@ -0,0 +1,128 @@
+//! Built-in pipelines for common jobs, as starting points for configs of your own.
+//!
+//! Presets are versioned so that their output stays the same for anyone relying on it. Changes
+//! are added as a new version, and [`get`] returns the latest one unless a version is asked for
+//! with `name@version`.
+
+use crate::{ImageOutputFormat, Operation};
+use serde::Deserialize;
+
+/// A named pipeline which ships with the crate.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Preset {
+	pub name: String,
+	pub version: u32,
+	pub description: String,
+	pub out_format: ImageOutputFormat,
+	#[serde(default)]
+	pub auto_orient: bool,
+	pub operations: Vec<Operation>,
+}
+
+const PRESETS: &[&str] = &[
+	r#"
+	name = "web-thumbnail"
+	version = 1
+	description = "Fits within 320x320 without upscaling, as WebP"
+	out_format = "web-p"
+	auto_orient = true
+
+	[[operations]]
+	resize = { width = { pixel = { pixels = 320 } }, height = { pixel = { pixels = 320 } }, filter = "lanczos3", crop_mode = "shrink-only" }
+	"#,
+	r#"
+	name = "avatar"
+	version = 1
+	description = "Square 256x256 crop around the most detailed area, as WebP"
+	out_format = "web-p"
+	auto_orient = true
+
+	[[operations]]
+	smart-crop = { ratio = [1, 1] }
+
+	[[operations]]
+	resize = { width = { pixel = { pixels = 256 } }, height = { pixel = { pixels = 256 } }, filter = "lanczos3", crop_mode = "exact" }
+	"#,
+	r#"
+	name = "og-image"
+	version = 1
+	description = "1200x630 social sharing card, as JPEG"
+	out_format = { jpeg = { quality = 85 } }
+	auto_orient = true
+
+	[[operations]]
+	smart-crop = { ratio = [40, 21] }
+
+	[[operations]]
+	resize = { width = { pixel = { pixels = 1200 } }, height = { pixel = { pixels = 630 } }, filter = "lanczos3", crop_mode = "exact" }
+	"#,
+	r#"
+	name = "scan-cleanup"
+	version = 1
+	description = "Trims the margins of scanned documents and stretches their contrast, as grayscale PNG"
+	out_format = "png"
+	auto_orient = true
+
+	[[operations]]
+	trim = { tolerance = 0.1 }
+
+	[[operations]]
+	grayscale = {}
+
+	[[operations]]
+	auto-contrast = { clip = 0.01 }
+
+	[[operations]]
+	sharpen = "light"
+	"#,
+];
+
+fn parse(preset: &str) -> Preset {
+	toml::from_str(preset).expect("built-in presets are valid")
+}
+
+/// Looks up a preset by `name`, or `name@version` for an older version.
+pub fn get(name: &str) -> Option<Preset> {
+	let (name, version) = match name.split_once('@') {
+		Some((name, version)) => (name, Some(version.parse::<u32>().ok()?)),
+		None => (name, None),
+	};
+
+	PRESETS
+		.iter()
+		.map(|preset| parse(preset))
+		.filter(|preset| preset.name == name)
+		.filter(|preset| version.is_none_or(|version| preset.version == version))
+		.max_by_key(|preset| preset.version)
+}
+
+/// Names and latest versions of the built-in presets.
+pub fn list() -> Vec<(String, u32)> {
+	let mut presets: Vec<(String, u32)> = Vec::new();
+	for preset in PRESETS.iter().map(|preset| parse(preset)) {
+		match presets.iter_mut().find(|(name, _)| *name == preset.name) {
+			Some((_, version)) => *version = (*version).max(preset.version),
+			None => presets.push((preset.name, preset.version)),
+		}
+	}
+	presets
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{get, list};
+
+	#[test]
+	fn presets_parse() {
+		for (name, version) in list() {
+			let preset = get(&name).unwrap();
+			assert_eq!(version, preset.version);
+			assert!(get(&format!("{name}@{version}")).is_some());
+		}
+
+		assert_eq!(2, get("avatar").unwrap().operations.len());
+		assert!(get("avatar@99").is_none());
+		assert!(get("unknown").is_none());
+	}
+}
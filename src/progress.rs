@@ -0,0 +1,64 @@
+//! Hooks for following long running pipelines and batches, such as to drive progress bars.
+
+use crate::Operation;
+use std::{fmt, path::Path, time::Duration};
+
+/// A step of processing which has started or finished.
+#[derive(Debug)]
+pub enum Progress<'a> {
+	/// Operation `position` out of `count` is about to run
+	OperationStarted {
+		position: usize,
+		count: usize,
+		operation: &'a Operation,
+	},
+	OperationFinished {
+		position: usize,
+		count: usize,
+		operation: &'a Operation,
+		elapsed: Duration,
+	},
+	/// A batch is about to process `path`
+	FileStarted { path: &'a Path, count: usize },
+	/// A batch has processed `path`, and `completed` out of `count` files so far. Files can
+	/// finish out of order when processed in parallel.
+	FileFinished {
+		path: &'a Path,
+		completed: usize,
+		count: usize,
+		elapsed: Duration,
+		succeeded: bool,
+	},
+}
+
+/// Receives [`Progress`] while processing, which can be called from several threads at once
+/// in parallel batches. Implemented for closures taking a [`Progress`].
+pub trait ProgressHandler: Send + Sync {
+	fn on_progress(&self, progress: Progress<'_>);
+}
+
+impl<F: Fn(Progress<'_>) + Send + Sync> ProgressHandler for F {
+	fn on_progress(&self, progress: Progress<'_>) {
+		self(progress)
+	}
+}
+
+impl fmt::Debug for dyn ProgressHandler {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ProgressHandler")
+	}
+}
+
+/// Runs `f`, measuring how long it takes. There is no clock on wasm32, so no time passes there.
+pub(crate) fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+	#[cfg(not(target_arch = "wasm32"))]
+	{
+		let start = std::time::Instant::now();
+		let result = f();
+		(result, start.elapsed())
+	}
+	#[cfg(target_arch = "wasm32")]
+	{
+		(f(), Duration::ZERO)
+	}
+}
@@ -0,0 +1,172 @@
+//! Python bindings, built as an extension module with
+//! `maturin build --features python,pyo3/extension-module`.
+//!
+//! ```python
+//! import imageless
+//!
+//! pipeline = imageless.Pipeline({"jpeg": {"quality": 80}})
+//! pipeline.add("grayscale")
+//! pipeline.add("blur", sigma=1.5)
+//! output = imageless.process_file("input.png", pipeline)
+//! ```
+
+use crate::{Operation, Pipeline};
+use pyo3::{
+	create_exception,
+	exceptions::{PyException, PyValueError},
+	prelude::*,
+	types::{PyBytes, PyDict},
+};
+use serde::de::DeserializeOwned;
+use std::fs;
+
+create_exception!(imageless, ImagelessError, PyException);
+
+/// Builds up a list of operations and the output format to encode their result in.
+#[pyclass(name = "Pipeline")]
+struct PyPipeline {
+	pipeline: Pipeline,
+}
+
+#[pymethods]
+impl PyPipeline {
+	/// Creates an empty pipeline encoding to `out_format`, e.g. `"png"` or
+	/// `{"jpeg": {"quality": 80}}`.
+	#[new]
+	fn new(out_format: &Bound<'_, PyAny>) -> PyResult<Self> {
+		Ok(Self {
+			pipeline: Pipeline {
+				out_format: from_python(out_format)?,
+				auto_orient: false,
+				keep_metadata: false,
+				operations: Vec::new(),
+			},
+		})
+	}
+
+	/// Parses a pipeline from the same JSON accepted by the other bindings.
+	#[staticmethod]
+	fn from_json(json: &str) -> PyResult<Self> {
+		let pipeline =
+			serde_json::from_str(json).map_err(|err| PyValueError::new_err(err.to_string()))?;
+		Ok(Self { pipeline })
+	}
+
+	/// Appends an operation by name, with its parameters passed as keyword arguments, e.g.
+	/// `pipeline.add("blur", sigma=2.0)`.
+	#[pyo3(signature = (name, **params))]
+	fn add<'py>(
+		mut slf: PyRefMut<'py, Self>,
+		name: &str,
+		params: Option<&Bound<'py, PyDict>>,
+	) -> PyResult<PyRefMut<'py, Self>> {
+		let py = slf.py();
+		let operation = PyDict::new(py);
+		operation.set_item(name, params.cloned().unwrap_or_else(|| PyDict::new(py)))?;
+
+		let operation: Operation = from_python(operation.as_any())?;
+		slf.pipeline.operations.push(operation);
+		Ok(slf)
+	}
+
+	fn to_json(&self) -> PyResult<String> {
+		serde_json::to_string(&self.pipeline).map_err(|err| PyValueError::new_err(err.to_string()))
+	}
+
+	/// Processes an encoded image and returns the encoded result.
+	fn process_bytes<'py>(&self, py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+		let output = py
+			.allow_threads(|| self.pipeline.process_bytes(data))
+			.map_err(|err| ImagelessError::new_err(err.to_string()))?;
+		Ok(PyBytes::new(py, &output))
+	}
+}
+
+/// Processes an encoded image with `pipeline` and returns the encoded result.
+#[pyfunction]
+fn process_bytes<'py>(
+	py: Python<'py>,
+	data: &[u8],
+	pipeline: &PyPipeline,
+) -> PyResult<Bound<'py, PyBytes>> {
+	pipeline.process_bytes(py, data)
+}
+
+/// Processes the image at `path` with `pipeline` and returns the encoded result.
+#[pyfunction]
+fn process_file<'py>(
+	py: Python<'py>,
+	path: &str,
+	pipeline: &PyPipeline,
+) -> PyResult<Bound<'py, PyBytes>> {
+	let data = fs::read(path).map_err(|err| ImagelessError::new_err(err.to_string()))?;
+	pipeline.process_bytes(py, &data)
+}
+
+/// Converts a Python value to a serde type by round-tripping it through JSON.
+fn from_python<T: DeserializeOwned>(value: &Bound<'_, PyAny>) -> PyResult<T> {
+	let json: String = value
+		.py()
+		.import("json")?
+		.call_method1("dumps", (value,))?
+		.extract()?;
+	serde_json::from_str(&json).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+#[pymodule]
+fn imageless(m: &Bound<'_, PyModule>) -> PyResult<()> {
+	m.add("ImagelessError", m.py().get_type::<ImagelessError>())?;
+	m.add_class::<PyPipeline>()?;
+	m.add_function(wrap_pyfunction!(process_bytes, m)?)?;
+	m.add_function(wrap_pyfunction!(process_file, m)?)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::PyPipeline;
+	use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat, RgbImage};
+	use pyo3::{exceptions::PyValueError, ffi::c_str, prelude::*, types::PyDict};
+	use std::io::Cursor;
+
+	#[test]
+	fn builds_pipelines() -> PyResult<()> {
+		let mut png = Cursor::new(Vec::new());
+		DynamicImage::ImageRgb8(RgbImage::new(8, 6))
+			.write_to(&mut png, ImageOutputFormat::Png)
+			.unwrap();
+
+		pyo3::prepare_freethreaded_python();
+		Python::with_gil(|py| {
+			let out_format = py.eval(c_str!("{'jpeg': {'quality': 70}}"), None, None)?;
+			let pipeline = Bound::new(py, PyPipeline::new(&out_format)?)?;
+			let params = PyDict::new(py);
+			params.set_item("sigma", 1.5)?;
+			PyPipeline::add(pipeline.borrow_mut(), "blur", Some(&params))?;
+			PyPipeline::add(pipeline.borrow_mut(), "grayscale", None)?;
+			let unknown = PyPipeline::add(pipeline.borrow_mut(), "unknown", None).err();
+			assert!(unknown.is_some_and(|error| error.is_instance_of::<PyValueError>(py)));
+
+			let pipeline = pipeline.borrow();
+			assert_eq!(
+				r#"{"out_format":{"jpeg":{"quality":70}},"auto_orient":false,"keep_metadata":false,"operations":[{"blur":{"sigma":1.5}},{"grayscale":{}}]}"#,
+				pipeline.to_json()?
+			);
+			let json = PyPipeline::from_json(&pipeline.to_json()?)?;
+			assert_eq!(2, json.pipeline.operations.len());
+
+			let output = pipeline.process_bytes(py, png.get_ref())?;
+			assert_eq!(
+				ImageFormat::Jpeg,
+				image::guess_format(output.as_bytes()).unwrap()
+			);
+			assert_eq!(
+				(8, 6),
+				image::load_from_memory(output.as_bytes())
+					.unwrap()
+					.dimensions()
+			);
+			Ok(())
+		})
+	}
+}
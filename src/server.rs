@@ -0,0 +1,371 @@
+//! An axum router serving images transformed on the fly, enabled with the `server` feature.
+//!
+//! Requests use the [URL syntax](crate::url), with the path of the source image following the
+//! options, e.g. `GET /rs:fill:300:300/f:webp/photos/cat.jpg`:
+//!
+//! ```ignore
+//! use imageless::server::{DirectorySource, ImageService};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let router = ImageService::new(DirectorySource::new("./images")).into_router();
+//!     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+//!     axum::serve(listener, router).await.unwrap();
+//! }
+//! ```
+//!
+//! The router is a [`tower::Service`](https://docs.rs/tower/latest/tower/trait.Service.html), so
+//! it can also be nested in an existing application with [`Router::nest`].
+//!
+//! When a request doesn't specify an output format, AVIF or WebP is chosen if the `Accept` header
+//! allows it, falling back to the format of the source image.
+
+use crate::{
+	cache::Cache,
+	url::{self, UrlError},
+	DecodeLimits, ImageOutputFormat, Pipeline, ProcessOptions,
+};
+use axum::{
+	body::Body,
+	extract::{Path, State},
+	http::{
+		header::{ACCEPT, CONTENT_TYPE, VARY},
+		HeaderMap, StatusCode,
+	},
+	response::{IntoResponse, Response},
+	routing::get,
+	Router,
+};
+use std::{
+	io,
+	path::{Component, PathBuf},
+	sync::Arc,
+};
+
+/// Resolves the source path of a request to the bytes of the image.
+pub trait SourceResolver: Send + Sync + 'static {
+	fn resolve(&self, source: &str) -> io::Result<Vec<u8>>;
+}
+
+/// Resolves sources relative to a directory. Paths which would escape the directory are not found.
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+	root: PathBuf,
+}
+
+impl DirectorySource {
+	pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+		Self { root: root.into() }
+	}
+}
+
+impl SourceResolver for DirectorySource {
+	fn resolve(&self, source: &str) -> io::Result<Vec<u8>> {
+		let path = std::path::Path::new(source);
+		if !path
+			.components()
+			.all(|component| matches!(component, Component::Normal(_)))
+		{
+			return Err(io::ErrorKind::NotFound.into());
+		}
+
+		std::fs::read(self.root.join(path))
+	}
+}
+
+struct Signing {
+	key: Vec<u8>,
+	salt: Vec<u8>,
+}
+
+/// Serves transformed images from a [`SourceResolver`].
+pub struct ImageService {
+	source: Box<dyn SourceResolver>,
+	cache: Option<Arc<dyn Cache>>,
+	signing: Option<Signing>,
+	limits: DecodeLimits,
+}
+
+impl ImageService {
+	pub fn new<S: SourceResolver>(source: S) -> Self {
+		Self {
+			source: Box::new(source),
+			cache: None,
+			signing: None,
+			limits: DecodeLimits::default(),
+		}
+	}
+
+	/// Looks up processed images in `cache` before processing, and stores them after. Entries are
+	/// keyed by the source bytes and the pipeline with [`cache::key`](crate::cache::key), so a
+	/// changed source is processed again.
+	pub fn with_cache<C: Cache + 'static>(mut self, cache: C) -> Self {
+		self.cache = Some(Arc::new(cache));
+		self
+	}
+
+	/// Only serves paths signed with [`url::sign`] using the same key and salt.
+	pub fn with_signing_key(mut self, key: &[u8], salt: &[u8]) -> Self {
+		self.signing = Some(Signing {
+			key: key.to_vec(),
+			salt: salt.to_vec(),
+		});
+		self
+	}
+
+	/// Rejects sources beyond `limits` before decoding them, rather than the default of only
+	/// limiting allocations.
+	pub fn with_decode_limits(mut self, limits: DecodeLimits) -> Self {
+		self.limits = limits;
+		self
+	}
+
+	pub fn into_router(self) -> Router {
+		Router::new()
+			.route("/{*path}", get(handle))
+			.with_state(Arc::new(self))
+	}
+}
+
+async fn handle(
+	State(service): State<Arc<ImageService>>,
+	Path(path): Path<String>,
+	headers: HeaderMap,
+) -> Response {
+	let parsed = match &service.signing {
+		Some(signing) => url::parse_signed(&path, &signing.key, &signing.salt),
+		None => url::parse(&path),
+	};
+	let parsed = match parsed {
+		Ok(parsed) => parsed,
+		Err(err @ UrlError::InvalidSignature) => {
+			return (StatusCode::FORBIDDEN, err.to_string()).into_response()
+		}
+		Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+	};
+	let Some(source) = parsed.source else {
+		return (StatusCode::NOT_FOUND, "No source image").into_response();
+	};
+
+	let negotiated = parsed.out_format.is_none();
+	let out_format = match parsed.out_format {
+		Some(out_format) => out_format,
+		None => url::with_quality(negotiate(&headers, &source), parsed.quality),
+	};
+	// Sources are often straight from cameras, so they're always displayed upright.
+	let pipeline = Pipeline {
+		out_format,
+		auto_orient: true,
+		keep_metadata: false,
+		operations: parsed.operations,
+	};
+
+	let mime_type = pipeline.out_format.mime_type();
+
+	let result = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, (StatusCode, String)> {
+		let input = service
+			.source
+			.resolve(&source)
+			.map_err(|err| match err.kind() {
+				io::ErrorKind::NotFound => {
+					(StatusCode::NOT_FOUND, "Source image not found".to_string())
+				}
+				_ => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+			})?;
+		let options = ProcessOptions {
+			limits: service.limits,
+			cache: service.cache.clone(),
+			..ProcessOptions::default()
+		};
+		pipeline
+			.process_bytes_with_options(&input, options)
+			.map_err(|err| (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()))
+	})
+	.await;
+
+	match result {
+		Ok(Ok(output)) => {
+			let mut response = ([(CONTENT_TYPE, mime_type)], Body::from(output)).into_response();
+			if negotiated {
+				response
+					.headers_mut()
+					.insert(VARY, ACCEPT.as_str().parse().expect("valid header value"));
+			}
+			response
+		}
+		Ok(Err(err)) => err.into_response(),
+		Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+	}
+}
+
+/// Chooses AVIF or WebP when the client accepts them, preferring the higher quality value and
+/// AVIF when they are equal, otherwise the format of the source. Only explicit media types count,
+/// since wildcards are sent by clients regardless of the formats they decode, and a quality of
+/// zero refuses a type.
+fn negotiate(headers: &HeaderMap, source: &str) -> ImageOutputFormat {
+	let quality = |mime_type: &str| {
+		headers
+			.get_all(ACCEPT)
+			.iter()
+			.filter_map(|value| value.to_str().ok())
+			.flat_map(|value| value.split(','))
+			.filter_map(|accepted| {
+				let mut parameters = accepted.split(';');
+				if parameters.next()?.trim() != mime_type {
+					return None;
+				}
+				let quality = parameters
+					.filter_map(|parameter| parameter.trim().strip_prefix("q="))
+					.find_map(|quality| quality.trim().parse::<f32>().ok())
+					.unwrap_or(1.0);
+				Some(quality)
+			})
+			.fold(0.0f32, f32::max)
+	};
+
+	let avif = quality(ImageOutputFormat::Avif.mime_type());
+	let webp = quality(ImageOutputFormat::webp().mime_type());
+	if avif > 0.0 && avif >= webp {
+		ImageOutputFormat::Avif
+	} else if webp > 0.0 {
+		ImageOutputFormat::webp()
+	} else {
+		source
+			.rsplit_once('.')
+			.and_then(|(_, extension)| ImageOutputFormat::from_extension(extension))
+			.unwrap_or(ImageOutputFormat::png())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{handle, negotiate, ImageService, SourceResolver};
+	use crate::cache::DirCache;
+	use axum::{
+		extract::{Path, State},
+		http::{
+			header::{ACCEPT, CONTENT_TYPE},
+			HeaderMap,
+		},
+	};
+	use image::{DynamicImage, GenericImageView, ImageOutputFormat, Rgb, RgbImage};
+	use std::{
+		fs,
+		io::{self, Cursor},
+		sync::{Arc, Mutex},
+	};
+
+	struct MemorySource(Arc<Mutex<Vec<u8>>>);
+
+	impl SourceResolver for MemorySource {
+		fn resolve(&self, _source: &str) -> io::Result<Vec<u8>> {
+			Ok(self.0.lock().unwrap().clone())
+		}
+	}
+
+	fn png(color: Rgb<u8>) -> Vec<u8> {
+		let mut png = Cursor::new(Vec::new());
+		DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, color))
+			.write_to(&mut png, ImageOutputFormat::Png)
+			.unwrap();
+		png.into_inner()
+	}
+
+	fn negotiated(accept: &str, source: &str) -> &'static str {
+		let mut headers = HeaderMap::new();
+		headers.insert(ACCEPT, accept.parse().unwrap());
+		negotiate(&headers, source).mime_type()
+	}
+
+	#[test]
+	fn caches_by_source_contents() {
+		let source = Arc::new(Mutex::new(png(Rgb([255, 0, 0]))));
+		let dir = std::env::temp_dir().join(format!("imageless-server-{}", std::process::id()));
+		let service = Arc::new(
+			ImageService::new(MemorySource(source.clone())).with_cache(DirCache::new(&dir)),
+		);
+
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.unwrap();
+		let request = || {
+			runtime.block_on(async {
+				let response = handle(
+					State(service.clone()),
+					Path("f:png/photo.png".to_string()),
+					HeaderMap::new(),
+				)
+				.await;
+				let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+					.await
+					.unwrap();
+				image::load_from_memory(&body).unwrap().get_pixel(0, 0).0
+			})
+		};
+
+		assert_eq!([255, 0, 0, 255], request());
+		assert_eq!([255, 0, 0, 255], request());
+		assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+
+		// The same path with a changed source isn't served from the cache
+		*source.lock().unwrap() = png(Rgb([0, 0, 255]));
+		assert_eq!([0, 0, 255, 255], request());
+		assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn negotiates_formats_for_quality_only_paths() {
+		let service = Arc::new(ImageService::new(MemorySource(Arc::new(Mutex::new(png(
+			Rgb([255, 0, 0]),
+		))))));
+		let mut headers = HeaderMap::new();
+		headers.insert(ACCEPT, "image/webp".parse().unwrap());
+
+		let response = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.unwrap()
+			.block_on(handle(
+				State(service),
+				Path("q:50/photo.jpg".to_string()),
+				headers,
+			));
+		assert_eq!("image/webp", response.headers()[CONTENT_TYPE]);
+	}
+
+	#[test]
+	fn negotiates_by_quality() {
+		assert_eq!("image/avif", negotiated("image/avif,image/webp", "a.jpg"));
+		assert_eq!(
+			"image/webp",
+			negotiated("image/avif;q=0.5, image/webp;q=0.9", "a.jpg")
+		);
+		assert_eq!(
+			"image/avif",
+			negotiated("image/webp;q=0.8,image/avif;q=0.8", "a.jpg")
+		);
+		assert_eq!("image/webp", negotiated("image/webp", "a.jpg"));
+	}
+
+	#[test]
+	fn excludes_refused_types() {
+		assert_eq!(
+			"image/webp",
+			negotiated("image/avif;q=0,image/webp", "a.jpg")
+		);
+		assert_eq!(
+			"image/jpeg",
+			negotiated("image/avif; q=0, image/webp;q=0.0", "a.jpg")
+		);
+	}
+
+	#[test]
+	fn ignores_wildcards() {
+		assert_eq!("image/jpeg", negotiated("image/*,*/*;q=0.8", "a.jpg"));
+		assert_eq!("image/png", negotiated("*/*", "a.svg"));
+		assert_eq!(
+			"image/avif",
+			negotiated("image/avif,image/*;q=0.9,*/*;q=0.8", "a.png")
+		);
+	}
+}
@@ -0,0 +1,165 @@
+//! Loops over 8-bit samples for the hottest per-pixel operations. With the `simd` feature they run
+//! on SSE2 and, when the CPU has it, AVX2 on x86_64. Vectorized and scalar loops give identical
+//! results, and the scalar ones handle other architectures and the samples after the last full
+//! vector.
+
+/// Whether sample `index` of interleaved pixels with `channels` samples is alpha, which is the last
+/// sample of pixels with 2 or 4 channels.
+fn is_alpha(index: usize, channels: usize) -> bool {
+	channels.is_multiple_of(2) && index % channels == channels - 1
+}
+
+/// Adds `value` to the color samples of interleaved pixels with `channels` samples, saturating at
+/// 0 and 255, and leaves alpha as it is.
+pub(crate) fn brighten(samples: &mut [u8], channels: usize, value: i32) {
+	#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+	let done = brighten_sse2(samples, channels, value);
+	#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+	let done = 0;
+
+	for (index, sample) in samples.iter_mut().enumerate().skip(done) {
+		if !is_alpha(index, channels) {
+			*sample = (*sample as i32 + value).clamp(0, 255) as u8;
+		}
+	}
+}
+
+/// Brightens whole vectors of 16 samples, returning how many samples were done. Pixels with alpha
+/// have 2 or 4 channels, so every vector starts at the first channel of a pixel.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+fn brighten_sse2(samples: &mut [u8], channels: usize, value: i32) -> usize {
+	use std::arch::x86_64::*;
+
+	let amount = value.unsigned_abs().min(255) as u8;
+	let lanes: [u8; 16] =
+		std::array::from_fn(|lane| if is_alpha(lane, channels) { 0 } else { amount });
+	let done = samples.len() / 16 * 16;
+
+	// SAFETY: SSE2 is part of x86_64, and each unaligned load and store covers one chunk
+	unsafe {
+		let amount = _mm_loadu_si128(lanes.as_ptr().cast());
+		for chunk in samples[..done].chunks_exact_mut(16) {
+			let pointer = chunk.as_mut_ptr().cast::<__m128i>();
+			let vector = _mm_loadu_si128(pointer);
+			let vector = if value < 0 {
+				_mm_subs_epu8(vector, amount)
+			} else {
+				_mm_adds_epu8(vector, amount)
+			};
+			_mm_storeu_si128(pointer, vector);
+		}
+	}
+
+	done
+}
+
+/// Maps the color samples of interleaved pixels with `channels` samples through the table of their
+/// channel, and leaves alpha as it is.
+pub(crate) fn apply_lut(samples: &mut [u8], channels: usize, tables: &[[u8; 256]; 3]) {
+	#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+	let done = if std::arch::is_x86_feature_detected!("avx2") {
+		// SAFETY: the CPU has just been checked for AVX2
+		unsafe { apply_lut_avx2(samples, channels, tables) }
+	} else {
+		0
+	};
+	#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+	let done = 0;
+
+	for (index, sample) in samples.iter_mut().enumerate().skip(done) {
+		*sample = lut_entry(tables, channels, index % channels, *sample);
+	}
+}
+
+fn lut_entry(tables: &[[u8; 256]; 3], channels: usize, channel: usize, sample: u8) -> u8 {
+	if is_alpha(channel, channels) {
+		sample
+	} else {
+		tables[channel.min(2)][sample as usize]
+	}
+}
+
+/// Looks up whole vectors of 8 samples with gathers, returning how many samples were done. Vectors
+/// are taken `channels` at a time, after which the channels of the lanes repeat.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn apply_lut_avx2(samples: &mut [u8], channels: usize, tables: &[[u8; 256]; 3]) -> usize {
+	use std::arch::x86_64::*;
+
+	// The tables of every channel widened for gathering, indexed by channel * 256 + sample
+	let wide: Vec<i32> = (0..channels)
+		.flat_map(|channel| (0..=255).map(move |sample| (channel, sample)))
+		.map(|(channel, sample)| lut_entry(tables, channels, channel, sample) as i32)
+		.collect();
+	let offsets: Vec<__m256i> = (0..channels)
+		.map(|vector| {
+			let lanes: [i32; 8] =
+				std::array::from_fn(|lane| ((vector * 8 + lane) % channels * 256) as i32);
+			_mm256_loadu_si256(lanes.as_ptr().cast())
+		})
+		.collect();
+	// Packing leaves the first four bytes in the first 32 bits of each half
+	let halves = _mm256_setr_epi32(0, 4, 0, 0, 0, 0, 0, 0);
+	let done = samples.len() / (8 * channels) * 8 * channels;
+
+	for (index, chunk) in samples[..done].chunks_exact_mut(8).enumerate() {
+		let pointer = chunk.as_mut_ptr().cast::<__m128i>();
+		let indices = _mm256_add_epi32(
+			_mm256_cvtepu8_epi32(_mm_loadl_epi64(pointer)),
+			offsets[index % channels],
+		);
+		let values = _mm256_i32gather_epi32::<4>(wide.as_ptr(), indices);
+		let words = _mm256_packus_epi32(values, values);
+		let bytes = _mm256_permutevar8x32_epi32(_mm256_packus_epi16(words, words), halves);
+		_mm_storel_epi64(pointer, _mm256_castsi256_si128(bytes));
+	}
+
+	done
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{apply_lut, brighten};
+	use image::{DynamicImage, Rgba, RgbaImage};
+
+	#[test]
+	fn matches_scalar_loops() {
+		// An odd size leaves samples after the last full vector
+		let image = RgbaImage::from_fn(37, 23, |x, y| {
+			Rgba([
+				(x * 7) as u8,
+				(y * 11) as u8,
+				(x * y) as u8,
+				(x + y * 3) as u8,
+			])
+		});
+
+		for value in [40, -70, 300] {
+			let mut samples = image.clone().into_raw();
+			brighten(&mut samples, 4, value);
+			let expected = DynamicImage::ImageRgba8(image.clone()).brighten(value);
+			assert_eq!(expected.as_bytes(), samples);
+		}
+
+		let tables = [
+			std::array::from_fn(|sample| 255 - sample as u8),
+			std::array::from_fn(|sample| (sample * sample / 255) as u8),
+			std::array::from_fn(|sample| (sample / 2) as u8),
+		];
+		for channels in [3, 4] {
+			let mut samples = image.clone().into_raw();
+			samples.truncate(samples.len() / 12 * 12);
+			let expected: Vec<u8> = samples
+				.iter()
+				.enumerate()
+				.map(|(index, &sample)| match index % channels {
+					3 => sample,
+					channel => tables[channel][sample as usize],
+				})
+				.collect();
+
+			apply_lut(&mut samples, channels, &tables);
+			assert_eq!(expected, samples);
+		}
+	}
+}
@@ -0,0 +1,986 @@
+//! Processing in strips of rows, so that images too large to decode in full can still be
+//! processed.
+//!
+//! PNG and TIFF sources are read a strip at a time, each strip runs through the operations, and
+//! the result is written out as PNG as soon as it is ready. Interlaced PNGs, TIFFs with unusual
+//! sample types and other formats are decoded in full first.
+//!
+//! Only operations which need a bounded number of neighbouring rows can run this way, which are
+//! those changing each pixel on its own, neighbourhood filters such as `blur`, crops, and resizes
+//! which don't crop. Results match processing the whole image, other than resizes rounding
+//! differently by a level or two with the `fast-resize` feature.
+
+use crate::{
+	decode, encodable,
+	encode::png_encoding_error,
+	operations::{CropMode, FilterType, Resize, Sharpen},
+	optimize::crop_bounds,
+	plan::ImageInfo,
+	Error, ImageOutputFormat, Operation, OperationError, ProcessOptions,
+};
+use image::{
+	error::{DecodingError, EncodingError, ImageFormatHint},
+	io::Reader as ImageReader,
+	ColorType, DynamicImage, ImageBuffer, ImageError, ImageFormat, Pixel, Primitive,
+};
+use num::{NumCast, ToPrimitive};
+use std::{
+	borrow::Cow,
+	f32::consts::PI,
+	io::{BufRead, Read, Seek, SeekFrom, Write},
+	ops::Range,
+};
+use tiff::decoder::{ChunkType, Decoder as TiffDecoder, DecodingResult};
+
+/// Rows read from the source at a time by default.
+pub const DEFAULT_STRIP_HEIGHT: u32 = 256;
+
+/// How an operation can run on strips.
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+	/// Each pixel only depends on itself
+	Pixel,
+	/// Each pixel depends on the rows up to this far away
+	Window(u32),
+	Crop,
+	Resample(FilterType),
+}
+
+fn kind(operation: &Operation) -> Option<Kind> {
+	// Rows within reach of the blurs in `image`
+	let blur_radius = |sigma: f32| {
+		let sigma = if sigma <= 0.0 { 1.0 } else { sigma };
+		(2.0 * sigma).ceil() as u32 + 1
+	};
+
+	match operation {
+		Operation::AdjustBrightness(_)
+		| Operation::Curves(_)
+		| Operation::GradientMap(_)
+		| Operation::Grayscale(_)
+		| Operation::HueRotate(_)
+		| Operation::Invert(_)
+		| Operation::Saturation(_)
+		| Operation::Sepia(_)
+		| Operation::Solarize(_)
+		| Operation::Tint(_) => Some(Kind::Pixel),
+		Operation::Blur(blur) => Some(Kind::Window(blur_radius(blur.sigma))),
+		Operation::Unsharpen(unsharpen) => Some(Kind::Window(blur_radius(unsharpen.sigma))),
+		// Invalid kernels fail when they're processed
+		Operation::Convolve(convolve) => Some(Kind::Window(
+			convolve
+				.kernel
+				.weights()
+				.map_or(1, |(_, size)| size as u32 / 2),
+		)),
+		Operation::Sharpen(Sharpen::Custom(rows)) => Some(Kind::Window(rows.len() as u32 / 2)),
+		Operation::Sharpen(_) => Some(Kind::Window(1)),
+		Operation::MedianFilter(median) => Some(Kind::Window(median.radius)),
+		Operation::Crop(_) | Operation::CropGravity(_) | Operation::CropToAspect(_) => {
+			Some(Kind::Crop)
+		}
+		Operation::Resize(Resize {
+			filter,
+			crop_mode:
+				CropMode::Preserve | CropMode::Exact | CropMode::ShrinkOnly | CropMode::EnlargeOnly,
+			..
+		}) => Some(Kind::Resample(*filter)),
+		Operation::Scale(scale) => Some(Kind::Resample(scale.filter)),
+		_ => None,
+	}
+}
+
+/// Whether every one of `operations` can run on strips.
+pub fn supports(operations: &[Operation]) -> bool {
+	operations.iter().all(|operation| kind(operation).is_some())
+}
+
+/// Processes the image from `reader` with `operations` a strip of `strip_height` rows at a time,
+/// writing the result to `writer` as PNG. Returns the dimensions of the result.
+///
+/// The decode limits and cancellation of `options` apply, but the source is not oriented.
+pub fn process_strips<R: BufRead + Seek, W: Write>(
+	reader: R,
+	writer: W,
+	operations: &[Operation],
+	strip_height: u32,
+	options: &ProcessOptions,
+) -> Result<(u32, u32), Error> {
+	let mut source = Source::open(reader, options)?;
+	let mut stages = Vec::with_capacity(operations.len());
+	let mut image = source.info();
+	for operation in operations {
+		let Some(kind) = kind(operation) else {
+			return Err(OperationError::new(format!(
+				"`{}` can't run on strips of the image",
+				operation.name()
+			))
+			.into());
+		};
+		let Some((width, height)) = operation.get_process().plan(image)? else {
+			unreachable!("operations which run on strips have a known size");
+		};
+
+		stages.push(match kind {
+			Kind::Pixel => Stage::Pixel(operation),
+			Kind::Window(radius) => Stage::Window {
+				operation,
+				radius,
+				height,
+				rows: Rows::default(),
+				next: 0,
+			},
+			Kind::Crop => Stage::Crop {
+				bounds: crop_bounds(operation, image).expect("crops have bounds"),
+				row: 0,
+			},
+			// Resizing to the same size leaves the image as it is
+			Kind::Resample(_) if (width, height) == image.dimensions() => Stage::Pixel(operation),
+			Kind::Resample(filter) => Stage::Resample {
+				vertical: Axis::new(image.height, height, filter),
+				horizontal: Axis::new(image.width, width, filter),
+				rows: Rows::default(),
+				next: 0,
+			},
+		});
+		image = ImageInfo::new(width, height, image.color_type);
+	}
+
+	// Output strips in order, which may take several strips of the source each
+	let mut finished = false;
+	let mut next = || -> Result<Option<DynamicImage>, Error> {
+		while !finished {
+			if options.is_cancelled() {
+				return Err(Error::Cancelled);
+			}
+
+			let mut strip = source.read(strip_height.max(1))?;
+			finished = strip.is_none();
+			for stage in stages.iter_mut() {
+				strip = stage.push(strip, finished)?;
+			}
+			if let Some(strip) = strip {
+				return Ok(Some(
+					encodable(&strip, &ImageOutputFormat::png()).unwrap_or(strip),
+				));
+			}
+		}
+		Ok(None)
+	};
+
+	// The color type is only known once operations have run on the first strip
+	if let Some(strip) = next()? {
+		let mut encoder = png_writer(writer, image.dimensions(), strip.color())?;
+		let mut stream = encoder.stream_writer().map_err(png_encoding_error)?;
+		stream.write_all(&png_samples(&strip))?;
+		while let Some(strip) = next()? {
+			stream.write_all(&png_samples(&strip))?;
+		}
+		stream.finish().map_err(png_encoding_error)?;
+	}
+
+	Ok(image.dimensions())
+}
+
+/// Rows which a stage has received and still needs, from row `start` up to row `end`.
+#[derive(Debug, Default)]
+struct Rows {
+	image: Option<DynamicImage>,
+	start: u32,
+	end: u32,
+}
+
+impl Rows {
+	fn push(&mut self, strip: DynamicImage) {
+		self.end += strip.height();
+		self.image = Some(match self.image.take() {
+			Some(image) => stack(image, &strip),
+			None => strip,
+		});
+	}
+
+	fn get(&self, rows: Range<u32>) -> DynamicImage {
+		let image = self.image.as_ref().expect("rows have been received");
+		image.crop_imm(0, rows.start - self.start, image.width(), rows.len() as u32)
+	}
+
+	fn drop_before(&mut self, row: u32) {
+		let row = row.min(self.end);
+		if row <= self.start {
+			return;
+		}
+
+		self.image = match self.image.take() {
+			Some(image) if row < self.end => {
+				Some(image.crop_imm(0, row - self.start, image.width(), self.end - row))
+			}
+			_ => None,
+		};
+		self.start = row;
+	}
+}
+
+/// Appends the rows of `bottom` to `top`.
+fn stack(top: DynamicImage, bottom: &DynamicImage) -> DynamicImage {
+	fn join<P: Pixel>(
+		top: ImageBuffer<P, Vec<P::Subpixel>>,
+		bottom: &ImageBuffer<P, Vec<P::Subpixel>>,
+	) -> ImageBuffer<P, Vec<P::Subpixel>> {
+		let (width, height) = (top.width(), top.height() + bottom.height());
+		let mut samples = top.into_raw();
+		samples.extend_from_slice(bottom.as_raw());
+		ImageBuffer::from_raw(width, height, samples).expect("rows have the same width")
+	}
+
+	match (top, bottom) {
+		(DynamicImage::ImageLuma8(top), DynamicImage::ImageLuma8(bottom)) => {
+			DynamicImage::ImageLuma8(join(top, bottom))
+		}
+		(DynamicImage::ImageLumaA8(top), DynamicImage::ImageLumaA8(bottom)) => {
+			DynamicImage::ImageLumaA8(join(top, bottom))
+		}
+		(DynamicImage::ImageRgb8(top), DynamicImage::ImageRgb8(bottom)) => {
+			DynamicImage::ImageRgb8(join(top, bottom))
+		}
+		(DynamicImage::ImageRgba8(top), DynamicImage::ImageRgba8(bottom)) => {
+			DynamicImage::ImageRgba8(join(top, bottom))
+		}
+		(DynamicImage::ImageLuma16(top), DynamicImage::ImageLuma16(bottom)) => {
+			DynamicImage::ImageLuma16(join(top, bottom))
+		}
+		(DynamicImage::ImageLumaA16(top), DynamicImage::ImageLumaA16(bottom)) => {
+			DynamicImage::ImageLumaA16(join(top, bottom))
+		}
+		(DynamicImage::ImageRgb16(top), DynamicImage::ImageRgb16(bottom)) => {
+			DynamicImage::ImageRgb16(join(top, bottom))
+		}
+		(DynamicImage::ImageRgba16(top), DynamicImage::ImageRgba16(bottom)) => {
+			DynamicImage::ImageRgba16(join(top, bottom))
+		}
+		(DynamicImage::ImageRgb32F(top), DynamicImage::ImageRgb32F(bottom)) => {
+			DynamicImage::ImageRgb32F(join(top, bottom))
+		}
+		(top, bottom) => DynamicImage::ImageRgba32F(join(top.into_rgba32f(), &bottom.to_rgba32f())),
+	}
+}
+
+/// An operation running on strips, which takes rows in order and returns rows of its result in
+/// order once they can be worked out.
+#[derive(Debug)]
+enum Stage<'a> {
+	Pixel(&'a Operation),
+	Crop {
+		bounds: [u32; 4],
+		/// Index of the next row received
+		row: u32,
+	},
+	Window {
+		operation: &'a Operation,
+		radius: u32,
+		height: u32,
+		rows: Rows,
+		/// Index of the next row returned
+		next: u32,
+	},
+	Resample {
+		vertical: Axis,
+		horizontal: Axis,
+		rows: Rows,
+		next: u32,
+	},
+}
+
+impl Stage<'_> {
+	/// Takes the next strip, if there is one, and returns the rows which are ready. Once `done`,
+	/// all remaining rows are returned.
+	fn push(
+		&mut self,
+		strip: Option<DynamicImage>,
+		done: bool,
+	) -> Result<Option<DynamicImage>, Error> {
+		match self {
+			Self::Pixel(operation) => Ok(strip
+				.map(|strip| operation.get_process().process(strip))
+				.transpose()?),
+			Self::Crop {
+				bounds: [x, y, width, height],
+				row,
+			} => {
+				let Some(strip) = strip else {
+					return Ok(None);
+				};
+				let first = *row;
+				*row += strip.height();
+
+				let rows = first.max(*y)..(*row).min(*y + *height);
+				Ok((!rows.is_empty())
+					.then(|| strip.crop_imm(*x, rows.start - first, *width, rows.len() as u32)))
+			}
+			Self::Window {
+				operation,
+				radius,
+				height,
+				rows,
+				next,
+			} => {
+				if let Some(strip) = strip {
+					rows.push(strip);
+				}
+				let ready = if done {
+					*height
+				} else {
+					rows.end.saturating_sub(*radius)
+				};
+				if ready <= *next {
+					return Ok(None);
+				}
+
+				let window = next.saturating_sub(*radius)..(ready + *radius).min(rows.end);
+				let processed = operation.get_process().process(rows.get(window.clone()))?;
+				let output =
+					processed.crop_imm(0, *next - window.start, processed.width(), ready - *next);
+				*next = ready;
+				rows.drop_before(ready.saturating_sub(*radius));
+
+				Ok(Some(output))
+			}
+			Self::Resample {
+				vertical,
+				horizontal,
+				rows,
+				next,
+			} => {
+				if let Some(strip) = strip {
+					rows.push(strip);
+				}
+				let first = *next;
+				while let Some(taps) = vertical.taps.get(*next as usize) {
+					if !done && taps.end() > rows.end {
+						break;
+					}
+					*next += 1;
+				}
+				if *next == first {
+					return Ok(None);
+				}
+
+				let output = resample(rows, first..*next, vertical, horizontal);
+				if let Some(taps) = vertical.taps.get(*next as usize) {
+					rows.drop_before(taps.start);
+				}
+
+				Ok(Some(output))
+			}
+		}
+	}
+}
+
+/// The input pixels making up an output pixel along one axis, and their weights.
+#[derive(Debug)]
+pub(crate) struct Taps {
+	pub start: u32,
+	pub weights: Vec<f32>,
+}
+
+impl Taps {
+	fn end(&self) -> u32 {
+		self.start + self.weights.len() as u32
+	}
+}
+
+/// Resampling along one axis, working out the taps of each output pixel the same way as
+/// resizing in `image` does so that the results match.
+#[derive(Debug)]
+pub(crate) struct Axis {
+	pub taps: Vec<Taps>,
+}
+
+impl Axis {
+	pub(crate) fn new(input: u32, output: u32, filter: FilterType) -> Self {
+		let (kernel, support): (fn(f32) -> f32, f32) = match filter {
+			FilterType::Nearest => (|_| 1.0, 0.0),
+			FilterType::Triangle => (triangle, 1.0),
+			FilterType::CatmullRom => (catmull_rom, 2.0),
+			FilterType::Gaussian => (|x| gaussian(x, 0.5), 3.0),
+			FilterType::Lanczos3 => (|x| lanczos(x, 3.0), 3.0),
+		};
+
+		Self::with_kernel(input, output, kernel, support)
+	}
+
+	/// Taps weighted by `kernel`, which reaches `support` pixels either side of the center when
+	/// scaling up, and further when scaling down.
+	pub(crate) fn with_kernel(
+		input: u32,
+		output: u32,
+		kernel: impl Fn(f32) -> f32,
+		support: f32,
+	) -> Self {
+		let ratio = input as f32 / output as f32;
+		let scale = ratio.max(1.0);
+		let support = support * scale;
+		let taps = (0..output)
+			.map(|position| {
+				let center = (position as f32 + 0.5) * ratio;
+				let start = ((center - support).floor() as i64).clamp(0, input as i64 - 1);
+				let end = ((center + support).ceil() as i64).clamp(start + 1, input as i64);
+
+				let center = center - 0.5;
+				let mut weights: Vec<f32> = (start..end)
+					.map(|input| kernel((input as f32 - center) / scale))
+					.collect();
+				let sum: f32 = weights.iter().sum();
+				weights.iter_mut().for_each(|weight| *weight /= sum);
+
+				Taps {
+					start: start as u32,
+					weights,
+				}
+			})
+			.collect();
+
+		Self { taps }
+	}
+}
+
+fn triangle(x: f32) -> f32 {
+	if x.abs() < 1.0 {
+		1.0 - x.abs()
+	} else {
+		0.0
+	}
+}
+
+/// The cubic spline of Mitchell and Netravali with B = 0 and C = 0.5.
+fn catmull_rom(x: f32) -> f32 {
+	let (b, c) = (0.0, 0.5);
+	let a = x.abs();
+	let k = if a < 1.0 {
+		(12.0 - 9.0 * b - 6.0 * c) * a.powi(3)
+			+ (-18.0 + 12.0 * b + 6.0 * c) * a.powi(2)
+			+ (6.0 - 2.0 * b)
+	} else if a < 2.0 {
+		(-b - 6.0 * c) * a.powi(3)
+			+ (6.0 * b + 30.0 * c) * a.powi(2)
+			+ (-12.0 * b - 48.0 * c) * a
+			+ (8.0 * b + 24.0 * c)
+	} else {
+		0.0
+	};
+
+	k / 6.0
+}
+
+pub(crate) fn gaussian(x: f32, sigma: f32) -> f32 {
+	((2.0 * PI).sqrt() * sigma).recip() * (-x.powi(2) / (2.0 * sigma.powi(2))).exp()
+}
+
+fn lanczos(x: f32, window: f32) -> f32 {
+	let sinc = |x: f32| {
+		if x == 0.0 {
+			1.0
+		} else {
+			(x * PI).sin() / (x * PI)
+		}
+	};
+
+	if x.abs() < window {
+		sinc(x) * sinc(x / window)
+	} else {
+		0.0
+	}
+}
+
+/// Output `rows` of a resample from the received rows, resampling vertically then horizontally.
+fn resample(rows: &Rows, output: Range<u32>, vertical: &Axis, horizontal: &Axis) -> DynamicImage {
+	let image = rows.image.as_ref().expect("rows have been received");
+	macro_rules! resample {
+		($buffer:expr) => {
+			resample_buffer($buffer, rows.start, output, vertical, horizontal)
+		};
+	}
+
+	match image {
+		DynamicImage::ImageLuma8(buffer) => DynamicImage::ImageLuma8(resample!(buffer)),
+		DynamicImage::ImageLumaA8(buffer) => DynamicImage::ImageLumaA8(resample!(buffer)),
+		DynamicImage::ImageRgb8(buffer) => DynamicImage::ImageRgb8(resample!(buffer)),
+		DynamicImage::ImageRgba8(buffer) => DynamicImage::ImageRgba8(resample!(buffer)),
+		DynamicImage::ImageLuma16(buffer) => DynamicImage::ImageLuma16(resample!(buffer)),
+		DynamicImage::ImageLumaA16(buffer) => DynamicImage::ImageLumaA16(resample!(buffer)),
+		DynamicImage::ImageRgb16(buffer) => DynamicImage::ImageRgb16(resample!(buffer)),
+		DynamicImage::ImageRgba16(buffer) => DynamicImage::ImageRgba16(resample!(buffer)),
+		DynamicImage::ImageRgb32F(buffer) => DynamicImage::ImageRgb32F(resample!(buffer)),
+		DynamicImage::ImageRgba32F(buffer) => DynamicImage::ImageRgba32F(resample!(buffer)),
+		image => DynamicImage::ImageRgba32F(resample!(&image.to_rgba32f())),
+	}
+}
+
+fn resample_buffer<P: Pixel>(
+	buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+	first_row: u32,
+	output: Range<u32>,
+	vertical: &Axis,
+	horizontal: &Axis,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+	let channels = P::CHANNEL_COUNT as usize;
+	let stride = buffer.width() as usize * channels;
+	let limit = |value: P::Subpixel| value.to_f32().unwrap_or_default();
+	let (min, max) = (
+		limit(P::Subpixel::DEFAULT_MIN_VALUE),
+		limit(P::Subpixel::DEFAULT_MAX_VALUE),
+	);
+	let to_subpixel = |value: f32| {
+		let value = value.clamp(min, max);
+		let value = if max > 1.0 { value.round() } else { value };
+		NumCast::from(value).unwrap_or(P::Subpixel::DEFAULT_MIN_VALUE)
+	};
+
+	let samples = buffer.as_raw();
+	let mut column = vec![0.0; stride];
+	let mut resampled = Vec::with_capacity(horizontal.taps.len() * channels * output.len());
+	for row in output.clone() {
+		let taps = &vertical.taps[row as usize];
+		column.fill(0.0);
+		for (offset, weight) in taps.weights.iter().enumerate() {
+			let start = (taps.start - first_row) as usize * stride + offset * stride;
+			for (sum, sample) in column.iter_mut().zip(&samples[start..start + stride]) {
+				*sum += sample.to_f32().unwrap_or_default() * weight;
+			}
+		}
+
+		for taps in horizontal.taps.iter() {
+			for channel in 0..channels {
+				let mut sum = 0.0;
+				for (offset, weight) in taps.weights.iter().enumerate() {
+					sum += column[(taps.start as usize + offset) * channels + channel] * weight;
+				}
+				resampled.push(to_subpixel(sum));
+			}
+		}
+	}
+
+	ImageBuffer::from_raw(horizontal.taps.len() as u32, output.len() as u32, resampled)
+		.expect("resampled rows fill the buffer")
+}
+
+/// Rows of a source, read from the top.
+enum Source<R: Read + Seek> {
+	Png {
+		reader: Box<png::Reader<R>>,
+		info: ImageInfo,
+	},
+	Tiff {
+		decoder: Box<TiffDecoder<R>>,
+		info: ImageInfo,
+		/// Decoded rows which haven't been read yet
+		rows: Rows,
+		/// Index of the next row of chunks to decode
+		chunk_row: u32,
+	},
+	Decoded {
+		image: DynamicImage,
+		row: u32,
+	},
+}
+
+impl<R: BufRead + Seek> Source<R> {
+	fn open(reader: R, options: &ProcessOptions) -> Result<Self, Error> {
+		let reader = ImageReader::new(reader).with_guessed_format()?;
+		let format = reader.format();
+		let mut reader = reader.into_inner();
+		let start = reader.stream_position()?;
+
+		let source = match format {
+			Some(ImageFormat::Png) if !png_interlaced(&mut reader, start)? => {
+				let mut decoder = png::Decoder::new(reader);
+				decoder.set_transformations(png::Transformations::EXPAND);
+				let reader = decoder.read_info().map_err(png_decoding_error)?;
+				let (width, height) = (reader.info().width, reader.info().height);
+				let color_type = match reader.output_color_type() {
+					(color, png::BitDepth::Eight) => png_color_type(color, false),
+					(color, png::BitDepth::Sixteen) => png_color_type(color, true),
+					_ => None,
+				}
+				.ok_or_else(|| unsupported(ImageFormat::Png))?;
+
+				Self::Png {
+					reader: Box::new(reader),
+					info: ImageInfo::new(width, height, color_type),
+				}
+			}
+			Some(ImageFormat::Tiff) => match tiff_color_type(&mut reader, start)? {
+				Some(color_type) => {
+					let mut decoder = TiffDecoder::new(reader).map_err(tiff_error)?;
+					let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+					Self::Tiff {
+						decoder: Box::new(decoder),
+						info: ImageInfo::new(width, height, color_type),
+						rows: Rows::default(),
+						chunk_row: 0,
+					}
+				}
+				None => Self::decoded(reader, start, options)?,
+			},
+			_ => Self::decoded(reader, start, options)?,
+		};
+
+		let info = source.info();
+		options.limits.check(info.width, info.height)?;
+		Ok(source)
+	}
+
+	fn decoded(mut reader: R, start: u64, options: &ProcessOptions) -> Result<Self, Error> {
+		reader.seek(SeekFrom::Start(start))?;
+		Ok(Self::Decoded {
+			image: decode(reader, options)?,
+			row: 0,
+		})
+	}
+
+	fn info(&self) -> ImageInfo {
+		match self {
+			Self::Png { info, .. } | Self::Tiff { info, .. } => *info,
+			Self::Decoded { image, .. } => ImageInfo::of(image),
+		}
+	}
+
+	/// Reads up to `count` rows, or `None` once every row has been read.
+	fn read(&mut self, count: u32) -> Result<Option<DynamicImage>, Error> {
+		match self {
+			Self::Png { reader, info } => {
+				let mut samples = Vec::new();
+				let mut rows = 0;
+				while rows < count {
+					let Some(row) = reader.next_row().map_err(png_decoding_error)? else {
+						break;
+					};
+					samples.extend_from_slice(row.data());
+					rows += 1;
+				}
+
+				if rows == 0 {
+					return Ok(None);
+				}
+				let strip =
+					if info.color_type.bytes_per_pixel() / info.color_type.channel_count() == 2 {
+						let samples = samples
+							.chunks_exact(2)
+							.map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+							.collect();
+						from_u16(info.color_type, info.width, rows, samples)
+					} else {
+						from_u8(info.color_type, info.width, rows, samples)
+					};
+				Ok(Some(strip.ok_or_else(|| unsupported(ImageFormat::Png))?))
+			}
+			Self::Tiff {
+				decoder,
+				info,
+				rows,
+				chunk_row,
+			} => {
+				let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+				let chunk_rows = info.height.div_ceil(chunk_height);
+				while rows.end - rows.start < count && *chunk_row < chunk_rows {
+					let across = match decoder.get_chunk_type() {
+						ChunkType::Strip => 1,
+						ChunkType::Tile => info.width.div_ceil(chunk_width),
+					};
+					rows.push(read_tiff_chunks(
+						decoder,
+						*info,
+						*chunk_row * across,
+						across,
+					)?);
+					*chunk_row += 1;
+				}
+
+				if rows.end == rows.start {
+					return Ok(None);
+				}
+				let read = rows.start..(rows.start + count).min(rows.end);
+				let strip = rows.get(read.clone());
+				rows.drop_before(read.end);
+				Ok(Some(strip))
+			}
+			Self::Decoded { image, row } => {
+				if *row >= image.height() {
+					return Ok(None);
+				}
+				let rows = count.min(image.height() - *row);
+				let strip = image.crop_imm(0, *row, image.width(), rows);
+				*row += rows;
+				Ok(Some(strip))
+			}
+		}
+	}
+}
+
+fn png_interlaced<R: BufRead + Seek>(reader: &mut R, start: u64) -> Result<bool, Error> {
+	let interlaced = png::Decoder::new(&mut *reader)
+		.read_info()
+		.map_err(png_decoding_error)?
+		.info()
+		.interlaced;
+	reader.seek(SeekFrom::Start(start))?;
+	Ok(interlaced)
+}
+
+fn png_color_type(color: png::ColorType, sixteen_bit: bool) -> Option<ColorType> {
+	Some(match (color, sixteen_bit) {
+		(png::ColorType::Grayscale, false) => ColorType::L8,
+		(png::ColorType::GrayscaleAlpha, false) => ColorType::La8,
+		(png::ColorType::Rgb, false) => ColorType::Rgb8,
+		(png::ColorType::Rgba, false) => ColorType::Rgba8,
+		(png::ColorType::Grayscale, true) => ColorType::L16,
+		(png::ColorType::GrayscaleAlpha, true) => ColorType::La16,
+		(png::ColorType::Rgb, true) => ColorType::Rgb16,
+		(png::ColorType::Rgba, true) => ColorType::Rgba16,
+		_ => return None,
+	})
+}
+
+/// Color type of a TIFF which can be read in chunks, or `None` for ones which are decoded in
+/// full.
+fn tiff_color_type<R: Read + Seek>(reader: &mut R, start: u64) -> Result<Option<ColorType>, Error> {
+	let color = TiffDecoder::new(&mut *reader)
+		.and_then(|mut decoder| decoder.colortype())
+		.map_err(tiff_error)?;
+	reader.seek(SeekFrom::Start(start))?;
+
+	Ok(match color {
+		tiff::ColorType::Gray(8) => Some(ColorType::L8),
+		tiff::ColorType::GrayA(8) => Some(ColorType::La8),
+		tiff::ColorType::RGB(8) => Some(ColorType::Rgb8),
+		tiff::ColorType::RGBA(8) => Some(ColorType::Rgba8),
+		tiff::ColorType::Gray(16) => Some(ColorType::L16),
+		tiff::ColorType::GrayA(16) => Some(ColorType::La16),
+		tiff::ColorType::RGB(16) => Some(ColorType::Rgb16),
+		tiff::ColorType::RGBA(16) => Some(ColorType::Rgba16),
+		_ => None,
+	})
+}
+
+/// Decodes `count` chunks from `first`, which lie side by side, as one strip.
+fn read_tiff_chunks<R: Read + Seek>(
+	decoder: &mut TiffDecoder<R>,
+	info: ImageInfo,
+	first: u32,
+	count: u32,
+) -> Result<DynamicImage, Error> {
+	let channels = info.color_type.channel_count() as usize;
+	let rows = decoder.chunk_data_dimensions(first).1;
+	let mut u8_samples = Vec::new();
+	let mut u16_samples = Vec::new();
+	for index in first..first + count {
+		let (width, _) = decoder.chunk_data_dimensions(index);
+		match decoder.read_chunk(index).map_err(tiff_error)? {
+			DecodingResult::U8(samples) => u8_samples.push((width as usize * channels, samples)),
+			DecodingResult::U16(samples) => u16_samples.push((width as usize * channels, samples)),
+			_ => return Err(unsupported(ImageFormat::Tiff)),
+		}
+	}
+
+	let strip = if u16_samples.is_empty() {
+		from_u8(info.color_type, info.width, rows, interleave(u8_samples))
+	} else {
+		from_u16(info.color_type, info.width, rows, interleave(u16_samples))
+	};
+	strip.ok_or_else(|| unsupported(ImageFormat::Tiff))
+}
+
+/// Joins chunks lying side by side, given as the length of their rows and their samples, into
+/// rows.
+fn interleave<T: Copy>(chunks: Vec<(usize, Vec<T>)>) -> Vec<T> {
+	let mut samples = Vec::with_capacity(chunks.iter().map(|(_, samples)| samples.len()).sum());
+	let rows = chunks
+		.first()
+		.map_or(0, |(stride, samples)| samples.len() / stride.max(&1));
+	for row in 0..rows {
+		for (stride, chunk) in chunks.iter() {
+			samples.extend_from_slice(&chunk[row * stride..(row + 1) * stride]);
+		}
+	}
+	samples
+}
+
+fn from_u8(color: ColorType, width: u32, height: u32, samples: Vec<u8>) -> Option<DynamicImage> {
+	match color {
+		ColorType::L8 => {
+			ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLuma8)
+		}
+		ColorType::La8 => {
+			ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLumaA8)
+		}
+		ColorType::Rgb8 => {
+			ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb8)
+		}
+		ColorType::Rgba8 => {
+			ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba8)
+		}
+		_ => None,
+	}
+}
+
+fn from_u16(color: ColorType, width: u32, height: u32, samples: Vec<u16>) -> Option<DynamicImage> {
+	match color {
+		ColorType::L16 => {
+			ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLuma16)
+		}
+		ColorType::La16 => {
+			ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLumaA16)
+		}
+		ColorType::Rgb16 => {
+			ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb16)
+		}
+		ColorType::Rgba16 => {
+			ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba16)
+		}
+		_ => None,
+	}
+}
+
+fn png_writer<W: Write>(
+	writer: W,
+	(width, height): (u32, u32),
+	color: ColorType,
+) -> Result<png::Writer<W>, Error> {
+	let (color, depth) = match color {
+		ColorType::L8 => (png::ColorType::Grayscale, png::BitDepth::Eight),
+		ColorType::La8 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Eight),
+		ColorType::Rgb8 => (png::ColorType::Rgb, png::BitDepth::Eight),
+		ColorType::Rgba8 => (png::ColorType::Rgba, png::BitDepth::Eight),
+		ColorType::L16 => (png::ColorType::Grayscale, png::BitDepth::Sixteen),
+		ColorType::La16 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen),
+		ColorType::Rgb16 => (png::ColorType::Rgb, png::BitDepth::Sixteen),
+		ColorType::Rgba16 => (png::ColorType::Rgba, png::BitDepth::Sixteen),
+		color => {
+			return Err(ImageError::Encoding(EncodingError::new(
+				ImageFormatHint::Exact(ImageFormat::Png),
+				format!("Cannot write {color:?} strips"),
+			))
+			.into())
+		}
+	};
+
+	let mut encoder = png::Encoder::new(writer, width, height);
+	encoder.set_color(color);
+	encoder.set_depth(depth);
+	encoder.write_header().map_err(png_encoding_error)
+}
+
+/// Samples of a strip as PNG stores them, with 16-bit samples big-endian.
+fn png_samples(image: &DynamicImage) -> Cow<'_, [u8]> {
+	let color = image.color();
+	if color.bytes_per_pixel() / color.channel_count() == 2 {
+		image
+			.as_bytes()
+			.chunks_exact(2)
+			.flat_map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]).to_be_bytes())
+			.collect()
+	} else {
+		Cow::Borrowed(image.as_bytes())
+	}
+}
+
+fn unsupported(format: ImageFormat) -> Error {
+	ImageError::Decoding(DecodingError::new(
+		ImageFormatHint::Exact(format),
+		"Unsupported sample type",
+	))
+	.into()
+}
+
+fn png_decoding_error(error: png::DecodingError) -> Error {
+	ImageError::Decoding(DecodingError::new(
+		ImageFormatHint::Exact(ImageFormat::Png),
+		error,
+	))
+	.into()
+}
+
+fn tiff_error(error: tiff::TiffError) -> Error {
+	ImageError::Decoding(DecodingError::new(
+		ImageFormatHint::Exact(ImageFormat::Tiff),
+		error,
+	))
+	.into()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{process_strips, supports};
+	use crate::{process_bytes, Operation, ProcessOptions};
+	use image::{DynamicImage, GenericImageView, ImageOutputFormat, Rgb, RgbImage};
+	use serde::Deserialize;
+	use std::io::Cursor;
+
+	#[derive(Deserialize)]
+	struct Config {
+		operations: Vec<Operation>,
+	}
+
+	#[test]
+	fn matches_whole_image() {
+		let operations = || {
+			toml::from_str::<Config>(
+				r#"
+			[[operations]]
+			crop-gravity = { width = { pixel = { pixels = 150 } }, height = { pixel = { pixels = 110 } }, gravity = "bottom" }
+
+			[[operations]]
+			blur = { sigma = 1.5 }
+
+			[[operations]]
+			resize = { width = { pixel = { pixels = 61 } }, height = { pixel = { pixels = 47 } }, filter = "lanczos3", crop_mode = "exact" }
+
+			[[operations]]
+			median-filter = { radius = 2 }
+
+			[[operations]]
+			sepia = {}
+
+			[[operations]]
+			scale = { factor = 1.7, filter = "catmull-rom" }
+			"#,
+			)
+			.unwrap()
+			.operations
+		};
+		assert!(supports(&operations()));
+
+		let image = DynamicImage::ImageRgb8(RgbImage::from_fn(160, 120, |x, y| {
+			Rgb([
+				(x * 7 % 256) as u8,
+				(y * 13 % 256) as u8,
+				((x * y) % 256) as u8,
+			])
+		}));
+		for format in [ImageOutputFormat::Png, ImageOutputFormat::Tiff] {
+			let mut source = Cursor::new(Vec::new());
+			image.write_to(&mut source, format).unwrap();
+			let expected = process_bytes(source.get_ref(), operations()).unwrap();
+
+			let mut output = Vec::new();
+			source.set_position(0);
+			let dimensions = process_strips(
+				source,
+				&mut output,
+				&operations(),
+				9,
+				&ProcessOptions::default(),
+			)
+			.unwrap();
+
+			let processed = image::load_from_memory(&output).unwrap();
+			assert_eq!(expected.dimensions(), dimensions);
+			let (expected, processed) = (expected.to_rgb8(), processed.to_rgb8());
+			if cfg!(feature = "fast-resize") {
+				let mut channels = expected.iter().zip(processed.iter());
+				assert!(channels.all(|(a, b)| a.abs_diff(*b) <= 2));
+			} else {
+				assert_eq!(expected, processed);
+			}
+		}
+	}
+}
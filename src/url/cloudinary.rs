@@ -0,0 +1,206 @@
+//! Translates Cloudinary delivery URLs such as
+//! `/demo/image/upload/c_fill,w_300,h_200/e_blur:300/q_80/v1312461204/sample.jpg`.
+//!
+//! Transformations using `w`, `h`, `x`, `y`, `c` (`scale`, `fit`, `limit`, `fill` or `crop`), `e`
+//! (`blur`, `brightness` or `grayscale`), `q` and `f` are supported, as well as a centered `g`. Any
+//! other parameter or value returns [`UrlError::UnsupportedOperation`].
+//!
+//! As with Cloudinary, integer lengths are pixels and decimal lengths are relative to the image
+//! dimension, e.g. `w_0.5`.
+
+use super::{brightness_from_percentage, UrlError, UrlPipeline};
+use crate::{
+	operations::{Blur, Crop, CropMode, CropOrigin, FilterType, Grayscale, Resize},
+	Coordinate, ImageOutputFormat, Operation, PercentageUnit, PixelUnit, Unit,
+};
+
+/// Transformation parameters, used to tell transformation segments apart from the public ID.
+const PARAMETERS: &[&str] = &[
+	"a", "ac", "af", "ar", "b", "bo", "br", "c", "co", "cs", "d", "dl", "dn", "dpr", "du", "e",
+	"eo", "f", "fl", "fn", "fps", "g", "h", "if", "ki", "l", "o", "p", "pg", "q", "r", "so", "sp",
+	"t", "u", "vc", "vs", "w", "x", "y", "z",
+];
+
+/// Parses a delivery URL path. Anything up to and including an `upload` segment is skipped, so
+/// both `/<cloud>/image/upload/…` and bare transformation paths are accepted.
+pub fn parse(path: &str) -> Result<UrlPipeline, UrlError> {
+	let path = path.trim_start_matches('/');
+	let path = match path.split_once("upload/") {
+		Some((prefix, path)) if prefix.is_empty() || prefix.ends_with('/') => path,
+		_ => path,
+	};
+
+	let mut pipeline = UrlPipeline::default();
+	let mut quality = None;
+
+	let mut segments = path.split('/').peekable();
+	while let Some(segment) = segments.next_if(|segment| is_transformation(segment)) {
+		parse_transformation(segment, &mut pipeline, &mut quality)?;
+	}
+
+	segments.next_if(|segment| {
+		segment.strip_prefix('v').is_some_and(|version| {
+			!version.is_empty() && version.bytes().all(|b| b.is_ascii_digit())
+		})
+	});
+
+	pipeline.set_quality(quality);
+
+	let source = segments.collect::<Vec<_>>().join("/");
+	pipeline.source = (!source.is_empty()).then_some(source);
+
+	Ok(pipeline)
+}
+
+fn is_transformation(segment: &str) -> bool {
+	segment.split(',').all(|component| {
+		component
+			.split_once('_')
+			.is_some_and(|(key, _)| PARAMETERS.contains(&key))
+	})
+}
+
+fn parse_transformation(
+	segment: &str,
+	pipeline: &mut UrlPipeline,
+	quality: &mut Option<u8>,
+) -> Result<(), UrlError> {
+	let mut width = None;
+	let mut height = None;
+	let mut x = None;
+	let mut y = None;
+	let mut mode = None;
+	let mut effect = None;
+
+	for component in segment.split(',') {
+		let (key, value) = component.split_once('_').unwrap_or((component, ""));
+		let invalid = || UrlError::InvalidArguments(key.to_string());
+
+		match key {
+			"w" => width = Some(parse_length(value).ok_or_else(invalid)?),
+			"h" => height = Some(parse_length(value).ok_or_else(invalid)?),
+			"x" => x = Some(parse_length(value).ok_or_else(invalid)?),
+			"y" => y = Some(parse_length(value).ok_or_else(invalid)?),
+			"c" => mode = Some(value),
+			"g" if value == "center" => {}
+			"e" => effect = Some(parse_effect(value)?),
+			"q" => *quality = Some(value.parse().map_err(|_| invalid())?),
+			"f" => {
+				pipeline.out_format = Some(
+					ImageOutputFormat::from_extension(value)
+						.ok_or_else(|| UrlError::UnknownFormat(value.to_string()))?,
+				)
+			}
+			_ => return Err(UrlError::UnsupportedOperation(component.to_string())),
+		}
+	}
+
+	let invalid = || UrlError::InvalidArguments("c".to_string());
+	let operation = match (mode.unwrap_or("scale"), width, height) {
+		(_, None, None) => None,
+		("crop", Some(width), Some(height)) => Some(Operation::Crop(Crop {
+			from: Coordinate {
+				x: x.ok_or_else(invalid)?,
+				y: y.ok_or_else(invalid)?,
+			},
+			to: CropOrigin::CropStart(Coordinate {
+				x: width,
+				y: height,
+			}),
+		})),
+		("fill", Some(width), Some(height)) => {
+			Some(resize(Some(width), Some(height), CropMode::Fill))
+		}
+		("scale", Some(width), Some(height)) => {
+			Some(resize(Some(width), Some(height), CropMode::Exact))
+		}
+		("scale" | "fit", width, height) => Some(resize(width, height, CropMode::Preserve)),
+		("limit", width, height) => Some(resize(width, height, CropMode::ShrinkOnly)),
+		("crop" | "fill", _, _) => return Err(invalid()),
+		(mode, _, _) => return Err(UrlError::UnsupportedOperation(format!("c_{mode}"))),
+	};
+
+	pipeline.operations.extend(operation);
+	pipeline.operations.extend(effect);
+
+	Ok(())
+}
+
+/// Parses integer pixels, or a decimal relative to the image dimension.
+fn parse_length(value: &str) -> Option<Unit> {
+	if value.contains('.') {
+		let percentage = PercentageUnit::try_from(value.parse::<f32>().ok()?).ok()?;
+		Some(Unit::Percentage(percentage))
+	} else {
+		Some(Unit::Pixel(PixelUnit::from(value.parse::<u32>().ok()?)))
+	}
+}
+
+fn resize(width: Option<Unit>, height: Option<Unit>, crop_mode: CropMode) -> Operation {
+	Operation::Resize(Resize {
+		width,
+		height,
+		filter: FilterType::Lanczos3,
+		crop_mode,
+	})
+}
+
+/// Parses `e_<effect>[:<level>]`.
+fn parse_effect(value: &str) -> Result<Operation, UrlError> {
+	let (name, level) = match value.split_once(':') {
+		Some((name, level)) => (name, Some(level)),
+		None => (value, None),
+	};
+	let invalid = || UrlError::InvalidArguments(format!("e_{name}"));
+	let level_or = |default: i32| match level {
+		Some(level) => level.parse::<i32>().map_err(|_| invalid()),
+		None => Ok(default),
+	};
+
+	let operation = match name {
+		// Blur strength ranges from 1 to 2000
+		"blur" => Operation::Blur(Blur {
+			sigma: level_or(100)?.clamp(1, 2000) as f32 / 100.0,
+		}),
+		"brightness" => Operation::AdjustBrightness(
+			brightness_from_percentage(level_or(80)?).ok_or_else(invalid)?,
+		),
+		"grayscale" if level.is_none() => Operation::Grayscale(Grayscale {}),
+		_ => return Err(UrlError::UnsupportedOperation(format!("e_{value}"))),
+	};
+
+	Ok(operation)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::parse;
+	use crate::{url::UrlError, Operation};
+
+	#[test]
+	fn parse_transformations_and_public_id() {
+		let pipeline = parse(
+			"/demo/image/upload/c_fill,w_300,h_0.5/e_grayscale/q_70/v1312461204/my_photo.jpg",
+		)
+		.unwrap();
+
+		assert_eq!(2, pipeline.operations.len());
+		assert!(matches!(pipeline.operations[0], Operation::Resize(_)));
+		assert!(matches!(pipeline.operations[1], Operation::Grayscale(_)));
+		assert_eq!(None, pipeline.out_format);
+		assert_eq!(Some(70), pipeline.quality);
+		assert_eq!(Some("my_photo.jpg".to_string()), pipeline.source);
+	}
+
+	#[test]
+	fn parse_unsupported() {
+		assert_eq!(
+			Some(UrlError::UnsupportedOperation("a_90".to_string())),
+			parse("/image/upload/a_90/sample.jpg").err()
+		);
+		assert_eq!(
+			Some(UrlError::UnsupportedOperation("c_pad".to_string())),
+			parse("/image/upload/c_pad,w_100/sample.jpg").err()
+		);
+	}
+}
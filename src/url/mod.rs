@@ -0,0 +1,493 @@
+//! Compact, URL-safe pipeline syntax in the style of imgproxy, e.g.
+//! `/rs:fill:300:300/bl:2/f:jpg/q:80/images/photo.png`.
+//!
+//! A path consists of option segments, each `name:arg:arg…`, followed by the path of the source
+//! image. Lengths are in pixels, or a percentage of the image dimension when suffixed with `p`
+//! (`50p`), or pixels back from the right or bottom edge when negative (`-16`). A resize width or
+//! height of `0` follows the aspect ratio from the other one.
+//!
+//! | Option | Operation |
+//! |---|---|
+//! | `rs:<fit\|fit-down\|fit-up\|fill\|force\|carve>:<width>:<height>[:<filter>]` | [`Resize`] |
+//! | `cr:<x>:<y>:<width>:<height>` | [`Crop`] from a point with a size |
+//! | `cr:<x>:<y>:<min\|max\|start>:<x>:<y>` | [`Crop`] with an explicit [`CropOrigin`] |
+//! | `bl:<sigma>` | [`Blur`] |
+//! | `br:<amount>` | [`AdjustBrightness`], negative to darken |
+//! | `gs:1` | [`Grayscale`] |
+//! | `st:[<label>]` | [`Stats`] |
+//! | `f:<extension>` | Output format |
+//! | `q:<quality>` | Output quality for formats which support it |
+//!
+//! Paths can be signed with [`sign`] so that servers only process pipelines they issued, with the
+//! signature prepended as the first segment: `/<signature>/rs:fill:300:300/photo.png`.
+//!
+//! URLs from Thumbor and Cloudinary can be translated with the [`thumbor`] and [`cloudinary`]
+//! adapters.
+
+pub mod cloudinary;
+pub mod thumbor;
+
+use crate::{
+	operations::{
+		AdjustBrightness, Blur, Crop, CropMode, CropOrigin, FilterType, Grayscale, Resize, Stats,
+	},
+	Coordinate, ImageOutputFormat, Operation, PercentageUnit, PixelUnit, Unit,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum UrlError {
+	#[error("Unknown option `{0}`")]
+	UnknownOption(String),
+
+	#[error("Invalid arguments for option `{0}`")]
+	InvalidArguments(String),
+
+	#[error("Unknown output format `{0}`")]
+	UnknownFormat(String),
+
+	#[error("Missing or invalid signature")]
+	InvalidSignature,
+
+	#[error("Operation `{0}` has no URL syntax")]
+	UnsupportedOperation(String),
+}
+
+/// A pipeline parsed from a URL path.
+#[derive(Debug, Default)]
+pub struct UrlPipeline {
+	pub operations: Vec<Operation>,
+	/// Left out when the path doesn't choose a format, such as for servers to negotiate one
+	pub out_format: Option<ImageOutputFormat>,
+	/// Quality from the path, already applied to `out_format` when there is one. See
+	/// [`with_quality`] for applying it to a format chosen some other way.
+	pub quality: Option<u8>,
+	/// Path of the source image following the options, without a leading `/`
+	pub source: Option<String>,
+}
+
+impl UrlPipeline {
+	fn set_quality(&mut self, quality: Option<u8>) {
+		self.quality = quality;
+		self.out_format = self
+			.out_format
+			.take()
+			.map(|out_format| with_quality(out_format, quality));
+	}
+}
+
+/// Parses the options and source of an unsigned path.
+pub fn parse(path: &str) -> Result<UrlPipeline, UrlError> {
+	let mut pipeline = UrlPipeline::default();
+	let mut quality = None;
+
+	let mut segments = path.trim_start_matches('/').split('/').peekable();
+	while let Some(segment) = segments.next_if(|segment| segment.contains(':')) {
+		let mut args = segment.split(':');
+		let name = args.next().unwrap_or_default();
+		let args = args.collect::<Vec<_>>();
+		let invalid = || UrlError::InvalidArguments(name.to_string());
+
+		let operation = match (name, args.as_slice()) {
+			("rs", [mode, width, height, filter @ ..]) if filter.len() <= 1 => {
+				Operation::Resize(Resize {
+					width: parse_dimension(width).ok_or_else(invalid)?,
+					height: parse_dimension(height).ok_or_else(invalid)?,
+					filter: match filter.first() {
+						Some(filter) => parse_filter(filter).ok_or_else(invalid)?,
+						None => FilterType::default(),
+					},
+					crop_mode: parse_crop_mode(mode).ok_or_else(invalid)?,
+				})
+			}
+			("cr", [x, y, width, height]) => Operation::Crop(Crop {
+				from: parse_coordinate(x, y).ok_or_else(invalid)?,
+				to: CropOrigin::CropStart(parse_coordinate(width, height).ok_or_else(invalid)?),
+			}),
+			("cr", [x, y, origin, to_x, to_y]) => {
+				let to = parse_coordinate(to_x, to_y).ok_or_else(invalid)?;
+				Operation::Crop(Crop {
+					from: parse_coordinate(x, y).ok_or_else(invalid)?,
+					to: match *origin {
+						"min" => CropOrigin::Minimum(to),
+						"max" => CropOrigin::Maximum(to),
+						"start" => CropOrigin::CropStart(to),
+						_ => return Err(invalid()),
+					},
+				})
+			}
+			("bl", [sigma]) => Operation::Blur(Blur {
+				sigma: sigma.parse().map_err(|_| invalid())?,
+			}),
+			("br", [amount]) => {
+				let amount: i32 = amount.parse().map_err(|_| invalid())?;
+				let value = u16::try_from(amount.unsigned_abs()).map_err(|_| invalid())?;
+				Operation::AdjustBrightness(if amount < 0 {
+					AdjustBrightness::Darken(value)
+				} else {
+					AdjustBrightness::Brighten(value)
+				})
+			}
+			("gs", ["1"]) => Operation::Grayscale(Grayscale {}),
+			// Options need a `:` to tell them apart from the source, so `st:` is the unlabelled form
+			("st", [""]) => Operation::Stats(Stats { label: None }),
+			("st", [label]) => Operation::Stats(Stats {
+				label: Some(label.to_string()),
+			}),
+			("f", [extension]) => {
+				pipeline.out_format = Some(
+					ImageOutputFormat::from_extension(extension)
+						.ok_or_else(|| UrlError::UnknownFormat(extension.to_string()))?,
+				);
+				continue;
+			}
+			("q", [value]) => {
+				quality = Some(value.parse::<u8>().map_err(|_| invalid())?);
+				continue;
+			}
+			("rs" | "cr" | "bl" | "br" | "gs" | "st", _) => return Err(invalid()),
+			_ => return Err(UrlError::UnknownOption(name.to_string())),
+		};
+
+		pipeline.operations.push(operation);
+	}
+
+	pipeline.set_quality(quality);
+
+	let source = segments.collect::<Vec<_>>().join("/");
+	pipeline.source = (!source.is_empty()).then_some(source);
+
+	Ok(pipeline)
+}
+
+/// Applies a quality to output formats which support one.
+pub fn with_quality(out_format: ImageOutputFormat, quality: Option<u8>) -> ImageOutputFormat {
+	let Some(quality) = quality else {
+		return out_format;
+	};
+
+	match out_format {
+		ImageOutputFormat::Jpeg { .. } => ImageOutputFormat::Jpeg { quality },
+		ImageOutputFormat::WebP {
+			lossless,
+			alpha_quality,
+			..
+		} => ImageOutputFormat::WebP {
+			quality: quality as f32,
+			lossless,
+			alpha_quality,
+		},
+		ImageOutputFormat::Jxl {
+			effort, lossless, ..
+		} => ImageOutputFormat::Jxl {
+			quality: quality as f32,
+			effort,
+			lossless,
+		},
+		out_format => out_format,
+	}
+}
+
+/// Brightness adjustment from a percentage between -100 and 100 of the full channel range.
+fn brightness_from_percentage(percentage: i32) -> Option<AdjustBrightness> {
+	if !(-100..=100).contains(&percentage) {
+		return None;
+	}
+
+	let value = (percentage.unsigned_abs() * 255 / 100) as u16;
+	Some(if percentage < 0 {
+		AdjustBrightness::Darken(value)
+	} else {
+		AdjustBrightness::Brighten(value)
+	})
+}
+
+/// Verifies the signature segment of a signed path and parses the rest of it.
+pub fn parse_signed(path: &str, key: &[u8], salt: &[u8]) -> Result<UrlPipeline, UrlError> {
+	let (signature, path) = path
+		.trim_start_matches('/')
+		.split_once('/')
+		.ok_or(UrlError::InvalidSignature)?;
+	let signature = URL_SAFE_NO_PAD
+		.decode(signature)
+		.map_err(|_| UrlError::InvalidSignature)?;
+
+	let path = format!("/{path}");
+	mac(key, salt, &path)
+		.verify_slice(&signature)
+		.map_err(|_| UrlError::InvalidSignature)?;
+
+	parse(&path)
+}
+
+/// Signs a path with HMAC-SHA256 over `salt` followed by the path, returning the signed path.
+pub fn sign(path: &str, key: &[u8], salt: &[u8]) -> String {
+	let path = format!("/{}", path.trim_start_matches('/'));
+	let signature = URL_SAFE_NO_PAD.encode(mac(key, salt, &path).finalize().into_bytes());
+	format!("/{signature}{path}")
+}
+
+fn mac(key: &[u8], salt: &[u8], path: &str) -> HmacSha256 {
+	let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+	mac.update(salt);
+	mac.update(path.as_bytes());
+	mac
+}
+
+/// Formats operations, an optional output format, and a source as a path which [`parse`] turns
+/// back into the same pipeline.
+pub fn to_path(
+	operations: &[Operation],
+	out_format: Option<&ImageOutputFormat>,
+	source: &str,
+) -> Result<String, UrlError> {
+	let mut segments = operations
+		.iter()
+		.map(format_operation)
+		.collect::<Result<Vec<_>, _>>()?;
+
+	if let Some(out_format) = out_format {
+		segments.push(format!("f:{}", out_format.extension()));
+		match out_format {
+			ImageOutputFormat::Jpeg { quality } => segments.push(format!("q:{quality}")),
+			ImageOutputFormat::WebP { quality, .. } | ImageOutputFormat::Jxl { quality, .. } => {
+				segments.push(format!("q:{}", quality.round()))
+			}
+			_ => {}
+		}
+	}
+
+	let source = source.trim_start_matches('/');
+	if !source.is_empty() {
+		segments.push(source.to_string());
+	}
+
+	Ok(format!("/{}", segments.join("/")))
+}
+
+fn format_operation(operation: &Operation) -> Result<String, UrlError> {
+	let unsupported = || UrlError::UnsupportedOperation(operation.name());
+
+	let segment = match operation {
+		Operation::Resize(resize) => {
+			let mode = match resize.crop_mode {
+				CropMode::Preserve => "fit",
+				CropMode::Fill => "fill",
+				CropMode::Exact => "force",
+				CropMode::SeamCarve => "carve",
+				CropMode::ShrinkOnly => "fit-down",
+				CropMode::EnlargeOnly => "fit-up",
+			};
+			let filter = match resize.filter {
+				FilterType::Nearest => "nearest",
+				FilterType::Triangle => "triangle",
+				FilterType::CatmullRom => "catmull-rom",
+				FilterType::Gaussian => "gaussian",
+				FilterType::Lanczos3 => "lanczos3",
+			};
+			format!(
+				"rs:{mode}:{}:{}:{filter}",
+				format_dimension(&resize.width).ok_or_else(unsupported)?,
+				format_dimension(&resize.height).ok_or_else(unsupported)?
+			)
+		}
+		Operation::Crop(crop) => {
+			let (origin, to) = match &crop.to {
+				CropOrigin::Minimum(to) => ("min", to),
+				CropOrigin::Maximum(to) => ("max", to),
+				CropOrigin::CropStart(to) => ("start", to),
+			};
+			format!(
+				"cr:{}:{origin}:{}",
+				format_coordinate(&crop.from).ok_or_else(unsupported)?,
+				format_coordinate(to).ok_or_else(unsupported)?
+			)
+		}
+		Operation::Blur(blur) => format!("bl:{}", blur.sigma),
+		Operation::AdjustBrightness(AdjustBrightness::Brighten(value)) => format!("br:{value}"),
+		Operation::AdjustBrightness(AdjustBrightness::Darken(value)) => format!("br:-{value}"),
+		Operation::Grayscale(_) => "gs:1".to_string(),
+		Operation::Stats(Stats { label: None }) => "st:".to_string(),
+		Operation::Stats(Stats { label: Some(label) })
+			if !label.is_empty() && !label.contains([':', '/']) =>
+		{
+			format!("st:{label}")
+		}
+		_ => return Err(unsupported()),
+	};
+
+	Ok(segment)
+}
+
+fn parse_unit(value: &str) -> Option<Unit> {
+	if let Some(pixels) = value.strip_prefix('-') {
+		return Some(Unit::FromEnd(PixelUnit::from(pixels.parse::<u32>().ok()?)));
+	}
+
+	match value.strip_suffix('p') {
+		Some(percentage) => {
+			let percentage = percentage.parse::<f32>().ok()? / 100.0;
+			Some(Unit::Percentage(PercentageUnit::try_from(percentage).ok()?))
+		}
+		None => Some(Unit::Pixel(PixelUnit::from(value.parse::<u32>().ok()?))),
+	}
+}
+
+/// Formats a length, or `None` for units which have no URL syntax.
+fn format_unit(unit: &Unit) -> Option<String> {
+	match unit {
+		Unit::Pixel(pixels) => Some(pixels.pixels.to_string()),
+		// Rounded, as `f32` fractions don't scale back to the percentages they were parsed from
+		Unit::Percentage(percentage) if percentage.of.is_none() => {
+			let percentage = (percentage.percentage as f64 * 1e6).round() / 1e4;
+			Some(format!("{percentage}p"))
+		}
+		Unit::FromEnd(pixels) => Some(format!("-{}", pixels.pixels)),
+		Unit::Percentage(_) | Unit::Expr(_) => None,
+	}
+}
+
+/// A resize dimension, where `0` follows the aspect ratio from the other dimension.
+fn parse_dimension(value: &str) -> Option<Option<Unit>> {
+	match value {
+		"0" => Some(None),
+		value => parse_unit(value).map(Some),
+	}
+}
+
+fn format_dimension(dimension: &Option<Unit>) -> Option<String> {
+	dimension
+		.as_ref()
+		.map_or_else(|| Some("0".to_string()), format_unit)
+}
+
+fn parse_coordinate(x: &str, y: &str) -> Option<Coordinate> {
+	Some(Coordinate {
+		x: parse_unit(x)?,
+		y: parse_unit(y)?,
+	})
+}
+
+fn format_coordinate(coordinate: &Coordinate) -> Option<String> {
+	Some(format!(
+		"{}:{}",
+		format_unit(&coordinate.x)?,
+		format_unit(&coordinate.y)?
+	))
+}
+
+fn parse_crop_mode(value: &str) -> Option<CropMode> {
+	match value {
+		"fit" => Some(CropMode::Preserve),
+		"fill" => Some(CropMode::Fill),
+		"force" => Some(CropMode::Exact),
+		"carve" => Some(CropMode::SeamCarve),
+		"fit-down" => Some(CropMode::ShrinkOnly),
+		"fit-up" => Some(CropMode::EnlargeOnly),
+		_ => None,
+	}
+}
+
+fn parse_filter(value: &str) -> Option<FilterType> {
+	match value {
+		"nearest" => Some(FilterType::Nearest),
+		"triangle" => Some(FilterType::Triangle),
+		"catmull-rom" => Some(FilterType::CatmullRom),
+		"gaussian" => Some(FilterType::Gaussian),
+		"lanczos3" => Some(FilterType::Lanczos3),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse, parse_signed, sign, to_path, UrlError};
+	use crate::{operations::Stats, ImageOutputFormat, Operation};
+
+	#[test]
+	fn parse_options_and_source() {
+		let pipeline = parse("/rs:fill:300:50p/bl:2/gs:1/q:70/images/photo.png").unwrap();
+
+		assert_eq!(3, pipeline.operations.len());
+		assert!(matches!(pipeline.operations[0], Operation::Resize(_)));
+		assert!(matches!(pipeline.operations[1], Operation::Blur(_)));
+		assert!(matches!(pipeline.operations[2], Operation::Grayscale(_)));
+		assert_eq!(None, pipeline.out_format);
+		assert_eq!(Some(70), pipeline.quality);
+		assert_eq!(Some("images/photo.png".to_string()), pipeline.source);
+
+		let pipeline = parse("/f:jpg/q:70/photo.png").unwrap();
+		assert_eq!(
+			Some(ImageOutputFormat::Jpeg { quality: 70 }),
+			pipeline.out_format
+		);
+	}
+
+	#[test]
+	fn parse_unknown_option() {
+		assert_eq!(
+			Some(UrlError::UnknownOption("zz".to_string())),
+			parse("/zz:1/photo.png").err()
+		);
+	}
+
+	#[test]
+	fn parse_invalid_arguments() {
+		assert_eq!(
+			Some(UrlError::InvalidArguments("rs".to_string())),
+			parse("/rs:squash:1:1/photo.png").err()
+		);
+	}
+
+	#[test]
+	fn round_trip() {
+		let path =
+			"/rs:fit:300:50p:lanczos3/cr:7p:33.33p:start:50p:50p/br:-20/st:resized/f:png/photo.png";
+		let pipeline = parse(path).unwrap();
+
+		assert_eq!(
+			path,
+			to_path(
+				&pipeline.operations,
+				pipeline.out_format.as_ref(),
+				pipeline.source.as_deref().unwrap()
+			)
+			.unwrap()
+		);
+	}
+
+	#[test]
+	fn unlabelled_stats_round_trip() {
+		let path = "/st:/bl:2/photo.png";
+		let pipeline = parse(path).unwrap();
+
+		assert!(matches!(
+			pipeline.operations[0],
+			Operation::Stats(Stats { label: None })
+		));
+		assert_eq!(
+			path,
+			to_path(&pipeline.operations, None, "photo.png").unwrap()
+		);
+	}
+
+	#[test]
+	fn signed_round_trip() {
+		let signed = sign("/bl:2/photo.png", b"key", b"salt");
+
+		assert!(parse_signed(&signed, b"key", b"salt").is_ok());
+		assert_eq!(
+			Some(UrlError::InvalidSignature),
+			parse_signed(&signed, b"other", b"salt").err()
+		);
+		assert_eq!(
+			Some(UrlError::InvalidSignature),
+			parse_signed(&signed.replace("bl:2", "bl:3"), b"key", b"salt").err()
+		);
+	}
+}
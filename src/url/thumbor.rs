@@ -0,0 +1,329 @@
+//! Translates Thumbor URLs such as
+//! `/unsafe/10x20:300x400/fit-in/200x0/filters:blur(2):quality(80)/photo.jpg`.
+//!
+//! Manual crops, resizing, flipping, and the `blur`, `brightness`, `grayscale`, `quality` and
+//! `format` filters are supported. Trimming, smart cropping, alignments other than centered, and
+//! other filters return [`UrlError::UnsupportedOperation`]. A dimension of `0` scales the image
+//! proportionally to the other dimension.
+
+use super::{brightness_from_percentage, UrlError, UrlPipeline};
+use crate::{
+	operations::{Blur, Crop, CropMode, CropOrigin, FilterType, Flip, Grayscale, Resize},
+	Coordinate, ImageOutputFormat, Operation, PixelUnit, Unit,
+};
+use base64::{engine::general_purpose::URL_SAFE, Engine};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// Parses an unsigned path, which must start with `unsafe/`.
+pub fn parse(path: &str) -> Result<UrlPipeline, UrlError> {
+	match path.trim_start_matches('/').split_once('/') {
+		Some(("unsafe", path)) => parse_options(path),
+		_ => Err(UrlError::InvalidSignature),
+	}
+}
+
+/// Verifies the HMAC-SHA1 signature of a path signed with Thumbor's security key and parses the
+/// rest of it.
+pub fn parse_signed(path: &str, key: &[u8]) -> Result<UrlPipeline, UrlError> {
+	let (signature, path) = path
+		.trim_start_matches('/')
+		.split_once('/')
+		.ok_or(UrlError::InvalidSignature)?;
+	let signature = URL_SAFE
+		.decode(signature)
+		.map_err(|_| UrlError::InvalidSignature)?;
+
+	mac(key, path)
+		.verify_slice(&signature)
+		.map_err(|_| UrlError::InvalidSignature)?;
+
+	parse_options(path)
+}
+
+/// Signs a path the same way as Thumbor's `libthumbor`, returning the signed path.
+pub fn sign(path: &str, key: &[u8]) -> String {
+	let path = path.trim_start_matches('/');
+	let signature = URL_SAFE.encode(mac(key, path).finalize().into_bytes());
+	format!("/{signature}/{path}")
+}
+
+fn mac(key: &[u8], path: &str) -> HmacSha1 {
+	let mut mac = HmacSha1::new_from_slice(key).expect("HMAC accepts keys of any length");
+	mac.update(path.as_bytes());
+	mac
+}
+
+fn parse_options(path: &str) -> Result<UrlPipeline, UrlError> {
+	let mut pipeline = UrlPipeline::default();
+	let mut quality = None;
+
+	let mut segments = path.split('/').peekable();
+
+	if segments
+		.next_if(|segment| segment.starts_with("trim"))
+		.is_some()
+	{
+		return Err(UrlError::UnsupportedOperation("trim".to_string()));
+	}
+
+	if let Some(crop) = segments.peek().and_then(|segment| parse_crop(segment)) {
+		segments.next();
+		pipeline.operations.push(Operation::Crop(crop));
+	}
+
+	let fit_in = segments.next_if_eq(&"fit-in").is_some();
+
+	if let Some(size) = segments.peek().and_then(|segment| parse_size(segment)) {
+		segments.next();
+		let Size {
+			width,
+			height,
+			flip,
+		} = size;
+		if let Some(resize) = resize((width, height), fit_in) {
+			pipeline.operations.push(Operation::Resize(resize));
+		}
+		pipeline.operations.extend(flip.map(Operation::Flip));
+	}
+
+	if let Some(halign) = segments.next_if(|segment| ["left", "center", "right"].contains(segment))
+	{
+		if halign != "center" {
+			return Err(UrlError::UnsupportedOperation(halign.to_string()));
+		}
+	}
+
+	if let Some(valign) = segments.next_if(|segment| ["top", "middle", "bottom"].contains(segment))
+	{
+		if valign != "middle" {
+			return Err(UrlError::UnsupportedOperation(valign.to_string()));
+		}
+	}
+
+	if segments.next_if_eq(&"smart").is_some() {
+		return Err(UrlError::UnsupportedOperation("smart".to_string()));
+	}
+
+	if let Some(filters) = segments.next_if(|segment| segment.starts_with("filters:")) {
+		parse_filters(&filters["filters:".len()..], &mut pipeline, &mut quality)?;
+	}
+
+	pipeline.set_quality(quality);
+
+	let source = segments.collect::<Vec<_>>().join("/");
+	pipeline.source = (!source.is_empty()).then_some(source);
+
+	Ok(pipeline)
+}
+
+/// Parses a manual crop, `<left>x<top>:<right>x<bottom>`.
+fn parse_crop(segment: &str) -> Option<Crop> {
+	let (from, to) = segment.split_once(':')?;
+	let (left, top) = from.split_once('x')?;
+	let (right, bottom) = to.split_once('x')?;
+
+	let pixel = |value: &str| {
+		value
+			.parse::<u32>()
+			.ok()
+			.map(PixelUnit::from)
+			.map(Unit::Pixel)
+	};
+
+	Some(Crop {
+		from: Coordinate {
+			x: pixel(left)?,
+			y: pixel(top)?,
+		},
+		to: CropOrigin::Minimum(Coordinate {
+			x: pixel(right)?,
+			y: pixel(bottom)?,
+		}),
+	})
+}
+
+struct Size {
+	width: u32,
+	height: u32,
+	flip: Option<Flip>,
+}
+
+/// Parses a size, `<width>x<height>`, where a `-` prefix flips the image along that axis.
+///
+/// Returns `None` when the segment isn't a size.
+fn parse_size(segment: &str) -> Option<Size> {
+	let (width, height) = segment.split_once('x')?;
+	let (flip_width, width) = strip_flip(width);
+	let (flip_height, height) = strip_flip(height);
+
+	Some(Size {
+		width: parse_dimension(width)?,
+		height: parse_dimension(height)?,
+		flip: match (flip_width, flip_height) {
+			(true, true) => Some(Flip::Both),
+			(true, false) => Some(Flip::Horizontal),
+			(false, true) => Some(Flip::Vertical),
+			(false, false) => None,
+		},
+	})
+}
+
+fn strip_flip(value: &str) -> (bool, &str) {
+	match value.strip_prefix('-') {
+		Some(value) => (true, value),
+		None => (false, value),
+	}
+}
+
+fn parse_dimension(value: &str) -> Option<u32> {
+	match value {
+		"" | "orig" => Some(0),
+		value => value.parse().ok(),
+	}
+}
+
+fn resize((width, height): (u32, u32), fit_in: bool) -> Option<Resize> {
+	if width == 0 && height == 0 {
+		return None;
+	}
+
+	// A missing dimension follows the aspect ratio from the other one
+	let dimension = |value: u32| match value {
+		0 => None,
+		value => Some(Unit::Pixel(PixelUnit::from(value))),
+	};
+
+	Some(Resize {
+		width: dimension(width),
+		height: dimension(height),
+		filter: FilterType::Lanczos3,
+		crop_mode: if fit_in || width == 0 || height == 0 {
+			CropMode::Preserve
+		} else {
+			CropMode::Fill
+		},
+	})
+}
+
+/// Parses `name(args):name(args)…` filters.
+fn parse_filters(
+	filters: &str,
+	pipeline: &mut UrlPipeline,
+	quality: &mut Option<u8>,
+) -> Result<(), UrlError> {
+	let mut rest = filters;
+	while !rest.is_empty() {
+		let invalid = || UrlError::InvalidArguments("filters".to_string());
+		let (name, remainder) = rest.split_once('(').ok_or_else(invalid)?;
+		let (args, remainder) = remainder.split_once(')').ok_or_else(invalid)?;
+		rest = match remainder.strip_prefix(':') {
+			Some(remainder) => remainder,
+			None if remainder.is_empty() => remainder,
+			None => return Err(invalid()),
+		};
+
+		let args = args
+			.split(',')
+			.filter(|arg| !arg.is_empty())
+			.collect::<Vec<_>>();
+		let invalid = || UrlError::InvalidArguments(name.to_string());
+
+		let operation = match (name, args.as_slice()) {
+			// `blur(radius[,sigma])`, where the sigma defaults to the radius
+			("blur", [sigma] | [_, sigma]) => Operation::Blur(Blur {
+				sigma: sigma.parse().map_err(|_| invalid())?,
+			}),
+			("brightness", [amount]) => Operation::AdjustBrightness(
+				amount
+					.parse()
+					.ok()
+					.and_then(brightness_from_percentage)
+					.ok_or_else(invalid)?,
+			),
+			("grayscale", []) => Operation::Grayscale(Grayscale {}),
+			("quality", [value]) => {
+				*quality = Some(value.parse().map_err(|_| invalid())?);
+				continue;
+			}
+			("format", [extension]) => {
+				pipeline.out_format = Some(
+					ImageOutputFormat::from_extension(extension)
+						.ok_or_else(|| UrlError::UnknownFormat(extension.to_string()))?,
+				);
+				continue;
+			}
+			("blur" | "brightness" | "grayscale" | "quality" | "format", _) => {
+				return Err(invalid())
+			}
+			(name, _) => return Err(UrlError::UnsupportedOperation(name.to_string())),
+		};
+
+		pipeline.operations.push(operation);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse, parse_signed, sign};
+	use crate::{
+		operations::{CropMode, Flip},
+		url::UrlError,
+		ImageOutputFormat, Operation,
+	};
+
+	#[test]
+	fn parse_options_and_source() {
+		let pipeline =
+			parse("/unsafe/10x20:300x400/fit-in/200x0/filters:blur(2):format(webp)/a/photo.jpg")
+				.unwrap();
+
+		assert_eq!(3, pipeline.operations.len());
+		assert!(matches!(pipeline.operations[0], Operation::Crop(_)));
+		assert!(matches!(
+			pipeline.operations[1],
+			Operation::Resize(ref resize) if matches!(resize.crop_mode, CropMode::Preserve)
+		));
+		assert!(matches!(pipeline.operations[2], Operation::Blur(_)));
+		assert_eq!(Some(ImageOutputFormat::webp()), pipeline.out_format);
+		assert_eq!(Some("a/photo.jpg".to_string()), pipeline.source);
+	}
+
+	#[test]
+	fn parse_flip() {
+		let pipeline = parse("/unsafe/-300x-0/photo.jpg").unwrap();
+
+		assert!(matches!(pipeline.operations[0], Operation::Resize(_)));
+		assert!(matches!(
+			pipeline.operations[1],
+			Operation::Flip(Flip::Both)
+		));
+	}
+
+	#[test]
+	fn parse_unsupported() {
+		assert_eq!(
+			Some(UrlError::UnsupportedOperation("smart".to_string())),
+			parse("/unsafe/300x200/smart/photo.jpg").err()
+		);
+	}
+
+	#[test]
+	fn signed() {
+		let signed = sign("300x200/photo.jpg", b"key");
+
+		assert!(parse_signed(&signed, b"key").is_ok());
+		assert_eq!(
+			Some(UrlError::InvalidSignature),
+			parse_signed(&signed, b"other").err()
+		);
+		assert_eq!(
+			Some(UrlError::InvalidSignature),
+			parse("/300x200/photo.jpg").err()
+		);
+	}
+}
@@ -0,0 +1,177 @@
+//! Sets of resized copies of an image for responsive `srcset`s.
+
+use crate::{
+	encode_to,
+	operations::{CropMode, FilterType, Resize},
+	Error, ImageOutputFormat, PixelUnit, Process, Unit,
+};
+use image::{DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::BTreeMap,
+	fs::File,
+	io::BufWriter,
+	path::{Path, PathBuf},
+};
+
+/// Widths and formats to write copies of an image in.
+///
+/// ```toml
+/// [variants]
+/// widths = [320, 640, 1280]
+/// formats = ["web-p", { jpeg = { quality = 80 } }]
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Variants {
+	pub widths: Vec<u32>,
+	pub formats: Vec<ImageOutputFormat>,
+	/// Defaults to lanczos3
+	#[serde(default = "Variants::default_filter")]
+	pub filter: FilterType,
+	/// Also writes widths larger than the image rather than leaving them out
+	#[serde(default)]
+	pub upscale: bool,
+}
+
+impl Variants {
+	fn default_filter() -> FilterType {
+		FilterType::Lanczos3
+	}
+
+	/// Writes a copy of `image` for each width and format next to `path`, named like
+	/// `photo-320.webp`.
+	pub fn write<P: AsRef<Path>>(
+		&self,
+		image: &DynamicImage,
+		path: P,
+	) -> Result<Vec<Variant>, Error> {
+		let path = path.as_ref();
+		let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+		let mut widths = self.widths.clone();
+		widths.sort_unstable();
+		widths.dedup();
+		if !self.upscale {
+			widths.retain(|&width| width <= image.width());
+		}
+
+		let mut variants = Vec::new();
+		for width in widths {
+			let resized = Resize {
+				width: Some(Unit::Pixel(PixelUnit::from(width))),
+				height: None,
+				filter: self.filter,
+				crop_mode: CropMode::Exact,
+			}
+			.process(image.clone())?;
+
+			for format in self.formats.iter() {
+				let file = path.with_file_name(format!("{stem}-{width}.{}", format.extension()));
+				let mut out = BufWriter::new(File::create(&file)?);
+				encode_to(&mut out, &resized, format.clone())?;
+
+				let (width, height) = resized.dimensions();
+				variants.push(Variant {
+					width,
+					height,
+					format: format.clone(),
+					path: file,
+				});
+			}
+		}
+
+		Ok(variants)
+	}
+}
+
+/// A copy of an image written by [`Variants::write`].
+#[derive(Debug)]
+pub struct Variant {
+	pub width: u32,
+	pub height: u32,
+	pub format: ImageOutputFormat,
+	pub path: PathBuf,
+}
+
+/// Description of written variants, for generating `srcset`s.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Manifest {
+	pub variants: Vec<ManifestEntry>,
+	/// `srcset` attribute values keyed by MIME type
+	pub srcset: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ManifestEntry {
+	pub width: u32,
+	pub height: u32,
+	/// MIME type of the file
+	pub format: String,
+	/// Name of the file, without its directory
+	pub file: String,
+}
+
+impl Manifest {
+	pub fn new(variants: &[Variant]) -> Self {
+		let mut manifest = Self::default();
+		for variant in variants {
+			let file = variant
+				.path
+				.file_name()
+				.unwrap_or_default()
+				.to_string_lossy()
+				.into_owned();
+			let format = variant.format.mime_type().to_string();
+
+			let srcset = manifest.srcset.entry(format.clone()).or_default();
+			if !srcset.is_empty() {
+				srcset.push_str(", ");
+			}
+			srcset.push_str(&format!("{file} {}w", variant.width));
+
+			manifest.variants.push(ManifestEntry {
+				width: variant.width,
+				height: variant.height,
+				format,
+				file,
+			});
+		}
+
+		manifest
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Manifest, Variants};
+	use crate::{operations::FilterType, ImageOutputFormat};
+	use image::{DynamicImage, RgbImage};
+	use std::fs;
+
+	#[test]
+	fn writes_each_width_and_format() {
+		let dir = std::env::temp_dir().join(format!("imageless-variants-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let variants = Variants {
+			widths: vec![20, 10, 80],
+			formats: vec![ImageOutputFormat::png(), ImageOutputFormat::Bmp],
+			filter: FilterType::Triangle,
+			upscale: false,
+		};
+
+		let image = DynamicImage::ImageRgb8(RgbImage::new(40, 20));
+		let written = variants.write(&image, dir.join("photo.jpg"));
+		fs::remove_dir_all(&dir).ok();
+
+		let manifest = Manifest::new(&written.unwrap());
+		assert_eq!(4, manifest.variants.len());
+		assert_eq!(5, manifest.variants[0].height);
+		assert_eq!(
+			"photo-10.png 10w, photo-20.png 20w",
+			manifest.srcset["image/png"]
+		);
+	}
+}
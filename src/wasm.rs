@@ -0,0 +1,66 @@
+//! JavaScript bindings, enabled with the `wasm` feature. Build the module, then generate the
+//! JavaScript for it with `wasm-bindgen`:
+//!
+//! ```sh
+//! cargo rustc --lib --release --target wasm32-unknown-unknown --crate-type cdylib --features wasm
+//! ```
+
+use crate::{metadata::Orientation, Pipeline};
+use wasm_bindgen::prelude::*;
+
+/// Processes an encoded image with a JSON encoded [`Pipeline`], returning the encoded output.
+///
+/// ```js
+/// const output = processBytes(input, JSON.stringify({
+///   out_format: { jpeg: { quality: 80 } },
+///   operations: [{ grayscale: {} }],
+/// }));
+/// ```
+#[wasm_bindgen(js_name = processBytes)]
+pub fn process_bytes(bytes: &[u8], pipeline: &str) -> Result<Vec<u8>, JsError> {
+	let pipeline: Pipeline = serde_json::from_str(pipeline)?;
+	Ok(pipeline.process_bytes(bytes)?)
+}
+
+/// Width and height of the output of a JSON encoded [`Pipeline`] for a source of `width` by
+/// `height` with an optional EXIF `orientation` from 1 to 8, without any image data. Throws when
+/// the size depends on the pixels.
+///
+/// ```js
+/// const [width, height] = outputDimensions(pipeline, 4000, 3000, 6);
+/// ```
+#[wasm_bindgen(js_name = outputDimensions)]
+pub fn output_dimensions(
+	pipeline: &str,
+	width: u32,
+	height: u32,
+	orientation: Option<u32>,
+) -> Result<Vec<u32>, JsError> {
+	let pipeline: Pipeline = serde_json::from_str(pipeline)?;
+	let orientation = orientation.and_then(Orientation::from_exif);
+	let (width, height) = pipeline.output_dimensions((width, height), orientation)?;
+	Ok(vec![width, height])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::process_bytes;
+	use image::{DynamicImage, GenericImageView, ImageOutputFormat, Rgb, RgbImage};
+	use std::io::Cursor;
+
+	#[test]
+	fn processes_with_json_pipeline() {
+		let mut png = Cursor::new(Vec::new());
+		DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 4, Rgb([200, 100, 50])))
+			.write_to(&mut png, ImageOutputFormat::Png)
+			.unwrap();
+
+		let pipeline = r#"{ "out_format": "bmp", "operations": [{ "grayscale": {} }] }"#;
+		let output = process_bytes(png.get_ref(), pipeline).unwrap();
+
+		let image = image::load_from_memory(&output).unwrap();
+		assert_eq!((8, 4), image.dimensions());
+		let [red, green, blue, _] = image.get_pixel(3, 2).0;
+		assert!(red == green && green == blue && red > 50 && red < 200);
+	}
+}